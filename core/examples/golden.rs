@@ -0,0 +1,17 @@
+//! Run the golden merge fixtures: `cargo run --example golden -- [dir] [--bless]`
+//! (defaults to `fixtures/`).
+
+use sink_core::testing::Golden;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let bless = args.iter().any(|a| a == "--bless");
+    let root = args.iter().find(|a| !a.starts_with("--")).map_or("fixtures", String::as_str);
+
+    let report = Golden::new(root).bless(bless).run()?;
+    println!("{report}");
+    if report.failures().next().is_some() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
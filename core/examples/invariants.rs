@@ -0,0 +1,36 @@
+//! Check the merge invariants (determinism, symmetry, identity, idempotence,
+//! convergence) on every
+//! golden fixture: `cargo run --example invariants -- [dir]`.
+
+use sink_core::testing::{
+    Golden, check_convergence, check_determinism, check_idempotence, check_identity, check_symmetry,
+};
+
+fn main() -> anyhow::Result<()> {
+    let root = std::env::args().nth(1).unwrap_or_else(|| "fixtures".to_string());
+    let mut failed = 0;
+    for fixture in Golden::new(&root).discover()? {
+        let read = |stem: &str| std::fs::read_to_string(fixture.dir.join(format!("{stem}.{}", fixture.ext)));
+        let (base, a, b) = (read("base")?, read("a")?, read("b")?);
+        let lang = fixture.lang();
+        let checks = [
+            ("determinism", check_determinism(&base, &a, &b, lang)),
+            ("symmetry", check_symmetry(&base, &a, &b, lang)),
+            ("identity(A)", check_identity(&base, &a, lang)),
+            ("identity(B)", check_identity(&base, &b, lang)),
+            ("idempotence", check_idempotence(&base, &a, &b, lang)),
+            ("convergence", check_convergence(&base, &a, &b, lang)),
+        ];
+        for (name, result) in checks {
+            if let Err(e) = result {
+                failed += 1;
+                println!("FAIL {} {name}: {e:#}", fixture.name);
+            }
+        }
+    }
+    println!("{failed} invariant violations");
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -23,12 +23,15 @@ export function updateUser(name: string) {
 
     let res = three_way_merge_top_level(base, a, b, AstLanguage::TypeScript).unwrap();
     println!("--- MERGED CODE ---\n{}", res.merged_code);
+    for w in &res.warnings {
+        println!("note: {w}");
+    }
     if res.conflicts.is_empty() {
         println!("(no conflicts)");
     } else {
         println!("--- CONFLICTS ---");
         for c in res.conflicts {
-            println!("- {c}");
+            println!("- [{}] {c}", c.id);
         }
     }
 }
\ No newline at end of file
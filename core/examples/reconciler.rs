@@ -0,0 +1,21 @@
+use sink_core::{three_way_merge, AstLanguage, MemberMerge, MergeOptions};
+
+/// Both branches bumped `export const SCHEMA_VERSION = N;`: keep the higher one.
+fn highest_version(_base: &str, a: &str, b: &str, _label: &str, _lang: AstLanguage) -> MemberMerge {
+    let version = |code: &str| code.rsplit('=').next()?.trim().trim_end_matches(';').parse::<u32>().ok();
+    match (version(a), version(b)) {
+        (Some(x), Some(y)) => MemberMerge::Merged(if x >= y { a } else { b }.to_string()),
+        _ => MemberMerge::NotApplicable,
+    }
+}
+
+fn main() {
+    let base = "export const SCHEMA_VERSION = 3;\n";
+    let a = "export const SCHEMA_VERSION = 4;\n";
+    let b = "export const SCHEMA_VERSION = 5;\n";
+
+    let opts = MergeOptions::default().with_reconciler("lexical_declaration", Box::new(highest_version));
+    let res = three_way_merge(base, a, b, AstLanguage::TypeScript, &opts).unwrap();
+    println!("--- MERGED CODE ---\n{}", res.merged_code);
+    println!("conflicts: {:?}", res.conflicts);
+}
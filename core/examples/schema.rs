@@ -0,0 +1,64 @@
+//! Read back the `AstFile` documents of every schema version:
+//! `cargo run --example schema -- [dir]` (defaults to `fixtures/schema/`).
+//!
+//! Each document must read, carry hashes that match its units, and come out
+//! of a write and re-read unchanged. The current version needs a `v<N>.json`,
+//! and a document newer than it must be rejected.
+
+use sink_core::{AST_SCHEMA_VERSION, AstFile, content_hash};
+
+fn main() -> anyhow::Result<()> {
+    let dir = std::env::args().nth(1).unwrap_or_else(|| "fixtures/schema".to_string());
+    let mut paths: Vec<_> = std::fs::read_dir(&dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|p| p.extension().is_some_and(|e| e == "json"));
+    paths.sort();
+
+    let mut failures = Vec::new();
+    let mut current = None;
+    for path in &paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let text = std::fs::read_to_string(path)?;
+        let file: AstFile = match serde_json::from_str(&text) {
+            Ok(file) => file,
+            Err(e) => {
+                failures.push(format!("{name}: {e}"));
+                continue;
+            }
+        };
+        if let Some(u) = file.units.iter().find(|u| u.content_hash != content_hash(&file.code[u.start_byte..u.end_byte])) {
+            failures.push(format!("{name}: stale hash on {}", u.kind));
+        }
+        let written = serde_json::to_value(&file)?;
+        if written["schema"] != AST_SCHEMA_VERSION {
+            failures.push(format!("{name}: written as schema {}", written["schema"]));
+        }
+        let again: AstFile = serde_json::from_value(written.clone())?;
+        if serde_json::to_value(&again)? != written {
+            failures.push(format!("{name}: changed on a second round trip"));
+        }
+        if name == format!("v{AST_SCHEMA_VERSION}.json") {
+            current = Some(written);
+        }
+    }
+
+    match current {
+        Some(mut newer) => {
+            newer["schema"] = (AST_SCHEMA_VERSION + 1).into();
+            if serde_json::from_value::<AstFile>(newer).is_ok() {
+                failures.push(format!("schema {} was read", AST_SCHEMA_VERSION + 1));
+            }
+        }
+        None => failures.push(format!("no v{AST_SCHEMA_VERSION}.json for the current schema")),
+    }
+
+    println!("{} documents, {} failures", paths.len(), failures.len());
+    for failure in &failures {
+        println!("  {failure}");
+    }
+    if !failures.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
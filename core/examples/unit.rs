@@ -0,0 +1,17 @@
+use sink_core::{merge_unit, AstLanguage};
+
+fn main() {
+    // one conflicted function, as an editor would hand it over
+    let base = "export function greet(name: string) {\n  return \"Hello \" + name;\n}";
+    let a = "export function greet(userName: string) {\n  return \"Hello \" + userName;\n}";
+    let b = "export function greet(name: string) {\n  return \"Hello, \" + name + \"!\";\n}";
+
+    let res = merge_unit(base, a, b, "function_declaration", AstLanguage::TypeScript);
+    println!("--- MERGED UNIT ---\n{}", res.merged_code);
+    for w in &res.warnings {
+        println!("note: {w}");
+    }
+    for c in &res.conflicts {
+        println!("- [{}] {c}", c.id);
+    }
+}
@@ -0,0 +1,26 @@
+use sink_core::{parse_typescript_to_ast, AstLanguage, WalkControl};
+
+fn main() {
+    let code = r#"
+import { log } from './util';
+export function updateUser(name: string) {
+    log(name);
+    return "Hello, " + name;
+}
+"#;
+
+    let ast = parse_typescript_to_ast(code, AstLanguage::TypeScript).unwrap();
+
+    // every call expression, without re-parsing
+    ast.walk(|node, depth| {
+        if node.kind() == "call_expression" {
+            println!("{}call at {:?}: {}", "  ".repeat(depth), node.start_position(), &code[node.byte_range()]);
+        }
+        WalkControl::Continue
+    });
+
+    let at = code.find("name;").unwrap();
+    let node = ast.node_at(at).unwrap();
+    let unit = ast.unit_at(at).unwrap();
+    println!("byte {at}: {} inside {}::{}", node.kind(), unit.kind, unit.name.as_deref().unwrap_or("?"));
+}
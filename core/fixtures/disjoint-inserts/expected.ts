@@ -0,0 +1,5 @@
+function f() {}
+
+function h() {}
+
+function g() {}
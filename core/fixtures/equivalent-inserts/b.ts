@@ -0,0 +1,2 @@
+function f() {}
+export function helper(x:number){return [x,x+1,];}
@@ -0,0 +1,5 @@
+function f() {}
+
+export function helper(x: number) {
+  return [x, x + 1];
+}
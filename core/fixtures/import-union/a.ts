@@ -0,0 +1,3 @@
+import b from "b";
+import a from "a";
+function f() {}
@@ -0,0 +1,3 @@
+export function updateUser(userName: string) {
+  return "Hello " + userName;
+}
@@ -0,0 +1,3 @@
+export function updateUser(name: string) {
+  return "Hello " + name;
+}
@@ -0,0 +1,3 @@
+app.use(a);
+app.use(b);
+app.listen(1);
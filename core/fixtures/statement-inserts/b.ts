@@ -0,0 +1,3 @@
+app.use(a);
+app.use(c);
+app.listen(1);
@@ -0,0 +1,2 @@
+app.use(a);
+app.listen(1);
@@ -0,0 +1,4 @@
+app.use(a);
+app.use(c);
+app.use(b);
+app.listen(1);
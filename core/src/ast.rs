@@ -3,105 +3,799 @@
 
 use anyhow::*;
 use serde::{Deserialize, Serialize};
-use tree_sitter::{Language, Node, Parser, Tree};
-use tree_sitter_typescript::language_tsx;
-use tree_sitter_typescript::language_typescript;
+use std::collections::HashMap;
+use tree_sitter::{Language, Node, Tree};
 
-#[derive(Debug, Clone, Copy)]
+use crate::hash::{bytes_hash, content_hash};
+use crate::policy::{FilePolicy, GeneratedMerge};
+use crate::regions;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AstLanguage {
     TypeScript,
     Tsx,
+    /// A grammar loaded at runtime (see [`crate::load_grammar`]).
+    Runtime(u16),
+}
+
+/// The grammar for `lang`, if its `lang-*` cargo feature compiled it in.
+#[cfg(feature = "lang-typescript")]
+pub(crate) fn ts_language(lang: AstLanguage) -> Result<Language> {
+    Ok(match lang {
+        AstLanguage::TypeScript => tree_sitter_typescript::language_typescript(),
+        AstLanguage::Tsx => tree_sitter_typescript::language_tsx(),
+        AstLanguage::Runtime(id) => crate::grammars::language(id)?,
+    })
 }
 
-fn ts_language(lang: AstLanguage) -> Language {
+#[cfg(not(feature = "lang-typescript"))]
+pub(crate) fn ts_language(lang: AstLanguage) -> Result<Language> {
     match lang {
-        AstLanguage::TypeScript => language_typescript(),
-        AstLanguage::Tsx => language_tsx(),
+        AstLanguage::Runtime(id) => crate::grammars::language(id),
+        _ => bail!("no grammar for {lang:?}: sink_core was built without the lang-typescript feature"),
     }
 }
 
+/// A top-level unit. The byte range covers the whole statement (`export` and
+/// `declare` wrappers included) plus its leading doc comment and decorators,
+/// so splicing never detaches or duplicates them.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TopLevel {
     pub kind: String,         // e.g., "function_declaration"
     pub name: Option<String>, // e.g., "updateUser"
+    /// Names of the containers the unit is nested in, outermost first
+    /// (`["User"]` for a method `save` a [`crate::UnitQuery`] picked out of
+    /// class `User`); empty for a unit of the file's top level.
+    #[serde(default)]
+    pub scope: Vec<String>,
+    /// Normalized parameter types of function-like units, e.g. `(string,number?)`;
+    /// only collected with [`ParseOptions::signatures`].
+    #[serde(default)]
+    pub signature: Option<String>,
     pub start_byte: usize,
     pub end_byte: usize,
+    /// [`content_hash`] of the unit's text.
+    #[serde(default)]
+    pub content_hash: u64,
+    /// Hash of the unit's exact text, whitespace included, so diffing can
+    /// skip units unchanged from base without comparing their text. Not
+    /// serialized: it's recomputed from the code when a file is read.
+    #[serde(skip)]
+    pub(crate) text_hash: u64,
+    /// The declaration node itself, without docs, decorators or wrappers.
+    #[serde(default)]
+    pub inner: Option<(usize, usize)>,
+    /// `const`/`let`/`var` units: the declarator's own range (`a = 1`).
+    #[serde(default)]
+    pub declarator: Option<(usize, usize)>,
+    /// Set when several declarators share one statement (`const a = 1, b = 2;`).
+    /// Each becomes its own unit whose range is just its declarator; this is
+    /// the enclosing statement's range.
+    #[serde(default)]
+    pub statement: Option<(usize, usize)>,
 }
 
+impl TopLevel {
+    /// The unit's name qualified by its scope, e.g. `User::save` or
+    /// `Api::get`, so same-named members of different containers never
+    /// collide.
+    pub fn qualified_name(&self) -> Option<String> {
+        let name = self.name.as_deref()?;
+        Some(self.scope.iter().map(String::as_str).chain([name]).collect::<Vec<_>>().join("::"))
+    }
+
+    /// The unit's identity within its kind: its qualified name, plus the
+    /// signature when collected, so overloads (`f(string)`, `f(number)`) stay apart.
+    pub fn identity(&self) -> Option<String> {
+        let name = self.qualified_name()?;
+        Some(match &self.signature {
+            Some(sig) => format!("{name}{sig}"),
+            None => name,
+        })
+    }
+
+    pub(crate) fn new(kind: &str, name: Option<String>, start_byte: usize, end_byte: usize) -> Self {
+        TopLevel {
+            kind: kind.to_string(),
+            name,
+            scope: Vec::new(),
+            signature: None,
+            start_byte,
+            end_byte,
+            content_hash: 0,
+            text_hash: 0,
+            inner: None,
+            declarator: None,
+            statement: None,
+        }
+    }
+}
+
+/// The version of [`AstFile`]'s serialized form, written as its `schema`
+/// field.
+///
+/// Within a version, fields are only ever added (with a default, so older
+/// documents still read), never renamed, removed or changed in meaning, and
+/// readers should ignore fields they don't know. Anything else bumps the
+/// version. Documents of older versions, including those from before the
+/// field existed, read as the current version; newer ones are an error.
+/// `fixtures/schema/` holds documents of every version, read back by
+/// `cargo test` and `cargo run --example schema`.
+///
+/// The stable fields are those of [`AstFile`] (but not the id of an
+/// [`AstLanguage::Runtime`], which is only valid in the process that loaded
+/// the grammar), [`TopLevel`] and [`SyntaxError`]. `content_hash` values
+/// follow the hash in [`crate::content_hash`], which may change between
+/// releases; compare them only within one.
+///
+/// Version 2 added [`TopLevel::scope`], which changes what nested units are
+/// keyed by (`User::save`, not `save`); older documents read with every unit
+/// at the top level.
+pub const AST_SCHEMA_VERSION: u32 = 2;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "StoredAstFile")]
 pub struct AstFile {
+    /// Always [`AST_SCHEMA_VERSION`] once read.
+    schema: Schema,
     pub code: String,
+    pub lang: AstLanguage,
     pub units: Vec<TopLevel>,
+    /// ERROR and MISSING nodes tree-sitter recovered from; units near them
+    /// may be garbage.
+    #[serde(default)]
+    pub errors: Vec<SyntaxError>,
+    /// The full tree-sitter tree, for [`AstFile::walk`] and friends. Not
+    /// serialized; see [`AstFile::ensure_tree`] after deserializing.
+    #[serde(skip)]
+    tree: Option<Tree>,
 }
 
-pub fn parse_typescript_to_ast(code: &str, lang: AstLanguage) -> Result<AstFile> {
-    let mut parser = Parser::new();
-    parser
-        .set_language(ts_language(lang))
-        .map_err(|_| anyhow!("failed to set TypeScript language"))?;
+/// What [`AstFile::walk`] does after visiting a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkControl {
+    Continue,
+    /// Don't descend into this node's children.
+    SkipChildren,
+    Stop,
+}
+
+/// Serializes as [`AST_SCHEMA_VERSION`].
+#[derive(Debug, Clone, Copy)]
+struct Schema;
+
+impl Serialize for Schema {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u32(AST_SCHEMA_VERSION)
+    }
+}
+
+/// An [`AstFile`] as serialized by any schema version.
+#[derive(Deserialize)]
+struct StoredAstFile {
+    /// Missing before versioning, which is version 0.
+    #[serde(default)]
+    schema: u32,
+    code: String,
+    /// Missing in version 0's earliest documents, all TypeScript.
+    lang: Option<AstLanguage>,
+    units: Vec<TopLevel>,
+    #[serde(default)]
+    errors: Vec<SyntaxError>,
+}
+
+impl TryFrom<StoredAstFile> for AstFile {
+    type Error = String;
+
+    fn try_from(stored: StoredAstFile) -> std::result::Result<Self, String> {
+        if stored.schema > AST_SCHEMA_VERSION {
+            return Err(format!("AstFile schema {} is newer than this sinkit reads ({AST_SCHEMA_VERSION})", stored.schema));
+        }
+        let StoredAstFile { schema, code, lang, mut units, errors } = stored;
+        check_ranges(&code, &units, &errors)?;
+        // version 0 documents may predate content hashes
+        if schema == 0 {
+            for u in &mut units {
+                u.content_hash = content_hash(&code[u.start_byte..u.end_byte]);
+            }
+        }
+        hash_texts(&code, &mut units);
+        std::result::Result::Ok(AstFile { schema: Schema, code, lang: lang.unwrap_or(AstLanguage::TypeScript), units, errors, tree: None })
+    }
+}
+
+/// Check that every range of `units` and `errors` lies within `code`, on
+/// char boundaries, so a corrupt document fails to load instead of panicking
+/// when it's sliced.
+pub(crate) fn check_ranges(code: &str, units: &[TopLevel], errors: &[SyntaxError]) -> std::result::Result<(), String> {
+    let check = |kind: &str, (start, end): (usize, usize)| {
+        if end > code.len() || start > end || !code.is_char_boundary(start) || !code.is_char_boundary(end) {
+            return Err(format!("{kind} at {start}..{end} isn't within the code"));
+        }
+        std::result::Result::Ok(())
+    };
+    for u in units {
+        check(&u.kind, (u.start_byte, u.end_byte))?;
+        for range in [u.inner, u.declarator, u.statement].into_iter().flatten() {
+            check(&u.kind, range)?;
+        }
+    }
+    for e in errors {
+        check(&e.kind, (e.start_byte, e.end_byte))?;
+    }
+    std::result::Result::Ok(())
+}
+
+/// Set each unit's [`TopLevel::text_hash`] from `code`.
+fn hash_texts(code: &str, units: &mut [TopLevel]) {
+    for u in units {
+        u.text_hash = bytes_hash(&code.as_bytes()[u.start_byte..u.end_byte]);
+    }
+}
+
+impl AstFile {
+    /// A file as read back from storage, without its tree.
+    #[cfg(feature = "binary")]
+    pub(crate) fn without_tree(code: String, lang: AstLanguage, mut units: Vec<TopLevel>, errors: Vec<SyntaxError>) -> Self {
+        hash_texts(&code, &mut units);
+        AstFile { schema: Schema, code, lang, units, errors, tree: None }
+    }
+
+    /// The retained syntax tree (`None` for a deserialized file until
+    /// [`AstFile::ensure_tree`] re-parses it).
+    pub fn tree(&self) -> Option<&Tree> {
+        self.tree.as_ref()
+    }
+
+    /// Re-parse `code` if the tree isn't retained.
+    pub fn ensure_tree(&mut self) -> Result<&Tree> {
+        if self.tree.is_none() {
+            self.tree = Some(parse_tree(&self.code, self.lang)?);
+        }
+        Ok(self.tree.as_ref().expect("tree was just parsed"))
+    }
 
-    let tree = parser
-        .parse(code, None)
-        .ok_or_else(|| anyhow!("tree-sitter parse returned None"))?;
+    /// Pre-order walk over every node (anonymous tokens included), with the
+    /// node's depth below the root.
+    pub fn walk<'t>(&'t self, mut visit: impl FnMut(Node<'t>, usize) -> WalkControl) {
+        let Some(tree) = &self.tree else { return };
+        let mut cursor = tree.walk();
+        let mut depth = 0;
+        loop {
+            let descend = match visit(cursor.node(), depth) {
+                WalkControl::Stop => return,
+                WalkControl::SkipChildren => false,
+                WalkControl::Continue => true,
+            };
+            if descend && cursor.goto_first_child() {
+                depth += 1;
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return;
+                }
+                depth -= 1;
+            }
+        }
+    }
+
+    /// The smallest named node spanning `byte`.
+    pub fn node_at(&self, byte: usize) -> Option<Node<'_>> {
+        let root = self.tree.as_ref()?.root_node();
+        root.named_descendant_for_byte_range(byte, byte)
+    }
+
+    /// The unit whose range contains `byte`, if any.
+    pub fn unit_at(&self, byte: usize) -> Option<&TopLevel> {
+        self.units.iter().find(|u| u.start_byte <= byte && byte < u.end_byte)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    /// Whether `u`'s range overlaps a syntax error of this file.
+    pub fn overlaps_error(&self, u: &TopLevel) -> bool {
+        // MISSING nodes are zero-width: count one touching the unit
+        self.errors.iter().any(|e| e.start_byte <= u.end_byte && u.start_byte <= e.end_byte)
+    }
+
+    /// The syntax errors no unit holds: what they cover isn't in any unit, so
+    /// a unit-by-unit merge would drop it.
+    pub fn stray_errors(&self) -> impl Iterator<Item = &SyntaxError> {
+        self.errors
+            .iter()
+            .filter(|e| !self.units.iter().any(|u| u.start_byte <= e.start_byte && e.end_byte <= u.end_byte))
+    }
+
+    /// Whether `name` occurs inside an ERROR region, i.e. a unit of that name
+    /// may have been swallowed by the parser's error recovery.
+    pub fn error_mentions(&self, name: &str) -> bool {
+        self.errors.iter().any(|e| self.code[e.start_byte..e.end_byte].contains(name))
+    }
+
+    /// Content hashes of the named units, keyed like [`AstFile::keyed_units`].
+    pub fn unit_hashes(&self) -> HashMap<(String, String), u64> {
+        self.keyed_units().into_iter().map(|(key, u)| (key, u.content_hash)).collect()
+    }
+
+    /// Named units with their `(kind, identity)` keys. Repeated identities
+    /// (overloads without signatures, redeclarations) get `#2`, `#3`, ...
+    /// suffixes in source order so each key names exactly one unit.
+    pub fn keyed_units(&self) -> Vec<((String, String), &TopLevel)> {
+        let mut seen: HashMap<(String, String), usize> = HashMap::new();
+        let mut out = Vec::new();
+        for u in &self.units {
+            let Some(id) = u.identity() else { continue };
+            let n = seen.entry((u.kind.clone(), id.clone())).or_insert(0);
+            *n += 1;
+            let name = if *n == 1 { id } else { format!("{id}#{n}") };
+            out.push(((u.kind.clone(), name), u));
+        }
+        out
+    }
+}
+
+/// A syntax error recovered by the parser. Lines and columns are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyntaxError {
+    /// `ERROR`, or `MISSING <token>` for a token the parser had to invent.
+    pub kind: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}:{}", self.kind, self.line, self.column)
+    }
+}
+
+/// What the merge pipeline does when an input has syntax errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyntaxErrorPolicy {
+    /// Merge semantically anyway and list the errors in the outcome. A
+    /// branch with an error outside every unit still merges textually.
+    #[default]
+    Report,
+    /// Skip semantic merging: take the only changed side, or conflict on the whole file.
+    Textual,
+    /// Return an error.
+    Fail,
+}
+
+/// How the merge pipeline treats inputs with syntax errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Syntax errors trigger [`ParseOptions::on_syntax_error`].
+    #[default]
+    Strict,
+    /// Proceed despite localized syntax errors (work-in-progress branches),
+    /// but keep units overlapping ERROR regions out of semantic diffing:
+    /// their base version is kept and a conflict reported if they changed.
+    /// Errors outside every unit still make the merge textual.
+    Tolerant,
+}
 
-    let units = collect_top_level(&tree, code);
+/// Knobs for top-level unit collection.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Treat top-level `describe(...)`/`it(...)`/`test(...)` calls as units
+    /// (kind `test_block`) keyed by their string titles.
+    pub test_blocks: bool,
+    /// Include parameter-type signatures in the identity of function units,
+    /// so overload sets don't collapse into one unit.
+    pub signatures: bool,
+    /// How merges treat inputs with syntax errors.
+    pub on_syntax_error: SyntaxErrorPolicy,
+    /// `Strict` hands inputs with syntax errors to `on_syntax_error`;
+    /// `Tolerant` merges them, keeping the units they damage out of the diff.
+    pub mode: ParseMode,
+    /// Merge policy for this file (see [`crate::Policy::for_path`]).
+    pub policy: FilePolicy,
+}
+
+impl ParseOptions {
+    /// Options suited to a repo-relative `path`: spec files (`*.test.ts`,
+    /// `*.spec.tsx`, `*.test.mts`, ...) get test-block granularity.
+    pub fn for_path(path: &str) -> Self {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        ParseOptions {
+            test_blocks: name.contains(".test.") || name.contains(".spec."),
+            ..Default::default()
+        }
+    }
+}
+
+pub fn parse_typescript_to_ast(code: &str, lang: AstLanguage) -> Result<AstFile> {
+    parse_typescript_to_ast_with(code, lang, &ParseOptions::default())
+}
+
+pub fn parse_typescript_to_ast_with(code: &str, lang: AstLanguage, opts: &ParseOptions) -> Result<AstFile> {
+    let tree = parse_tree(code, lang)?;
+    let mut units = match (&opts.policy.unit_query, lang) {
+        (Some(query), _) if query.lang() == lang => collect_picked_units(&tree, code, &query.units(&tree, code)),
+        (_, AstLanguage::Runtime(id)) => {
+            let kinds = crate::grammars::unit_kinds(id);
+            let root = tree.root_node();
+            let picked: Vec<(Node, Option<String>)> = (0..root.named_child_count())
+                .filter_map(|i| root.named_child(i))
+                .filter(|ch| kinds.iter().any(|k| k == ch.kind()))
+                .map(|ch| {
+                    let name = ch.child_by_field_name("name").or_else(|| find_identifier(ch));
+                    (ch, name.and_then(|n| n.utf8_text(code.as_bytes()).ok()).map(str::to_string))
+                })
+                .collect();
+            collect_picked_units(&tree, code, &picked)
+        }
+        _ => collect_top_level(&tree, code, opts),
+    };
+    if opts.policy.generated != GeneratedMerge::Semantic {
+        let mut regions = regions::fenced(&tree, code);
+        if let Some(query) = opts.policy.unit_query.as_ref().filter(|q| q.lang() == lang) {
+            regions.extend(regions::enclosing(&tree, &query.ignored(&tree, code)));
+        }
+        regions::apply(&mut units, code, regions);
+    }
+    for u in &mut units {
+        u.content_hash = content_hash(&code[u.start_byte..u.end_byte]);
+    }
+    hash_texts(code, &mut units);
+    let mut errors = Vec::new();
+    collect_errors(tree.root_node(), &mut errors);
 
     Ok(AstFile {
+        schema: Schema,
         code: code.to_string(),
+        lang,
         units,
+        errors,
+        tree: Some(tree),
+    })
+}
+
+/// Outermost ERROR nodes and all MISSING nodes, in source order.
+fn collect_errors(node: Node, out: &mut Vec<SyntaxError>) {
+    if node.is_error() || node.is_missing() {
+        let pos = node.start_position();
+        out.push(SyntaxError {
+            kind: if node.is_missing() { format!("MISSING {}", node.kind()) } else { "ERROR".to_string() },
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+            line: pos.row + 1,
+            column: pos.column + 1,
+        });
+        if node.is_error() {
+            return;
+        }
+    }
+    if !node.has_error() {
+        return;
+    }
+    for i in 0..node.child_count() {
+        if let Some(c) = node.child(i) {
+            collect_errors(c, out);
+        }
+    }
+}
+
+/// Parse `code` into a raw tree-sitter tree. Member-level reconcilers use this
+/// to look inside unit payloads (enum bodies, interface bodies, ...). The
+/// parser comes from the shared pool (see [`crate::parsers`]).
+pub(crate) fn parse_tree(code: &str, lang: AstLanguage) -> Result<Tree> {
+    crate::parsers::parse(code, lang)
+}
+
+/// Depth-first search for the first node of `kind` under (and including) `node`.
+pub(crate) fn find_first<'t>(node: Node<'t>, kind: &str) -> Option<Node<'t>> {
+    if node.kind() == kind {
+        return Some(node);
+    }
+    for i in 0..node.child_count() {
+        if let Some(found) = node.child(i).and_then(|c| find_first(c, kind)) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// Declaration kinds collected as units, at the root or under `export`.
+pub(crate) const UNIT_KINDS: &[&str] = &[
+    "function_declaration",
+    "class_declaration",
+    "enum_declaration",
+    "interface_declaration",
+    "type_alias_declaration",
+    "lexical_declaration",
+    "variable_declaration",
+    "import_statement",
+    "method_definition",
+    // `declare function f(): void;` and overload signatures
+    "function_signature",
+];
+
+/// Unnamed top-level statements collected as units (besides expression
+/// statements), named by [`statement_anchor`].
+const STATEMENT_KINDS: &[&str] = &[
+    "if_statement",
+    "for_statement",
+    "for_in_statement",
+    "while_statement",
+    "do_statement",
+    "try_statement",
+    "switch_statement",
+    "statement_block",
+    "labeled_statement",
+];
+
+/// Synthetic name for an unnamed statement: the callee of a call
+/// (`app.listen`), the target of an assignment (`module.exports`), `iife`
+/// for immediately-invoked functions, else the statement keyword. Repeats
+/// are told apart by occurrence (`app.use#2`, see [`AstFile::keyed_units`]).
+fn statement_anchor(st: &Node, code: &str) -> String {
+    let text = |n: Node| {
+        let raw = n.utf8_text(code.as_bytes()).unwrap_or_default();
+        raw.split_whitespace().collect::<String>()
+    };
+    let mut expr = match st.kind() {
+        "expression_statement" => st.named_child(0),
+        "labeled_statement" => st.child_by_field_name("label"),
+        _ => None,
+    };
+    while let Some(e) = expr.filter(|e| matches!(e.kind(), "await_expression" | "parenthesized_expression")) {
+        expr = e.named_child(0);
+    }
+    match expr.map(|e| (e.kind(), e)) {
+        Some(("call_expression", call)) => match call.child_by_field_name("function") {
+            Some(f) if matches!(f.kind(), "identifier" | "member_expression") => text(f),
+            _ => "iife".to_string(),
+        },
+        Some(("new_expression", new)) => {
+            new.child_by_field_name("constructor").map(|c| format!("new {}", text(c))).unwrap_or_default()
+        }
+        Some(("assignment_expression" | "augmented_assignment_expression", a)) => {
+            a.child_by_field_name("left").map(text).unwrap_or_default()
+        }
+        Some(("statement_identifier", label)) => format!("{}:", text(label)),
+        _ => st.kind().trim_end_matches("_statement").to_string(),
+    }
+}
+
+/// Units of the `picked` nodes (runtime grammars' unit kinds, or a
+/// [`crate::UnitQuery`]'s captures), named or keyed by their first line. A
+/// root child holding one picked node is that unit's range; one holding
+/// several splits into them; one holding none is a unit of its own kind,
+/// keyed by its first line. Comments not separated from a unit by a blank
+/// line belong to it.
+fn collect_picked_units(tree: &Tree, code: &str, picked: &[(Node, Option<String>)]) -> Vec<TopLevel> {
+    let first_line = |n: &Node| -> String {
+        code[n.start_byte()..n.end_byte()].lines().next().unwrap_or_default().split_whitespace().collect()
+    };
+    let root = tree.root_node();
+    let mut out = Vec::new();
+    let mut pending_doc: Option<usize> = None;
+    let mut last_end = 0;
+    for ch in (0..root.named_child_count()).filter_map(|i| root.named_child(i)) {
+        if code[last_end..ch.start_byte()].matches('\n').count() > 1 {
+            pending_doc = None;
+        }
+        last_end = ch.end_byte();
+        let inside: Vec<&(Node, Option<String>)> =
+            picked.iter().filter(|(n, _)| ch.start_byte() <= n.start_byte() && n.end_byte() <= ch.end_byte()).collect();
+        if inside.is_empty() && ch.kind().contains("comment") {
+            pending_doc = Some(pending_doc.unwrap_or(ch.start_byte()));
+            continue;
+        }
+        let start = pending_doc.take().unwrap_or(ch.start_byte());
+        match inside.as_slice() {
+            [] => out.push(TopLevel {
+                inner: Some((ch.start_byte(), ch.end_byte())),
+                ..TopLevel::new(ch.kind(), Some(first_line(&ch)), start, ch.end_byte())
+            }),
+            [(n, name)] => out.push(TopLevel {
+                inner: Some((n.start_byte(), n.end_byte())),
+                scope: scope_of(n, &ch, code),
+                ..TopLevel::new(n.kind(), Some(name.clone().unwrap_or_else(|| first_line(n))), start, ch.end_byte())
+            }),
+            many => {
+                for (n, name) in many {
+                    out.push(TopLevel {
+                        inner: Some((n.start_byte(), n.end_byte())),
+                        scope: scope_of(n, &ch, code),
+                        ..TopLevel::new(n.kind(), Some(name.clone().unwrap_or_else(|| first_line(n))), n.start_byte(), n.end_byte())
+                    });
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Names of the named nodes (classes, namespaces, ...) enclosing `node` up to
+/// and including `root`, outermost first.
+fn scope_of(node: &Node, root: &Node, code: &str) -> Vec<String> {
+    let mut scope = Vec::new();
+    let mut at = *node;
+    while at.id() != root.id()
+        && let Some(parent) = at.parent()
+    {
+        let name = parent.child_by_field_name("name").and_then(|n| n.utf8_text(code.as_bytes()).ok());
+        if let Some(name) = name {
+            scope.push(name.trim_matches(['"', '\'']).to_string());
+        }
+        at = parent;
+    }
+    scope.reverse();
+    scope
+}
+
+/// The first identifier-like node under `node`, depth first.
+fn find_identifier(node: Node) -> Option<Node> {
+    (0..node.named_child_count()).filter_map(|i| node.named_child(i)).find_map(|c| {
+        if c.kind().contains("identifier") || c.kind() == "name" { Some(c) } else { find_identifier(c) }
     })
 }
 
-fn collect_top_level(tree: &Tree, code: &str) -> Vec<TopLevel> {
+fn collect_top_level(tree: &Tree, code: &str, opts: &ParseOptions) -> Vec<TopLevel> {
     let root = tree.root_node();
     let mut out = Vec::new();
 
+    // Decorators that tree-sitter leaves as standalone root children attach to the next unit,
+    // as do leading comment blocks (JSDoc) not separated from it by a blank line.
+    let mut pending_decorators: Option<usize> = None;
+    let mut pending_doc: Option<usize> = None;
+    let mut last_end = 0;
+
     for i in 0..root.child_count() {
         if let Some(ch) = root.child(i) {
             let kind = ch.kind();
+            let leading = pending_decorators.take();
+            let gap = &code[last_end..ch.start_byte()];
+            if gap.matches('\n').count() > 1 {
+                pending_doc = None;
+            }
+            let doc = pending_doc.take();
+            last_end = ch.end_byte();
 
-            let mut push_unit = |n: Node| {
-                let k = n.kind().to_string();
-                let name = extract_unit_name(&n, code);  // <-- updated line
-                out.push(TopLevel {
-                    kind: k,
-                    name,
-                    start_byte: n.start_byte(),
-                    end_byte: n.end_byte(),
-                });
+            // A unit's range is the whole root statement (`export`/`declare`
+            // wrappers included); `start` lets leading docs/decorators extend it.
+            let mut push_unit = |n: Node, start: usize| {
+                let start = start.min(ch.start_byte());
+                let inner = Some((n.start_byte(), n.end_byte()));
+                let signature = if opts.signatures { signature_of(&n, code) } else { None };
+                let declarators = declarators(&n);
+                match declarators.as_slice() {
+                    [] => out.push(TopLevel {
+                        inner,
+                        signature,
+                        ..TopLevel::new(n.kind(), extract_unit_name(&n, code), start, ch.end_byte())
+                    }),
+                    [d] => out.push(TopLevel {
+                        inner,
+                        declarator: Some((d.start_byte(), d.end_byte())),
+                        ..TopLevel::new(n.kind(), extract_unit_name(&n, code), start, ch.end_byte())
+                    }),
+                    // `const a = 1, b = 2;`: one unit per declarator
+                    many => {
+                        for d in many {
+                            let name = d
+                                .child_by_field_name("name")
+                                .and_then(|id| id.utf8_text(code.as_bytes()).ok())
+                                .map(str::to_string);
+                            let range = Some((d.start_byte(), d.end_byte()));
+                            out.push(TopLevel {
+                                inner: range,
+                                declarator: range,
+                                statement: Some((ch.start_byte(), ch.end_byte())),
+                                ..TopLevel::new(n.kind(), name, d.start_byte(), d.end_byte())
+                            });
+                        }
+                    }
+                }
             };
 
             match kind {
-                "function_declaration"
-                | "class_declaration"
-                | "lexical_declaration"
-                | "variable_declaration"
-                | "import_statement"
-                | "method_definition" => {
-                    push_unit(ch);
+                "decorator" => {
+                    pending_decorators = Some(leading.unwrap_or(ch.start_byte()));
+                    pending_doc = doc;
+                }
+
+                "comment" => {
+                    pending_decorators = leading;
+                    // a comment trailing the previous statement on its line is not a doc
+                    if i == 0 || gap.contains('\n') {
+                        pending_doc = Some(doc.unwrap_or(ch.start_byte()));
+                    }
+                }
+
+                k if UNIT_KINDS.contains(&k) => {
+                    push_unit(ch, doc.or(leading).unwrap_or(ch.start_byte()));
+                }
+
+                // `namespace Api { ... }` parses as an expression statement
+                "expression_statement"
+                    if ch.named_child(0).is_some_and(|n| n.kind() == "internal_module") =>
+                {
+                    if let Some(inner) = ch.named_child(0) {
+                        push_unit(inner, doc.unwrap_or(ch.start_byte()));
+                    }
+                }
+
+                // `declare module "x" { ... }`, `declare namespace X { ... }`,
+                // `declare function/const/class ...` and `declare global { ... }`
+                "ambient_declaration" => {
+                    let start = doc.unwrap_or(ch.start_byte());
+                    if let Some(inner) = ambient_inner(&ch) {
+                        push_unit(inner, start);
+                    } else if (0..ch.child_count()).filter_map(|j| ch.child(j)).any(|c| c.kind() == "global") {
+                        out.push(TopLevel {
+                            inner: Some((ch.start_byte(), ch.end_byte())),
+                            ..TopLevel::new("global_declaration", Some("global".to_string()), start, ch.end_byte())
+                        });
+                    }
+                }
+
+                "expression_statement" => {
+                    let start = doc.unwrap_or(ch.start_byte());
+                    let test = ch
+                        .named_child(0)
+                        .filter(|_| opts.test_blocks)
+                        .and_then(|call| test_call_title(&call, code));
+                    let (kind, name) = match test {
+                        Some((_, title)) => ("test_block", title),
+                        None => (kind, statement_anchor(&ch, code)),
+                    };
+                    out.push(TopLevel {
+                        inner: Some((ch.start_byte(), ch.end_byte())),
+                        ..TopLevel::new(kind, Some(name), start, ch.end_byte())
+                    });
+                }
+
+                // unnamed side-effect code, keyed by anchor + occurrence
+                k if STATEMENT_KINDS.contains(&k) => {
+                    let name = statement_anchor(&ch, code);
+                    out.push(TopLevel {
+                        inner: Some((ch.start_byte(), ch.end_byte())),
+                        ..TopLevel::new(k, Some(name), doc.unwrap_or(ch.start_byte()), ch.end_byte())
+                    });
                 }
 
                 "export_statement" => {
+                    // `@Component(...) export class X {}` puts the decorators inside
+                    // the export statement, so they are covered by its range.
+                    let start = doc.or(leading).unwrap_or(ch.start_byte());
                     let mut found_inner = false;
                     for j in 0..ch.child_count() {
-                        if let Some(inner) = ch.child(j) {
-                            match inner.kind() {
-                                "function_declaration"
-                                | "class_declaration"
-                                | "lexical_declaration"
-                                | "variable_declaration"
-                                | "import_statement"
-                                | "method_definition" => {
-                                    push_unit(inner);
-                                    found_inner = true;
-                                    break;
-                                }
-                                _ => {}
-                            }
+                        if let Some(inner) = ch.child(j)
+                            && (UNIT_KINDS.contains(&inner.kind()) || inner.kind() == "internal_module")
+                        {
+                            push_unit(inner, start);
+                            found_inner = true;
+                            break;
+                        }
+                        // `export declare function f(): void;`
+                        if let Some(ambient) = ch.child(j).filter(|c| c.kind() == "ambient_declaration")
+                            && let Some(inner) = ambient_inner(&ambient)
+                        {
+                            push_unit(inner, start);
+                            found_inner = true;
+                            break;
                         }
                     }
-                    if !found_inner {
-                        // Skip re-exports in the MVP
+                    // `export { a } from "./m"` / `export * from "./m"`, keyed by
+                    // the module like imports (see `reexports`)
+                    if !found_inner
+                        && let Some(source) = ch.child_by_field_name("source")
+                        && let std::result::Result::Ok(module) = source.utf8_text(code.as_bytes())
+                    {
+                        let module = module.trim_matches(&['"', '\''][..]).to_string();
+                        out.push(TopLevel {
+                            inner: Some((ch.start_byte(), ch.end_byte())),
+                            ..TopLevel::new("export_statement", Some(module), start, ch.end_byte())
+                        });
                     }
                 }
 
@@ -113,8 +807,101 @@ fn collect_top_level(tree: &Tree, code: &str) -> Vec<TopLevel> {
     out
 }
 
-fn extract_unit_name(node: &Node, code: &str) -> Option<String> {
+/// The `variable_declarator`s of a `const`/`let`/`var` statement (empty for other kinds).
+fn declarators<'t>(n: &Node<'t>) -> Vec<Node<'t>> {
+    if !matches!(n.kind(), "lexical_declaration" | "variable_declaration") {
+        return Vec::new();
+    }
+    (0..n.named_child_count())
+        .filter_map(|j| n.named_child(j))
+        .filter(|c| c.kind() == "variable_declarator")
+        .collect()
+}
+
+/// `(T1,T2?,...T3)` for function-like declarations: parameter types with
+/// whitespace removed (`_` when untyped); names don't matter.
+fn signature_of(n: &Node, code: &str) -> Option<String> {
+    if !matches!(n.kind(), "function_declaration" | "function_signature" | "method_definition") {
+        return None;
+    }
+    let params = n.child_by_field_name("parameters")?;
+    let mut types = Vec::new();
+    for i in 0..params.named_child_count() {
+        let p = params.named_child(i)?;
+        if !matches!(p.kind(), "required_parameter" | "optional_parameter") {
+            continue;
+        }
+        let ty = match p.child_by_field_name("type") {
+            Some(t) => t.utf8_text(code.as_bytes()).ok()?.trim_start_matches(':').split_whitespace().collect(),
+            None => "_".to_string(),
+        };
+        let rest = if p.named_child(0).is_some_and(|c| c.kind() == "rest_pattern") { "..." } else { "" };
+        let optional = if p.kind() == "optional_parameter" { "?" } else { "" };
+        types.push(format!("{rest}{ty}{optional}"));
+    }
+    Some(format!("({})", types.join(",")))
+}
+
+/// The declaration wrapped by `declare ...`, if it is one we collect.
+fn ambient_inner<'t>(ambient: &Node<'t>) -> Option<Node<'t>> {
+    (0..ambient.named_child_count())
+        .filter_map(|j| ambient.named_child(j))
+        .find(|c| UNIT_KINDS.contains(&c.kind()) || matches!(c.kind(), "module" | "internal_module"))
+}
+
+/// Split a unit payload into its leading comment block (including the
+/// whitespace after it) and the code that follows.
+pub(crate) fn split_leading_comments(payload: &str) -> (&str, &str) {
+    let mut rest = payload;
+    loop {
+        let trimmed = rest.trim_start();
+        if trimmed.starts_with("//") {
+            rest = trimmed.find('\n').map_or("", |nl| &trimmed[nl..]);
+        } else if trimmed.starts_with("/*") {
+            match trimmed.find("*/") {
+                Some(end) => rest = &trimmed[end + 2..],
+                None => break,
+            }
+        } else {
+            break;
+        }
+    }
+    let code = rest.trim_start();
+    payload.split_at(payload.len() - code.len())
+}
+
+/// For a `describe("title", ...)`-style call (also `it`, `test`, and
+/// modifiers like `it.only`), return the base callee and the title.
+pub(crate) fn test_call_title(call: &Node, code: &str) -> Option<(String, String)> {
+    if call.kind() != "call_expression" {
+        return None;
+    }
+    let callee = call.child_by_field_name("function")?;
+    let base = match callee.kind() {
+        "identifier" => callee,
+        "member_expression" => callee.child_by_field_name("object")?,
+        _ => return None,
+    };
+    let base = base.utf8_text(code.as_bytes()).ok()?;
+    if !matches!(base, "describe" | "it" | "test") {
+        return None;
+    }
+    let title = call.child_by_field_name("arguments")?.named_child(0)?;
+    if !matches!(title.kind(), "string" | "template_string") {
+        return None;
+    }
+    let raw = title.utf8_text(code.as_bytes()).ok()?;
+    Some((base.to_string(), raw.trim_matches(&['"', '\'', '`'][..]).to_string()))
+}
+
+pub(crate) fn extract_unit_name(node: &Node, code: &str) -> Option<String> {
     match node.kind() {
+        // namespace Api.V1 { ... } / module "x" { ... }
+        "internal_module" | "module" => {
+            let raw = node.child_by_field_name("name")?.utf8_text(code.as_bytes()).ok()?;
+            Some(raw.trim_matches(&['"', '\''][..]).to_string())
+        }
+
         // import ... from "module";
         "import_statement" => {
             // Look for the string literal module name
@@ -160,4 +947,29 @@ fn extract_unit_name(node: &Node, code: &str) -> Option<String> {
             None
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::UnitQuery;
+
+    #[test]
+    fn picked_members_are_keyed_by_their_container() {
+        let query = "(method_definition name: (property_identifier) @name) @unit";
+        let mut opts = ParseOptions::default();
+        opts.policy.unit_query = Some(Arc::new(UnitQuery::new(AstLanguage::TypeScript, query).unwrap()));
+        let code = "class User {\n  save() {}\n  load() {}\n}\n\nclass Order {\n  save() {}\n  load() {}\n}\n";
+        let file = parse_typescript_to_ast_with(code, AstLanguage::TypeScript, &opts).unwrap();
+        let keys: Vec<String> = file.keyed_units().into_iter().map(|((_, name), _)| name).collect();
+        assert_eq!(keys, ["User::save", "User::load", "Order::save", "Order::load"]);
+    }
+
+    #[test]
+    fn top_level_units_have_no_scope() {
+        let file = parse_typescript_to_ast("export function save() {}\n", AstLanguage::TypeScript).unwrap();
+        assert!(file.units[0].scope.is_empty());
+        assert_eq!(file.units[0].qualified_name().as_deref(), Some("save"));
+    }
+}
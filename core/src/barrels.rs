@@ -0,0 +1,276 @@
+//! barrels.rs
+//! Merging barrel files: `index.ts` files of nothing but re-exports.
+//!
+//! Every feature branch adds a line to its package's barrel, so they
+//! conflict on nearly every merge. With `merge = "barrel"` (see
+//! [`crate::Policy`]) such a file merges as a list of re-exports keyed by
+//! module, `export *`, `export * as ns` and `export type { ... }` of a
+//! module being keys of their own: additions from both branches are kept,
+//! deletions applied, and specifier lists both branches changed merge as
+//! sets (see `reexports`). What's left to conflict is true divergence: a
+//! re-export both changed incompatibly, one deleted while the other changed
+//! it, or a name the union would export from two modules. Comments directly
+//! above a re-export move with it; the file's header comment stays on top.
+//!
+//! [`BarrelOrder`] places the lines: where the branches put them, or sorted
+//! by module path with each list of names sorted too.
+
+use std::collections::{HashMap, HashSet};
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, parse_tree, split_leading_comments};
+use crate::members::{Member, MemberMerge, Side, merge_members_with, pick_frame};
+use crate::policy::BarrelOrder;
+use crate::reexports::{exported_names, merge_reexports, sort_specifiers};
+
+/// A barrel file split into its re-exports.
+struct Barrel<'c> {
+    /// Everything above the first re-export (and the comments on it).
+    header: &'c str,
+    /// Re-exports keyed by module and clause, each with its comments.
+    entries: Vec<Member>,
+    /// Keys of the entries with a blank line above them.
+    spaced: HashSet<String>,
+    /// Module path of each entry, by key.
+    modules: HashMap<String, String>,
+    /// Everything below the last re-export.
+    trailer: &'c str,
+}
+
+/// Merge the barrel file `base` into `a` and `b`: the merged file, or what
+/// diverged. `None` if a version has more than re-exports in it, or
+/// re-exports one module twice the same way.
+pub(crate) fn merge_barrel(
+    base: &str,
+    a: &str,
+    b: &str,
+    order: BarrelOrder,
+    lang: AstLanguage,
+) -> Option<Result<String, Vec<String>>> {
+    let (o, mut x, mut y) = (split(base, lang)?, split(a, lang)?, split(b, lang)?);
+    let mut found = Vec::new();
+    let header = frame(o.header, x.header, y.header, "header", &mut found);
+    let trailer = frame(o.trailer, x.trailer, y.trailer, "end", &mut found);
+
+    // both added a re-export of the same module: unite them up front, so
+    // the merge below sees one addition
+    let added: HashSet<&str> = o.entries.iter().map(|m| m.key.as_str()).collect();
+    for m in x.entries.iter_mut().filter(|m| !added.contains(m.key.as_str())) {
+        if let Some(n) = y.entries.iter_mut().find(|n| n.key == m.key && n.text != m.text)
+            && let Some(united) = merge_entry("", &m.text, &n.text, &m.key, lang)
+        {
+            n.text = united.clone();
+            m.text = united;
+        }
+    }
+    let mut entries = match merge_members_with(&o.entries, &x.entries, &y.entries, "re-export", |key, o, a, b| {
+        merge_entry(o, a, b, key, lang)
+    }) {
+        Ok(entries) => entries,
+        Err(conflicts) => {
+            found.extend(conflicts);
+            return Some(Err(found));
+        }
+    };
+
+    let mut modules = o.modules;
+    modules.extend(y.modules);
+    modules.extend(x.modules);
+    // a name both branches re-export, each from a module of its own
+    let (dup_a, dup_b) = (duplicates(&x.entries, lang), duplicates(&y.entries, lang));
+    let mut exporter: HashMap<String, &str> = HashMap::new();
+    for e in &entries {
+        for name in exported_names(&e.text, lang) {
+            let module = modules[&e.key].as_str();
+            if let Some(first) = exporter.insert(name.clone(), module)
+                && first != module
+                && !dup_a.contains(&name)
+                && !dup_b.contains(&name)
+            {
+                found.push(format!("Both branches export {name}: from \"{first}\" and from \"{module}\""));
+            }
+        }
+    }
+    if !found.is_empty() {
+        return Some(Err(found));
+    }
+
+    if order == BarrelOrder::Sorted {
+        entries.sort_by(|p, q| (&modules[&p.key], &p.key).cmp(&(&modules[&q.key], &q.key)));
+        for e in &mut entries {
+            let (comments, code) = split_leading_comments(&e.text);
+            if let Some(sorted) = sort_specifiers(code, lang) {
+                e.text = format!("{comments}{sorted}");
+            }
+        }
+    }
+    let in_a: HashSet<&str> = x.entries.iter().map(|m| m.key.as_str()).collect();
+    let mut out = header.to_string();
+    for (i, e) in entries.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+            // the blank line above a re-export comes from the side it's from
+            let spaced = if in_a.contains(e.key.as_str()) { &x.spaced } else { &y.spaced };
+            if order == BarrelOrder::Keep && spaced.contains(&e.key) {
+                out.push('\n');
+            }
+        }
+        out.push_str(&e.text);
+    }
+    out.push_str(trailer);
+    Some(Ok(out))
+}
+
+/// The one side's change to `o`, else A's, noting the divergence.
+fn frame<'c>(o: &str, a: &'c str, b: &'c str, what: &str, found: &mut Vec<String>) -> &'c str {
+    match pick_frame(o, a, b) {
+        Some(Side::A) => a,
+        Some(Side::B) => b,
+        None => {
+            found.push(format!("Both branches changed the {what} of the barrel file differently"));
+            a
+        }
+    }
+}
+
+/// Merge one re-export both branches changed (or added, if `base` is
+/// empty), its comments included.
+fn merge_entry(base: &str, a: &str, b: &str, key: &str, lang: AstLanguage) -> Option<String> {
+    let ((co, o), (ca, a), (cb, b)) = (split_leading_comments(base), split_leading_comments(a), split_leading_comments(b));
+    let comments = match pick_frame(co, ca, cb)? {
+        Side::A => ca,
+        Side::B => cb,
+    };
+    match merge_reexports(o, a, b, key, lang) {
+        MemberMerge::Merged(merged) => Some(format!("{comments}{merged}")),
+        _ => None,
+    }
+}
+
+/// Names `entries` export more than once.
+fn duplicates(entries: &[Member], lang: AstLanguage) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    entries
+        .iter()
+        .flat_map(|e| exported_names(&e.text, lang))
+        .filter(|name| !seen.insert(name.clone()))
+        .collect()
+}
+
+fn split(code: &str, lang: AstLanguage) -> Option<Barrel<'_>> {
+    let tree = parse_tree(code, lang).ok()?;
+    let root = tree.root_node();
+    if root.has_error() {
+        return None;
+    }
+    let mut barrel = Barrel { header: code, entries: Vec::new(), spaced: HashSet::new(), modules: HashMap::new(), trailer: "" };
+    // (start, end) of each entry, and of the comments above the next one
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut comments: Option<(usize, usize)> = None;
+    let blank = |from: usize, to: usize| code[from..to].matches('\n').count() > 1;
+    for node in (0..root.named_child_count()).filter_map(|i| root.named_child(i)) {
+        let (start, end) = (node.start_byte(), node.end_byte());
+        if node.kind() == "comment" {
+            match (ranges.last_mut(), &mut comments) {
+                // a comment after a re-export on its line belongs to it
+                (Some(last), None) if !code[last.1..start].contains('\n') => last.1 = end,
+                (_, Some(run)) if !blank(run.1, start) => run.1 = end,
+                _ => comments = Some((start, end)),
+            }
+            continue;
+        }
+        let key = key(code, node)?;
+        let start = match comments.take() {
+            Some(run) if !blank(run.1, start) => run.0,
+            _ => start,
+        };
+        if barrel.modules.insert(key.0.clone(), key.1).is_some() {
+            return None;
+        }
+        match ranges.last() {
+            None => barrel.header = &code[..start],
+            Some(&(_, prev)) if blank(prev, start) => {
+                barrel.spaced.insert(key.0.clone());
+            }
+            Some(_) => {}
+        }
+        barrel.entries.push(Member { key: key.0, text: String::new() });
+        ranges.push((start, end));
+    }
+    for (e, &(start, end)) in barrel.entries.iter_mut().zip(&ranges) {
+        e.text = code[start..end].to_string();
+    }
+    if let Some(&(_, end)) = ranges.last() {
+        barrel.trailer = &code[end..];
+    }
+    Some(barrel)
+}
+
+/// (key, module path) of the re-export `stmt`; `None` if it's anything else.
+fn key(code: &str, stmt: Node) -> Option<(String, String)> {
+    let source = stmt.child_by_field_name("source").filter(|_| stmt.kind() == "export_statement")?;
+    let module = code[source.start_byte()..source.end_byte()].trim_matches(|c| c == '"' || c == '\'').to_string();
+    let children: Vec<Node> = (0..stmt.child_count()).filter_map(|i| stmt.child(i)).collect();
+    let key = if let Some(ns) = children.iter().find(|c| c.kind() == "namespace_export") {
+        format!("{} {module}", code[ns.start_byte()..ns.end_byte()].split_whitespace().collect::<Vec<_>>().join(" "))
+    } else if children.iter().any(|c| c.kind() == "export_clause") {
+        if children.iter().any(|c| c.kind() == "type") { format!("type {module}") } else { module.clone() }
+    } else {
+        format!("* {module}")
+    };
+    Some((key, module))
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, a: &str, b: &str, order: BarrelOrder) -> Option<Result<String, Vec<String>>> {
+        merge_barrel(base, a, b, order, AstLanguage::TypeScript)
+    }
+
+    const BASE: &str = "// The public API.\n\nexport { Cart } from './cart';\nexport * from './user';\n";
+
+    #[test]
+    fn lines_both_branches_added_merge() {
+        let a = format!("{BASE}export {{ Order }} from './order';\n");
+        let b = BASE.replace("export * from './user';\n", "export * from './user';\nexport * as billing from './billing';\n");
+        let merged = merge(BASE, &a, &b, BarrelOrder::Keep).unwrap().unwrap();
+        assert_eq!(merged, format!("{BASE}export {{ Order }} from './order';\nexport * as billing from './billing';\n"));
+    }
+
+    #[test]
+    fn names_both_added_to_one_module_unite() {
+        let a = BASE.replace("{ Cart }", "{ Cart, CartItem }");
+        let b = BASE.replace("{ Cart }", "{ Cart, emptyCart }");
+        let merged = merge(BASE, &a, &b, BarrelOrder::Keep).unwrap().unwrap();
+        assert_eq!(merged, BASE.replace("{ Cart }", "{ Cart, CartItem, emptyCart }"));
+    }
+
+    #[test]
+    fn sorted_order_sorts_modules_and_names() {
+        let a = format!("{BASE}export {{ b, a }} from './zeta';\n");
+        let b = format!("{BASE}export {{ Alpha }} from './alpha';\n");
+        let merged = merge(BASE, &a, &b, BarrelOrder::Sorted).unwrap().unwrap();
+        assert_eq!(
+            merged,
+            "// The public API.\n\nexport { Alpha } from './alpha';\nexport { Cart } from './cart';\nexport * from './user';\nexport { a, b } from './zeta';\n"
+        );
+    }
+
+    #[test]
+    fn a_name_exported_from_two_modules_conflicts() {
+        let a = format!("{BASE}export {{ Total }} from './order';\n");
+        let b = format!("{BASE}export {{ Total }} from './invoice';\n");
+        assert_eq!(merge(BASE, &a, &b, BarrelOrder::Keep), Some(Err(vec![
+            "Both branches export Total: from \"./order\" and from \"./invoice\"".to_string()
+        ])));
+    }
+
+    #[test]
+    fn files_with_more_than_reexports_are_not_barrels() {
+        let a = format!("{BASE}export const version = 1;\n");
+        assert_eq!(merge(BASE, &a, BASE, BarrelOrder::Keep), None);
+    }
+}
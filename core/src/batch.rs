@@ -0,0 +1,285 @@
+//! batch.rs
+//! Many file merges in one call.
+//!
+//! Merge queues evaluate whole candidate merges at a time; [`merge_batch`]
+//! takes every `(path, base, a, b)` of them and spreads the files over a
+//! worker per core ([`merge_batch_with`] picks the count), sharing one
+//! [`Policy`], and hands the outcomes back in the order of the jobs, however
+//! many threads ran them. [`merge_tree`] does the same for paths read from
+//! three revisions of a [`FileSource`]. The threads are rayon's; without
+//! the `parallel` feature the files merge one after another on the
+//! caller's thread, in the same order.
+//!
+//! The policy's memory limit is a budget for the whole batch: a worker only
+//! starts a file once the estimated memory of the files in flight leaves
+//! room for it, and a file that alone exceeds the limit merges textually.
+
+use std::sync::{Condvar, Mutex};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{AstLanguage, ParseOptions};
+use crate::compose::{MergeOptions, MergeOutcome};
+use crate::policy::{Adapter, Policy};
+
+/// One file to merge: its repo-relative path (which picks the adapter and the
+/// policy rules) and its three versions.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MergeJob {
+    pub path: String,
+    pub base: String,
+    pub a: String,
+    pub b: String,
+}
+
+impl MergeJob {
+    /// Merge the file with the adapter `policy` maps its path to; files of
+    /// unknown type merge textually.
+    pub fn run(&self, policy: &Policy) -> Result<MergeOutcome> {
+        let (lang, opts) = self.options(policy);
+        crate::three_way_merge(&self.base, &self.a, &self.b, lang, &opts)
+    }
+
+    /// The language and options `policy` gives the file.
+    pub(crate) fn options(&self, policy: &Policy) -> (AstLanguage, MergeOptions) {
+        let mut file_policy = policy.for_path(&self.path);
+        // no grammar to parse a file of unknown type with
+        file_policy.textual |= policy.adapter_for(&self.path).is_none();
+        let adapter = policy.adapter_for(&self.path).unwrap_or(Adapter::Text);
+        let opts = ParseOptions { policy: file_policy, ..ParseOptions::for_path(&self.path) };
+        let opts = MergeOptions::new(opts)
+            .with_path(&self.path)
+            .with_suppressed(policy.suppressed())
+            .with_max_parse_bytes(policy.max_parse_bytes())
+            .with_memory_limit(policy.memory_limit());
+        let lang = match adapter {
+            Adapter::Ast(lang) => lang,
+            Adapter::Text | Adapter::OpenApi | Adapter::Properties => AstLanguage::TypeScript,
+        };
+        (lang, opts)
+    }
+
+    /// Estimated peak memory of merging the file semantically.
+    pub fn memory(&self) -> usize {
+        crate::merge_memory(&self.base, &self.a, &self.b)
+    }
+}
+
+/// The policy's memory limit as a budget for the files in flight, shared by
+/// the threads merging them.
+pub(crate) struct Budget {
+    limit: usize,
+    left: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Budget {
+    /// Without a limit, nobody waits.
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        let limit = limit.unwrap_or(usize::MAX);
+        Budget { limit, left: Mutex::new(limit), freed: Condvar::new() }
+    }
+
+    /// Run `work` once `cost` (at most the whole limit) fits in what's left,
+    /// blocking the thread until then.
+    pub(crate) fn run<T>(&self, cost: usize, work: impl FnOnce() -> T) -> T {
+        let cost = cost.min(self.limit);
+        let mut left = self.freed.wait_while(self.left.lock().unwrap(), |left| *left < cost).unwrap();
+        *left -= cost;
+        drop(left);
+        let out = work();
+        *self.left.lock().unwrap() += cost;
+        self.freed.notify_all();
+        out
+    }
+}
+
+/// Run every job, concurrently; the outcomes line up with `jobs`.
+pub fn merge_batch(jobs: &[MergeJob], policy: &Policy) -> Vec<Result<MergeOutcome>> {
+    merge_batch_with(jobs, policy, 0)
+}
+
+/// [`merge_batch`] on `threads` worker threads (0: one per core).
+pub fn merge_batch_with(jobs: &[MergeJob], policy: &Policy, threads: usize) -> Vec<Result<MergeOutcome>> {
+    let budget = Budget::new(policy.memory_limit());
+    each(jobs, threads, |job| budget.run(job.memory(), || job.run(policy)))
+}
+
+/// Files by revision, for [`merge_tree`] (an object store, a snapshot
+/// archive, ...); read from the worker threads.
+pub trait FileSource: Sync {
+    /// `path` at `rev`, or `None` if the revision has no such file.
+    fn read(&self, rev: &str, path: &str) -> Result<Option<String>>;
+}
+
+/// Merge `paths` between the revisions `base`, `a` and `b` of `source`,
+/// concurrently like [`merge_batch`]; the outcomes line up with `paths`. A
+/// file a branch deleted merges with [`crate::merge_deleted`] (deleted, or
+/// a `delete-modify` conflict); one a branch added merges against an empty
+/// base. A path whose versions can't be read fails on its own.
+pub fn merge_tree<S: FileSource + ?Sized>(
+    source: &S,
+    revs: (&str, &str, &str),
+    paths: &[String],
+    policy: &Policy,
+) -> Vec<Result<MergeOutcome>> {
+    merge_tree_with(source, revs, paths, policy, 0)
+}
+
+/// [`merge_tree`] on `threads` worker threads (0: one per core).
+pub fn merge_tree_with<S: FileSource + ?Sized>(
+    source: &S,
+    (base, a, b): (&str, &str, &str),
+    paths: &[String],
+    policy: &Policy,
+    threads: usize,
+) -> Vec<Result<MergeOutcome>> {
+    let budget = Budget::new(policy.memory_limit());
+    each(paths, threads, |path| {
+        let versions = (source.read(base, path)?, source.read(a, path)?, source.read(b, path)?);
+        merge_versions(path.clone(), versions, policy, &budget)
+    })
+}
+
+/// `work` on every item, on a rayon pool of `threads` threads (0: the
+/// global one, a thread per core); the results line up with `items`.
+#[cfg(feature = "parallel")]
+fn each<T: Sync, R: Send>(items: &[T], threads: usize, work: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    use rayon::prelude::*;
+    let run = || items.par_iter().map(&work).collect();
+    match threads {
+        0 => run(),
+        n => match rayon::ThreadPoolBuilder::new().num_threads(n).build() {
+            Ok(pool) => pool.install(run),
+            Err(_) => items.iter().map(&work).collect(),
+        },
+    }
+}
+
+/// `work` on every item in turn, on the caller's thread: without the
+/// `parallel` feature, `threads` is ignored.
+#[cfg(not(feature = "parallel"))]
+fn each<T, R>(items: &[T], _threads: usize, work: impl Fn(&T) -> R) -> Vec<R> {
+    items.iter().map(work).collect()
+}
+
+/// One file of [`merge_tree`] (or [`crate::merge_tree_async`]), from its
+/// versions (`None`: missing on that side).
+pub(crate) fn merge_versions(
+    path: String,
+    (base, a, b): (Option<String>, Option<String>, Option<String>),
+    policy: &Policy,
+    budget: &Budget,
+) -> Result<MergeOutcome> {
+    let deleted = (base.is_some() && (a.is_none() || b.is_none())) || (a.is_none() && b.is_none());
+    let job = MergeJob {
+        path,
+        base: base.unwrap_or_default(),
+        a: a.clone().unwrap_or_default(),
+        b: b.clone().unwrap_or_default(),
+    };
+    budget.run(job.memory(), || {
+        if deleted {
+            let (_, opts) = job.options(policy);
+            Ok(crate::merge_deleted(&job.base, a.as_deref(), b.as_deref(), &opts))
+        } else {
+            job.run(policy)
+        }
+    })
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use anyhow::bail;
+
+    use super::*;
+
+    struct Revisions(HashMap<(String, String), String>);
+
+    impl FileSource for Revisions {
+        fn read(&self, rev: &str, path: &str) -> Result<Option<String>> {
+            if path == "unreadable.ts" {
+                bail!("no access to {path}");
+            }
+            Ok(self.0.get(&(rev.to_string(), path.to_string())).cloned())
+        }
+    }
+
+    fn revisions(files: &[(&str, &str, &str)]) -> Revisions {
+        Revisions(files.iter().map(|&(rev, path, text)| ((rev.to_string(), path.to_string()), text.to_string())).collect())
+    }
+
+    #[test]
+    fn outcomes_line_up_with_the_paths_on_any_thread_count() {
+        let mut files = Vec::new();
+        let paths: Vec<String> = (0..40).map(|i| format!("f{i}.ts")).collect();
+        let texts: Vec<[String; 3]> = (0..40)
+            .map(|i| {
+                let base = format!("export const x{i} = {i};\n");
+                [base.clone(), format!("{base}export const a{i} = 1;\n"), format!("export const b{i} = 1;\n{base}")]
+            })
+            .collect();
+        for (path, [base, a, b]) in paths.iter().zip(&texts) {
+            files.extend([("base", path.as_str(), base.as_str()), ("a", path, a), ("b", path, b)]);
+        }
+        let source = revisions(&files);
+        let merged = |threads| -> Vec<String> {
+            merge_tree_with(&source, ("base", "a", "b"), &paths, &Policy::default(), threads)
+                .into_iter()
+                .map(|o| o.unwrap().merged_code)
+                .collect()
+        };
+        let one = merged(1);
+        for (i, code) in one.iter().enumerate() {
+            assert!(code.contains(&format!("a{i} ")) && code.contains(&format!("b{i} ")), "{code}");
+        }
+        assert_eq!(merged(4), one);
+        assert_eq!(merged(0), one);
+    }
+
+    #[test]
+    fn missing_versions_merge_as_deletions_and_read_errors_stay_with_their_path() {
+        let base = "export const x = 1;\n";
+        let source = revisions(&[("base", "gone.ts", base), ("a", "gone.ts", base)]);
+        let paths = ["gone.ts".to_string(), "unreadable.ts".to_string()];
+        let outcomes = merge_tree(&source, ("base", "a", "b"), &paths, &Policy::default());
+        assert!(outcomes[0].as_ref().unwrap().deleted);
+        assert!(outcomes[1].as_ref().unwrap_err().to_string().contains("unreadable.ts"));
+    }
+
+    #[test]
+    fn batches_keep_their_order() {
+        let jobs: Vec<MergeJob> = (0..10)
+            .map(|i| MergeJob {
+                path: format!("f{i}.ts"),
+                base: "let x = 1;\n".to_string(),
+                a: format!("let x = {i};\n"),
+                b: "let x = 1;\n".to_string(),
+            })
+            .collect();
+        let outcomes = merge_batch_with(&jobs, &Policy::default(), 3);
+        for (i, outcome) in outcomes.into_iter().enumerate() {
+            assert_eq!(outcome.unwrap().merged_code, format!("let x = {i};\n"));
+        }
+    }
+
+    #[test]
+    fn jobs_follow_the_policy_rules_for_their_path() {
+        let policy = Policy::parse("[[rule]]\npath = \"**/*.generated.ts\"\nmerge = \"textual\"\n").unwrap();
+        let base = "export const x = 1;\n";
+        let job = |path: &str| MergeJob {
+            path: path.to_string(),
+            base: base.to_string(),
+            a: format!("{base}export const a = 1;\n"),
+            b: format!("export const b = 1;\n{base}"),
+        };
+        let outcomes = merge_batch(&[job("src/api.generated.ts"), job("src/api.ts"), job("README")], &policy);
+        let outcomes: Vec<MergeOutcome> = outcomes.into_iter().map(Result::unwrap).collect();
+        assert!(outcomes[0].textual && outcomes[0].conflicts.len() == 1, "{:?}", outcomes[0].conflicts);
+        assert!(!outcomes[1].textual && outcomes[1].conflicts.is_empty(), "{:?}", outcomes[1].conflicts);
+        assert!(outcomes[2].textual);
+    }
+}
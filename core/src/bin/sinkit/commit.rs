@@ -0,0 +1,205 @@
+//! `commit-merge`: recording the result as a real merge commit.
+//!
+//! The tree is built with git's plumbing (`read-tree`, `update-index`,
+//! `merge-file`, `write-tree`, `commit-tree`), like every other git
+//! operation sinkit does (see `git.rs`), rather than libgit2: the same git
+//! reads the repo throughout, with the user's config, and sinkit builds
+//! without a C library. Scratch files (the merge's index, the versions
+//! `git merge-file` merges) go in a private directory inside `$GIT_DIR`,
+//! removed when it's done.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, bail};
+
+use crate::git::{Repo, git, git_with};
+use crate::source::{GITLINK, SYMLINK};
+use crate::display::say;
+use crate::symlink;
+
+/// A path sinkit merged, kept for `commit-merge`.
+pub(crate) struct Merged {
+    pub(crate) path: String,
+    pub(crate) mode: String,
+    /// The merged contents (markers included when conflicted); a symlink's
+    /// target, a submodule's commit ID. `None`: the merge deleted it.
+    pub(crate) content: Option<Vec<u8>>,
+    pub(crate) conflicted: bool,
+}
+
+/// `commit-merge`: build the merged tree in a scratch index (git's trivial
+/// merge of base/A/B, sinkit's results on top, `git merge-file` for the other
+/// paths both sides changed) and commit it with parents A and B. With
+/// conflicts left, a checkout of A is put into git's merge state instead.
+pub(crate) fn commit_merge(
+    repo: &Repo,
+    work_tree: Option<&Path>,
+    refs: [&str; 3],
+    merged: &[Merged],
+    message: &str,
+) -> Result<()> {
+    let [base, a, b] = refs;
+    let git_dir = git(repo, &["rev-parse", "--absolute-git-dir"])?;
+    let scratch = tempfile::Builder::new().prefix("sinkit-").tempdir_in(git_dir.trim())?;
+    let index = scratch.path().join("index");
+    // Ok(tree) when clean, Err(paths) with conflicts left
+    let tree = build_merge_index(repo, scratch.path(), refs, merged).and_then(|conflicted| match conflicted.is_empty() {
+        true => Ok(Ok(git_with(repo, &["write-tree"], Some(&index), None)?)),
+        false => Ok(Err(conflicted)),
+    });
+    drop(scratch);
+
+    match tree? {
+        Ok(tree) => {
+            let tree = String::from_utf8_lossy(&tree).trim().to_string();
+            let commit = git(repo, &["commit-tree", &tree, "-p", a, "-p", b, "-m", message])?.trim().to_string();
+            let head = git(repo, &["symbolic-ref", "-q", "HEAD"]).unwrap_or_default();
+            let branch = format!("refs/heads/{a}");
+            if work_tree.is_some() && (head.trim() == branch || a == "HEAD") {
+                // moves the checked-out branch and its work tree along
+                git(repo, &["merge", "--ff-only", "-q", &commit])?;
+                say!("Created merge commit {commit} on {a}");
+            } else if let Ok(old) = git(repo, &["rev-parse", "--verify", "-q", &branch]) {
+                git(repo, &["update-ref", "-m", message, &branch, &commit, old.trim()])?;
+                say!("Created merge commit {commit} on {a}");
+            } else {
+                say!("Created merge commit {commit}");
+            }
+            Ok(())
+        }
+        Err(conflicted) => {
+            let at_a = work_tree.is_some()
+                && git(repo, &["rev-parse", "HEAD"]).ok() == git(repo, &["rev-parse", &format!("{a}^{{commit}}")]).ok();
+            let Some(root) = work_tree.filter(|_| at_a) else {
+                bail!(
+                    "no merge commit: conflicts remain in {} (resolve them, or run from a checkout of {a})",
+                    conflicted.join(", ")
+                );
+            };
+            if !git(repo, &["status", "--porcelain", "--untracked-files=no"])?.trim().is_empty() {
+                bail!("no merge commit: conflicts remain, and the checkout has local changes to keep them apart");
+            }
+            // git's own merge state: trivial merge into the real index and work tree...
+            git(repo, &["read-tree", "-m", "-u", "--aggressive", base, "HEAD", b])?;
+            // ...sinkit's clean results on top...
+            for m in merged.iter().filter(|m| !m.conflicted) {
+                write_worktree(root, m)?;
+                stage(repo, None, m)?;
+            }
+            // ...and git-merge-one-file for the rest, leaving markers where it can't
+            let _ = git(repo, &["merge-index", "-o", "-q", "git-merge-one-file", "-a"]);
+            let b_oid = git(repo, &["rev-parse", &format!("{b}^{{commit}}")])?;
+            fs::write(root.join(git(repo, &["rev-parse", "--git-path", "MERGE_HEAD"])?.trim()), b_oid)?;
+            fs::write(root.join(git(repo, &["rev-parse", "--git-path", "MERGE_MSG"])?.trim()), format!("{message}\n"))?;
+            say!(
+                "Conflicts in {}; fix them, `git add` them and `git commit` to conclude the merge.",
+                conflicted.join(", ")
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Fill the index in the `scratch` directory with the merge; returns the
+/// paths still in conflict.
+fn build_merge_index(repo: &Repo, scratch: &Path, refs: [&str; 3], merged: &[Merged]) -> Result<Vec<String>> {
+    let [base, a, b] = refs;
+    let index = &scratch.join("index");
+    git_with(repo, &["read-tree", "-m", "-i", "--aggressive", base, a, b], Some(index), None)?;
+    let mut conflicted = Vec::new();
+    for m in merged {
+        if m.conflicted {
+            conflicted.push(m.path.clone());
+        } else {
+            stage(repo, Some(index), m)?;
+        }
+    }
+
+    // "<mode> <oid> <stage>\t<path>" per unmerged entry
+    let unmerged = git_with(repo, &["ls-files", "-u", "-z"], Some(index), None)?;
+    let mut stages: BTreeMap<String, [Option<(String, String)>; 3]> = BTreeMap::new();
+    for entry in String::from_utf8_lossy(&unmerged).split('\0') {
+        let Some((meta, path)) = entry.split_once('\t') else { continue };
+        let meta: Vec<&str> = meta.split(' ').collect();
+        let [mode, oid, n] = meta[..] else { continue };
+        let Some(slot) = n.parse::<usize>().ok().and_then(|n| n.checked_sub(1)).filter(|&n| n < 3) else { continue };
+        stages.entry(path.to_string()).or_default()[slot] = Some((mode.to_string(), oid.to_string()));
+    }
+    for (path, [base_entry, a_entry, b_entry]) in stages {
+        if conflicted.contains(&path) {
+            continue;
+        }
+        let textual = |e: &Option<(String, String)>| e.as_ref().is_some_and(|(m, _)| m.starts_with("100"));
+        if !(textual(&base_entry) && textual(&a_entry) && textual(&b_entry)) {
+            // added/deleted on one side, or not a regular file
+            conflicted.push(path);
+            continue;
+        }
+        let blob = |e: &Option<(String, String)>| {
+            let oid = &e.as_ref().expect("checked above").1;
+            git_with(repo, &["cat-file", "blob", oid], None, None)
+        };
+        let tmp = |side: &str| scratch.join(side);
+        fs::write(tmp("base"), blob(&base_entry)?)?;
+        fs::write(tmp("a"), blob(&a_entry)?)?;
+        fs::write(tmp("b"), blob(&b_entry)?)?;
+        let out = Command::new("git")
+            .args(["merge-file", "-p", "-q"])
+            .arg(tmp("a"))
+            .arg(tmp("base"))
+            .arg(tmp("b"))
+            .output()?;
+        if out.status.success() {
+            let mode = a_entry.expect("checked above").0;
+            stage(repo, Some(index), &Merged { path, mode, content: Some(out.stdout), conflicted: false })?;
+        } else {
+            conflicted.push(path);
+        }
+    }
+    conflicted.sort();
+    Ok(conflicted)
+}
+
+/// Stage a merged path at stage 0 (in `index`, or the repo's own index), or
+/// its removal.
+fn stage(repo: &Repo, index: Option<&Path>, m: &Merged) -> Result<()> {
+    let Some(content) = &m.content else {
+        git_with(repo, &["update-index", "--force-remove", "--", &m.path], index, None)?;
+        return Ok(());
+    };
+    let oid = if m.mode == GITLINK {
+        String::from_utf8_lossy(content).into_owned()
+    } else {
+        let oid = git_with(repo, &["hash-object", "-w", "--stdin"], index, Some(content))?;
+        String::from_utf8_lossy(&oid).trim().to_string()
+    };
+    let cacheinfo = format!("{},{oid},{}", m.mode, m.path);
+    git_with(repo, &["update-index", "--add", "--cacheinfo", &cacheinfo], index, None)?;
+    Ok(())
+}
+
+/// Write a merged path into the work tree at `root`, or remove it
+/// (submodules are left as checked out).
+fn write_worktree(root: &Path, m: &Merged) -> Result<()> {
+    if m.mode == GITLINK {
+        return Ok(());
+    }
+    let target = root.join(&m.path);
+    if fs::symlink_metadata(&target).is_ok() {
+        fs::remove_file(&target)?;
+    }
+    let Some(content) = &m.content else { return Ok(()) };
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if m.mode == SYMLINK {
+        symlink(&String::from_utf8_lossy(content), &target)?;
+    } else {
+        fs::write(&target, content)?;
+    }
+    Ok(())
+}
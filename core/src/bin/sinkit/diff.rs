@@ -0,0 +1,87 @@
+//! `sinkit diff <old> <new>`: what changed between two versions of a file,
+//! unit by unit (inserted, deleted, renamed, updated top-level units, each
+//! update with its lines).
+
+use sink_core::testing::line_diff;
+use sink_core::{Adapter, ParseOptions, diff_top_level_with, parse_typescript_to_ast_with};
+
+use super::{find_policy, read_text};
+use crate::display::{BOLD, CYAN, GREEN, RED, YELLOW, diff_line, paint};
+
+/// `diff <old> <new> [--path <repo path>]`; exits 1 if the files differ.
+pub(crate) fn diff(args: &[String], trust_repo_config: bool) -> anyhow::Result<()> {
+    let mut files = Vec::new();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--path" => path = args.next(),
+            _ => files.push(arg),
+        }
+    }
+    let [old_path, new_path] = files[..] else {
+        eprintln!("Usage: sinkit diff <old> <new> [--path <repo path>] [--trust-repo-config]");
+        std::process::exit(1);
+    };
+    let ((old, _), (new, _)) = (read_text(old_path)?, read_text(new_path)?);
+    if old == new {
+        return Ok(());
+    }
+    let name = path.unwrap_or(new_path);
+    let policy = find_policy(trust_repo_config)?;
+    let lang = match policy.adapter_for(name) {
+        Some(Adapter::Ast(lang)) if !policy.for_path(name).textual => lang,
+        // no grammar (or a textual rule): a plain line diff
+        _ => {
+            println!("{}", paint(BOLD, &format!("~ {name}")));
+            print_lines(&line_diff(&old, &new));
+            std::process::exit(1);
+        }
+    };
+    let opts = ParseOptions { policy: policy.for_path(name), ..ParseOptions::for_path(name) };
+    let base = parse_typescript_to_ast_with(&old, lang, &opts)?;
+    let other = parse_typescript_to_ast_with(&new, lang, &opts)?;
+    let base_units = base.keyed_units();
+    let base_text = |kind: &str, name: &str| {
+        base_units
+            .iter()
+            .find(|((k, n), _)| k == kind && n == name)
+            .map(|(_, u)| &old[u.start_byte..u.end_byte])
+            .unwrap_or("")
+    };
+    let edits = diff_top_level_with(&base, &other, &opts);
+    for e in &edits {
+        let label = format!("{} {}", e.unit_kind, if e.name.is_empty() { "(unnamed)" } else { &e.name });
+        let payload = e.payload.as_deref().unwrap_or("");
+        match e.kind.as_str() {
+            "insert" => {
+                println!("{}", paint(GREEN, &format!("+ {label}")));
+                print_lines(&line_diff("", payload));
+            }
+            "delete" => {
+                println!("{}", paint(RED, &format!("- {label}")));
+                print_lines(&line_diff(base_text(&e.unit_kind, &e.name), ""));
+            }
+            "rename" => {
+                let from = e.renamed_from.as_deref().unwrap_or_default();
+                println!("{}", paint(YELLOW, &format!("~ {} {from} -> {}", e.unit_kind, e.name)));
+                print_lines(&line_diff(base_text(&e.unit_kind, from), payload));
+            }
+            "update" => {
+                println!("{}", paint(YELLOW, &format!("~ {label}")));
+                print_lines(&line_diff(base_text(&e.unit_kind, &e.name), payload));
+            }
+            kind => println!("{}", paint(CYAN, &format!("? {label} ({kind})"))),
+        }
+    }
+    if edits.is_empty() {
+        println!("(formatting only)");
+    }
+    std::process::exit(1);
+}
+
+fn print_lines(diff: &str) {
+    for line in diff.lines() {
+        println!("    {}", diff_line(line));
+    }
+}
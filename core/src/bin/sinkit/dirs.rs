@@ -0,0 +1,208 @@
+//! `sinkit dirs`: three-way merge of plain directory trees, no VCS involved
+//! (vendored snapshot upgrades, source archives exported by a build).
+//!
+//! Every path in any of the trees is merged on its own: a path only one side
+//! changed (or added, or deleted) takes that side, and a path both changed
+//! is merged like `merge-file` would. Binary files, links and directories
+//! both sides changed differently, and deletions the other side edited, are
+//! reported as conflicts and keep one side's version.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use sink_core::{Encoding, decode_text, is_binary};
+
+use super::{find_policy, merge_loose, symlink};
+use crate::display::{GREEN, YELLOW, paint, print_conflicts};
+
+/// What a tree has at a path.
+#[derive(PartialEq)]
+enum Entry {
+    File(Vec<u8>),
+    Link(PathBuf),
+    Dir,
+}
+
+/// `dirs <base_dir> <a_dir> <b_dir> -o <out_dir>`.
+pub(crate) fn merge_dirs(args: &[String], trust_repo_config: bool) -> anyhow::Result<()> {
+    let mut dirs = Vec::new();
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next(),
+            _ => dirs.push(PathBuf::from(arg)),
+        }
+    }
+    let (Ok(trees), Some(out)) = (<[PathBuf; 3]>::try_from(dirs), output) else {
+        eprintln!("Usage: sinkit dirs <base_dir> <A_dir> <B_dir> -o <out_dir> [--trust-repo-config]");
+        std::process::exit(1);
+    };
+    for tree in &trees {
+        if !tree.is_dir() {
+            bail!("{} is not a directory", tree.display());
+        }
+    }
+    let out = PathBuf::from(out);
+    if fs::read_dir(&out).is_ok_and(|mut entries| entries.next().is_some()) {
+        bail!("{} is not empty", out.display());
+    }
+
+    let mut paths = BTreeSet::new();
+    for tree in &trees {
+        walk(tree, "", &mut paths)?;
+    }
+    let policy = find_policy(trust_repo_config)?;
+    fs::create_dir_all(&out)?;
+
+    let mut autos = 0usize;
+    let mut conflicts = 0usize;
+    let mut warned = 0usize;
+    let mut taken = 0usize;
+    let mut deleted = 0usize;
+    for path in paths {
+        let [base, a, b] = [0, 1, 2].map(|i| read(&trees[i].join(&path)));
+        let (base, a, b) = (base?, a?, b?);
+        let target = out.join(&path);
+
+        // only one side changed the path (or both the same way)
+        let side = if a == b || b == base {
+            Some((1, &a))
+        } else if a == base {
+            Some((2, &b))
+        } else {
+            None
+        };
+        if let Some((i, entry)) = side {
+            match entry {
+                None => deleted += 1,
+                Some(Entry::Dir) => {}
+                Some(entry) => {
+                    taken += 1;
+                    write(entry, &trees[i].join(&path), &target)?;
+                }
+            }
+            continue;
+        }
+
+        // both changed it: merge text files, report anything else
+        let (Some((base_code, base_enc)), Some((a_code, a_enc)), Some((b_code, b_enc)), Some(_), Some(_)) =
+            (text(&base), text(&a), text(&b), &a, &b)
+        else {
+            conflicts += 1;
+            let (i, entry, kept, other) = match (&a, &b) {
+                (Some(entry), _) => (1, entry, "A", "B"),
+                (None, Some(entry)) => (2, entry, "B", "A"),
+                (None, None) => unreachable!("a path both sides deleted is taken above"),
+            };
+            let why = if a.is_none() || b.is_none() {
+                format!("deleted in {other}, changed in {kept}")
+            } else {
+                "changed differently on both sides, not as text".to_string()
+            };
+            println!("{} {} ({}; kept {}'s version)", paint(YELLOW, "⚠"), path, why, kept);
+            write(entry, &trees[i].join(&path), &target)?;
+            continue;
+        };
+        let res = merge_loose(&policy, &path, &base_code, &a_code, &b_code)?;
+        let encoding = Encoding::merged(if base.is_some() { base_enc } else { a_enc }, a_enc, b_enc);
+        let bytes = encoding.encode(&res.merged_code).unwrap_or_else(|e| {
+            println!("  ℹ kept the merge of {path} in UTF-8, not {encoding:?}: {e}");
+            res.merged_code.clone().into_bytes()
+        });
+        write(&Entry::File(bytes), &trees[1].join(&path), &target)?;
+        for e in &res.syntax_errors {
+            eprintln!("  syntax error in {}: {}", path, e);
+        }
+        if res.conflicts.is_empty() {
+            autos += 1;
+            println!("{} {}", paint(GREEN, "✓"), path);
+        } else {
+            conflicts += 1;
+            println!("{} {} ({} conflicts)", paint(YELLOW, "⚠"), path, res.conflicts.len());
+            print_conflicts(&res.conflicts);
+        }
+        if !res.warnings.is_empty() {
+            warned += 1;
+        }
+        for w in &res.warnings {
+            println!("  ℹ {}", w);
+        }
+    }
+
+    println!("\n--- Summary ---");
+    println!("Auto-merged files: {}", autos);
+    println!("With conflicts:    {}", conflicts);
+    println!("With warnings:     {}", warned);
+    println!("Taken from a side: {}", taken);
+    println!("Deleted:           {}", deleted);
+    if conflicts > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Collect the non-directory paths under `root/rel`, relative to `root`,
+/// skipping VCS metadata.
+fn walk(root: &Path, rel: &str, out: &mut BTreeSet<String>) -> anyhow::Result<()> {
+    let dir = root.join(rel);
+    for entry in fs::read_dir(&dir).with_context(|| format!("reading {}", dir.display()))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if matches!(name.as_str(), ".git" | ".hg" | ".jj") {
+            continue;
+        }
+        let path = if rel.is_empty() { name } else { format!("{rel}/{name}") };
+        if entry.file_type()?.is_dir() {
+            walk(root, &path, out)?;
+        } else {
+            out.insert(path);
+        }
+    }
+    Ok(())
+}
+
+/// A file's text and encoding, unless it's binary; a missing file is empty
+/// (an add/add merges from nothing).
+fn text(entry: &Option<Entry>) -> Option<(String, Encoding)> {
+    match entry {
+        Some(Entry::File(bytes)) if !is_binary(bytes) || Encoding::detect(bytes) != Encoding::Latin1 => {
+            Some(decode_text(bytes))
+        }
+        None => Some((String::new(), Encoding::default())),
+        _ => None,
+    }
+}
+
+fn read(path: &Path) -> anyhow::Result<Option<Entry>> {
+    let Ok(meta) = fs::symlink_metadata(path) else { return Ok(None) };
+    let entry = if meta.is_symlink() {
+        Entry::Link(fs::read_link(path)?)
+    } else if meta.is_dir() {
+        Entry::Dir
+    } else {
+        Entry::File(fs::read(path).with_context(|| format!("reading {}", path.display()))?)
+    };
+    Ok(Some(entry))
+}
+
+/// Write `entry` to `target`, with the permissions of `from` (the side it
+/// came from, or A for merged files).
+fn write(entry: &Entry, from: &Path, target: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match entry {
+        Entry::File(bytes) => {
+            fs::write(target, bytes).with_context(|| format!("writing {}", target.display()))?;
+            if let Ok(meta) = fs::metadata(from) {
+                fs::set_permissions(target, meta.permissions())?;
+            }
+        }
+        Entry::Link(dest) => symlink(&dest.to_string_lossy(), target)?,
+        Entry::Dir => fs::create_dir_all(target)?,
+    }
+    Ok(())
+}
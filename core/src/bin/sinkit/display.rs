@@ -0,0 +1,231 @@
+//! Terminal output: colors, conflict excerpts, and the porcelain format.
+//!
+//! Colors are on when stdout is a terminal, unless `--no-color` is passed or
+//! `NO_COLOR` is set (<https://no-color.org>).
+//!
+//! With `--porcelain`, stdout carries only one line per path,
+//! `status\tpath\tconflicts\tengine`, and that format never changes:
+//!
+//! - status: `merged`, `conflict`, `deleted` or `skipped` (missing on a side)
+//! - conflicts: the number of conflicts left
+//! - engine: `ast`, `text`, `symlink`, `submodule` or `binary`
+//!
+//! Tabs, newlines and backslashes in paths are escaped as `\t`, `\n`, `\\`.
+//! Everything else (progress, warnings, the summary) is left out; errors and
+//! notes still go to stderr. A `--format` report (see `report`) leaves out
+//! the human output the same way.
+
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use sink_core::{Conflict, ConflictSite, cluster_conflicts};
+
+use crate::owners::CodeOwners;
+use crate::workspaces::Workspaces;
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+static PORCELAIN: AtomicBool = AtomicBool::new(false);
+static HUMAN: AtomicBool = AtomicBool::new(true);
+
+pub(crate) const RED: &str = "31";
+pub(crate) const GREEN: &str = "32";
+pub(crate) const YELLOW: &str = "33";
+pub(crate) const CYAN: &str = "36";
+pub(crate) const BOLD: &str = "1";
+
+/// Decide once, at startup, whether output is colored, porcelain or a report.
+pub(crate) fn init(no_color: bool, porcelain: bool, report: bool) {
+    let human = !porcelain && !report;
+    let wanted = !no_color && human && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+    COLOR.store(wanted && std::io::stdout().is_terminal(), Ordering::Relaxed);
+    PORCELAIN.store(porcelain, Ordering::Relaxed);
+    HUMAN.store(human, Ordering::Relaxed);
+}
+
+pub(crate) fn porcelain() -> bool {
+    PORCELAIN.load(Ordering::Relaxed)
+}
+
+pub(crate) fn human() -> bool {
+    HUMAN.load(Ordering::Relaxed)
+}
+
+/// `println!` for human-readable output, which `--porcelain` and `--format`
+/// drop.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::display::human() {
+            println!($($arg)*);
+        }
+    };
+}
+pub(crate) use say;
+
+/// The porcelain line of one path.
+pub(crate) fn porcelain_line(status: &str, path: &str, conflicts: usize, engine: &str) {
+    if porcelain() {
+        let path = path.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n");
+        println!("{status}\t{path}\t{conflicts}\t{engine}");
+    }
+}
+
+/// `text` in SGR `style`, if colors are on.
+pub(crate) fn paint(style: &str, text: &str) -> String {
+    if COLOR.load(Ordering::Relaxed) { format!("\x1b[{style}m{text}\x1b[0m") } else { text.to_string() }
+}
+
+/// Each conflict boxed under its file's line: unit and ID, then the reason.
+pub(crate) fn print_conflicts(conflicts: &[Conflict]) {
+    for c in conflicts {
+        let unit = c.unit.as_deref().unwrap_or("(whole file)");
+        say!("  {} {} {}", paint(RED, "┌"), paint(BOLD, unit), paint(CYAN, &format!("[{}]", c.id)));
+        for line in c.message.lines() {
+            say!("  {} {}", paint(RED, "│"), line);
+        }
+        say!("  {}", paint(RED, "└"));
+    }
+}
+
+/// One line of a `-`/`+`/` ` line diff, colored by its prefix.
+pub(crate) fn diff_line(line: &str) -> String {
+    match line.as_bytes().first() {
+        Some(b'+') => paint(GREEN, line),
+        Some(b'-') => paint(RED, line),
+        _ => line.to_string(),
+    }
+}
+
+/// Rows shown per ranking in the summary.
+const TOP: usize = 10;
+
+/// Where a merge's conflicts are: directories ranked by conflicts (with how
+/// many of their files conflict), then the files with the most conflicts and
+/// the kinds involved, and the owners of the conflicted files (by
+/// CODEOWNERS). `files` is every merged path and whether it conflicts.
+pub(crate) fn print_hotspots<'p>(
+    files: impl Iterator<Item = (&'p str, bool)>,
+    conflicts: &[(String, Conflict)],
+    owners: &CodeOwners,
+) {
+    if conflicts.is_empty() {
+        return;
+    }
+    let dir = |path: &str| path.rsplit_once('/').map_or(".".to_string(), |(dir, _)| format!("{dir}/"));
+    // dir -> (files, conflicted files, conflicts)
+    let mut dirs: BTreeMap<String, (usize, usize, usize)> = BTreeMap::new();
+    for (path, conflicted) in files {
+        let entry = dirs.entry(dir(path)).or_default();
+        entry.0 += 1;
+        entry.1 += usize::from(conflicted);
+    }
+    // file -> kind -> count
+    let mut by_file: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+    for (path, c) in conflicts {
+        dirs.entry(dir(path)).or_default().2 += 1;
+        *by_file.entry(path).or_default().entry(&c.kind).or_default() += 1;
+    }
+
+    say!("\n--- Where the conflicts are ---");
+    let mut ranked: Vec<_> = dirs.iter().filter(|(_, (_, _, n))| *n > 0).collect();
+    ranked.sort_by(|(x, (_, _, m)), (y, (_, _, n))| n.cmp(m).then(x.cmp(y)));
+    let width = ranked.iter().take(TOP).map(|(d, _)| d.chars().count()).max().unwrap_or(0);
+    say!("By directory:");
+    for (dir, (files, conflicted, n)) in ranked.iter().take(TOP) {
+        say!("  {:width$}  {} of {} files, {} conflicts", dir, conflicted, files, n);
+    }
+    if ranked.len() > TOP {
+        say!("  ... and {} more", ranked.len() - TOP);
+    }
+
+    let mut ranked: Vec<(&str, usize, &BTreeMap<&str, usize>)> =
+        by_file.iter().map(|(path, kinds)| (*path, kinds.values().sum(), kinds)).collect();
+    ranked.sort_by(|(x, m, _), (y, n, _)| n.cmp(m).then(x.cmp(y)));
+    say!("Most conflicts:");
+    for (path, n, kinds) in ranked.iter().take(TOP) {
+        let kinds: Vec<String> = kinds.iter().map(|(kind, k)| format!("{kind} ×{k}")).collect();
+        say!("  {:>4}  {}  ({})", n, paint(BOLD, path), kinds.join(", "));
+    }
+    if ranked.len() > TOP {
+        say!("  ... and {} more", ranked.len() - TOP);
+    }
+
+    // owner -> (conflicted files, conflicts)
+    let mut by_owner: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for (path, kinds) in &by_file {
+        for owner in owners.of(path) {
+            let entry = by_owner.entry(owner).or_default();
+            entry.0 += 1;
+            entry.1 += kinds.values().sum::<usize>();
+        }
+    }
+    if by_owner.is_empty() {
+        return;
+    }
+    let mut ranked: Vec<_> = by_owner.into_iter().collect();
+    ranked.sort_by(|(x, (_, m)), (y, (_, n))| n.cmp(m).then(x.cmp(y)));
+    let width = ranked.iter().take(TOP).map(|(o, _)| o.chars().count()).max().unwrap_or(0);
+    say!("By owner:");
+    for (owner, (files, n)) in ranked.iter().take(TOP) {
+        say!("  {:width$}  {} files, {} conflicts", owner, files, n);
+    }
+    if ranked.len() > TOP {
+        say!("  ... and {} more", ranked.len() - TOP);
+    }
+}
+
+/// Conflicts that one divergence probably caused, grouped under their
+/// suspected root (see `cluster_conflicts`), so it can be resolved first.
+pub(crate) fn print_clusters(sites: &[(String, ConflictSite)]) {
+    let clusters = cluster_conflicts(sites);
+    if clusters.is_empty() {
+        return;
+    }
+    let by_id: BTreeMap<&str, (&str, &Conflict)> =
+        sites.iter().map(|(path, site)| (site.conflict.id.as_str(), (path.as_str(), &site.conflict))).collect();
+    say!("\n--- Related conflicts ---");
+    for cluster in clusters.iter().take(TOP) {
+        say!("{} {}", paint(CYAN, &format!("[{}]", cluster.id)), cluster.cause);
+        for (i, id) in cluster.members.iter().enumerate() {
+            let Some((path, c)) = by_id.get(id.as_str()) else { continue };
+            let unit = c.unit.as_deref().unwrap_or("(whole file)");
+            let mark = if i == 0 { paint(BOLD, "root") } else { "    ".to_string() };
+            say!("  {}  {}: {} ({}) [{}]", mark, path, unit, c.kind, id);
+        }
+    }
+    if clusters.len() > TOP {
+        say!("  ... and {} more", clusters.len() - TOP);
+    }
+}
+
+/// Files and conflicts per workspace package (paths outside every package
+/// under `(root)`), and whether each is ready to land; nothing outside a
+/// monorepo.
+pub(crate) fn print_packages<'p>(
+    files: impl Iterator<Item = (&'p str, bool)>,
+    conflicts: &[(String, Conflict)],
+    workspaces: &Workspaces,
+) {
+    if workspaces.is_empty() {
+        return;
+    }
+    let package = |path: &str| workspaces.of(path).map_or(("(root)", ""), |p| (p.name.as_str(), p.dir.as_str()));
+    // (name, dir) -> (files, conflicts)
+    let mut packages: BTreeMap<(&str, &str), (usize, usize)> = BTreeMap::new();
+    for (path, _) in files {
+        packages.entry(package(path)).or_default().0 += 1;
+    }
+    for (path, _) in conflicts {
+        packages.entry(package(path)).or_default().1 += 1;
+    }
+    if packages.is_empty() {
+        return;
+    }
+    let width = packages.keys().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+    let dir_width = packages.keys().map(|(_, dir)| dir.chars().count()).max().unwrap_or(0);
+    say!("\n--- Packages ---");
+    for ((name, dir), (files, n)) in &packages {
+        let status = if *n == 0 { paint(GREEN, "ready") } else { paint(YELLOW, "blocked") };
+        say!("  {:width$}  {:dir_width$}  {} files, {} conflicts  {}", name, dir, files, n, status);
+    }
+}
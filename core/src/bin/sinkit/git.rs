@@ -0,0 +1,250 @@
+//! Git as a [`FileSource`], plus the plumbing the git-only features use.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+
+use crate::source::{Entries, FileSource};
+
+/// Where git runs: a checkout or bare repo (`<repo_path>`), or an explicit
+/// `--git-dir`.
+pub(crate) struct Repo {
+    pub(crate) dir: PathBuf,
+    pub(crate) git_dir: Option<PathBuf>,
+    /// The root of the checkout (linked worktrees included), if any.
+    pub(crate) work_tree: Option<PathBuf>,
+    /// The `cat-file --batch` process blobs are read through, once started.
+    cat_file: RefCell<Option<CatFile>>,
+}
+
+/// A long-lived `git cat-file --batch`: one process for every blob of a run
+/// instead of a `git show` per file and side.
+struct CatFile {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Drop for CatFile {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Repo {
+    /// Open the repo at `dir` (or `git_dir`); git runs from the checkout's
+    /// root, since paths (`ls-tree`, `ls-files`, `<ref>:<path>`) are relative
+    /// to it.
+    pub(crate) fn open(dir: PathBuf, git_dir: Option<PathBuf>) -> Result<Self> {
+        let mut repo = Repo { dir, git_dir, work_tree: None, cat_file: RefCell::default() };
+        if repo.git_dir.is_none() && git(&repo, &["rev-parse", "--is-bare-repository"])?.trim() != "true" {
+            let root = PathBuf::from(git(&repo, &["rev-parse", "--show-toplevel"])?.trim_end_matches('\n'));
+            repo.dir = root.clone();
+            repo.work_tree = Some(root);
+        }
+        Ok(repo)
+    }
+
+    /// A submodule's checkout (or any other plain checkout).
+    pub(crate) fn at(dir: PathBuf) -> Self {
+        Repo { dir, git_dir: None, work_tree: None, cat_file: RefCell::default() }
+    }
+
+    fn spawn_cat_file(&self) -> Result<CatFile> {
+        let mut cmd = Command::new("git");
+        if let Some(git_dir) = &self.git_dir {
+            cmd.arg("--git-dir").arg(git_dir);
+        }
+        cmd.args(["cat-file", "--batch"]).current_dir(&self.dir);
+        let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn()?;
+        let stdin = child.stdin.take().context("cat-file stdin")?;
+        let stdout = BufReader::new(child.stdout.take().context("cat-file stdout")?);
+        Ok(CatFile { child, stdin, stdout })
+    }
+}
+
+impl FileSource for Repo {
+    fn resolve(&self, rev: &str) -> Result<String> {
+        Ok(git(self, &["rev-parse", "--verify", "--quiet", &format!("{rev}^{{commit}}")])?.trim().to_string())
+    }
+
+    fn merge_base(&self, a: &str, b: &str, deepen: bool) -> Result<String> {
+        merge_base(self, a, b, deepen)
+    }
+
+    /// Blobs and gitlinks.
+    fn entries(&self, rev: &str) -> Result<Entries> {
+        let out = git(self, &["ls-tree", "-r", "--full-tree", rev])?;
+        // "<mode> <type> <oid>\t<path>"
+        Ok(out
+            .lines()
+            .filter_map(|l| {
+                let (meta, path) = l.split_once('\t')?;
+                let mut meta = meta.split(' ');
+                let (mode, _, oid) = (meta.next()?, meta.next()?, meta.next()?);
+                Some((path.to_string(), (mode.to_string(), oid.to_string())))
+            })
+            .collect())
+    }
+
+    fn commit_time(&self, rev: &str) -> Result<i64> {
+        Ok(git(self, &["log", "-1", "--format=%ct", rev])?.trim().parse()?)
+    }
+
+    fn show_bytes(&self, rev: &str, path: &str) -> Result<Vec<u8>> {
+        // batch requests are lines, so a path with a newline needs its own process
+        if path.contains('\n') {
+            return git_with(self, &["show", &format!("{}:{}", rev, path)], None, None);
+        }
+        let mut cat_file = self.cat_file.borrow_mut();
+        let cat_file = match &mut *cat_file {
+            Some(cat_file) => cat_file,
+            None => cat_file.insert(self.spawn_cat_file()?),
+        };
+        // "<oid> <type> <size>\n<contents>\n", or "<object> missing\n"
+        writeln!(cat_file.stdin, "{rev}:{path}")?;
+        cat_file.stdin.flush()?;
+        let mut header = String::new();
+        cat_file.stdout.read_line(&mut header)?;
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        let &[_, kind, size] = &fields[..] else { bail!("{rev}:{path} does not exist") };
+        let size: usize = size.parse()?;
+        let mut contents = vec![0; size + 1];
+        cat_file.stdout.read_exact(&mut contents)?;
+        contents.pop();
+        if kind != "blob" {
+            bail!("{rev}:{path} is a {kind}, not a file");
+        }
+        Ok(contents)
+    }
+
+    fn work_tree(&self) -> Option<&Path> {
+        self.work_tree.as_deref()
+    }
+
+    fn as_git(&self) -> Option<&Repo> {
+        Some(self)
+    }
+}
+
+pub(crate) fn git(repo: &Repo, args: &[&str]) -> Result<String> {
+    Ok(String::from_utf8_lossy(&git_with(repo, args, None, None)?).into_owned())
+}
+
+/// Run git with another index file (`GIT_INDEX_FILE`) and/or stdin; raw stdout.
+pub(crate) fn git_with(repo: &Repo, args: &[&str], index: Option<&Path>, input: Option<&[u8]>) -> Result<Vec<u8>> {
+    let mut cmd = Command::new("git");
+    if let Some(git_dir) = &repo.git_dir {
+        cmd.arg("--git-dir").arg(git_dir);
+    }
+    if let Some(index) = index {
+        cmd.env("GIT_INDEX_FILE", index);
+    }
+    cmd.args(args).current_dir(&repo.dir).stdout(Stdio::piped()).stderr(Stdio::piped());
+    cmd.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() });
+    let mut child = cmd.spawn()?;
+    // feed stdin from a thread so git can't deadlock on a full stdout pipe
+    // (`check-attr --stdin` answers as it reads)
+    let (out, fed) = std::thread::scope(|scope| {
+        let feeder = match (input, child.stdin.take()) {
+            (Some(input), Some(mut stdin)) => Some(scope.spawn(move || stdin.write_all(input))),
+            _ => None,
+        };
+        let out = child.wait_with_output();
+        (out, feeder.map_or(Ok(()), |f| f.join().unwrap_or(Ok(()))))
+    });
+    let out = out?;
+    if out.status.success() {
+        fed?;
+        Ok(out.stdout)
+    } else {
+        let err = String::from_utf8_lossy(&out.stderr);
+        bail!("git {:?} failed: {}", args, err);
+    }
+}
+
+/// Fetches of 50 commits before `--deepen` gives up and unshallows.
+const DEEPEN_STEPS: usize = 5;
+
+/// `git merge-base a b`. A shallow clone may have cut the history off above
+/// the merge base: with `deepen`, fetch more of it until one shows up.
+fn merge_base(repo: &Repo, a: &str, b: &str, deepen: bool) -> Result<String> {
+    let mut fetches = 0;
+    loop {
+        if let Ok(bases) = git(repo, &["merge-base", "--all", a, b]) {
+            let bases: Vec<&str> = bases.lines().collect();
+            if bases.len() > 1 {
+                eprintln!(
+                    "note: {a} and {b} have {} merge bases (criss-cross history); using {}. \
+                     Pass --base <ref> to pick another.",
+                    bases.len(),
+                    bases[0]
+                );
+            }
+            return Ok(bases[0].to_string());
+        }
+        let shallow = git(repo, &["rev-parse", "--is-shallow-repository"]).is_ok_and(|s| s.trim() == "true");
+        if !shallow {
+            bail!("{a} and {b} have no common history; pass the base explicitly with --base <ref>");
+        }
+        if !deepen {
+            bail!(
+                "no merge base of {a} and {b} in this shallow clone; re-run with --deepen to fetch more \
+                 history, or pass the base explicitly with --base <ref>"
+            );
+        }
+        if fetches < DEEPEN_STEPS {
+            eprintln!("note: shallow clone without a merge base; fetching 50 more commits");
+            git(repo, &["fetch", "--deepen=50"])?;
+        } else {
+            eprintln!("note: still no merge base; fetching the full history");
+            git(repo, &["fetch", "--unshallow"])?;
+        }
+        fetches += 1;
+    }
+}
+
+/// Paths a sparse checkout leaves out of the work tree (skip-worktree bit).
+pub(crate) fn skip_worktree(repo: &Repo) -> Result<BTreeSet<String>> {
+    let out = git(repo, &["ls-files", "-t", "-z"])?;
+    Ok(out.split('\0').filter_map(|entry| entry.strip_prefix("S ")).map(str::to_string).collect())
+}
+
+/// What the checkout's `.gitattributes` say about the merged files.
+#[derive(Default)]
+pub(crate) struct Attributes {
+    /// `working-tree-encoding` of the paths that have one (their blobs are
+    /// UTF-8, their files in that encoding).
+    pub(crate) encodings: BTreeMap<String, String>,
+    /// The paths marked `linguist-generated`.
+    pub(crate) generated: BTreeSet<String>,
+}
+
+/// The [`Attributes`] of `paths`, in one `git check-attr` run.
+pub(crate) fn attributes<'p>(repo: &Repo, paths: impl Iterator<Item = &'p str>) -> Result<Attributes> {
+    let input: Vec<u8> = paths.flat_map(|p| p.bytes().chain([0])).collect();
+    let args = ["check-attr", "-z", "--stdin", "working-tree-encoding", "linguist-generated"];
+    let out = git_with(repo, &args, None, Some(&input))?;
+    // "<path>\0<attribute>\0<value>\0" per path and attribute
+    let out = String::from_utf8_lossy(&out);
+    let fields: Vec<&str> = out.split('\0').collect();
+    let mut attributes = Attributes::default();
+    for f in fields.chunks_exact(3) {
+        match (f[1], f[2]) {
+            ("working-tree-encoding", "unspecified" | "unset" | "set") => {}
+            ("working-tree-encoding", encoding) => {
+                attributes.encodings.insert(f[0].to_string(), encoding.to_string());
+            }
+            ("linguist-generated", "set" | "true") => {
+                attributes.generated.insert(f[0].to_string());
+            }
+            _ => {}
+        }
+    }
+    Ok(attributes)
+}
@@ -0,0 +1,100 @@
+//! Mercurial as a [`FileSource`] (`hg debugancestor`, `hg manifest`,
+//! `hg cat`).
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Result, bail};
+
+use crate::source::{Entries, FileSource, SYMLINK};
+
+pub(crate) struct Hg {
+    /// The repo's root.
+    root: PathBuf,
+}
+
+impl Hg {
+    pub(crate) fn open(dir: &Path) -> Result<Self> {
+        let root = PathBuf::from(hg(dir, &["root"])?.trim_end_matches('\n'));
+        Ok(Hg { root })
+    }
+
+    /// Whether `dir` is inside a Mercurial (rather than git) checkout.
+    pub(crate) fn detect(dir: &Path) -> bool {
+        let Ok(dir) = dir.canonicalize() else { return false };
+        dir.ancestors()
+            .find_map(|d| {
+                if d.join(".hg").is_dir() {
+                    Some(true)
+                } else {
+                    d.join(".git").exists().then_some(false)
+                }
+            })
+            .unwrap_or(false)
+    }
+}
+
+impl FileSource for Hg {
+    fn resolve(&self, rev: &str) -> Result<String> {
+        Ok(hg(&self.root, &["log", "-r", rev, "-T", "{node}"])?.trim().to_string())
+    }
+
+    /// Mercurial has no shallow clones, so `deepen` doesn't apply.
+    fn merge_base(&self, a: &str, b: &str, _deepen: bool) -> Result<String> {
+        // "<rev>:<node>"
+        let out = hg(&self.root, &["debugancestor", a, b])?;
+        match out.trim().split_once(':') {
+            Some((_, node)) if !node.chars().all(|c| c == '0') => Ok(node.to_string()),
+            _ => bail!("{a} and {b} have no common history; pass the base explicitly with --base <ref>"),
+        }
+    }
+
+    /// Modes are mapped to git's: `@` (symlink) to 120000, `*` (executable)
+    /// to 100755.
+    fn entries(&self, rev: &str) -> Result<Entries> {
+        let out = hg(&self.root, &["manifest", "--debug", "-r", rev])?;
+        // "<node> <perm> <flag> <path>", the flag `@`, `*` or a space
+        Ok(out
+            .lines()
+            .filter_map(|l| {
+                let (node, rest) = l.split_once(' ')?;
+                let (flag, path) = (rest.get(4..5)?, rest.get(6..)?);
+                let mode = match flag {
+                    "@" => SYMLINK,
+                    "*" => "100755",
+                    _ => "100644",
+                };
+                Some((path.to_string(), (mode.to_string(), node.to_string())))
+            })
+            .collect())
+    }
+
+    fn commit_time(&self, rev: &str) -> Result<i64> {
+        // "<seconds> <timezone offset>"
+        let out = hg(&self.root, &["log", "-r", rev, "-T", "{date|hgdate}"])?;
+        Ok(out.split_whitespace().next().unwrap_or_default().parse()?)
+    }
+
+    fn show_bytes(&self, rev: &str, path: &str) -> Result<Vec<u8>> {
+        hg_bytes(&self.root, &["cat", "-r", rev, &format!("path:{path}")])
+    }
+
+    fn work_tree(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+}
+
+fn hg(dir: &Path, args: &[&str]) -> Result<String> {
+    Ok(String::from_utf8_lossy(&hg_bytes(dir, args)?).into_owned())
+}
+
+/// Run hg; raw stdout.
+fn hg_bytes(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    // HGPLAIN: no aliases, pagers or localized output
+    let out = Command::new("hg").args(args).current_dir(dir).env("HGPLAIN", "1").output()?;
+    if out.status.success() {
+        Ok(out.stdout)
+    } else {
+        bail!("hg {:?} failed: {}", args, String::from_utf8_lossy(&out.stderr));
+    }
+}
@@ -0,0 +1,1403 @@
+//! sinkit: minimal multi-file semantic merge runner for TS/TSX repos.
+//!
+//! Usage:
+//!   cargo run --bin sinkit -- [merge | commit-merge] (<repo_path> | --git-dir <dir>) <A_ref> <B_ref>
+//!          [--base <ref>] [--deepen] [--resolutions <file|dir>] [--export-conflicts <dir>] [--prune-imports] [--keep-indentation]
+//!          [--insert-anchor base|nearest] [--min-confidence <0..1>] [--package <name>]... [--format-cmd <command>] [--ast-cache <dir>] [--apply] [--fail-fast] [--trust-repo-config] [-m <message>] [--porcelain | --format <report>] [--no-color]
+//!   sinkit merge-file <base> <A> <B> [-o <output>] [--path <repo path>] [--trust-repo-config]
+//!   sinkit driver %O %A %B %P [--trust-repo-config]
+//!   sinkit resolve <file>... [--trust-repo-config]
+//!   sinkit diff <old> <new> [--path <repo path>] [--trust-repo-config]
+//!   sinkit dirs <base_dir> <A_dir> <B_dir> -o <out_dir> [--trust-repo-config]
+//!   sinkit serve --http <[host]:port> [--trust-repo-config]
+//!
+//! `merge-file` merges three loose files, as a merge tool for jj (or git):
+//!
+//! ```toml
+//! [merge-tools.sinkit]                # jj config
+//! merge-args = ["merge-file", "$base", "$left", "$right", "-o", "$output"]
+//! ```
+//!
+//! It exits 1 without writing the output when conflicts remain. `resolve`
+//! rebuilds the versions of files left with conflict markers in the working
+//! copy (jj's, or git's diff3 style), merges them and writes back the ones
+//! that come out clean. Both read the `.sinkit.toml` files of the current
+//! directory and its parents (the outermost one as the root, the others as
+//! profiles of its subdirectories).
+//!
+//! `driver` is `merge-file` as a git merge driver, so `git merge` (and
+//! rebase, cherry-pick, ...) merge the files it's assigned to with sinkit:
+//!
+//! ```text
+//! # .gitattributes
+//! *.ts merge=sinkit
+//! # .git/config
+//! [merge "sinkit"]
+//!     driver = sinkit driver %O %A %B %P
+//! ```
+//!
+//! The merge goes into `%A`, and it exits 0 if it's clean. If conflicts
+//! remain (or the file can't be merged), `%A` gets `git merge-file`'s
+//! markers instead, with sinkit's reasons on stderr, and it exits 1 for git
+//! to report the file as conflicted.
+//!
+//! `dirs` does the same for three whole directory trees without any VCS
+//! (vendored snapshot upgrades, exported source archives), writing the merged
+//! tree to an empty `<out_dir>`; it also exits 1 if conflicts remain.
+//!
+//! `diff` shows what changed between two versions of a file unit by unit
+//! (inserted, deleted and updated units, with their lines). Output is colored
+//! on a terminal; `--no-color` (or `NO_COLOR`) turns that off.
+//!
+//! `serve` answers `/merge`, `/diff` and `/parse` requests (JSON over HTTP)
+//! for web tools; see `serve.rs` for the request shapes.
+//!
+//! `commit-merge` records the result as a merge commit with parents A and B
+//! (and moves branch A to it), a drop-in for `git merge`: paths sinkit doesn't
+//! merge itself get git's own merge. If conflicts remain and A is checked
+//! out, the checkout is left mid-merge instead (conflicted files get git's
+//! textual markers; sinkit's reasons are in `.codesync/`), for `git commit`
+//! to conclude once they're fixed.
+//!
+//! The base is `git merge-base A B`, or `--base <ref>`, which skips the
+//! lookup: rebase-style replays, a specific ancestor in criss-cross histories
+//! (which have several merge bases; sinkit notes when it picked one), or a
+//! base that can't be computed. In a shallow clone without a merge base,
+//! `--deepen` fetches more history until one turns up.
+//!
+//! The merge only reads objects (`merge-base`, `ls-tree`, `show`), so it never
+//! needs a work tree: `--git-dir` (or a bare `<repo_path>`) runs against CI
+//! mirrors and from server-side hooks. Linked worktrees (`git worktree add`)
+//! work like any checkout; git resolves their common dir.
+//!
+//! `--apply` writes the results into the work tree instead of `.codesync/`.
+//! In a sparse checkout the whole tree is still merged, but paths outside
+//! the sparse cone (skip-worktree) are reported and left unwritten.
+//!
+//! `--fail-fast` stops at the first path with a conflict and exits 1,
+//! without the rest of the merge or the report (the manifest lists the
+//! paths merged up to it).
+//!
+//! In a monorepo (pnpm, yarn or npm workspaces, or a Cargo workspace; see
+//! `workspaces.rs`), the summary lists every package's files and conflicts.
+//! `--package @acme/billing` (by name or directory, repeatable) merges only
+//! the packages given, so CI can gate each package on its own (exiting 1
+//! if they still have conflicts, as every merge with conflicts left does).
+//!
+//! `--porcelain` prints one stable `status\tpath\tconflicts\tengine` line
+//! per path instead of the human output, for scripts (see `display.rs`).
+//! `--format gitlab-codequality` or `--format junit` prints a CI report
+//! instead (see `report.rs`).
+//!
+//! `--prune-imports` drops imports whose last users the merge removed.
+//! Units a branch inserted or updated are re-indented to the file's style
+//! (tabs or a number of spaces, as base has it unless both branches changed
+//! it alike); `--keep-indentation` splices them as is.
+//! New units go next to the base units around them in their branch;
+//! `--insert-anchor nearest` puts them right after the unit that precedes
+//! them there, even a new one (see `InsertAnchor`).
+//! `--min-confidence 0.9` reports resolutions a heuristic is less sure of
+//! (a parameter rename carried over, the same change up to formatting, an
+//! edit carried into a split or moved unit) as conflicts instead.
+//! `--format-cmd "prettier --stdin-filepath {path}"` pipes every file both
+//! branches changed through a formatter (run in the repo's work tree, if it
+//! has one) before writing. Like every command sinkit runs, it comes from
+//! the user (here, the command line), never from a branch.
+//! `--ast-cache <dir>` keeps parsed files there, so repeated runs (watch
+//! mode, CI retries) don't parse unchanged blobs again.
+//
+//! Symlinks (mode 120000) are merged by target only: the same target is kept,
+//! one retargeted side is taken, two different targets conflict. Submodules
+//! (mode 160000) are merged by commit: the same commit is kept, a
+//! fast-forward takes the newer one (ancestry is checked in the checked-out
+//! submodule), divergent commits conflict. `--apply` stages the merged
+//! gitlink.
+//! Binary files either branch changed are taken byte for byte from the side
+//! that changed them; two different changes conflict. A file deleted on one
+//! side and modified on the other is a `delete-modify` conflict; one deleted
+//! and left alone (or deleted on both sides) is deleted, from the work tree
+//! too with `--apply`.
+//! Units a branch moved into a file it added take the other branch's edits
+//! there (see `moves.rs`).
+//! Text in another encoding than UTF-8 (by BOM, UTF-16's NULs, else Latin-1,
+//! or `working-tree-encoding` in `.gitattributes`) is merged decoded and
+//! written back in its encoding.
+//! Generated files (a `@generated`-style header, or `linguist-generated` in
+//! `.gitattributes`) merge by their policy: textually by default, or keeping
+//! A's version, or the version of the branch with the newer commit.
+//!
+//! `<repo_path>` may also be a Mercurial checkout (`hg debugancestor`,
+//! `hg cat`); the git-only features (`--git-dir`, `--deepen`, sparse
+//! checkouts, submodules, `commit-merge`) don't apply there.
+//!
+//! Policy rules, suppressed conflict IDs and extra file extensions are read
+//! from `<repo_path>/.sinkit.toml` (see `Policy`); without a work tree, from
+//! `.sinkit.toml` as committed on A. A subdirectory's own `.sinkit.toml` (on
+//! A) overrides them for its subtree. A `.sinkit.toml` is whoever pushed
+//! it's, checked out or read from A's commit, so its `[resolve] cmd` doesn't
+//! run and its `[grammars]` aren't loaded (with a warning) unless
+//! `--trust-repo-config` says they may; the same goes for the `.sinkit.toml`
+//! files every other command reads.
+//!
+//! Output:
+//!   Writes merged files to ./ .codesync/<original/path>.ts (symlinks as links),
+//!   or in place with `--apply`
+//!   Conflicts (ID, reason, range, A's and B's versions) go next to it in
+//!   <file>.conflicts.json
+//!   When conflicts remain, writes a .codesync/resolutions.toml skeleton; fill
+//!   in a choice per conflict and re-run with `--resolutions` to apply them.
+//!   Conflicts the `[resolve]` command settled are recorded in
+//!   .codesync/resolver.toml.
+//!   .codesync/manifest.json lists every path with its output, status,
+//!   conflicts, engine, content hashes and CODEOWNERS owners (see
+//!   `manifest` and `owners`).
+//!   Prints summary of autos / conflicts / warnings / suppressed / deleted.
+//!   Exits 1 if conflicts remain.
+
+mod commit;
+mod diff;
+mod dirs;
+mod display;
+mod git;
+mod hg;
+mod manifest;
+mod moves;
+mod owners;
+mod report;
+#[cfg(feature = "serve")]
+mod serve;
+mod source;
+mod workspaces;
+
+use std::env;
+use std::fs;
+use std::io::Write;
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use commit::{Merged, commit_merge};
+use display::{GREEN, YELLOW, paint, porcelain_line, print_clusters, print_conflicts, print_hotspots, print_packages, say};
+use git::{Attributes, Repo, attributes, git, skip_worktree};
+use hg::Hg;
+use moves::{Move, find_moves};
+use owners::CodeOwners;
+use source::{Entries, FileSource, GITLINK, SYMLINK};
+use workspaces::Workspaces;
+use sink_core::{
+    carry_moved_edits, conflict_bundles, decode_text, is_binary, merge_binary, merge_deleted, merge_submodule, merge_symlink, MergeJob, three_way_merge, Adapter, AstLanguage, CommandFormatter, CommandResolver, Conflict, ConflictResolver, ConflictBundle, ConflictSite, DirAstCache, Encoding, InsertAnchor, MergeOptions, MergeOutcome, MergeStats,
+    locate_conflicts,
+    ParseOptions, Policy, Resolution, Resolutions, split_conflict_markers,
+};
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    let report = match args.iter().position(|a| a == "--format") {
+        Some(i) if i + 1 < args.len() => Some(report::Format::parse(&args.drain(i..=i + 1).nth(1).unwrap())?),
+        Some(_) => anyhow::bail!("--format needs a report format"),
+        None => None,
+    };
+    let porcelain = args.iter().any(|a| a == "--porcelain");
+    if porcelain && report.is_some() {
+        anyhow::bail!("--porcelain and --format don't go together");
+    }
+    display::init(args.iter().any(|a| a == "--no-color"), porcelain, report.is_some());
+    let trust_repo_config = args.iter().any(|a| a == "--trust-repo-config");
+    args.retain(|a| a != "--no-color" && a != "--porcelain" && a != "--trust-repo-config");
+    match args.first().map(String::as_str) {
+        Some("diff") => return diff::diff(&args[1..], trust_repo_config),
+        Some("merge-file") => return merge_file(&args[1..], trust_repo_config),
+        Some("driver") => return driver(&args[1..], trust_repo_config),
+        Some("resolve") => return resolve(&args[1..], trust_repo_config),
+        Some("dirs") => return dirs::merge_dirs(&args[1..], trust_repo_config),
+        #[cfg(feature = "serve")]
+        Some("serve") => return serve::serve(&args[1..], trust_repo_config),
+        #[cfg(not(feature = "serve"))]
+        Some("serve") => anyhow::bail!("this sinkit was built without the serve feature"),
+        _ => {}
+    }
+    let commit_mode = args.first().map(String::as_str) == Some("commit-merge");
+    if commit_mode || args.first().map(String::as_str) == Some("merge") {
+        args.remove(0);
+    }
+    let prune_imports = args.iter().any(|a| a == "--prune-imports");
+    let keep_indentation = args.iter().any(|a| a == "--keep-indentation");
+    let apply = args.iter().any(|a| a == "--apply");
+    let deepen = args.iter().any(|a| a == "--deepen");
+    let fail_fast = args.iter().any(|a| a == "--fail-fast");
+    args.retain(|a| {
+        !["--prune-imports", "--keep-indentation", "--apply", "--deepen", "--fail-fast"].contains(&a.as_str())
+    });
+    let mut option = |flag: &str| {
+        let i = args.iter().position(|a| a == flag)?;
+        let value = (i + 1 < args.len()).then(|| args.remove(i + 1));
+        args.remove(i);
+        value
+    };
+    let resolutions_path = option("--resolutions").map(PathBuf::from);
+    let export_dir = option("--export-conflicts").map(PathBuf::from);
+    let format_cmd = option("--format-cmd");
+    let ast_cache = option("--ast-cache").map(PathBuf::from);
+    let git_dir = option("--git-dir").map(PathBuf::from);
+    let explicit_base = option("--base");
+    let insert_anchor = option("--insert-anchor");
+    let package_names: Vec<String> = std::iter::from_fn(|| option("--package")).collect();
+    let min_confidence = option("--min-confidence");
+    let message = option("-m");
+    if args.len() != if git_dir.is_some() { 2 } else { 3 } {
+        eprintln!(
+            "Usage: sinkit [merge | commit-merge] (<repo_path> | --git-dir <dir>) <A_ref> <B_ref> [--base <ref>]\n    \
+             [--deepen] [--resolutions <file|dir>] [--export-conflicts <dir>] [--prune-imports] [--keep-indentation]\n    \
+             [--insert-anchor base|nearest] [--min-confidence <0..1>] [--package <name>]...\n    \
+             [--format-cmd <command>] [--ast-cache <dir>] [--apply] [--fail-fast] [--trust-repo-config] [-m <message>]\n    \
+             [--porcelain | --format gitlab-codequality|junit] [--no-color]"
+        );
+        std::process::exit(1);
+    }
+    let insert_anchor = match insert_anchor.as_deref() {
+        None | Some("base") => InsertAnchor::Base,
+        Some("nearest") => InsertAnchor::Nearest,
+        Some(other) => anyhow::bail!("unknown --insert-anchor {other} (expected base or nearest)"),
+    };
+    let min_confidence = match min_confidence.as_deref().map(str::parse::<f64>) {
+        None => None,
+        Some(Ok(min)) if (0.0..=1.0).contains(&min) => Some(min),
+        Some(_) => anyhow::bail!("--min-confidence needs a number from 0 to 1"),
+    };
+    let resolutions = match &resolutions_path {
+        Some(path) => Resolutions::load(path)?,
+        None => Resolutions::default(),
+    };
+    let source: Box<dyn FileSource> = match git_dir {
+        Some(git_dir) => Box::new(Repo::open(PathBuf::from("."), Some(git_dir))?),
+        None => {
+            let dir = PathBuf::from(args.remove(0));
+            if Hg::detect(&dir) { Box::new(Hg::open(&dir)?) } else { Box::new(Repo::open(dir, None)?) }
+        }
+    };
+    let a_ref = &args[0];
+    let b_ref = &args[1];
+
+    // 1) merge-base
+    let base_ref = match explicit_base {
+        Some(base) => source.resolve(&base).map_err(|_| anyhow::anyhow!("--base {base} is not a commit"))?,
+        None => source.merge_base(a_ref, b_ref, deepen)?,
+    };
+
+    let mut base_entries = source.entries(&base_ref)?;
+    let (mut a_entries, mut b_entries) = (source.entries(a_ref)?, source.entries(b_ref)?);
+
+    // merge policy rules and extensions from the repo's .sinkit.toml (if
+    // any), and the profiles in subdirectories that have one on A
+    let work_tree = source.work_tree().map(Path::to_path_buf);
+    let read_policy = |path: &str| -> anyhow::Result<Policy> {
+        match &work_tree {
+            Some(dir) => Policy::load(&dir.join(path)),
+            None => match source.show(a_ref, path) {
+                Ok(text) => Policy::parse(&text).with_context(|| format!("reading {path}")),
+                Err(_) => Ok(Policy::default()),
+            },
+        }
+    };
+    let mut policy = read_policy(".sinkit.toml")?;
+    for path in a_entries.keys().filter(|p| p.ends_with("/.sinkit.toml")) {
+        policy = policy.with_profile(&path[..path.len() - "/.sinkit.toml".len()], read_policy(path)?);
+    }
+    let from = match &work_tree {
+        Some(dir) => dir.join(".sinkit.toml").display().to_string(),
+        None => format!("the .sinkit.toml on {a_ref}"),
+    };
+    let policy = trusted(policy, trust_repo_config, &from).with_grammars(work_tree.as_deref())?;
+
+    // workspace packages, for the report; with --package, the run only
+    // covers theirs
+    let workspaces = Workspaces::load(&*source, a_ref, &a_entries);
+    let selected = package_names
+        .iter()
+        .map(|name| match workspaces.find(name) {
+            Some(package) => Ok(package.dir.clone()),
+            None if workspaces.is_empty() => anyhow::bail!("--package {name}: no workspaces found on {a_ref}"),
+            None => anyhow::bail!("--package {name} is not a workspace package"),
+        })
+        .collect::<anyhow::Result<Vec<String>>>()?;
+    if !selected.is_empty() {
+        let under = |path: &String, dir: &String| path.strip_prefix(dir.as_str()).is_some_and(|r| r.starts_with('/'));
+        for entries in [&mut base_entries, &mut a_entries, &mut b_entries] {
+            entries.retain(|path, _| selected.iter().any(|dir| under(path, dir)));
+        }
+    }
+
+    // 2) list files with a known extension (.ts/.tsx + configured) and
+    //    submodules at base
+    let files = base_entries
+        .iter()
+        .filter(|(_, (mode, _))| mode != GITLINK)
+        .filter_map(|(f, _)| Some((f.clone(), policy.adapter_for(f)?)))
+        .collect::<Vec<_>>();
+    let submodules = base_entries
+        .iter()
+        .filter(|(_, (mode, _))| mode == GITLINK)
+        .map(|(path, (_, oid))| (path.clone(), oid.clone()))
+        .collect::<Vec<_>>();
+
+    // prep output dir; with --apply, results go into the work tree (minus
+    // the paths a sparse checkout leaves out)
+    let out_root = PathBuf::from(".codesync");
+    fs::create_dir_all(&out_root)?;
+    let apply_root = match (apply, &work_tree) {
+        (false, _) => None,
+        (true, _) if commit_mode => anyhow::bail!("--apply and commit-merge don't go together"),
+        (true, Some(dir)) => Some(dir.clone()),
+        (true, None) => anyhow::bail!("--apply needs a work tree"),
+    };
+    if let Some(dir) = &export_dir {
+        fs::create_dir_all(dir)?;
+    }
+    let sparse = match (&apply_root, source.as_git()) {
+        (Some(_), Some(repo)) => skip_worktree(repo)?,
+        _ => BTreeSet::new(),
+    };
+
+    // files checked out in another encoding than their blobs' (UTF-8), and
+    // files .gitattributes mark generated (others are recognized by their
+    // header)
+    let Attributes { encodings: declared_encodings, generated: marked_generated } = match (source.as_git(), &work_tree) {
+        (Some(repo), Some(_)) => attributes(repo, files.iter().map(|(f, _)| f.as_str()))?,
+        _ => Attributes::default(),
+    };
+    // whose version a take-newer policy takes
+    let b_newer = matches!((source.commit_time(a_ref), source.commit_time(b_ref)), (Ok(a), Ok(b)) if b > a);
+
+    // the commands the run executes through `sh`, in the work tree if there
+    // is one: the formatter from the command line, the resolver from a
+    // trusted .sinkit.toml (see above), never anything a branch brings
+    let formatter = format_cmd.as_deref().map(|cmd| match &work_tree {
+        Some(dir) => CommandFormatter::new(cmd).in_dir(dir),
+        None => CommandFormatter::new(cmd),
+    });
+    let resolver = policy.resolve_cmd().map(|cmd| match &work_tree {
+        Some(dir) => CommandResolver::new(cmd).in_dir(dir),
+        None => CommandResolver::new(cmd),
+    });
+    // what the resolver settled
+    let mut settled: Vec<(String, Conflict, Resolution)> = Vec::new();
+
+    // who owns what, to route conflicts
+    let owners = CodeOwners::load(&*source, a_ref);
+
+    // units a branch extracted into a file it added
+    let moves = find_moves(&*source, &policy, [&base_ref, a_ref, b_ref], [&base_entries, &a_entries, &b_entries]);
+
+    let mut autos = 0usize;
+    let mut conflicts = 0usize;
+    let mut warned = 0usize;
+    let mut suppressed = 0usize;
+    let mut resolved = 0usize;
+    let mut skipped = 0usize;
+    let mut deleted = 0usize;
+    let mut unwritten = 0usize;
+    let mut bumped = 0usize;
+    let mut stats = MergeStats::default();
+    let mut pending: Vec<(String, Conflict)> = Vec::new();
+    let mut merged: Vec<Merged> = Vec::new();
+    let mut manifest: Vec<manifest::Entry> = Vec::new();
+    let mut sites: Vec<(String, ConflictSite)> = Vec::new();
+    // with --fail-fast, the first path with a conflict
+    let mut stopped_at: Option<String> = None;
+
+    // submodules: commit-level merge of the gitlink, never their contents
+    for (path, base_oid) in submodules {
+        let gitlink = |entries: &Entries| match entries.get(&path) {
+            Some((mode, oid)) if mode == GITLINK => Some(oid.clone()),
+            _ => None,
+        };
+        let (Some(a_oid), Some(b_oid)) = (gitlink(&a_entries), gitlink(&b_entries)) else {
+            skipped += 1;
+            porcelain_line("skipped", &path, 0, "submodule");
+            manifest.push(manifest::Entry {
+                path: path.clone(),
+                output: None,
+                status: "skipped",
+                conflicts: 0,
+                engine: "submodule",
+                hashes: manifest::Hashes {
+                    base: Some(base_oid),
+                    a: gitlink(&a_entries),
+                    b: gitlink(&b_entries),
+                    merged: None,
+                },
+            });
+            continue;
+        };
+        // ancestry needs the submodule's objects, i.e. a checked-out submodule
+        let sub = work_tree.as_ref().map(|dir| Repo::at(dir.join(&path)));
+        let is_ancestor = |x: &str, y: &str| {
+            sub.as_ref().is_some_and(|sub| git(sub, &["merge-base", "--is-ancestor", x, y]).is_ok())
+        };
+        let opts = MergeOptions::new(ParseOptions::default())
+            .with_path(&path)
+            .with_suppressed(policy.suppressed())
+            .with_resolutions(resolutions.clone());
+        let res = merge_submodule(&base_oid, &a_oid, &b_oid, is_ancestor, &opts);
+        let status = if res.conflicts.is_empty() { "merged" } else { "conflict" };
+        porcelain_line(status, &path, res.conflicts.len(), "submodule");
+        manifest.push(manifest::Entry {
+            path: path.clone(),
+            output: None,
+            status,
+            conflicts: res.conflicts.len(),
+            engine: "submodule",
+            hashes: manifest::Hashes {
+                base: Some(base_oid.clone()),
+                a: Some(a_oid.clone()),
+                b: Some(b_oid.clone()),
+                merged: res.conflicts.is_empty().then(|| res.merged_code.clone()),
+            },
+        });
+        if !res.warnings.is_empty() {
+            warned += 1;
+        }
+        suppressed += res.suppressed.len();
+        resolved += res.resolved.len();
+        stats += res.stats;
+        if res.conflicts.is_empty() {
+            let oid = &res.merged_code;
+            if oid != &base_oid {
+                bumped += 1;
+            }
+            if let Some(root) = &apply_root {
+                let cacheinfo = format!("{GITLINK},{oid},{path}");
+                git(&Repo::at(root.clone()), &["update-index", "--cacheinfo", &cacheinfo])?;
+            }
+            say!("{} {} (submodule at {})", paint(GREEN, "⊙"), path, &oid[..oid.len().min(12)]);
+            merged.push(Merged {
+                path: path.clone(),
+                mode: GITLINK.into(),
+                content: Some(oid.clone().into_bytes()),
+                conflicted: false,
+            });
+            for w in &res.warnings {
+                say!("  ℹ {}", w);
+            }
+        } else {
+            conflicts += 1;
+            say!("{} {} (submodule, {} conflicts)", paint(YELLOW, "⚠"), path, res.conflicts.len());
+            print_conflicts(&res.conflicts);
+            // no file to point into: just both commits
+            let found: Vec<ConflictSite> = res
+                .conflicts
+                .iter()
+                .map(|c| ConflictSite {
+                    conflict: c.clone(),
+                    bytes: None,
+                    lines: None,
+                    a: Some(a_oid.clone()),
+                    b: Some(b_oid.clone()),
+                })
+                .collect();
+            write_sidecar(&out_root.join(format!("{}.conflicts.json", path.replace('/', "__"))), &path, &owners, &found)?;
+            sites.extend(found.into_iter().map(|site| (path.clone(), site)));
+            merged.push(Merged { path: path.clone(), mode: GITLINK.into(), content: Some(a_oid.into_bytes()), conflicted: true });
+            pending.extend(res.conflicts.into_iter().map(|c| (path.clone(), c)));
+            if fail_fast {
+                stopped_at = Some(path);
+                break;
+            }
+        }
+    }
+
+    for (file, adapter) in files {
+        if stopped_at.is_some() {
+            break;
+        }
+        // merge (.tsx needs the TSX grammar for JSX-aware reconciles; text,
+        // OpenAPI and key-value files never reach the parser)
+        let lang = match adapter {
+            Adapter::Ast(lang) => lang,
+            Adapter::Text | Adapter::OpenApi | Adapter::Properties => AstLanguage::TypeScript,
+        };
+        let opts = ParseOptions { policy: policy.for_path(&file), ..ParseOptions::for_path(&file) };
+        let opts = MergeOptions::new(opts)
+            .with_path(&file)
+            .with_suppressed(policy.suppressed())
+            .with_resolutions(resolutions.clone())
+            .with_pruned_imports(prune_imports)
+            .with_kept_indentation(keep_indentation)
+            .with_insert_anchor(insert_anchor)
+            .with_min_confidence(min_confidence)
+            .with_moved(moves.iter().filter(|m| m.from == file).flat_map(Move::unit_names))
+            .with_generated(marked_generated.contains(&file))
+            .with_b_newer(b_newer)
+            .with_max_parse_bytes(policy.max_parse_bytes())
+            .with_memory_limit(policy.memory_limit());
+        let opts = match &formatter {
+            Some(formatter) => opts.with_formatter(Box::new(formatter.clone())),
+            None => opts,
+        };
+        let mut opts = match &ast_cache {
+            Some(dir) => opts.with_ast_cache(Box::new(DirAstCache::new(dir))),
+            None => opts,
+        };
+
+        // read file content from each ref (None: deleted on that side); a
+        // file both deleted, or one deleted and the other left alone, is
+        // skipped, and deleted against modified is a file-level conflict
+        let engine = if matches!(adapter, Adapter::Ast(_)) { "ast" } else { "text" };
+        // decoded to UTF-8 (blobs with a working-tree-encoding are already)
+        let declared = declared_encodings.get(&file).and_then(|label| Encoding::for_label(label));
+        let read = |rev: &str| -> anyhow::Result<(String, Option<Encoding>)> {
+            let bytes = source.show_bytes(rev, &file)?;
+            Ok(match declared {
+                Some(_) => (String::from_utf8_lossy(&bytes).into_owned(), None),
+                None => {
+                    let (text, encoding) = decode_text(&bytes);
+                    (text, Some(encoding))
+                }
+            })
+        };
+        let show = |rev: &str, entries: &Entries| entries.contains_key(&file).then(|| read(rev)).transpose();
+        let versions = (read(&base_ref), show(a_ref, &a_entries), show(b_ref, &b_entries));
+        let encoding_of = |v: &anyhow::Result<Option<(String, Option<Encoding>)>>| {
+            v.as_ref().ok().and_then(|v| v.as_ref()?.1)
+        };
+        let base_encoding = versions.0.as_ref().ok().and_then(|v| v.1).unwrap_or_default();
+        let (a_encoding, b_encoding) = (encoding_of(&versions.1), encoding_of(&versions.2));
+        let encoding = declared.unwrap_or_else(|| {
+            Encoding::merged(base_encoding, a_encoding.unwrap_or(base_encoding), b_encoding.unwrap_or(base_encoding))
+        });
+        let text = |(text, _): (String, Option<Encoding>)| text;
+        let versions = (versions.0.map(text), versions.1.map(|v| v.map(text)), versions.2.map(|v| v.map(text)));
+        let deletion = match &versions {
+            (Ok(base), Ok(a), Ok(b)) if a.is_none() || b.is_none() => {
+                Some(merge_deleted(base, a.as_deref(), b.as_deref(), &opts))
+            }
+            _ => None,
+        };
+        let (base_code, a_code, b_code) = match versions {
+            (Ok(base), Ok(a), Ok(b)) if deletion.as_ref().is_some_and(|res| res.deleted) => {
+                let res = deletion.expect("checked above");
+                deleted += 1;
+                resolved += res.resolved.len();
+                let written = !commit_mode && !sparse.contains(&file);
+                let shown = match &apply_root {
+                    Some(root) if written => {
+                        remove_output(&root.join(&file))?;
+                        root.join(&file).display().to_string()
+                    }
+                    _ => file.clone(),
+                };
+                porcelain_line("deleted", &file, 0, engine);
+                manifest.push(manifest::Entry {
+                    path: file.clone(),
+                    output: None,
+                    status: "deleted",
+                    conflicts: 0,
+                    engine,
+                    hashes: manifest::Hashes::of([Some(&base), a.as_deref(), b.as_deref(), None]),
+                });
+                let mode = base_entries.get(&file).map_or_else(|| "100644".to_string(), |(mode, _)| mode.clone());
+                merged.push(Merged { path: file.clone(), mode, content: None, conflicted: false });
+                say!("{} {} (deleted)", paint(GREEN, "✓"), shown);
+                for w in &res.warnings {
+                    say!("  ℹ {}", w);
+                }
+                continue;
+            }
+            (Ok(base), Ok(a), Ok(b)) => (base, a, b),
+            (base, a, b) => {
+                skipped += 1;
+                porcelain_line("skipped", &file, 0, engine);
+                let (base, a, b) = (base.ok(), a.ok().flatten(), b.ok().flatten());
+                manifest.push(manifest::Entry {
+                    path: file.clone(),
+                    output: None,
+                    status: "skipped",
+                    conflicts: 0,
+                    engine,
+                    hashes: manifest::Hashes::of([base.as_deref(), a.as_deref(), b.as_deref(), None]),
+                });
+                continue;
+            }
+        };
+        let is_link = |entries: &Entries| entries.get(&file).is_some_and(|(m, _)| m == SYMLINK);
+        let links = [is_link(&base_entries), is_link(&a_entries), is_link(&b_entries)];
+        let (mut res, link) = match (deletion, &a_code, &b_code) {
+            (Some(res), _, _) => (res, false),
+            (None, Some(a_code), Some(b_code)) if links.contains(&true) => merge_link(
+                [(links[0], base_code.as_str()), (links[1], a_code.as_str()), (links[2], b_code.as_str())],
+                &opts,
+            ),
+            (None, Some(a_code), Some(b_code)) => (three_way_merge(&base_code, a_code, b_code, lang, &opts)?, false),
+            (None, _, _) => unreachable!("deletions are merged above"),
+        };
+        // conflicts in the contents the resolver command settles: merged
+        // again with its answers
+        if let Some(resolver) = &resolver
+            && !res.conflicts.is_empty()
+            && !res.deleted
+            && !links.contains(&true)
+            && let (Some(a_code), Some(b_code)) = (&a_code, &b_code)
+        {
+            let found = locate_conflicts(&res.conflicts, &res.merged_code, Some(a_code), Some(b_code), lang, &opts.parse);
+            let (mut notes, mut answered) = (Vec::new(), false);
+            for bundle in conflict_bundles(&file, &found, Some(&base_code), &res.merged_code, lang, &opts.parse) {
+                let about = bundle.conflict.unit.clone().unwrap_or_else(|| "the file".to_string());
+                match resolver.resolve(&bundle) {
+                    Ok(Some(resolution)) => {
+                        notes.push(format!("The resolver command settled {about} [{}]", bundle.conflict.id));
+                        opts.resolutions.insert(bundle.conflict.id.clone(), resolution.clone());
+                        answered = true;
+                        settled.push((file.clone(), bundle.conflict, resolution));
+                    }
+                    Ok(None) => {}
+                    Err(e) => notes.push(format!("The resolver command failed on {about} [{}]: {e:#}", bundle.conflict.id)),
+                }
+            }
+            if answered {
+                res = three_way_merge(&base_code, a_code, b_code, lang, &opts)?;
+            }
+            res.warnings.extend(notes);
+        }
+        if let (Some(a), Some(b)) = (a_encoding, b_encoding)
+            && a != base_encoding
+            && b != base_encoding
+            && a != b
+        {
+            res.warnings.push(format!("Both branches changed the encoding ({a:?}, {b:?}); kept A's"));
+        }
+        // back to the file's encoding (link targets are paths, not text)
+        let encoded = match encoding.encode(&res.merged_code) {
+            Ok(bytes) if !link => bytes,
+            Ok(_) => res.merged_code.clone().into_bytes(),
+            Err(e) => {
+                res.warnings.push(format!("Kept the merge in UTF-8, not {encoding:?}: {e}"));
+                res.merged_code.clone().into_bytes()
+            }
+        };
+
+        // ensure target path exists
+        let out_path = out_root.join(file.replace('/', "__"));
+        let target = match &apply_root {
+            Some(root) => root.join(&file),
+            None => out_path.clone(),
+        };
+        let written = !commit_mode && !sparse.contains(&file);
+        let shown = if commit_mode {
+            file.clone()
+        } else if !written {
+            unwritten += 1;
+            format!("{file} (outside the sparse checkout; merged, not written)")
+        } else {
+            write_output(&target, &encoded, link)?;
+            target.display().to_string()
+        };
+        for e in &res.syntax_errors {
+            eprintln!("  syntax error in {}: {}", file, e);
+        }
+        let engine = if links.contains(&true) { "symlink" } else if res.textual { "text" } else { engine };
+        let status = if res.conflicts.is_empty() { "merged" } else { "conflict" };
+        porcelain_line(status, &file, res.conflicts.len(), engine);
+        manifest.push(manifest::Entry {
+            path: file.clone(),
+            output: written.then(|| target.display().to_string()),
+            status,
+            conflicts: res.conflicts.len(),
+            engine,
+            hashes: manifest::Hashes::of([Some(&base_code), a_code.as_deref(), b_code.as_deref(), Some(&res.merged_code)]),
+        });
+        let mode = match a_entries.get(&file).or_else(|| b_entries.get(&file)) {
+            _ if link => SYMLINK.to_string(),
+            Some((mode, _)) => mode.clone(),
+            None => "100644".to_string(),
+        };
+        merged.push(Merged {
+            path: file.clone(),
+            mode,
+            content: Some(if declared.is_some() { res.merged_code.clone().into_bytes() } else { encoded }),
+            conflicted: !res.conflicts.is_empty(),
+        });
+        if !res.warnings.is_empty() {
+            warned += 1;
+        }
+        suppressed += res.suppressed.len();
+        resolved += res.resolved.len();
+        stats += res.stats;
+
+        if res.conflicts.is_empty() {
+            autos += 1;
+            say!("{} {}", paint(GREEN, "✓"), shown);
+            for w in &res.warnings {
+                say!("  ℹ {}", w);
+            }
+        } else {
+            conflicts += 1;
+            say!("{} {} ({} conflicts)", paint(YELLOW, "⚠"), shown, res.conflicts.len());
+            print_conflicts(&res.conflicts);
+            for w in &res.warnings {
+                say!("  ℹ {}", w);
+            }
+            // where each conflict is, with both sides, for editors
+            let (a_code, b_code) = (a_code.as_deref(), b_code.as_deref());
+            let found = locate_conflicts(&res.conflicts, &res.merged_code, a_code, b_code, lang, &opts.parse);
+            if let Some(dir) = &export_dir {
+                write_bundles(dir, &conflict_bundles(&file, &found, Some(&base_code), &res.merged_code, lang, &opts.parse))?;
+            }
+            let mut cpath = out_path.clone();
+            cpath.set_extension("conflicts.json");
+            write_sidecar(&cpath, &file, &owners, &found)?;
+            sites.extend(found.into_iter().map(|site| (file.clone(), site)));
+            pending.extend(res.conflicts.into_iter().map(|c| (file.clone(), c)));
+            if fail_fast {
+                stopped_at = Some(file);
+            }
+        }
+    }
+
+    // files units were moved into get the other branch's edits of them (as
+    // added files, they're otherwise the adding branch's alone)
+    let targets: BTreeSet<&String> = moves.iter().map(|m| &m.to).collect();
+    for to in targets {
+        let Some(Adapter::Ast(lang)) = policy.adapter_for(to) else { continue };
+        if stopped_at.is_some() {
+            break;
+        }
+        let opts = MergeOptions::new(ParseOptions { policy: policy.for_path(to), ..ParseOptions::for_path(to) })
+            .with_path(to)
+            .with_suppressed(policy.suppressed())
+            .with_resolutions(resolutions.clone());
+        let mut res = MergeOutcome::default();
+        // (the file so far, the branch that added it)
+        let mut carried: Option<(String, &String)> = None;
+        for m in moves.iter().filter(|m| &m.to == to) {
+            let (mover, editor) = if m.by_a { (a_ref, b_ref) } else { (b_ref, a_ref) };
+            let code = match carried.take() {
+                Some((code, _)) => code,
+                None => source.show(mover, to)?,
+            };
+            let (base, edited) = (source.show(&base_ref, &m.from)?, source.show(editor, &m.from));
+            let Ok(edited) = edited else {
+                // the other branch deleted the file too
+                carried = Some((code, mover));
+                continue;
+            };
+            let moved = carry_moved_edits(&base, &edited, &code, &m.units, lang, &opts)?;
+            carried = Some((moved.merged_code, mover));
+            res.warnings.extend(moved.warnings.into_iter().map(|w| format!("{w} (in {})", m.from)));
+            res.conflicts.extend(moved.conflicts);
+            res.suppressed.extend(moved.suppressed);
+            res.resolved.extend(moved.resolved);
+            res.auto_resolutions.extend(moved.auto_resolutions);
+            res.stats += moved.stats;
+        }
+        let Some((code, mover)) = carried else { continue };
+        if res.warnings.is_empty() && res.conflicts.is_empty() {
+            continue;
+        }
+        res.merged_code = code;
+        let out_path = out_root.join(to.replace('/', "__"));
+        let target = apply_root.as_ref().map_or_else(|| out_path.clone(), |root| root.join(to));
+        let written = !commit_mode && !sparse.contains(to);
+        if written {
+            write_output(&target, res.merged_code.as_bytes(), false)?;
+        }
+        let status = if res.conflicts.is_empty() { "merged" } else { "conflict" };
+        porcelain_line(status, to, res.conflicts.len(), "ast");
+        manifest.push(manifest::Entry {
+            path: to.clone(),
+            output: written.then(|| target.display().to_string()),
+            status,
+            conflicts: res.conflicts.len(),
+            engine: "ast",
+            hashes: manifest::Hashes::of([None, None, None, Some(&res.merged_code)]),
+        });
+        let mode = [&a_entries, &b_entries].iter().find_map(|e| e.get(to)).map_or("100644".into(), |(m, _)| m.clone());
+        let conflicted = !res.conflicts.is_empty();
+        merged.push(Merged { path: to.clone(), mode, content: Some(res.merged_code.clone().into_bytes()), conflicted });
+        warned += 1;
+        suppressed += res.suppressed.len();
+        resolved += res.resolved.len();
+        stats += res.stats;
+        let shown = if written { target.display().to_string() } else { to.clone() };
+        if res.conflicts.is_empty() {
+            autos += 1;
+            say!("{} {} (moved units, with {}'s edits)", paint(GREEN, "✓"), shown, if mover == a_ref { b_ref } else { a_ref });
+        } else {
+            conflicts += 1;
+            say!("{} {} (moved units, {} conflicts)", paint(YELLOW, "⚠"), shown, res.conflicts.len());
+            print_conflicts(&res.conflicts);
+            let found = locate_conflicts(&res.conflicts, &res.merged_code, None, None, lang, &opts.parse);
+            if let Some(dir) = &export_dir {
+                write_bundles(dir, &conflict_bundles(to, &found, None, &res.merged_code, lang, &opts.parse))?;
+            }
+            write_sidecar(&out_path.with_extension("conflicts.json"), to, &owners, &found)?;
+            sites.extend(found.into_iter().map(|site| (to.clone(), site)));
+            pending.extend(res.conflicts.into_iter().map(|c| (to.clone(), c)));
+            if fail_fast {
+                stopped_at = Some(to.clone());
+            }
+        }
+        for w in &res.warnings {
+            say!("  ℹ {}", w);
+        }
+    }
+
+    // binary files something changed (the rest are of no concern): taken
+    // from the side that changed them, byte for byte, never merged
+    let binaries = base_entries
+        .iter()
+        .filter(|(f, (mode, _))| mode != GITLINK && mode != SYMLINK && policy.adapter_for(f).is_none())
+        .filter(|(f, (_, oid))| [&a_entries, &b_entries].iter().any(|e| e.get(*f).map(|(_, o)| o) != Some(oid)));
+    for (file, _) in binaries {
+        if stopped_at.is_some() {
+            break;
+        }
+        let show = |rev: &str, entries: &Entries| entries.contains_key(file).then(|| source.show_bytes(rev, file)).transpose();
+        let (Ok(base_bytes), Ok(a_bytes), Ok(b_bytes)) =
+            (source.show_bytes(&base_ref, file), show(a_ref, &a_entries), show(b_ref, &b_entries))
+        else {
+            continue;
+        };
+        if ![Some(&base_bytes), a_bytes.as_ref(), b_bytes.as_ref()].into_iter().flatten().any(|v| is_binary(v)) {
+            continue;
+        }
+        let opts = MergeOptions::new(ParseOptions::default())
+            .with_path(file)
+            .with_suppressed(policy.suppressed())
+            .with_resolutions(resolutions.clone());
+        let (res, bytes) = merge_binary(&base_bytes, a_bytes.as_deref(), b_bytes.as_deref(), &opts);
+        let oid = |entries: &Entries| entries.get(file).map(|(_, oid)| oid.clone());
+        let (base_oid, a_oid, b_oid) = (oid(&base_entries), oid(&a_entries), oid(&b_entries));
+        suppressed += res.suppressed.len();
+        resolved += res.resolved.len();
+        let Some(bytes) = bytes else {
+            deleted += 1;
+            let written = !commit_mode && !sparse.contains(file);
+            let shown = match &apply_root {
+                Some(root) if written => {
+                    remove_output(&root.join(file))?;
+                    root.join(file).display().to_string()
+                }
+                _ => file.clone(),
+            };
+            porcelain_line("deleted", file, 0, "binary");
+            manifest.push(manifest::Entry {
+                path: file.clone(),
+                output: None,
+                status: "deleted",
+                conflicts: 0,
+                engine: "binary",
+                hashes: manifest::Hashes { base: base_oid, a: a_oid, b: b_oid, merged: None },
+            });
+            let mode = base_entries.get(file).map_or_else(|| "100644".to_string(), |(mode, _)| mode.clone());
+            merged.push(Merged { path: file.clone(), mode, content: None, conflicted: false });
+            say!("{} {} (binary, deleted)", paint(GREEN, "✓"), shown);
+            continue;
+        };
+        // the side the bytes came from
+        let side = if a_bytes.as_deref() == Some(bytes) { &a_entries } else { &b_entries };
+
+        let out_path = out_root.join(file.replace('/', "__"));
+        let target = match &apply_root {
+            Some(root) => root.join(file),
+            None => out_path.clone(),
+        };
+        let written = !commit_mode && !sparse.contains(file);
+        let shown = if commit_mode {
+            file.clone()
+        } else if !written {
+            unwritten += 1;
+            format!("{file} (outside the sparse checkout; merged, not written)")
+        } else {
+            write_output(&target, bytes, false)?;
+            target.display().to_string()
+        };
+        let status = if res.conflicts.is_empty() { "merged" } else { "conflict" };
+        porcelain_line(status, file, res.conflicts.len(), "binary");
+        manifest.push(manifest::Entry {
+            path: file.clone(),
+            output: written.then(|| target.display().to_string()),
+            status,
+            conflicts: res.conflicts.len(),
+            engine: "binary",
+            hashes: manifest::Hashes { base: base_oid, a: a_oid.clone(), b: b_oid.clone(), merged: oid(side) },
+        });
+        merged.push(Merged {
+            path: file.clone(),
+            mode: side.get(file).map_or_else(|| "100644".to_string(), |(mode, _)| mode.clone()),
+            content: Some(bytes.to_vec()),
+            conflicted: !res.conflicts.is_empty(),
+        });
+        if !res.warnings.is_empty() {
+            warned += 1;
+        }
+        if res.conflicts.is_empty() {
+            autos += 1;
+            say!("{} {} (binary)", paint(GREEN, "✓"), shown);
+        } else {
+            conflicts += 1;
+            say!("{} {} (binary, {} conflicts)", paint(YELLOW, "⚠"), shown, res.conflicts.len());
+            print_conflicts(&res.conflicts);
+            // no text to point into: just both blobs
+            let found: Vec<ConflictSite> = res
+                .conflicts
+                .iter()
+                .map(|c| ConflictSite { conflict: c.clone(), bytes: None, lines: None, a: a_oid.clone(), b: b_oid.clone() })
+                .collect();
+            let mut cpath = out_path.clone();
+            cpath.set_extension("conflicts.json");
+            write_sidecar(&cpath, file, &owners, &found)?;
+            sites.extend(found.into_iter().map(|site| (file.clone(), site)));
+            pending.extend(res.conflicts.into_iter().map(|c| (file.clone(), c)));
+            if fail_fast {
+                stopped_at = Some(file.clone());
+            }
+        }
+        for w in &res.warnings {
+            say!("  ℹ {}", w);
+        }
+    }
+
+    // the paths none of the above took up: files a branch added, and text
+    // files no adapter reads. Where only B changed one, B's side; where both
+    // did, their merge, or a conflict (commit-merge leaves them to git's
+    // trivial merge, and A's versions are what --apply starts from)
+    let seen: BTreeSet<String> = manifest.iter().map(|e| e.path.clone()).collect();
+    let rest: BTreeSet<&String> = [&base_entries, &a_entries, &b_entries]
+        .into_iter()
+        .flat_map(|entries| entries.keys())
+        .filter(|path| !commit_mode && !seen.contains(*path))
+        .collect();
+    for file in rest {
+        if stopped_at.is_some() {
+            break;
+        }
+        let (base, a, b) = (base_entries.get(file), a_entries.get(file), b_entries.get(file));
+        if a == b || b == base || [base, a, b].into_iter().flatten().any(|(mode, _)| mode == GITLINK) {
+            continue;
+        }
+        let show = |rev: &str, entry: Option<&(String, String)>| entry.map(|_| source.show_bytes(rev, file)).transpose();
+        let (base_bytes, a_bytes, b_bytes) = (show(&base_ref, base)?, show(a_ref, a)?, show(b_ref, b)?);
+        let opts = MergeOptions::new(ParseOptions { policy: policy.for_path(file), ..ParseOptions::for_path(file) })
+            .with_path(file)
+            .with_suppressed(policy.suppressed())
+            .with_resolutions(resolutions.clone());
+        // regular files of text, merged by line (or by unit, with an adapter)
+        let text = |bytes: &Option<Vec<u8>>| match bytes {
+            Some(bytes) if is_binary(bytes) => None,
+            Some(bytes) => String::from_utf8(bytes.clone()).ok().map(Some),
+            None => Some(None),
+        };
+        let regular = [base, a, b].into_iter().flatten().all(|(mode, _)| mode == "100644" || mode == "100755");
+        let (mut res, bytes, engine) = match (text(&base_bytes), text(&a_bytes), text(&b_bytes)) {
+            _ if a == base => {
+                let res = MergeOutcome { textual: true, deleted: b.is_none(), ..MergeOutcome::default() };
+                (res, b_bytes.clone(), "text")
+            }
+            (Some(base_code), Some(a_code), Some(b_code)) if regular => {
+                let base_code = base_code.unwrap_or_default();
+                let res = match (&a_code, &b_code) {
+                    (Some(a_code), Some(b_code)) => {
+                        let lang = match policy.adapter_for(file) {
+                            Some(Adapter::Ast(lang)) => lang,
+                            _ => AstLanguage::TypeScript,
+                        };
+                        let mut opts = opts;
+                        opts.parse.policy.textual |= policy.adapter_for(file).is_none();
+                        three_way_merge(&base_code, a_code, b_code, lang, &opts)?
+                    }
+                    _ => merge_deleted(&base_code, a_code.as_deref(), b_code.as_deref(), &opts),
+                };
+                let bytes = (!res.deleted).then(|| res.merged_code.clone().into_bytes());
+                let engine = if res.textual { "text" } else { "ast" };
+                (res, bytes, engine)
+            }
+            _ => {
+                let base_bytes = base_bytes.as_deref().unwrap_or_default();
+                let (res, bytes) = merge_binary(base_bytes, a_bytes.as_deref(), b_bytes.as_deref(), &opts);
+                (res, bytes.map(<[u8]>::to_vec), "binary")
+            }
+        };
+        suppressed += res.suppressed.len();
+        resolved += res.resolved.len();
+        stats += res.stats;
+        let oid = |entry: Option<&(String, String)>| entry.map(|(_, oid)| oid.clone());
+        let Some(bytes) = bytes else {
+            deleted += 1;
+            let written = !sparse.contains(file);
+            let shown = match &apply_root {
+                Some(root) if written => {
+                    remove_output(&root.join(file))?;
+                    root.join(file).display().to_string()
+                }
+                _ => file.clone(),
+            };
+            porcelain_line("deleted", file, 0, engine);
+            manifest.push(manifest::Entry {
+                path: file.clone(),
+                output: None,
+                status: "deleted",
+                conflicts: 0,
+                engine,
+                hashes: manifest::Hashes { base: oid(base), a: oid(a), b: oid(b), merged: None },
+            });
+            let mode = base.map_or_else(|| "100644".to_string(), |(mode, _)| mode.clone());
+            merged.push(Merged { path: file.clone(), mode, content: None, conflicted: false });
+            say!("{} {} (deleted)", paint(GREEN, "✓"), shown);
+            continue;
+        };
+        // B's mode where the merge is B's side
+        let (mode, link) = match if a == base { b } else { a.or(b) } {
+            Some((mode, _)) => (mode.clone(), mode == SYMLINK),
+            None => ("100644".to_string(), false),
+        };
+        let out_path = out_root.join(file.replace('/', "__"));
+        let target = match &apply_root {
+            Some(root) => root.join(file),
+            None => out_path.clone(),
+        };
+        let written = !sparse.contains(file);
+        let shown = if !written {
+            unwritten += 1;
+            format!("{file} (outside the sparse checkout; merged, not written)")
+        } else {
+            write_output(&target, &bytes, link)?;
+            target.display().to_string()
+        };
+        let status = if res.conflicts.is_empty() { "merged" } else { "conflict" };
+        porcelain_line(status, file, res.conflicts.len(), engine);
+        let merged_oid = if a == base { oid(b) } else { None };
+        manifest.push(manifest::Entry {
+            path: file.clone(),
+            output: written.then(|| target.display().to_string()),
+            status,
+            conflicts: res.conflicts.len(),
+            engine,
+            hashes: manifest::Hashes { base: oid(base), a: oid(a), b: oid(b), merged: merged_oid },
+        });
+        merged.push(Merged { path: file.clone(), mode, content: Some(bytes), conflicted: !res.conflicts.is_empty() });
+        if !res.warnings.is_empty() {
+            warned += 1;
+        }
+        if res.conflicts.is_empty() {
+            autos += 1;
+            say!("{} {}", paint(GREEN, "✓"), shown);
+        } else {
+            conflicts += 1;
+            say!("{} {} ({} conflicts)", paint(YELLOW, "⚠"), shown, res.conflicts.len());
+            print_conflicts(&res.conflicts);
+            let found: Vec<ConflictSite> = res
+                .conflicts
+                .iter()
+                .map(|c| ConflictSite { conflict: c.clone(), bytes: None, lines: None, a: oid(a), b: oid(b) })
+                .collect();
+            write_sidecar(&out_path.with_extension("conflicts.json"), file, &owners, &found)?;
+            sites.extend(found.into_iter().map(|site| (file.clone(), site)));
+            pending.extend(res.conflicts.drain(..).map(|c| (file.clone(), c)));
+            if fail_fast {
+                stopped_at = Some(file.clone());
+            }
+        }
+        for w in &res.warnings {
+            say!("  ℹ {}", w);
+        }
+    }
+
+    // the candidate is out; the rest of the report isn't needed, but the
+    // manifest of the paths merged so far is
+    if let Some(path) = stopped_at {
+        manifest::write(&out_root.join("manifest.json"), &manifest, &owners, &workspaces)?;
+        eprintln!("Stopped at the first conflict (--fail-fast), in {path}");
+        std::process::exit(1);
+    }
+
+    // what the resolver command decided, to re-run without it
+    if !settled.is_empty() {
+        let record = Resolutions::record(settled.iter().map(|(f, c, r)| (f.as_str(), c, r)));
+        fs::write(out_root.join("resolver.toml"), record)?;
+    }
+
+    // resolution skeleton for the conflicts left (never over the file just applied)
+    if !pending.is_empty() {
+        let skeleton_path = out_root.join("resolutions.toml");
+        let same = |p: &PathBuf| fs::canonicalize(p).ok() == fs::canonicalize(&skeleton_path).ok();
+        if resolutions_path.as_ref().is_some_and(same) {
+            eprintln!("note: {} conflicts remain unresolved in {}", pending.len(), skeleton_path.display());
+        } else {
+            let skeleton = Resolutions::skeleton(pending.iter().map(|(f, c)| (f.as_str(), c)));
+            fs::write(&skeleton_path, skeleton)?;
+            say!("\nEdit {} and re-run with --resolutions to apply it.", skeleton_path.display());
+        }
+    }
+
+    manifest::write(&out_root.join("manifest.json"), &manifest, &owners, &workspaces)?;
+
+    // summary
+    say!("\n--- Summary ---");
+    say!("Auto-merged files: {}", autos);
+    say!("With conflicts:    {}", conflicts);
+    say!("With warnings:     {}", warned);
+    say!("Suppressed:        {}", suppressed);
+    say!("Resolved:          {}", resolved);
+    say!("Submodules moved:  {}", bumped);
+    say!("Deleted:           {}", deleted);
+    say!("Skipped (missing): {}", skipped);
+    if stats != MergeStats::default() {
+        say!(
+            "Units taken:       A {}, B {}; {} changed alike, {} reconciled",
+            stats.from_a(),
+            stats.from_b(),
+            stats.same_changes,
+            stats.reconciled
+        );
+    }
+    if apply_root.is_some() {
+        say!("Outside sparse:    {}", unwritten);
+    }
+    print_hotspots(merged.iter().map(|m| (m.path.as_str(), m.conflicted)), &pending, &owners);
+    print_clusters(&sites);
+    print_packages(merged.iter().map(|m| (m.path.as_str(), m.conflicted)), &pending, &workspaces);
+    if let Some(format) = report {
+        print!("{}", report::render(format, &manifest, &sites, &owners)?);
+    }
+
+    if commit_mode {
+        let message = message.unwrap_or_else(|| format!("Merge {b_ref} into {a_ref}"));
+        say!();
+        let Some(repo) = source.as_git() else {
+            anyhow::bail!("commit-merge needs a git repository");
+        };
+        commit_merge(repo, work_tree.as_deref(), [&base_ref, a_ref, b_ref], &merged, &message)?;
+    }
+
+    // not a finished merge while conflicts remain (in the packages asked for,
+    // with --package: the others aren't merged)
+    if !pending.is_empty() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// `merge-file <base> <A> <B> [-o <output>] [--path <p>]`; the language comes
+/// from `--path`, else the output's (or A's) file name.
+fn merge_file(args: &[String], trust_repo_config: bool) -> anyhow::Result<()> {
+    let mut files = Vec::new();
+    let (mut output, mut path) = (None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next(),
+            "--path" => path = args.next(),
+            _ => files.push(arg),
+        }
+    }
+    let [base, a, b] = files[..] else {
+        eprintln!("Usage: sinkit merge-file <base> <A> <B> [-o <output>] [--path <repo path>] [--trust-repo-config]");
+        std::process::exit(1);
+    };
+    let ((base, base_encoding), (a, a_encoding), (b, b_encoding)) = (read_text(base)?, read_text(a)?, read_text(b)?);
+    let name = path.or(output).map_or(files[1].as_str(), String::as_str);
+    let res = merge_loose(&find_policy(trust_repo_config)?, name, &base, &a, &b)?;
+    for w in &res.warnings {
+        eprintln!("  ℹ {}", w);
+    }
+    if !res.conflicts.is_empty() {
+        for c in &res.conflicts {
+            eprintln!("- [{}] {}", c.id, c.message);
+        }
+        std::process::exit(1);
+    }
+    let merged = Encoding::merged(base_encoding, a_encoding, b_encoding).encode(&res.merged_code)?;
+    match output {
+        Some(out) => fs::write(out, merged)?,
+        None => std::io::stdout().write_all(&merged)?,
+    }
+    Ok(())
+}
+
+/// `driver <base> <A> <B> <path>`: a git merge driver (`%O %A %B %P`),
+/// merging into `<A>`.
+fn driver(args: &[String], trust_repo_config: bool) -> anyhow::Result<()> {
+    let [base, a, b, path] = args else {
+        eprintln!("Usage: sinkit driver %O %A %B %P [--trust-repo-config]");
+        std::process::exit(1);
+    };
+    let merge = || -> anyhow::Result<(MergeOutcome, Encoding)> {
+        let ((base, base_encoding), (a, a_encoding), (b, b_encoding)) = (read_text(base)?, read_text(a)?, read_text(b)?);
+        let res = merge_loose(&find_policy(trust_repo_config)?, path, &base, &a, &b)?;
+        Ok((res, Encoding::merged(base_encoding, a_encoding, b_encoding)))
+    };
+    match merge() {
+        Ok((res, encoding)) => {
+            for w in &res.warnings {
+                eprintln!("  ℹ {path}: {w}");
+            }
+            if res.conflicts.is_empty() {
+                fs::write(a, encoding.encode(&res.merged_code)?)?;
+                return Ok(());
+            }
+            for c in &res.conflicts {
+                eprintln!("- {path} [{}] {}", c.id, c.message);
+            }
+        }
+        Err(e) => eprintln!("sinkit: {path}: {e:#}"),
+    }
+    // git's own markers, for the user (or `sinkit resolve`) to work from
+    std::process::Command::new("git")
+        .args(["merge-file", "-q", "-L", "A", "-L", "base", "-L", "B"])
+        .args([a, base, b])
+        .status()
+        .context("running git merge-file")?;
+    std::process::exit(1);
+}
+
+/// A file's text, decoded from the encoding it's in.
+fn read_text(path: &str) -> anyhow::Result<(String, Encoding)> {
+    Ok(decode_text(&fs::read(path).with_context(|| format!("reading {path}"))?))
+}
+
+/// `resolve <file>...`: merge files left with conflict markers, in place.
+fn resolve(files: &[String], trust_repo_config: bool) -> anyhow::Result<()> {
+    if files.is_empty() {
+        eprintln!("Usage: sinkit resolve <file>... [--trust-repo-config]");
+        std::process::exit(1);
+    }
+    let policy = find_policy(trust_repo_config)?;
+    let mut remaining = 0usize;
+    for file in files {
+        let (text, encoding) = read_text(file)?;
+        let sides = match split_conflict_markers(&text) {
+            Ok(Some(sides)) => sides,
+            Ok(None) => {
+                say!("· {} (no conflict markers)", file);
+                continue;
+            }
+            Err(e) => {
+                remaining += 1;
+                say!("{} {} ({})", paint(YELLOW, "⚠"), file, e);
+                continue;
+            }
+        };
+        let res = merge_loose(&policy, file, &sides.base, &sides.a, &sides.b)?;
+        if res.conflicts.is_empty() {
+            fs::write(file, encoding.encode(&res.merged_code)?)?;
+            say!("{} {}", paint(GREEN, "✓"), file);
+        } else {
+            remaining += 1;
+            say!("{} {} ({} conflicts; left as is)", paint(YELLOW, "⚠"), file, res.conflicts.len());
+            print_conflicts(&res.conflicts);
+        }
+        for w in &res.warnings {
+            say!("  ℹ {}", w);
+        }
+    }
+    if remaining > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Merge three versions of `path` outside any repo walk (files of unknown
+/// type merge textually).
+fn merge_loose(policy: &Policy, path: &str, base: &str, a: &str, b: &str) -> anyhow::Result<MergeOutcome> {
+    let job = MergeJob { path: path.into(), base: base.into(), a: a.into(), b: b.into() };
+    job.run(policy)
+}
+
+/// Write a merge result to `target`, replacing whatever is there.
+fn write_output(target: &Path, content: &[u8], link: bool) -> std::io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(target).is_ok() {
+        fs::remove_file(target)?;
+    }
+    if link { symlink(&String::from_utf8_lossy(content), target) } else { fs::write(target, content) }
+}
+
+/// Remove a path the merge deleted (one already gone is fine).
+fn remove_output(target: &Path) -> std::io::Result<()> {
+    match fs::remove_file(target) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// `<file>.conflicts.json`: `{"path": ..., "owners": [...], "conflicts":
+/// [ConflictSite, ...]}`.
+fn write_sidecar(out: &Path, path: &str, owners: &CodeOwners, conflicts: &[ConflictSite]) -> anyhow::Result<()> {
+    #[derive(serde::Serialize)]
+    struct Sidecar<'s> {
+        path: &'s str,
+        owners: &'s [String],
+        conflicts: &'s [ConflictSite],
+    }
+    let owners = owners.of(path);
+    fs::write(out, serde_json::to_string_pretty(&Sidecar { path, owners, conflicts })? + "\n")?;
+    Ok(())
+}
+
+/// `<dir>/<conflict ID>.json` for each bundle.
+fn write_bundles(dir: &Path, bundles: &[ConflictBundle]) -> anyhow::Result<()> {
+    for bundle in bundles {
+        fs::write(dir.join(format!("{}.json", bundle.conflict.id)), serde_json::to_string_pretty(bundle)? + "\n")?;
+    }
+    Ok(())
+}
+
+/// The `.sinkit.toml` files of the current directory and its parents: the
+/// outermost is the root policy, the others profiles of its subdirectories
+/// (their commands only with `trust_repo_config`, see [`trusted`]).
+fn find_policy(trust_repo_config: bool) -> anyhow::Result<Policy> {
+    let cwd = env::current_dir()?;
+    let mut dirs: Vec<&Path> = cwd.ancestors().filter(|dir| dir.join(".sinkit.toml").is_file()).collect();
+    let Some(root) = dirs.pop() else { return Ok(Policy::default()) };
+    let mut policy = Policy::load(&root.join(".sinkit.toml"))?;
+    for dir in dirs.into_iter().rev() {
+        let rel = dir.strip_prefix(root)?.to_string_lossy().replace('\\', "/");
+        policy = policy.with_profile(&rel, Policy::load(&dir.join(".sinkit.toml"))?);
+    }
+    trusted(policy, trust_repo_config, &root.join(".sinkit.toml").display().to_string()).with_grammars(Some(root))
+}
+
+/// `policy` without its commands (a `[resolve] cmd`, `[grammars]`) unless
+/// `--trust-repo-config` lets them run: checked out or read from a commit,
+/// a `.sinkit.toml` comes with the branches, whoever pushed them. `from`
+/// names it in the warning.
+fn trusted(policy: Policy, trust_repo_config: bool, from: &str) -> Policy {
+    if !policy.has_commands() || trust_repo_config {
+        return policy;
+    }
+    eprintln!("⚠ not running the [resolve] cmd / [grammars] from {from}; pass --trust-repo-config to allow it");
+    policy.without_commands()
+}
+
+/// Merge a path that is a symlink on some side; each side is `(is_symlink,
+/// blob)`. Returns the outcome and whether the result is a symlink.
+fn merge_link(sides: [(bool, &str); 3], opts: &MergeOptions) -> (MergeOutcome, bool) {
+    let [base, a, b] = sides;
+    let res = merge_symlink(base.1, a.1, b.1, opts);
+    let link = if a == b || b == base {
+        a.0
+    } else if a == base {
+        b.0
+    } else if !res.conflicts.is_empty() {
+        false
+    } else {
+        // settled through a resolution (or a file <-> link swap with one blob)
+        if res.merged_code == b.1 { b.0 } else { a.0 }
+    };
+    (res, link)
+}
+
+#[cfg(unix)]
+fn symlink(target: &str, path: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(not(unix))]
+fn symlink(target: &str, path: &Path) -> std::io::Result<()> {
+    fs::write(path, target)
+}
+
@@ -0,0 +1,77 @@
+//! `.codesync/manifest.json`: every path of a run, for automation.
+//!
+//! ```json
+//! {"files": [{"path": "src/user.ts", "output": ".codesync/src__user.ts",
+//!   "status": "merged", "conflicts": 0, "engine": "ast",
+//!   "hashes": {"base": "…", "a": "…", "b": "…", "merged": "…"},
+//!   "owners": ["@acme/users"], "package": "@acme/users"}]}
+//! ```
+//!
+//! `status` and `engine` take the `--porcelain` values. `output` is where the
+//! result was written (`null` if it wasn't: deleted and skipped paths, sparse
+//! checkouts, `commit-merge`). Hashes are `sink_core::content_hash` (16 hex digits,
+//! stable across runs; whitespace-only changes hash the same), `null` for a
+//! version that doesn't exist; submodules and binary files list commit and
+//! blob IDs instead. `owners` are the path's CODEOWNERS (see `owners`); `package` is its
+//! workspace package, if any (see `workspaces`).
+
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use sink_core::content_hash;
+
+use crate::owners::CodeOwners;
+use crate::workspaces::Workspaces;
+
+#[derive(Serialize)]
+pub(crate) struct Entry {
+    pub(crate) path: String,
+    pub(crate) output: Option<String>,
+    pub(crate) status: &'static str,
+    pub(crate) conflicts: usize,
+    pub(crate) engine: &'static str,
+    pub(crate) hashes: Hashes,
+}
+
+#[derive(Serialize, Default)]
+pub(crate) struct Hashes {
+    pub(crate) base: Option<String>,
+    pub(crate) a: Option<String>,
+    pub(crate) b: Option<String>,
+    pub(crate) merged: Option<String>,
+}
+
+impl Hashes {
+    /// Hashes of whichever versions exist.
+    pub(crate) fn of(versions: [Option<&str>; 4]) -> Self {
+        let [base, a, b, merged] = versions.map(|v| v.map(|text| format!("{:016x}", content_hash(text))));
+        Hashes { base, a, b, merged }
+    }
+}
+
+#[derive(Serialize)]
+struct Manifest<'e> {
+    files: Vec<Owned<'e>>,
+}
+
+#[derive(Serialize)]
+struct Owned<'e> {
+    #[serde(flatten)]
+    entry: &'e Entry,
+    owners: &'e [String],
+    package: Option<&'e str>,
+}
+
+pub(crate) fn write(path: &Path, files: &[Entry], owners: &CodeOwners, workspaces: &Workspaces) -> anyhow::Result<()> {
+    let files = files
+        .iter()
+        .map(|entry| Owned {
+            entry,
+            owners: owners.of(&entry.path),
+            package: workspaces.of(&entry.path).map(|p| p.name.as_str()),
+        })
+        .collect();
+    fs::write(path, serde_json::to_string_pretty(&Manifest { files })? + "\n")?;
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Units a branch moved out of a file into a file it added (see
+//! `sink_core::extracted_units`): the old file takes the deletion, and the
+//! other branch's edits of the units are made in the new file.
+
+use sink_core::{Adapter, MergeOptions, ParseOptions, Policy, extracted_units};
+
+use crate::source::{Entries, FileSource, GITLINK};
+
+pub(crate) struct Move {
+    /// The file the units were in, and the one they're in now.
+    pub(crate) from: String,
+    pub(crate) to: String,
+    /// Whether A moved them (else B).
+    pub(crate) by_a: bool,
+    pub(crate) units: Vec<(String, String)>,
+}
+
+impl Move {
+    /// The units as `MergeOptions::with_moved` takes them.
+    pub(crate) fn unit_names(&self) -> impl Iterator<Item = String> + '_ {
+        self.units.iter().map(|(kind, name)| format!("{kind}::{name}"))
+    }
+}
+
+/// Every move either branch made: pairs of a file it added (that the other
+/// branch doesn't have) and a parsed file it changed. Files that can't be
+/// read or parsed are left out.
+pub(crate) fn find_moves(
+    source: &dyn FileSource,
+    policy: &Policy,
+    [base_ref, a_ref, b_ref]: [&str; 3],
+    [base_entries, a_entries, b_entries]: [&Entries; 3],
+) -> Vec<Move> {
+    let parsed = |path: &str| matches!(policy.adapter_for(path), Some(Adapter::Ast(_)));
+    let mut moves = Vec::new();
+    for (by_a, rev, entries, other) in [(true, a_ref, a_entries, b_entries), (false, b_ref, b_entries, a_entries)] {
+        let added = entries.keys().filter(|p| !base_entries.contains_key(*p) && !other.contains_key(*p) && parsed(p));
+        let changed: Vec<&String> = base_entries
+            .iter()
+            .filter(|(p, (mode, oid))| mode != GITLINK && parsed(p) && entries.get(*p).is_some_and(|(_, o)| o != oid))
+            .map(|(p, _)| p)
+            .collect();
+        for to in added {
+            let Ok(new_file) = source.show(rev, to) else { continue };
+            for from in &changed {
+                let Some(Adapter::Ast(lang)) = policy.adapter_for(from) else { continue };
+                let (Ok(base), Ok(side)) = (source.show(base_ref, from), source.show(rev, from)) else { continue };
+                let opts = MergeOptions::new(ParseOptions::for_path(from));
+                let units = extracted_units(&base, &side, &new_file, lang, &opts).unwrap_or_default();
+                if !units.is_empty() {
+                    moves.push(Move { from: (*from).clone(), to: to.clone(), by_a, units });
+                }
+            }
+        }
+    }
+    moves
+}
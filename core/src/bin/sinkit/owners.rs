@@ -0,0 +1,140 @@
+//! CODEOWNERS: who owns each merged path, so a large merge's conflicts can
+//! be routed to the teams that review them (the manifest, sidecars, reports
+//! and the summary list them).
+//!
+//! The file is read as committed on A, from the first of `.github/`, the
+//! root and `docs/` that has one, like GitHub. The last matching line wins;
+//! a line without owners leaves its paths unowned. Patterns are gitignore's:
+//! one without a slash (other than a trailing one) matches at any depth, and
+//! a directory's pattern covers everything under it.
+
+use sink_core::glob_match;
+
+use crate::source::FileSource;
+
+const LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+#[derive(Debug, Default)]
+pub(crate) struct CodeOwners {
+    /// (pattern, directories only, owners), in file order.
+    rules: Vec<(String, bool, Vec<String>)>,
+}
+
+impl CodeOwners {
+    /// The CODEOWNERS file at `rev`; none is an empty one.
+    pub(crate) fn load(source: &dyn FileSource, rev: &str) -> Self {
+        LOCATIONS.iter().find_map(|path| source.show(rev, path).ok()).map(|text| Self::parse(&text)).unwrap_or_default()
+    }
+
+    pub(crate) fn parse(text: &str) -> Self {
+        let rules = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|l| {
+                let mut fields = l.split_whitespace();
+                let pattern = fields.next()?;
+                // owners end at a trailing comment
+                let owners = fields.take_while(|f| !f.starts_with('#')).map(str::to_string).collect();
+                let dir_only = pattern.len() > 1 && pattern.ends_with('/');
+                Some((pattern.trim_end_matches('/').to_string(), dir_only, owners))
+            })
+            .collect();
+        CodeOwners { rules }
+    }
+
+    /// The owners of a repo-relative `path` (empty if none).
+    pub(crate) fn of(&self, path: &str) -> &[String] {
+        let dirs = || path.match_indices('/').map(|(i, _)| &path[..i]);
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, dir_only, _)| {
+                (!dir_only && glob_match(pattern, path)) || dirs().any(|dir| glob_match(pattern, dir))
+            })
+            .map_or(&[], |(_, _, owners)| owners)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    use anyhow::{Result, bail};
+
+    use super::*;
+    use crate::source::Entries;
+
+    /// Files at revisions, `(rev, path) -> text`; nothing else.
+    struct Files(BTreeMap<(&'static str, &'static str), &'static str>);
+
+    impl FileSource for Files {
+        fn resolve(&self, rev: &str) -> Result<String> {
+            Ok(rev.to_string())
+        }
+        fn merge_base(&self, _: &str, _: &str, _: bool) -> Result<String> {
+            bail!("no history")
+        }
+        fn entries(&self, _: &str) -> Result<Entries> {
+            bail!("no trees")
+        }
+        fn commit_time(&self, _: &str) -> Result<i64> {
+            bail!("no commits")
+        }
+        fn show_bytes(&self, rev: &str, path: &str) -> Result<Vec<u8>> {
+            match self.0.iter().find(|((r, p), _)| *r == rev && *p == path) {
+                Some((_, text)) => Ok(text.as_bytes().to_vec()),
+                None => bail!("no {path} at {rev}"),
+            }
+        }
+        fn work_tree(&self) -> Option<&Path> {
+            None
+        }
+    }
+
+    #[test]
+    fn the_last_matching_line_wins() {
+        let owners = CodeOwners::parse(concat!(
+            "# comment\n",
+            "*       @everyone\n",
+            "*.ts    @web # trailing comment\n",
+            "/src/generated/\n",
+            "src/api/*.ts @api @web\n",
+        ));
+        assert_eq!(owners.of("README.md"), ["@everyone"]);
+        assert_eq!(owners.of("src/app.ts"), ["@web"]);
+        assert_eq!(owners.of("src/api/users.ts"), ["@api", "@web"]);
+        // a line without owners unowns its paths
+        assert!(owners.of("src/generated/schema.ts").is_empty());
+        assert!(CodeOwners::default().of("src/app.ts").is_empty());
+    }
+
+    #[test]
+    fn slashes_anchor_patterns() {
+        let owners = CodeOwners::parse("docs/ @writers\n/build @ci\nlib/util.ts @core\nMakefile @ci\n");
+        // no slash but a trailing one: at any depth, and everything under it
+        assert_eq!(owners.of("docs/intro.md"), ["@writers"]);
+        assert_eq!(owners.of("packages/app/docs/intro.md"), ["@writers"]);
+        assert!(owners.of("docs").is_empty());
+        assert_eq!(owners.of("tools/Makefile"), ["@ci"]);
+        // a leading or inner slash: from the root only
+        assert_eq!(owners.of("build/out.js"), ["@ci"]);
+        assert!(owners.of("src/build/out.js").is_empty());
+        assert_eq!(owners.of("lib/util.ts"), ["@core"]);
+        assert!(owners.of("packages/lib/util.ts").is_empty());
+    }
+
+    #[test]
+    fn the_file_is_read_where_github_looks_first() {
+        let files = Files(BTreeMap::from([
+            (("a", "CODEOWNERS"), "* @root\n"),
+            (("a", "docs/CODEOWNERS"), "* @docs\n"),
+            (("a", ".github/CODEOWNERS"), "* @github\n"),
+            (("b", "docs/CODEOWNERS"), "* @docs\n"),
+        ]));
+        assert_eq!(CodeOwners::load(&files, "a").of("x.ts"), ["@github"]);
+        assert_eq!(CodeOwners::load(&files, "b").of("x.ts"), ["@docs"]);
+        assert!(CodeOwners::load(&files, "c").of("x.ts").is_empty());
+    }
+}
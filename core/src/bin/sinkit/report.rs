@@ -0,0 +1,262 @@
+//! `--format <report>`: the run as a CI report on stdout, in place of the
+//! human output.
+//!
+//! - `gitlab-codequality`: a GitLab Code Quality report (a JSON array of
+//!   issues), so conflicts show up inline in merge request widgets. Each
+//!   conflict's fingerprint is its ID; `internal` and `unmerged` conflicts are
+//!   `critical`, the rest `major`. The description names the path's owners.
+//! - `junit`: JUnit XML, one test case per path (classname `sinkit.<engine>`),
+//!   failing with its conflicts, or skipped if it's missing on a side, with
+//!   its owners as an `owners` property.
+
+use std::fmt::Write;
+
+use serde::Serialize;
+use sink_core::ConflictSite;
+
+use crate::manifest::Entry;
+use crate::owners::CodeOwners;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    GitlabCodeQuality,
+    Junit,
+}
+
+impl Format {
+    pub(crate) fn parse(name: &str) -> anyhow::Result<Self> {
+        match name {
+            "gitlab-codequality" => Ok(Format::GitlabCodeQuality),
+            "junit" => Ok(Format::Junit),
+            _ => anyhow::bail!("unknown --format {name} (expected gitlab-codequality or junit)"),
+        }
+    }
+}
+
+/// The run's `files` and `conflicts` (with the repo path of each) in `format`.
+pub(crate) fn render(
+    format: Format,
+    files: &[Entry],
+    conflicts: &[(String, ConflictSite)],
+    owners: &CodeOwners,
+) -> anyhow::Result<String> {
+    match format {
+        Format::GitlabCodeQuality => gitlab_codequality(conflicts, owners),
+        Format::Junit => Ok(junit(files, conflicts, owners)),
+    }
+}
+
+#[derive(Serialize)]
+struct Issue<'c> {
+    description: String,
+    check_name: String,
+    fingerprint: &'c str,
+    severity: &'static str,
+    location: Location<'c>,
+}
+
+#[derive(Serialize)]
+struct Location<'c> {
+    path: &'c str,
+    lines: Lines,
+}
+
+#[derive(Serialize)]
+struct Lines {
+    begin: usize,
+}
+
+fn gitlab_codequality(conflicts: &[(String, ConflictSite)], owners: &CodeOwners) -> anyhow::Result<String> {
+    let issues: Vec<Issue> = conflicts
+        .iter()
+        .map(|(path, site)| {
+            let c = &site.conflict;
+            let mut description = match &c.unit {
+                Some(unit) => format!("Merge conflict in {unit}: {}", c.message),
+                None => format!("Merge conflict: {}", c.message),
+            };
+            if let owners @ [_, ..] = owners.of(path) {
+                let _ = write!(description, " (owners: {})", owners.join(" "));
+            }
+            Issue {
+                description,
+                check_name: format!("sinkit/{}", c.kind),
+                fingerprint: &c.id,
+                severity: if matches!(c.kind.as_str(), "internal" | "unmerged") { "critical" } else { "major" },
+                location: Location { path, lines: Lines { begin: site.lines.map_or(1, |(begin, _)| begin) } },
+            }
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&issues)? + "\n")
+}
+
+fn junit(files: &[Entry], conflicts: &[(String, ConflictSite)], owners: &CodeOwners) -> String {
+    let failures = files.iter().filter(|f| f.status == "conflict").count();
+    let skipped = files.iter().filter(|f| f.status == "skipped").count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"sinkit\" tests=\"{}\" failures=\"{failures}\" errors=\"0\" skipped=\"{skipped}\">",
+        files.len()
+    );
+    for file in files {
+        let _ = write!(xml, "    <testcase classname=\"sinkit.{}\" name=\"{}\"", file.engine, escape(&file.path));
+        let owned = owners.of(&file.path);
+        let properties = if owned.is_empty() {
+            String::new()
+        } else {
+            let value = escape(&owned.join(" "));
+            format!("\n      <properties><property name=\"owners\" value=\"{value}\"/></properties>")
+        };
+        match file.status {
+            "conflict" => {
+                let _ = write!(xml, ">{properties}\n      <failure type=\"conflict\" message=\"{} conflicts\">", file.conflicts);
+                for (_, site) in conflicts.iter().filter(|(path, _)| *path == file.path) {
+                    let c = &site.conflict;
+                    let at = site.lines.map(|(begin, _)| format!(" (line {begin})")).unwrap_or_default();
+                    let unit = c.unit.as_deref().unwrap_or("(whole file)");
+                    let _ = write!(xml, "{}", escape(&format!("[{}] {unit}{at}: {}\n", c.id, c.message)));
+                }
+                let _ = writeln!(xml, "</failure>\n    </testcase>");
+            }
+            "skipped" => {
+                let _ = writeln!(xml, ">{properties}\n      <skipped message=\"missing on a side\"/>\n    </testcase>");
+            }
+            _ if properties.is_empty() => {
+                let _ = writeln!(xml, "/>");
+            }
+            _ => {
+                let _ = writeln!(xml, ">{properties}\n    </testcase>");
+            }
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// `text` as XML character data or attribute value.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\n' | '\t' | '\r' => out.push(ch),
+            // not allowed in XML 1.0 at all
+            c if (c as u32) < 0x20 => out.push('\u{fffd}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use sink_core::Conflict;
+
+    use super::*;
+    use crate::manifest::Hashes;
+
+    fn site(path: &str, id: &str, kind: &str, unit: Option<&str>, message: &str, lines: Option<(usize, usize)>) -> (String, ConflictSite) {
+        let conflict =
+            Conflict { id: id.to_string(), kind: kind.to_string(), unit: unit.map(Into::into), message: message.to_string() };
+        (path.to_string(), ConflictSite { conflict, bytes: None, lines, a: None, b: None })
+    }
+
+    fn entry(path: &str, status: &'static str, conflicts: usize, engine: &'static str) -> Entry {
+        Entry { path: path.to_string(), output: None, status, conflicts, engine, hashes: Hashes::default() }
+    }
+
+    /// A run's files, its conflicts and the repo's owners.
+    fn run() -> (Vec<Entry>, Vec<(String, ConflictSite)>, CodeOwners) {
+        let files = vec![
+            entry("src/api.ts", "conflict", 2, "ast"),
+            entry("src/ok.ts", "merged", 0, "ast"),
+            entry("docs/a&b.md", "skipped", 0, "text"),
+            entry("logo.png", "merged", 0, "binary"),
+            entry("README.md", "deleted", 0, "text"),
+        ];
+        let save = Some("function_declaration::save");
+        let conflicts = vec![
+            site("src/api.ts", "00000000000000a1", "update-update", save, "Both branches updated save <T>", Some((3, 9))),
+            site("src/api.ts", "00000000000000a2", "unmerged", None, "Syntax errors in A", None),
+        ];
+        (files, conflicts, CodeOwners::parse("src/ @web @api\n*.png @design\n"))
+    }
+
+    #[test]
+    fn gitlab_codequality_report() {
+        let (files, conflicts, owners) = run();
+        let report = render(Format::parse("gitlab-codequality").unwrap(), &files, &conflicts, &owners).unwrap();
+        assert_eq!(
+            report,
+            r#"[
+  {
+    "description": "Merge conflict in function_declaration::save: Both branches updated save <T> (owners: @web @api)",
+    "check_name": "sinkit/update-update",
+    "fingerprint": "00000000000000a1",
+    "severity": "major",
+    "location": {
+      "path": "src/api.ts",
+      "lines": {
+        "begin": 3
+      }
+    }
+  },
+  {
+    "description": "Merge conflict: Syntax errors in A (owners: @web @api)",
+    "check_name": "sinkit/unmerged",
+    "fingerprint": "00000000000000a2",
+    "severity": "critical",
+    "location": {
+      "path": "src/api.ts",
+      "lines": {
+        "begin": 1
+      }
+    }
+  }
+]
+"#
+        );
+        assert_eq!(render(Format::GitlabCodeQuality, &files, &[], &owners).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn junit_report() {
+        let (files, conflicts, owners) = run();
+        let report = render(Format::parse("junit").unwrap(), &files, &conflicts, &owners).unwrap();
+        assert_eq!(
+            report,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="sinkit" tests="5" failures="1" errors="0" skipped="1">
+    <testcase classname="sinkit.ast" name="src/api.ts">
+      <properties><property name="owners" value="@web @api"/></properties>
+      <failure type="conflict" message="2 conflicts">[00000000000000a1] function_declaration::save (line 3): Both branches updated save &lt;T&gt;
+[00000000000000a2] (whole file): Syntax errors in A
+</failure>
+    </testcase>
+    <testcase classname="sinkit.ast" name="src/ok.ts">
+      <properties><property name="owners" value="@web @api"/></properties>
+    </testcase>
+    <testcase classname="sinkit.text" name="docs/a&amp;b.md">
+      <skipped message="missing on a side"/>
+    </testcase>
+    <testcase classname="sinkit.binary" name="logo.png">
+      <properties><property name="owners" value="@design"/></properties>
+    </testcase>
+    <testcase classname="sinkit.text" name="README.md"/>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn xml_escapes() {
+        assert_eq!(escape("a<b>&\"c'\n\u{1}"), "a&lt;b&gt;&amp;&quot;c&apos;\n\u{fffd}");
+    }
+}
@@ -0,0 +1,349 @@
+//! `sinkit serve --http <addr>`: the merge, diff and parse entry points over
+//! HTTP, for tools that can't link the library or spawn a process per file.
+//!
+//! Every endpoint takes a JSON body with `POST` and answers JSON:
+//!
+//! - `/merge` `{base, a, b, path?, language?}` → a `MergeOutcome`
+//! - `/diff` `{base, other, path?, language?}` → the `Edit`s from base
+//! - `/parse` `{code, path?, language?}` → the `AstFile`
+//! - `/batch` `{jobs: [{path, base, a, b}, ...]}` → `{outcomes: [...]}`, a
+//!   `MergeOutcome` (or `{error}`) per job, merged concurrently
+//!
+//! `/parse` and `/diff` answer in the library's compact binary form instead
+//! (`sink_core::ast_to_bytes`, `edits_to_bytes`) when the request has
+//! `Accept: application/octet-stream`.
+//!
+//! The language comes from `language` (`"TypeScript"` or `"Tsx"`), else
+//! from `path` like `merge-file` (unknown extensions merge textually), else
+//! TypeScript. Failures are `{"error": ...}` with a 4xx status. Policy rules
+//! come from the `.sinkit.toml` nearest the directory the server started in.
+//!
+//! The server is axum on tokio, plain HTTP/1.1; put it behind a proxy for
+//! TLS or anything exposed. `:8080` listens on the loopback interface only
+//! (`0.0.0.0:8080` for every interface). Requests are worked on one per core
+//! at a time, the others waiting (unread) for their turn; the merges run on
+//! tokio's blocking pool. A body over 64 MiB is answered 413, one that takes
+//! over 30 seconds to arrive 408, and a request head over hyper's limits
+//! 431. Built without the `serve` feature, sinkit has no `serve`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, anyhow, bail};
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, FromRequest, Request, State};
+use axum::http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{MethodRouter, post};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sink_core::{
+    Adapter, AstLanguage, FilePolicy, MergeJob, MergeOptions, ParseOptions, Policy, diff_top_level_with,
+    merge_batch, parse_typescript_to_ast_with, three_way_merge,
+};
+use tokio::sync::Semaphore;
+
+use super::{find_policy, merge_loose};
+
+/// Largest request body accepted.
+const MAX_BODY: usize = 64 << 20;
+
+/// How long a request body may take to arrive.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    base: String,
+    a: String,
+    b: String,
+    path: Option<String>,
+    language: Option<AstLanguage>,
+}
+
+#[derive(Deserialize)]
+struct BatchRequest {
+    jobs: Vec<MergeJob>,
+}
+
+#[derive(Deserialize)]
+struct DiffRequest {
+    base: String,
+    other: String,
+    path: Option<String>,
+    language: Option<AstLanguage>,
+}
+
+#[derive(Deserialize)]
+struct ParseRequest {
+    code: String,
+    path: Option<String>,
+    language: Option<AstLanguage>,
+}
+
+/// `serve --http <addr>`; `:8080` listens on the loopback interface.
+pub(crate) fn serve(args: &[String], trust_repo_config: bool) -> anyhow::Result<()> {
+    let addr = match args {
+        [flag, addr] if flag == "--http" => addr,
+        _ => {
+            eprintln!("Usage: sinkit serve --http <[host]:port> [--trust-repo-config]");
+            std::process::exit(1);
+        }
+    };
+    let addr = if addr.starts_with(':') { format!("127.0.0.1{addr}") } else { addr.clone() };
+    let policy = find_policy(trust_repo_config)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(&addr).await.with_context(|| format!("listening on {addr}"))?;
+        println!("sinkit listening on http://{}", listener.local_addr()?);
+        axum::serve(listener, router(policy)).await?;
+        Ok(())
+    })
+}
+
+/// What every request shares: the policy, and a turn per core.
+struct Server {
+    policy: Policy,
+    turns: Semaphore,
+}
+
+fn router(policy: Policy) -> Router {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let server = Arc::new(Server { policy, turns: Semaphore::new(cores) });
+    let endpoint = || -> MethodRouter<Arc<Server>> { post(handle).fallback(async || refuse(405, "use POST")) };
+    Router::new()
+        .route("/merge", endpoint())
+        .route("/diff", endpoint())
+        .route("/parse", endpoint())
+        .route("/batch", endpoint())
+        .fallback(async |uri: Uri| refuse(404, &format!("no endpoint {}", uri.path())))
+        .layer(DefaultBodyLimit::max(MAX_BODY))
+        .with_state(server)
+}
+
+async fn handle(State(server): State<Arc<Server>>, request: Request) -> Response {
+    let path = request.uri().path().to_string();
+    let accept = request.headers().get(ACCEPT).and_then(|v| v.to_str().ok());
+    let binary = accept.is_some_and(|v| v.contains(OCTET_STREAM));
+    let length = request.headers().get(CONTENT_LENGTH).and_then(|v| v.to_str().ok()?.parse::<usize>().ok());
+    if length.is_some_and(|n| n > MAX_BODY) {
+        return refuse(413, &format!("request body over {MAX_BODY} bytes"));
+    }
+    // the body is only read once it's the request's turn
+    let Ok(_turn) = server.turns.acquire().await else {
+        return refuse(503, "shutting down");
+    };
+    let body = match tokio::time::timeout(TIMEOUT, Bytes::from_request(request, &())).await {
+        Ok(Ok(body)) => body,
+        Ok(Err(rejection)) => return refuse(rejection.status().as_u16(), &rejection.body_text()),
+        Err(_) => return refuse(408, &format!("the body took over {} seconds", TIMEOUT.as_secs())),
+    };
+    let work = Arc::clone(&server);
+    let answer = tokio::task::spawn_blocking(move || {
+        if binary && matches!(path.as_str(), "/parse" | "/diff") {
+            route_binary(&path, &body, &work.policy).map(|bytes| ([(CONTENT_TYPE, OCTET_STREAM)], bytes).into_response())
+        } else {
+            route(&path, &body, &work.policy).map(|value| axum::Json(value).into_response())
+        }
+    });
+    match answer.await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => refuse(400, &format!("{e:#}")),
+        Err(e) => refuse(500, &format!("{e}")),
+    }
+}
+
+/// `{"error": ...}` with `status`.
+fn refuse(status: u16, error: &str) -> Response {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, axum::Json(json!({ "error": error }))).into_response()
+}
+
+fn route(endpoint: &str, body: &[u8], policy: &Policy) -> anyhow::Result<Value> {
+    let value = match endpoint {
+        "/merge" => {
+            let req: MergeRequest = serde_json::from_slice(body)?;
+            let res = match (req.language, &req.path) {
+                (None, Some(path)) => merge_loose(policy, path, &req.base, &req.a, &req.b)?,
+                (lang, path) => {
+                    let name = path.as_deref().unwrap_or("");
+                    let lang = lang.unwrap_or(AstLanguage::TypeScript);
+                    let opts = ParseOptions { policy: policy.for_path(name), ..ParseOptions::for_path(name) };
+                    let opts = MergeOptions::new(opts)
+                        .with_suppressed(policy.suppressed())
+                        .with_max_parse_bytes(policy.max_parse_bytes())
+                        .with_memory_limit(policy.memory_limit());
+                    let opts = if name.is_empty() { opts } else { opts.with_path(name) };
+                    three_way_merge(&req.base, &req.a, &req.b, lang, &opts)?
+                }
+            };
+            serde_json::to_value(res)?
+        }
+        "/batch" => {
+            let req: BatchRequest = serde_json::from_slice(body)?;
+            let outcomes = merge_batch(&req.jobs, policy)
+                .into_iter()
+                .map(|res| match res {
+                    Ok(res) => serde_json::to_value(res),
+                    Err(e) => Ok(json!({ "error": format!("{e:#}") })),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            json!({ "outcomes": outcomes })
+        }
+        "/diff" => {
+            let req: DiffRequest = serde_json::from_slice(body)?;
+            let (lang, opts) = grammar(policy, req.path.as_deref(), req.language)?;
+            let base = parse_typescript_to_ast_with(&req.base, lang, &opts)?;
+            let other = parse_typescript_to_ast_with(&req.other, lang, &opts)?;
+            serde_json::to_value(diff_top_level_with(&base, &other, &opts))?
+        }
+        "/parse" => {
+            let req: ParseRequest = serde_json::from_slice(body)?;
+            let (lang, opts) = grammar(policy, req.path.as_deref(), req.language)?;
+            serde_json::to_value(parse_typescript_to_ast_with(&req.code, lang, &opts)?)?
+        }
+        _ => return Err(anyhow!("no endpoint {endpoint}")),
+    };
+    Ok(value)
+}
+
+/// `/parse` and `/diff` in the binary form.
+#[cfg(feature = "binary")]
+fn route_binary(endpoint: &str, body: &[u8], policy: &Policy) -> anyhow::Result<Vec<u8>> {
+    if endpoint == "/diff" {
+        let req: DiffRequest = serde_json::from_slice(body)?;
+        let (lang, opts) = grammar(policy, req.path.as_deref(), req.language)?;
+        let base = parse_typescript_to_ast_with(&req.base, lang, &opts)?;
+        let other = parse_typescript_to_ast_with(&req.other, lang, &opts)?;
+        return Ok(sink_core::edits_to_bytes(&diff_top_level_with(&base, &other, &opts)));
+    }
+    let req: ParseRequest = serde_json::from_slice(body)?;
+    let (lang, opts) = grammar(policy, req.path.as_deref(), req.language)?;
+    Ok(sink_core::ast_to_bytes(&parse_typescript_to_ast_with(&req.code, lang, &opts)?))
+}
+
+#[cfg(not(feature = "binary"))]
+fn route_binary(_endpoint: &str, _body: &[u8], _policy: &Policy) -> anyhow::Result<Vec<u8>> {
+    bail!("this server was built without the binary feature; ask for JSON")
+}
+
+/// The grammar and parse options for a request that needs a syntax tree.
+fn grammar(policy: &Policy, path: Option<&str>, language: Option<AstLanguage>) -> anyhow::Result<(AstLanguage, ParseOptions)> {
+    let name = path.unwrap_or("");
+    let lang = match (language, path.map(|p| policy.adapter_for(p))) {
+        (Some(lang), _) => lang,
+        (None, Some(Some(Adapter::Ast(lang)))) => lang,
+        (None, Some(_)) => bail!("{name} has no grammar; pass \"language\""),
+        (None, None) => AstLanguage::TypeScript,
+    };
+    let opts = ParseOptions { policy: FilePolicy { textual: false, ..policy.for_path(name) }, ..ParseOptions::for_path(name) };
+    Ok((lang, opts))
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::OnceLock;
+
+    use super::*;
+
+    /// A server on a free loopback port, for the whole test run.
+    fn server() -> SocketAddr {
+        static ADDR: OnceLock<SocketAddr> = OnceLock::new();
+        *ADDR.get_or_init(|| {
+            let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+            let listener = runtime.block_on(tokio::net::TcpListener::bind("127.0.0.1:0")).unwrap();
+            let addr = listener.local_addr().unwrap();
+            std::thread::spawn(move || runtime.block_on(async { axum::serve(listener, router(Policy::default())).await }));
+            addr
+        })
+    }
+
+    /// The status, content type and body of the answer to `request`.
+    fn send(method: &str, path: &str, headers: &str, body: &str) -> (u16, String, String) {
+        let mut stream = TcpStream::connect(server()).unwrap();
+        let head = format!("{method} {path} HTTP/1.1\r\nHost: sinkit\r\nConnection: close\r\n{headers}");
+        write!(stream, "{head}Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        let mut answer = Vec::new();
+        stream.read_to_end(&mut answer).unwrap();
+        let answer = String::from_utf8_lossy(&answer);
+        let (head, body) = answer.split_once("\r\n\r\n").unwrap();
+        let status = head[9..12].parse().unwrap();
+        let content_type = head
+            .lines()
+            .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-type: ").map(str::to_string))
+            .unwrap_or_default();
+        (status, content_type, body.to_string())
+    }
+
+    fn post(path: &str, body: Value) -> (u16, Value) {
+        let (status, _, body) = send("POST", path, "Content-Type: application/json\r\n", &body.to_string());
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn merge_answers_the_outcome() {
+        let (status, outcome) = post(
+            "/merge",
+            json!({
+                "base": "export const x = 1;\n",
+                "a": "export const x = 1;\nexport const a = 1;\n",
+                "b": "export const b = 1;\nexport const x = 1;\n",
+                "path": "src/x.ts",
+            }),
+        );
+        assert_eq!(status, 200);
+        assert_eq!(outcome["conflicts"], json!([]));
+        let merged = outcome["merged_code"].as_str().unwrap();
+        assert!(merged.contains("const a") && merged.contains("const b"), "{merged}");
+    }
+
+    #[test]
+    fn batch_answers_an_outcome_per_job() {
+        let job = json!({ "path": "x.ts", "base": "let x = 1;\n", "a": "let x = 2;\n", "b": "let x = 1;\n" });
+        let (status, answer) = post("/batch", json!({ "jobs": [job.clone(), job] }));
+        assert_eq!(status, 200);
+        let outcomes = answer["outcomes"].as_array().unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(|o| o["merged_code"] == "let x = 2;\n"));
+    }
+
+    #[test]
+    fn diff_and_parse_answer_json_or_bytes() {
+        let (status, edits) = post("/diff", json!({ "base": "let x = 1;\n", "other": "let x = 1;\nlet y = 2;\n" }));
+        assert_eq!(status, 200);
+        assert_eq!(edits.as_array().unwrap().len(), 1);
+        let (status, ast) = post("/parse", json!({ "code": "function f() {}\n", "language": "Tsx" }));
+        assert_eq!(status, 200);
+        assert_eq!(ast["units"][0]["name"], "f");
+        let body = json!({ "code": "function f() {}\n" }).to_string();
+        let (status, content_type, _) = send("POST", "/parse", &format!("Accept: {OCTET_STREAM}\r\n"), &body);
+        assert_eq!((status, content_type.as_str()), (200, OCTET_STREAM));
+    }
+
+    #[test]
+    fn bad_requests_get_errors() {
+        let (status, _, body) = send("GET", "/merge", "", "");
+        assert_eq!(status, 405);
+        assert!(body.contains("use POST"));
+        let (status, _, body) = send("POST", "/nope", "", "{}");
+        assert_eq!((status, body.as_str()), (404, r#"{"error":"no endpoint /nope"}"#));
+        let (status, error) = post("/merge", json!({ "base": "" }));
+        assert_eq!(status, 400);
+        assert!(error["error"].as_str().unwrap().contains("missing field"));
+    }
+
+    #[test]
+    fn bodies_over_the_limit_are_refused() {
+        let mut stream = TcpStream::connect(server()).unwrap();
+        let head = format!("POST /merge HTTP/1.1\r\nHost: sinkit\r\nConnection: close\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        stream.write_all(head.as_bytes()).unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).unwrap();
+        assert!(answer.starts_with("HTTP/1.1 413"), "{answer}");
+    }
+}
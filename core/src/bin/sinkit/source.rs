@@ -0,0 +1,42 @@
+//! The VCS layer: where the three versions of every file come from.
+//!
+//! The merge pipeline only needs a merge base, the file list of a revision
+//! and file contents, so any VCS that answers those can drive it. Git-only
+//! extras (`--apply` into sparse checkouts, submodules, `commit-merge`) reach
+//! the git repo through [`FileSource::as_git`].
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::git::Repo;
+
+/// Modes use git's notation whatever the VCS.
+pub(crate) const SYMLINK: &str = "120000";
+pub(crate) const GITLINK: &str = "160000";
+
+/// Path -> (mode, blob ID) of every file at a revision.
+pub(crate) type Entries = BTreeMap<String, (String, String)>;
+
+pub(crate) trait FileSource {
+    /// The full ID of revision `rev`; an error if it doesn't name one.
+    fn resolve(&self, rev: &str) -> Result<String>;
+    /// The merge base of `a` and `b`; `deepen` allows fetching missing
+    /// history where the VCS has shallow clones.
+    fn merge_base(&self, a: &str, b: &str, deepen: bool) -> Result<String>;
+    fn entries(&self, rev: &str) -> Result<Entries>;
+    /// When `rev` was committed, in seconds since the epoch.
+    fn commit_time(&self, rev: &str) -> Result<i64>;
+    /// A file's contents as stored.
+    fn show_bytes(&self, rev: &str, path: &str) -> Result<Vec<u8>>;
+    /// A file's contents as text (invalid UTF-8 replaced).
+    fn show(&self, rev: &str, path: &str) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.show_bytes(rev, path)?).into_owned())
+    }
+    /// The checkout's root, if there is one.
+    fn work_tree(&self) -> Option<&Path>;
+    fn as_git(&self) -> Option<&Repo> {
+        None
+    }
+}
@@ -0,0 +1,132 @@
+//! Monorepo workspaces: which package each merged path belongs to, so a
+//! run can be reported (and gated) per package, or restricted to some.
+//!
+//! Packages are read as committed on A from the root's pnpm-workspace.yaml
+//! (`packages:`), package.json (`workspaces`, or `workspaces.packages` for
+//! yarn) and Cargo.toml (`[workspace] members`). Their patterns are globs of
+//! package directories (`packages/*`; a leading `!` excludes); a directory
+//! matched is a package if it has a package.json or Cargo.toml, named by it
+//! (else by its path). A path belongs to the deepest package it's under.
+
+use serde_json::Value;
+use sink_core::glob_match;
+
+use crate::source::{Entries, FileSource};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Package {
+    /// `name` of its package.json or Cargo.toml.
+    pub(crate) name: String,
+    /// Repo-relative.
+    pub(crate) dir: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Workspaces {
+    /// Deepest first.
+    packages: Vec<Package>,
+}
+
+const MANIFESTS: [&str; 2] = ["package.json", "Cargo.toml"];
+
+impl Workspaces {
+    /// The packages of the workspaces at `rev`, whose files are `entries`;
+    /// none if the repo isn't a monorepo.
+    pub(crate) fn load(source: &dyn FileSource, rev: &str, entries: &Entries) -> Self {
+        let show = |path: &str| source.show(rev, path).ok();
+        let mut patterns = Vec::new();
+        if let Some(text) = show("pnpm-workspace.yaml") {
+            patterns.extend(pnpm_packages(&text));
+        }
+        if let Some(json) = show("package.json").and_then(|text| serde_json::from_str::<Value>(&text).ok()) {
+            let globs = match &json["workspaces"] {
+                Value::Object(yarn) => yarn.get("packages").cloned().unwrap_or_default(),
+                globs => globs.clone(),
+            };
+            patterns.extend(globs.as_array().into_iter().flatten().filter_map(Value::as_str).map(str::to_string));
+        }
+        if let Some(text) = show("Cargo.toml") {
+            patterns.extend(cargo_members(&text));
+        }
+        let globs = |excluded: bool| -> Vec<String> {
+            let clean = |p: &String| p.trim_start_matches('!').trim_start_matches("./").trim_end_matches('/').to_string();
+            patterns.iter().filter(|p| p.starts_with('!') == excluded).map(clean).collect()
+        };
+        let (included, excluded) = (globs(false), globs(true));
+
+        let mut packages: Vec<Package> = Vec::new();
+        for path in entries.keys() {
+            let Some((dir, manifest)) = path.rsplit_once('/') else { continue };
+            // anchored at the root, also without a slash
+            let matches = |globs: &[String]| globs.iter().any(|g| glob_match(&format!("/{g}"), dir));
+            if !MANIFESTS.contains(&manifest) || !matches(&included) || matches(&excluded) {
+                continue;
+            }
+            if packages.iter().any(|p| p.dir == dir) {
+                continue;
+            }
+            let name = show(path).and_then(|text| package_name(manifest, &text)).unwrap_or_else(|| dir.to_string());
+            packages.push(Package { name, dir: dir.to_string() });
+        }
+        packages.sort_by(|x, y| y.dir.matches('/').count().cmp(&x.dir.matches('/').count()).then(x.dir.cmp(&y.dir)));
+        Workspaces { packages }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// The package a repo-relative `path` is in, if any.
+    pub(crate) fn of(&self, path: &str) -> Option<&Package> {
+        self.packages.iter().find(|p| path.strip_prefix(p.dir.as_str()).is_some_and(|rest| rest.starts_with('/')))
+    }
+
+    /// The package named (or at) `name`.
+    pub(crate) fn find(&self, name: &str) -> Option<&Package> {
+        self.packages.iter().find(|p| p.name == name || p.dir == name.trim_end_matches('/'))
+    }
+}
+
+/// The list under `packages:` in a pnpm-workspace.yaml.
+fn pnpm_packages(text: &str) -> Vec<String> {
+    let lines = text.lines().skip_while(|l| l.trim_end() != "packages:").skip(1);
+    let mut globs = Vec::new();
+    for line in lines {
+        let item = line.trim();
+        if item.is_empty() || item.starts_with('#') {
+            continue;
+        }
+        let Some(glob) = item.strip_prefix("- ") else { break };
+        globs.push(glob.trim().trim_matches(['\'', '"']).to_string());
+    }
+    globs
+}
+
+/// The strings in `members = [...]` of a Cargo.toml's `[workspace]` table.
+fn cargo_members(text: &str) -> Vec<String> {
+    let Some(start) = text.lines().position(|l| l.trim() == "[workspace]") else { return Vec::new() };
+    let table: Vec<&str> = text.lines().skip(start + 1).take_while(|l| !l.trim_start().starts_with('[')).collect();
+    let table = table.join("\n");
+    let Some(members) = table.split_once("members").and_then(|(_, rest)| rest.split_once('[')) else {
+        return Vec::new();
+    };
+    let list = members.1.split(']').next().unwrap_or_default();
+    list.split(',').map(|m| m.trim().trim_matches('"').to_string()).filter(|m| !m.is_empty()).collect()
+}
+
+/// `name` in a package.json, or `[package] name` in a Cargo.toml.
+fn package_name(manifest: &str, text: &str) -> Option<String> {
+    if manifest == "package.json" {
+        let json: Value = serde_json::from_str(text).ok()?;
+        return json["name"].as_str().map(str::to_string);
+    }
+    let mut in_package = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+        } else if in_package && let Some((key, value)) = line.split_once('=') && key.trim() == "name" {
+            return Some(value.trim().trim_matches('"').to_string());
+        }
+    }
+    None
+}
@@ -0,0 +1,350 @@
+//! binary.rs
+//! A compact binary form of [`AstFile`]s and edit scripts, for the AST cache
+//! and the server: JSON spells out every field name of every unit, which on
+//! big files weighs several times the code itself.
+//!
+//! Integers are LEB128 varints, byte offsets are stored relative to the unit
+//! (units relative to the one before), and repeated strings (unit kinds,
+//! names, edit kinds) are written once and then referred to by index. Code
+//! and payloads are stored as is. Every encoding starts with a magic and a
+//! format version; decoding anything else is an error, never a panic.
+//!
+//! Behind the `binary` cargo feature (on by default).
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+
+use crate::ast::{AstFile, AstLanguage, SyntaxError, TopLevel, check_ranges};
+use crate::diff::Edit;
+
+const AST_MAGIC: &[u8; 4] = b"SKA\x02";
+const EDITS_MAGIC: &[u8; 4] = b"SKE\x02";
+
+/// `file` (without its tree) in the binary form.
+pub fn ast_to_bytes(file: &AstFile) -> Vec<u8> {
+    let mut w = Writer::new(AST_MAGIC);
+    w.blob(&file.code);
+    w.varint(match file.lang {
+        AstLanguage::TypeScript => 0,
+        AstLanguage::Tsx => 1,
+        AstLanguage::Runtime(id) => 2 + u64::from(id),
+    });
+    w.varint(file.units.len() as u64);
+    let mut last = 0;
+    for u in &file.units {
+        w.string(&u.kind);
+        w.option(u.name.as_deref(), Writer::string);
+        w.varint(u.scope.len() as u64);
+        for container in &u.scope {
+            w.string(container);
+        }
+        w.option(u.signature.as_deref(), Writer::string);
+        w.offset(last, u.start_byte);
+        w.varint((u.end_byte - u.start_byte) as u64);
+        w.out.extend(u.content_hash.to_le_bytes());
+        for range in [u.inner, u.declarator, u.statement] {
+            w.option(range, |w, (start, end)| {
+                w.offset(u.start_byte, start);
+                w.varint((end - start) as u64);
+            });
+        }
+        last = u.start_byte;
+    }
+    w.varint(file.errors.len() as u64);
+    for e in &file.errors {
+        w.string(&e.kind);
+        w.varint(e.start_byte as u64);
+        w.varint((e.end_byte - e.start_byte) as u64);
+        w.varint(e.line as u64);
+        w.varint(e.column as u64);
+    }
+    w.out
+}
+
+/// The [`AstFile`] [`ast_to_bytes`] encoded; its tree is re-parsed on
+/// demand (see [`AstFile::ensure_tree`]).
+pub fn ast_from_bytes(bytes: &[u8]) -> Result<AstFile> {
+    let mut r = Reader::new(bytes, AST_MAGIC, "AST")?;
+    let code = r.blob()?;
+    let lang = match r.varint()? {
+        0 => AstLanguage::TypeScript,
+        1 => AstLanguage::Tsx,
+        id => AstLanguage::Runtime(u16::try_from(id - 2)?),
+    };
+    let mut units = Vec::new();
+    let mut last = 0;
+    for _ in 0..r.varint()? {
+        let kind = r.string()?;
+        let name = r.option(Reader::string)?;
+        let scope = (0..r.varint()?).map(|_| r.string()).collect::<Result<_>>()?;
+        let signature = r.option(Reader::string)?;
+        let start_byte = r.offset(last)?;
+        let end_byte = r.end(start_byte)?;
+        let content_hash = u64::from_le_bytes(r.take(8)?.try_into()?);
+        let mut range = || {
+            r.option(|r| {
+                let start = r.offset(start_byte)?;
+                Ok((start, r.end(start)?))
+            })
+        };
+        let (inner, declarator, statement) = (range()?, range()?, range()?);
+        units.push(TopLevel { scope, signature, content_hash, inner, declarator, statement, ..TopLevel::new(&kind, name, start_byte, end_byte) });
+        last = start_byte;
+    }
+    let mut errors = Vec::new();
+    for _ in 0..r.varint()? {
+        let kind = r.string()?;
+        let start_byte = r.len()?;
+        let end_byte = r.end(start_byte)?;
+        let (line, column) = (r.len()?, r.len()?);
+        errors.push(SyntaxError { kind, start_byte, end_byte, line, column });
+    }
+    r.finish()?;
+    check_ranges(&code, &units, &errors).map_err(anyhow::Error::msg)?;
+    Ok(AstFile::without_tree(code, lang, units, errors))
+}
+
+/// `edits` in the binary form.
+pub fn edits_to_bytes(edits: &[Edit]) -> Vec<u8> {
+    let mut w = Writer::new(EDITS_MAGIC);
+    w.varint(edits.len() as u64);
+    for e in edits {
+        w.string(&e.kind);
+        w.string(&e.unit_kind);
+        w.string(&e.name);
+        w.option(e.payload.as_deref(), Writer::blob);
+        w.option(e.content_hash, |w, hash| w.out.extend(hash.to_le_bytes()));
+        for key in [&e.after, &e.before, &e.preceding] {
+            w.option(key.as_ref(), |w, (kind, name)| {
+                w.string(kind);
+                w.string(name);
+            });
+        }
+        w.option(e.renamed_from.as_deref(), Writer::string);
+    }
+    w.out
+}
+
+/// The edits [`edits_to_bytes`] encoded.
+pub fn edits_from_bytes(bytes: &[u8]) -> Result<Vec<Edit>> {
+    let mut r = Reader::new(bytes, EDITS_MAGIC, "edit script")?;
+    let mut edits = Vec::new();
+    for _ in 0..r.varint()? {
+        let (kind, unit_kind, name) = (r.string()?, r.string()?, r.string()?);
+        let payload = r.option(Reader::blob)?;
+        let content_hash = r.option(|r| Ok(u64::from_le_bytes(r.take(8)?.try_into()?)))?;
+        let mut key = || r.option(|r| Ok((r.string()?, r.string()?)));
+        let (after, before, preceding) = (key()?, key()?, key()?);
+        let renamed_from = r.option(Reader::string)?;
+        edits.push(Edit { kind, unit_kind, name, payload, content_hash, after, before, preceding, renamed_from });
+    }
+    r.finish()?;
+    Ok(edits)
+}
+
+struct Writer {
+    out: Vec<u8>,
+    strings: HashMap<String, u64>,
+}
+
+impl Writer {
+    fn new(magic: &[u8; 4]) -> Self {
+        Writer { out: magic.to_vec(), strings: HashMap::new() }
+    }
+
+    fn varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.out.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.out.push(n as u8);
+    }
+
+    /// `to`, as a signed distance from `from`.
+    fn offset(&mut self, from: usize, to: usize) {
+        let delta = to as i64 - from as i64;
+        self.varint(((delta << 1) ^ (delta >> 63)) as u64);
+    }
+
+    fn blob(&mut self, s: &str) {
+        self.varint(s.len() as u64);
+        self.out.extend(s.as_bytes());
+    }
+
+    /// A string seen before is its index plus one; a new one is 0, then the
+    /// string.
+    fn string(&mut self, s: &str) {
+        if let Some(&index) = self.strings.get(s) {
+            self.varint(index + 1);
+        } else {
+            self.varint(0);
+            self.blob(s);
+            self.strings.insert(s.to_string(), self.strings.len() as u64);
+        }
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        match value {
+            Some(value) => {
+                self.out.push(1);
+                write(self, value);
+            }
+            None => self.out.push(0),
+        }
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    strings: Vec<String>,
+}
+
+impl<'b> Reader<'b> {
+    fn new(bytes: &'b [u8], magic: &[u8; 4], what: &str) -> Result<Self> {
+        match bytes.strip_prefix(magic.as_slice()) {
+            Some(bytes) => Ok(Reader { bytes, strings: Vec::new() }),
+            None if bytes.starts_with(&magic[..3]) => bail!("unsupported binary {what} version"),
+            None => bail!("not a binary {what}"),
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'b [u8]> {
+        if n > self.bytes.len() {
+            bail!("truncated input");
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        bail!("bad varint")
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        Ok(usize::try_from(self.varint()?)?)
+    }
+
+    fn offset(&mut self, from: usize) -> Result<usize> {
+        let n = self.varint()?;
+        let delta = (n >> 1) as i64 ^ -((n & 1) as i64);
+        match (from as i64).checked_add(delta).and_then(|to| usize::try_from(to).ok()) {
+            Some(to) => Ok(to),
+            None => bail!("bad offset"),
+        }
+    }
+
+    /// The end of a range starting at `start`, stored as its length.
+    fn end(&mut self, start: usize) -> Result<usize> {
+        match start.checked_add(self.len()?) {
+            Some(end) => Ok(end),
+            None => bail!("bad length"),
+        }
+    }
+
+    fn blob(&mut self) -> Result<String> {
+        let n = self.len()?;
+        Ok(String::from_utf8(self.take(n)?.to_vec())?)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.len()? {
+            0 => {
+                let s = self.blob()?;
+                self.strings.push(s.clone());
+                Ok(s)
+            }
+            i => match self.strings.get(i - 1) {
+                Some(s) => Ok(s.clone()),
+                None => bail!("bad string reference {i}"),
+            },
+        }
+    }
+
+    fn option<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        match self.take(1)?[0] {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            tag => bail!("bad option tag {tag}"),
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        if !self.bytes.is_empty() {
+            bail!("{} trailing bytes", self.bytes.len());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+    use crate::ast::parse_typescript_to_ast;
+    use crate::diff::diff_top_level;
+
+    const BASE: &str = "import { a } from './a';\n\nexport class User {\n  name = 'ü';\n}\n\nfunction f() {}\n";
+    const OTHER: &str = "import { a, b } from './a';\n\nexport class User {\n  name = 'ü';\n  id = 1;\n}\n\nfunction g() {}\n\nconst x = (;\n";
+
+    fn json<T: serde::Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn ast_round_trips() {
+        for code in [BASE, OTHER, ""] {
+            let file = parse_typescript_to_ast(code, AstLanguage::Tsx).unwrap();
+            let back = ast_from_bytes(&ast_to_bytes(&file)).unwrap();
+            assert_eq!(json(&back), json(&file));
+        }
+    }
+
+    #[test]
+    fn edits_round_trip() {
+        let base = parse_typescript_to_ast(BASE, AstLanguage::TypeScript).unwrap();
+        let other = parse_typescript_to_ast(OTHER, AstLanguage::TypeScript).unwrap();
+        let edits = diff_top_level(&base, &other);
+        assert!(!edits.is_empty());
+        assert_eq!(json(&edits_from_bytes(&edits_to_bytes(&edits)).unwrap()), json(&edits));
+    }
+
+    #[test]
+    fn truncated_or_padded_input_is_an_error() {
+        let file = parse_typescript_to_ast(OTHER, AstLanguage::TypeScript).unwrap();
+        let ast = ast_to_bytes(&file);
+        let edits = edits_to_bytes(&diff_top_level(&parse_typescript_to_ast(BASE, AstLanguage::TypeScript).unwrap(), &file));
+        for n in 0..ast.len() {
+            assert!(ast_from_bytes(&ast[..n]).is_err(), "accepted {n} of {} AST bytes", ast.len());
+        }
+        for n in 0..edits.len() {
+            assert!(edits_from_bytes(&edits[..n]).is_err(), "accepted {n} of {} edit bytes", edits.len());
+        }
+        assert!(ast_from_bytes(&[ast.as_slice(), &[0]].concat()).is_err());
+        assert!(edits_from_bytes(&[edits.as_slice(), &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn foreign_input_is_an_error() {
+        let ast = ast_to_bytes(&parse_typescript_to_ast(BASE, AstLanguage::TypeScript).unwrap());
+        assert!(edits_from_bytes(&ast).is_err());
+        assert!(ast_from_bytes(&edits_to_bytes(&[])).is_err());
+        let mut old = ast.clone();
+        old[3] = 1;
+        assert!(ast_from_bytes(&old).is_err());
+        // an error count that runs past the end
+        let mut corrupt = ast;
+        let last = corrupt.len() - 1;
+        corrupt[last] = 0xff;
+        assert!(ast_from_bytes(&corrupt).is_err());
+        assert!(ast_from_bytes(&[0xff; 64]).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! body.rs
+//! Body-level merging inside function-like units.
+//!
+//! Switch statements are aligned by position and their cases keyed by label,
+//! so branches adding different cases to the same router/reducer merge
+//! automatically. Everything outside the switch bodies must not diverge.
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, parse_tree};
+use crate::members::{Member, MemberMerge, Side, commented_items, merge_members, normalize_ws, pick_frame, render_body};
+
+/// A switch statement's cases plus the byte range of its `{ ... }` body.
+struct SwitchBody {
+    cases: Vec<Member>,
+    range: (usize, usize),
+}
+
+/// Reconcile divergent updates to a function-like unit by merging the cases of
+/// its switch statements. Not applicable when the payloads have no switch, or
+/// a different number of them.
+pub(crate) fn merge_switch_cases(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+    let (Some(o), Some(x), Some(y)) = (
+        switch_bodies(base, lang),
+        switch_bodies(a, lang),
+        switch_bodies(b, lang),
+    ) else {
+        return MemberMerge::NotApplicable;
+    };
+    if o.is_empty() || o.len() != x.len() || o.len() != y.len() {
+        return MemberMerge::NotApplicable;
+    }
+
+    // Code outside the switch bodies (discriminants included) is the frame.
+    let (code, chosen) = match pick_frame(skeleton(base, &o), skeleton(a, &x), skeleton(b, &y)) {
+        Some(Side::A) => (a, &x),
+        Some(Side::B) => (b, &y),
+        None => return MemberMerge::NotApplicable,
+    };
+
+    let mut conflicts = Vec::new();
+    let mut out = code.to_string();
+    // splice right→left so earlier ranges stay valid
+    for i in (0..o.len()).rev() {
+        let switch_label = format!("{label}::switch#{i}");
+        match merge_members(&o[i].cases, &x[i].cases, &y[i].cases, &switch_label) {
+            Ok(merged) => {
+                let (s, e) = chosen[i].range;
+                let body = render_body(&code[s..e], &merged, None);
+                out.replace_range(s..e, &body);
+            }
+            Err(found) => conflicts.extend(found),
+        }
+    }
+
+    if conflicts.is_empty() {
+        MemberMerge::Merged(out)
+    } else {
+        conflicts.reverse();
+        MemberMerge::Conflicts(conflicts)
+    }
+}
+
+/// Collect the outermost switch statements of a payload, in source order.
+fn switch_bodies(code: &str, lang: AstLanguage) -> Option<Vec<SwitchBody>> {
+    let tree = parse_tree(code, lang).ok()?;
+    let mut nodes = Vec::new();
+    collect_switches(tree.root_node(), &mut nodes);
+
+    let mut out = Vec::new();
+    for sw in nodes {
+        let body = sw.child_by_field_name("body")?;
+        let (items, closing) = commented_items(body, code)?;
+        let mut cases = Vec::new();
+        for (c, text) in items {
+            let key = match c.kind() {
+                "switch_case" => {
+                    let value = c.child_by_field_name("value").or_else(|| c.named_child(0))?;
+                    let raw = value.utf8_text(code.as_bytes()).ok()?;
+                    normalize_ws(raw.trim_matches(&['"', '\'', '`'][..]))
+                }
+                "switch_default" => "default".to_string(),
+                _ => continue,
+            };
+            cases.push(Member { key, text });
+        }
+        cases.extend(closing);
+        out.push(SwitchBody {
+            cases,
+            range: (body.start_byte(), body.end_byte()),
+        });
+    }
+    Some(out)
+}
+
+fn collect_switches<'t>(node: Node<'t>, out: &mut Vec<Node<'t>>) {
+    if node.kind() == "switch_statement" {
+        out.push(node);
+        return;
+    }
+    for i in 0..node.child_count() {
+        if let Some(c) = node.child(i) {
+            collect_switches(c, out);
+        }
+    }
+}
+
+/// Payload text with every switch body blanked out.
+fn skeleton(code: &str, switches: &[SwitchBody]) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut last = 0;
+    for sw in switches {
+        out.push_str(&code[last..sw.range.0]);
+        out.push_str("{}");
+        last = sw.range.1;
+    }
+    out.push_str(&code[last..]);
+    out
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+
+    const BASE: &str = concat!(
+        "function reduce(state, action) {\n",
+        "  switch (action.type) {\n    case 'add':\n      return state + 1;\n    default:\n      return state;\n  }\n",
+        "}",
+    );
+
+    fn merge(base: &str, a: &str, b: &str) -> MemberMerge {
+        merge_switch_cases(base, a, b, "function_declaration::reduce", AstLanguage::TypeScript)
+    }
+
+    #[test]
+    fn cases_both_branches_added_merge() {
+        let a = BASE.replace("    default:", "    case 'reset':\n      return 0;\n    default:");
+        let b = BASE.replace("    default:", "    case 'double':\n      return state * 2;\n    default:");
+        let merged = BASE.replace("    default:", "    case 'reset':\n      return 0;\n    case 'double':\n      return state * 2;\n    default:");
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::Merged(merged));
+    }
+
+    #[test]
+    fn a_case_both_changed_conflicts() {
+        let (a, b) = (BASE.replace("state + 1", "state + 2"), BASE.replace("state + 1", "state + 3"));
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::Conflicts(vec![
+                "Both branches updated function_declaration::reduce::switch#0::add differently".to_string()
+            ]));
+    }
+
+    #[test]
+    fn code_outside_the_switch_must_not_diverge() {
+        let a = BASE.replace("  switch", "  log(action);\n  switch");
+        let b = BASE.replace("  switch", "  trace(action);\n  switch");
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::NotApplicable);
+        assert_eq!(merge("function f() {}", "function f() { a(); }", "function f() { b(); }"), MemberMerge::NotApplicable);
+    }
+}
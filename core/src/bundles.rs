@@ -0,0 +1,133 @@
+//! bundles.rs
+//! Conflict bundles: one conflict with everything needed to resolve it away
+//! from the merge (by an LLM or another external resolver), as one JSON
+//! document.
+//!
+//! ```json
+//! {"path": "src/user.ts", "id": "3864013143c3ef18", "kind": "update-update",
+//!  "unit": "function_declaration::save", "message": "…",
+//!  "language": "TypeScript", "base": "…", "a": "…", "b": "…",
+//!  "before": "…", "after": "…", "imports": ["import { db } from './db';"],
+//!  "choose": null, "text": null}
+//! ```
+//!
+//! `before` and `after` are the merged file's lines around the unit. The
+//! resolver answers by filling in `choose` or `text` as in a
+//! `resolutions.toml`; [`crate::Resolutions::load`] reads a directory of
+//! answered bundles like one.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{AstFile, AstLanguage, ParseOptions, parse_typescript_to_ast_with};
+use crate::conflicts::{Conflict, ConflictSite};
+use crate::resolutions::Resolution;
+
+/// Lines of context on either side of a unit.
+const CONTEXT_LINES: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictBundle {
+    /// Repo-relative path of the file.
+    pub path: String,
+    #[serde(flatten)]
+    pub conflict: Conflict,
+    pub language: AstLanguage,
+    /// The unit (or file) in base, A and B; `None` where it doesn't exist.
+    pub base: Option<String>,
+    pub a: Option<String>,
+    pub b: Option<String>,
+    /// The merged file's lines right before and after the unit (empty for a
+    /// conflict about the whole file).
+    pub before: String,
+    pub after: String,
+    /// The merged file's import statements.
+    pub imports: Vec<String>,
+    /// The answer: `"a"`, `"b"` or `"union"`, or the unit's text.
+    #[serde(default)]
+    pub choose: Option<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+impl ConflictBundle {
+    /// The resolution the bundle was answered with, if any.
+    pub fn resolution(&self) -> Result<Option<Resolution>> {
+        Ok(match (&self.text, self.choose.as_deref()) {
+            (Some(text), _) => Some(Resolution::Text(text.clone())),
+            (None, None | Some("")) => None,
+            (None, Some(choice)) => match Resolution::choice(choice) {
+                Some(resolution) => Some(resolution),
+                None => bail!("conflict {}: `choose` must be \"a\", \"b\" or \"union\"", self.conflict.id),
+            },
+        })
+    }
+}
+
+/// Bundles for the conflicts `sites` (see [`crate::locate_conflicts`]) of a
+/// merge of `path` into `merged`; `base` is the file in base, if there is one.
+pub fn conflict_bundles(
+    path: &str,
+    sites: &[ConflictSite],
+    base: Option<&str>,
+    merged: &str,
+    lang: AstLanguage,
+    opts: &ParseOptions,
+) -> Vec<ConflictBundle> {
+    let parse = |code: &str| parse_typescript_to_ast_with(code, lang, opts).ok();
+    let (merged_file, base_file) = (parse(merged), base.and_then(parse));
+    let imports: Vec<String> = merged_file
+        .iter()
+        .flat_map(AstFile::keyed_units)
+        .filter(|((kind, _), _)| kind == "import_statement")
+        .map(|(_, u)| merged[u.start_byte..u.end_byte].to_string())
+        .collect();
+    let unit_in = |file: &Option<AstFile>, unit: &str| {
+        let file = file.as_ref()?;
+        let (_, u) = file.keyed_units().into_iter().find(|((k, n), _)| format!("{k}::{n}") == unit)?;
+        Some(file.code[u.start_byte..u.end_byte].to_string())
+    };
+    sites
+        .iter()
+        .map(|site| {
+            let (base, before, after) = match (&site.conflict.unit, site.bytes) {
+                (Some(unit), Some((start, end))) => {
+                    let before: Vec<&str> = merged[..start].lines().rev().take(CONTEXT_LINES).collect();
+                    let after: Vec<&str> = merged[end..].lines().skip(1).take(CONTEXT_LINES).collect();
+                    let before = before.into_iter().rev().map(|l| format!("{l}\n")).collect();
+                    (unit_in(&base_file, unit), before, after.join("\n"))
+                }
+                (Some(unit), None) => (unit_in(&base_file, unit), String::new(), String::new()),
+                (None, _) => (base.map(str::to_string), String::new(), String::new()),
+            };
+            ConflictBundle {
+                path: path.to_string(),
+                conflict: site.conflict.clone(),
+                language: lang,
+                base,
+                a: site.a.clone(),
+                b: site.b.clone(),
+                before,
+                after,
+                imports: imports.clone(),
+                choose: None,
+                text: None,
+            }
+        })
+        .collect()
+}
+
+/// The bundles (`*.json`) in `dir`.
+pub(crate) fn read_dir(dir: &Path) -> Result<Vec<ConflictBundle>> {
+    let mut bundles = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            let text = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+            bundles.push(serde_json::from_str(&text).with_context(|| format!("reading {}", path.display()))?);
+        }
+    }
+    Ok(bundles)
+}
@@ -0,0 +1,210 @@
+//! cache.rs
+//! Parsed files kept across runs.
+//!
+//! Watch mode and CI retries merge the same blobs over and over. With an
+//! [`AstCache`] registered through [`crate::MergeOptions::with_ast_cache`],
+//! every parse of a merge input goes through the cache first. Entries are
+//! keyed by the code itself (plus language, parse options and sinkit's
+//! version), so a blob hits whatever ref or path it comes from, and an entry
+//! can't go stale. [`DirAstCache`] keeps them as files in a directory, in
+//! the compact binary form with the `binary` feature (see `binary`), else
+//! as JSON.
+//!
+//! Only units, hashes and syntax errors are stored, not tree-sitter trees
+//! ([`AstFile::ensure_tree`] re-parses if a caller needs one).
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::ast::{AstFile, AstLanguage, ParseOptions, parse_typescript_to_ast_with};
+use crate::hash::stable_hash;
+
+/// Storage for parsed files. Failures are misses: a cache never fails a merge.
+pub trait AstCache: Send + Sync {
+    fn get(&self, key: &str) -> Option<AstFile>;
+    fn put(&self, key: &str, file: &AstFile);
+}
+
+/// An [`AstCache`] of `<dir>/<key>.bin` files (`.json` without the
+/// `binary` feature), safe to share between concurrent runs.
+#[derive(Debug, Clone)]
+pub struct DirAstCache {
+    dir: PathBuf,
+}
+
+impl DirAstCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        DirAstCache { dir: dir.into() }
+    }
+}
+
+#[cfg(feature = "binary")]
+const EXTENSION: &str = "bin";
+#[cfg(not(feature = "binary"))]
+const EXTENSION: &str = "json";
+
+#[cfg(feature = "binary")]
+fn decode(bytes: &[u8]) -> Option<AstFile> {
+    crate::binary::ast_from_bytes(bytes).ok()
+}
+
+#[cfg(not(feature = "binary"))]
+fn decode(bytes: &[u8]) -> Option<AstFile> {
+    serde_json::from_slice(bytes).ok()
+}
+
+#[cfg(feature = "binary")]
+fn encode(file: &AstFile) -> Option<Vec<u8>> {
+    Some(crate::binary::ast_to_bytes(file))
+}
+
+#[cfg(not(feature = "binary"))]
+fn encode(file: &AstFile) -> Option<Vec<u8>> {
+    serde_json::to_vec(file).ok()
+}
+
+impl AstCache for DirAstCache {
+    fn get(&self, key: &str) -> Option<AstFile> {
+        let bytes = fs::read(self.dir.join(format!("{key}.{EXTENSION}"))).ok()?;
+        decode(&bytes)
+    }
+
+    fn put(&self, key: &str, file: &AstFile) {
+        let Some(bytes) = encode(file) else { return };
+        if fs::create_dir_all(&self.dir).is_err() {
+            return;
+        }
+        // write aside and rename, so a concurrent reader never sees half a file
+        let tmp = self.dir.join(format!("{key}.{}.tmp", std::process::id()));
+        if fs::write(&tmp, bytes).is_ok() && fs::rename(&tmp, self.dir.join(format!("{key}.{EXTENSION}"))).is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+}
+
+/// [`parse_typescript_to_ast_with`] through `cache`, if there is one.
+pub(crate) fn parse_cached(
+    code: &str,
+    lang: AstLanguage,
+    opts: &ParseOptions,
+    cache: Option<&dyn AstCache>,
+) -> Result<AstFile> {
+    let Some(cache) = cache else { return parse_typescript_to_ast_with(code, lang, opts) };
+    // a loaded grammar's index depends on load order: key it by what it is
+    let grammar = match lang {
+        AstLanguage::Runtime(id) => crate::grammars::fingerprint(id),
+        _ => None,
+    };
+    let options = format!("{} {opts:?}", grammar.unwrap_or_else(|| format!("{lang:?}")));
+    let key = format!("{:016x}", stable_hash(&[env!("CARGO_PKG_VERSION"), &options, code]));
+    // the stored code guards against hash collisions; a grammar's stored
+    // index may be another process's
+    if let Some(mut file) = cache.get(&key)
+        && file.code == code
+    {
+        file.lang = lang;
+        return Ok(file);
+    }
+    let file = parse_typescript_to_ast_with(code, lang, opts)?;
+    cache.put(&key, &file);
+    Ok(file)
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// A [`DirAstCache`] recording the keys it's asked for and given.
+    struct Recording {
+        dir: DirAstCache,
+        gets: Mutex<Vec<(String, bool)>>,
+        puts: Mutex<Vec<String>>,
+    }
+
+    impl Recording {
+        fn new(dir: &std::path::Path) -> Self {
+            Recording { dir: DirAstCache::new(dir), gets: Mutex::default(), puts: Mutex::default() }
+        }
+
+        fn hits(&self) -> usize {
+            self.gets.lock().unwrap().iter().filter(|(_, hit)| *hit).count()
+        }
+    }
+
+    impl AstCache for Recording {
+        fn get(&self, key: &str) -> Option<AstFile> {
+            let file = self.dir.get(key);
+            self.gets.lock().unwrap().push((key.to_string(), file.is_some()));
+            file
+        }
+
+        fn put(&self, key: &str, file: &AstFile) {
+            self.puts.lock().unwrap().push(key.to_string());
+            self.dir.put(key, file);
+        }
+    }
+
+    const CODE: &str = "export function f() {}\nexport const x = 1;\n";
+
+    fn names(file: &AstFile) -> Vec<String> {
+        file.keyed_units().into_iter().map(|((kind, name), _)| format!("{kind}::{name}")).collect()
+    }
+
+    #[test]
+    fn a_second_parse_is_a_hit() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Recording::new(dir.path());
+        let opts = ParseOptions::default();
+        let first = parse_cached(CODE, AstLanguage::TypeScript, &opts, Some(&cache)).unwrap();
+        let second = parse_cached(CODE, AstLanguage::TypeScript, &opts, Some(&cache)).unwrap();
+        assert_eq!((cache.hits(), cache.puts.lock().unwrap().len()), (1, 1));
+        assert_eq!(second.code, CODE);
+        assert_eq!(names(&second), names(&first));
+        assert_eq!(names(&second), ["function_declaration::f", "lexical_declaration::x"]);
+    }
+
+    #[test]
+    fn other_options_or_languages_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Recording::new(dir.path());
+        let tests = ParseOptions { test_blocks: true, ..ParseOptions::default() };
+        parse_cached(CODE, AstLanguage::TypeScript, &ParseOptions::default(), Some(&cache)).unwrap();
+        parse_cached(CODE, AstLanguage::TypeScript, &tests, Some(&cache)).unwrap();
+        parse_cached(CODE, AstLanguage::Tsx, &ParseOptions::default(), Some(&cache)).unwrap();
+        assert_eq!(cache.hits(), 0);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn an_entry_for_other_code_is_not_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Recording::new(dir.path());
+        let opts = ParseOptions::default();
+        parse_cached(CODE, AstLanguage::TypeScript, &opts, Some(&cache)).unwrap();
+        // another file's parse under the key, as a hash collision would leave it
+        let key = cache.puts.lock().unwrap()[0].clone();
+        let other = parse_typescript_to_ast_with("export class C {}\n", AstLanguage::TypeScript, &opts).unwrap();
+        cache.dir.put(&key, &other);
+        let file = parse_cached(CODE, AstLanguage::TypeScript, &opts, Some(&cache)).unwrap();
+        assert_eq!(file.code, CODE);
+        assert_eq!(names(&file), ["function_declaration::f", "lexical_declaration::x"]);
+        // and it's replaced
+        assert_eq!(cache.dir.get(&key).unwrap().code, CODE);
+    }
+
+    #[test]
+    fn unreadable_entries_are_misses() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DirAstCache::new(dir.path());
+        fs::write(dir.path().join(format!("junk.{EXTENSION}")), b"not an ast").unwrap();
+        assert!(cache.get("junk").is_none());
+        assert!(cache.get("missing").is_none());
+        // nowhere to write: nothing happens
+        let file = parse_typescript_to_ast_with(CODE, AstLanguage::TypeScript, &ParseOptions::default()).unwrap();
+        DirAstCache::new(dir.path().join(format!("junk.{EXTENSION}"))).put("k", &file);
+    }
+}
@@ -0,0 +1,194 @@
+//! clusters.rs
+//! Group conflicts that one divergence caused.
+//!
+//! Renaming a type on one branch while the other edits it reports a
+//! delete-update on the type, then an update-update in every unit that uses
+//! it: several conflicts, one decision. A conflict whose unit's name appears
+//! (as a whole identifier) in another conflicted unit's text in A or B is
+//! linked to it; each connected group of two or more is a cluster, rooted at
+//! the conflict the most others mention — the one to resolve first.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::conflicts::ConflictSite;
+use crate::hash::stable_hash;
+
+/// Conflicts that probably share a cause.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictCluster {
+    /// Stable ID, 16 hex digits (derived from the root's ID).
+    pub id: String,
+    /// ID of the conflict suspected to cause the others.
+    pub root: String,
+    /// IDs of every conflict in the cluster, the root first.
+    pub members: Vec<String>,
+    /// What the root conflict is and how many conflicts follow from it.
+    pub cause: String,
+}
+
+/// Clusters among `sites` (path, site), largest first. Conflicts in no
+/// cluster are left out.
+pub fn cluster_conflicts(sites: &[(String, ConflictSite)]) -> Vec<ConflictCluster> {
+    let symbols: Vec<Option<&str>> = sites.iter().map(|(_, site)| symbol(site)).collect();
+    // i -> the sites whose text mentions i's symbol
+    let mut users: Vec<Vec<usize>> = vec![Vec::new(); sites.len()];
+    let mut by_symbol: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, symbol) in symbols.iter().enumerate() {
+        if let Some(symbol) = symbol {
+            by_symbol.entry(symbol).or_default().push(i);
+        }
+    }
+    for (j, (_, site)) in sites.iter().enumerate() {
+        let mut mentioned: Vec<usize> = [&site.a, &site.b]
+            .into_iter()
+            .flatten()
+            .flat_map(|text| identifiers(text))
+            .filter_map(|word| by_symbol.get(word))
+            .flatten()
+            .copied()
+            .filter(|&i| i != j && symbols[i] != symbols[j])
+            .collect();
+        mentioned.sort_unstable();
+        mentioned.dedup();
+        for i in mentioned {
+            users[i].push(j);
+        }
+    }
+
+    let mut group: Vec<usize> = (0..sites.len()).collect();
+    fn find(group: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while group[root] != root {
+            root = group[root];
+        }
+        group[i] = root;
+        root
+    }
+    for (i, js) in users.iter().enumerate() {
+        for &j in js {
+            let (x, y) = (find(&mut group, i), find(&mut group, j));
+            group[x.max(y)] = x.min(y);
+        }
+    }
+    let mut components: Vec<Vec<usize>> = Vec::new();
+    let mut index: HashMap<usize, usize> = HashMap::new();
+    for i in 0..sites.len() {
+        let g = find(&mut group, i);
+        let at = *index.entry(g).or_insert_with(|| {
+            components.push(Vec::new());
+            components.len() - 1
+        });
+        components[at].push(i);
+    }
+
+    let mut clusters: Vec<ConflictCluster> = components
+        .into_iter()
+        .filter(|members| members.len() > 1)
+        .map(|members| {
+            let root = *members
+                .iter()
+                .max_by_key(|&&i| (users[i].len(), is_removal(&sites[i].1), std::cmp::Reverse(i)))
+                .expect("clusters have members");
+            let site = &sites[root].1;
+            let unit = site.conflict.unit.as_deref().unwrap_or("the file");
+            let n = members.len() - 1;
+            let follows = if n == 1 { "1 other conflict follows".to_string() } else { format!("{n} other conflicts follow") };
+            let cause = if is_removal(site) {
+                format!("{unit} was removed or renamed on one side and changed on the other; {follows} from it")
+            } else {
+                format!("both sides changed {unit}; {follows} from it")
+            };
+            let root_id = site.conflict.id.clone();
+            let members = std::iter::once(root)
+                .chain(members.into_iter().filter(|&i| i != root))
+                .map(|i| sites[i].1.conflict.id.clone())
+                .collect();
+            ConflictCluster { id: format!("{:016x}", stable_hash(&["cluster", &root_id])), root: root_id, members, cause }
+        })
+        .collect();
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.members.len()));
+    clusters
+}
+
+/// The identifier a conflicted unit is known by: the leading identifier of
+/// the name in `kind::name` (so `Foo.bar`, `foo#2` and `foo(a)` give `Foo`
+/// and `foo`). Imports name modules, not symbols, and are left out.
+fn symbol(site: &ConflictSite) -> Option<&str> {
+    let (kind, name) = site.conflict.unit.as_deref()?.split_once("::")?;
+    if kind == "import_statement" {
+        return None;
+    }
+    let end = name.find(|c: char| !is_ident(c)).unwrap_or(name.len());
+    let symbol = &name[..end];
+    (symbol.len() > 1 && !symbol.starts_with(|c: char| c.is_ascii_digit())).then_some(symbol)
+}
+
+fn is_removal(site: &ConflictSite) -> bool {
+    matches!(site.conflict.kind.as_str(), "delete-update" | "exported-delete" | "delete-modify" | "split-edit")
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+fn identifiers(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !is_ident(c)).filter(|word| !word.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Conflict;
+
+    fn site(kind: &str, unit: (&str, &str), a: Option<&str>, b: Option<&str>) -> (String, ConflictSite) {
+        let unit = (unit.0.to_string(), unit.1.to_string());
+        let conflict = Conflict::new(Some("app.ts"), kind, Some(&unit), (None, None), "m");
+        ("app.ts".to_string(), ConflictSite { conflict, bytes: None, lines: None, a: a.map(Into::into), b: b.map(Into::into) })
+    }
+
+    #[test]
+    fn one_divergence_is_one_cluster_rooted_at_its_cause() {
+        let update = |name, a, b| site("update-update", ("function_declaration", name), Some(a), Some(b));
+        let sites = [
+            update("save", "function save(u: User) {}", "function save(u: User, force) {}"),
+            site("update-update", ("lexical_declaration", "LIMIT"), Some("const LIMIT = 1;"), Some("const LIMIT = 2;")),
+            site("delete-update", ("interface_declaration", "User"), None, Some("interface User { id: string }")),
+            update("other", "function other() {}", "function other(x) {}"),
+            update("load", "function load(): User {}", "function load(id): User {}"),
+            update("clamp", "function clamp(x) { return LIMIT; }", "function clamp() { return LIMIT; }"),
+        ];
+        let id = |i: usize| sites[i].1.conflict.id.clone();
+        let clusters = cluster_conflicts(&sites);
+        let found: Vec<_> = clusters.iter().map(|c| (c.root.clone(), c.members.clone(), c.cause.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    id(2),
+                    vec![id(2), id(0), id(4)],
+                    concat!(
+                        "interface_declaration::User was removed or renamed on one side and changed on the other; ",
+                        "2 other conflicts follow from it"
+                    ),
+                ),
+                (id(1), vec![id(1), id(5)], "both sides changed lexical_declaration::LIMIT; 1 other conflict follows from it"),
+            ]
+        );
+        // stable from run to run
+        assert_eq!(cluster_conflicts(&sites), clusters);
+        assert_ne!(clusters[0].id, clusters[1].id);
+    }
+
+    #[test]
+    fn parts_of_words_and_imports_link_nothing() {
+        let update = |kind, name, a, b| site("update-update", (kind, name), Some(a), Some(b));
+        let sites = [
+            update("function_declaration", "id", "function id(x) { return x; }", "function id(x) { return x + 1; }"),
+            update("function_declaration", "idle", "function idle() { ids(); }", "function idle() {}"),
+            update("import_statement", "react", "import React from \"react\";", "import * as react from \"react\";"),
+        ];
+        assert!(cluster_conflicts(&sites).is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+//! compare.rs
+//! When two versions of a unit are the same change.
+//!
+//! Compose compares payloads to tell identical updates from divergent ones
+//! and to drop inserts both branches made. Both compare up to formatting
+//! ([`TokenComparer`]): a branch that ran a formatter with other quote or
+//! semicolon settings made the same change. A [`UnitComparer`] registered
+//! with [`crate::MergeOptions::with_comparer`] decides instead. Payloads
+//! the comparer equates but that differ in text keep A's version, with a
+//! warning.
+
+use crate::ast::AstLanguage;
+use crate::hash::{bytes_hash, content_hash, token_hash, tokens_hash};
+
+/// Decides which versions of a unit count as the same.
+pub trait UnitComparer: Send + Sync {
+    /// A fingerprint of `code`, equal for versions that count as the same
+    /// (and, as far as possible, different otherwise).
+    fn fingerprint(&self, code: &str, lang: AstLanguage) -> u64;
+}
+
+impl<F> UnitComparer for F
+where
+    F: Fn(&str, AstLanguage) -> u64 + Send + Sync,
+{
+    fn fingerprint(&self, code: &str, lang: AstLanguage) -> u64 {
+        self(code, lang)
+    }
+}
+
+/// Byte for byte.
+pub struct ExactComparer;
+
+impl UnitComparer for ExactComparer {
+    fn fingerprint(&self, code: &str, _lang: AstLanguage) -> u64 {
+        bytes_hash(code.as_bytes())
+    }
+}
+
+/// Up to whitespace ([`crate::content_hash`]).
+pub struct WhitespaceComparer;
+
+impl UnitComparer for WhitespaceComparer {
+    fn fingerprint(&self, code: &str, _lang: AstLanguage) -> u64 {
+        content_hash(code)
+    }
+}
+
+/// Up to whitespace and quote style: `'a'`, `"a"` and `` `a` `` are the
+/// same string.
+pub struct QuoteComparer;
+
+impl UnitComparer for QuoteComparer {
+    fn fingerprint(&self, code: &str, lang: AstLanguage) -> u64 {
+        tokens_hash(code, lang, true)
+    }
+}
+
+/// Up to formatting: whitespace, quote style, optional semicolons and
+/// trailing commas ([`crate::token_hash`]). The default.
+pub struct TokenComparer;
+
+impl UnitComparer for TokenComparer {
+    fn fingerprint(&self, code: &str, lang: AstLanguage) -> u64 {
+        token_hash(code, lang)
+    }
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+
+    /// Whether each of exact, whitespace, quote and token comparison counts
+    /// `a` and `b` the same.
+    fn same(a: &str, b: &str) -> [bool; 4] {
+        let comparers: [&dyn UnitComparer; 4] = [&ExactComparer, &WhitespaceComparer, &QuoteComparer, &TokenComparer];
+        comparers.map(|c| c.fingerprint(a, AstLanguage::TypeScript) == c.fingerprint(b, AstLanguage::TypeScript))
+    }
+
+    #[test]
+    fn each_comparer_ignores_its_own_differences() {
+        assert_eq!(same("const a = 1;", "const a = 1;"), [true, true, true, true]);
+        assert_eq!(same("const a = 1;", "const  a =\n  1;"), [false, true, true, true]);
+        assert_eq!(same("const s = \"x\";", "const s = 'x';"), [false, false, true, true]);
+        assert_eq!(same("const s = `x`;", "const s = \"x\";"), [false, false, true, true]);
+        assert_eq!(same("const s = \"it's\";", "const s = 'it\\'s';"), [false, false, true, true]);
+        // optional semicolons and trailing commas
+        assert_eq!(same("const a = 1;", "const a = 1"), [false, false, false, true]);
+        assert_eq!(same("f(a, b,);", "f(a, b);"), [false, false, false, true]);
+    }
+
+    #[test]
+    fn changes_are_not_formatting() {
+        assert_eq!(same("const a = 1;", "const a = 2;"), [false; 4]);
+        assert_eq!(same("const s = \"a\";", "const s = \"b\";"), [false; 4]);
+        // without the semicolon, the next line is a call of b
+        assert_eq!(same("const a = b\n(c)", "const a = b;\n(c)"), [false; 4]);
+        // whitespace comparison is textual, strings included
+        assert_eq!(same("const s = \"a b\";", "const s = \"a  b\";"), [false, true, false, false]);
+    }
+
+    #[test]
+    fn closures_are_comparers() {
+        let by_length = |code: &str, _: AstLanguage| code.len() as u64;
+        assert_eq!(by_length.fingerprint("abc", AstLanguage::TypeScript), by_length.fingerprint("xyz", AstLanguage::TypeScript));
+    }
+}
@@ -2,45 +2,601 @@
 //! Compose Base->A and Base->B edit scripts *safely* at the top level, with
 //! precise splicing and a small reconcilation for function parameter renames.
 
-use anyhow::*;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
-use crate::ast::AstFile;
+use crate::ast::{AstFile, AstLanguage, ParseOptions, TopLevel, parse_tree, split_leading_comments};
+use crate::cache::AstCache;
+use crate::conflicts::{self, Conflict};
 use crate::diff::Edit;
+use crate::compare::{TokenComparer, UnitComparer};
+use crate::formatter::Formatter;
+use crate::hash::content_hash;
+use crate::indent::{Indent, reindent};
+use crate::members::{self, MemberMerge, MemberReconciler, Side, pick_frame};
+use crate::policy::{GeneratedMerge, Spacing};
+use crate::regions::IGNORED_REGION;
+use crate::resolutions::{Resolution, Resolutions};
+use crate::{body, decorators, diff3, hooks, imports, jsx, namespaces, reexports, renames, specs, splits};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MergeOutcome {
     pub merged_code: String,
-    pub conflicts: Vec<String>,
+    pub conflicts: Vec<Conflict>,
+    /// Conflicts acknowledged via [`MergeOptions::with_suppressed`]; they no
+    /// longer block the merge.
+    #[serde(default)]
+    pub suppressed: Vec<Conflict>,
+    /// Conflicts settled by [`MergeOptions::with_resolutions`].
+    #[serde(default)]
+    pub resolved: Vec<Conflict>,
+    /// Findings that don't block an automatic merge but deserve a look
+    /// (heuristics applied, near-duplicate inserts, ...).
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// Syntax errors found in the inputs, e.g. `A: ERROR at 3:5`.
+    #[serde(default)]
+    pub syntax_errors: Vec<String>,
+    /// The file was merged as a whole, textually (policy, size limits, syntax
+    /// errors, no grammar), not unit by unit.
+    #[serde(default)]
+    pub textual: bool,
+    /// The merge deletes the file (see [`crate::merge_deleted`]).
+    #[serde(default)]
+    pub deleted: bool,
+    /// Resolutions a heuristic made, each with how sure it is (see
+    /// [`MergeOptions::with_min_confidence`]).
+    #[serde(default)]
+    pub auto_resolutions: Vec<AutoResolution>,
+    /// Edits taken from each branch, unit by unit.
+    #[serde(default)]
+    pub stats: MergeStats,
+}
+
+/// What a merge did, unit by unit: the edits it took from each branch, and
+/// the units both branches changed that it merged anyway (heuristic ones
+/// are also in [`MergeOutcome::auto_resolutions`]). Units in conflict,
+/// files merged textually and files only one branch changed (taken whole)
+/// count nowhere.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeStats {
+    pub inserts_a: usize,
+    pub inserts_b: usize,
+    pub updates_a: usize,
+    pub updates_b: usize,
+    pub deletes_a: usize,
+    pub deletes_b: usize,
+    /// Units both branches changed the same way (up to formatting), inserted
+    /// alike or deleted.
+    pub same_changes: usize,
+    /// Units both branches changed differently, merged by a reconciler
+    /// (imports, members, parameter renames, ...).
+    pub reconciled: usize,
+}
+
+impl MergeStats {
+    /// Edits taken from A alone.
+    pub fn from_a(&self) -> usize {
+        self.inserts_a + self.updates_a + self.deletes_a
+    }
+
+    /// Edits taken from B alone.
+    pub fn from_b(&self) -> usize {
+        self.inserts_b + self.updates_b + self.deletes_b
+    }
+}
+
+impl std::ops::AddAssign for MergeStats {
+    fn add_assign(&mut self, other: Self) {
+        self.inserts_a += other.inserts_a;
+        self.inserts_b += other.inserts_b;
+        self.updates_a += other.updates_a;
+        self.updates_b += other.updates_b;
+        self.deletes_a += other.deletes_a;
+        self.deletes_b += other.deletes_b;
+        self.same_changes += other.same_changes;
+        self.reconciled += other.reconciled;
+    }
+}
+
+/// A unit merged on a heuristic's judgement rather than because only one
+/// branch changed it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoResolution {
+    /// `kind::name` of the unit.
+    pub unit: String,
+    /// `formatting` (both branches made the same change or insert up to
+    /// formatting), `param-rename`, `rename` (an edit of, or a use of, a unit
+    /// the other branch renamed, carried to the new name), `split` (an edit
+    /// carried into the pieces of a split unit), `moved` (an edit carried
+    /// to where a unit moved), `line-merge` (edits of different lines of a
+    /// unit, merged line by line) or `higher-version` (a lockfile entry both
+    /// branches changed, see [`crate::LockfileFormat`]).
+    pub heuristic: String,
+    /// From 0 to 1.
+    pub confidence: f64,
+}
+
+impl AutoResolution {
+    fn new(unit: &(String, String), heuristic: &str, confidence: f64) -> Self {
+        AutoResolution { unit: format!("{}::{}", unit.0, unit.1), heuristic: heuristic.to_string(), confidence }
+    }
+}
+
+/// Confidence that versions equal up to whitespace, or only up to the
+/// comparer (quotes, semicolons, ...), are the same change.
+const SAME_BUT_WHITESPACE: f64 = 0.99;
+const SAME_BUT_FORMATTING: f64 = 0.95;
+const PARAM_RENAME: f64 = 0.8;
+const LINE_MERGE: f64 = 0.8;
+const RENAME: f64 = 0.85;
+const MOVED: f64 = 0.75;
+const SPLIT: f64 = 0.7;
+
+/// What reconciling an update found worth reporting besides conflicts.
+#[derive(Default)]
+pub(crate) struct Notes {
+    pub(crate) warnings: Vec<String>,
+    pub(crate) auto_resolutions: Vec<AutoResolution>,
+}
+
+fn formatting_confidence(a: &str, b: &str) -> f64 {
+    if content_hash(a) == content_hash(b) { SAME_BUT_WHITESPACE } else { SAME_BUT_FORMATTING }
+}
+
+/// A divergent-update reconciler, tried (before the built-in ones) when both
+/// branches changed a top-level unit of the kind it is registered for.
+/// `label` is `kind::name`, for conflict messages.
+pub trait Reconciler: Send + Sync {
+    fn reconcile(&self, base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge;
+}
+
+impl<F> Reconciler for F
+where
+    F: Fn(&str, &str, &str, &str, AstLanguage) -> MemberMerge + Send + Sync,
+{
+    fn reconcile(&self, base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+        self(base, a, b, label, lang)
+    }
+}
+
+/// Where units a branch inserted go.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InsertAnchor {
+    /// Next to the base units around them in their branch; inserts both
+    /// branches made at one spot are grouped by branch.
+    #[default]
+    Base,
+    /// Right after the unit that precedes them in their branch, even one
+    /// that's new itself (possibly added by both branches): a new
+    /// `validateEmail` lands right after a new `validateName`, wherever the
+    /// other branch's inserts went.
+    Nearest,
+}
+
+/// Options for a whole merge: unit collection plus custom reconcilers.
+#[derive(Default)]
+pub struct MergeOptions {
+    pub parse: ParseOptions,
+    /// Repo-relative path of the merged file; part of every conflict ID.
+    pub path: Option<String>,
+    /// IDs of conflicts that are reported as suppressed instead of conflicts.
+    pub suppressed: HashSet<String>,
+    /// Decisions for conflicts, by ID (see [`Resolutions`]).
+    pub resolutions: Resolutions,
+    /// Drop import bindings the merge left unused (see [`crate::imports`]).
+    pub prune_imports: bool,
+    /// Files with a version larger than this (in bytes) skip parsing and
+    /// merge textually, bounding memory on huge generated files.
+    pub max_parse_bytes: Option<usize>,
+    /// Memory (in bytes) a merge may take for its trees and snippets; files
+    /// estimated to need more merge textually (see [`crate::merge_batch`]
+    /// for a budget shared across files).
+    pub memory_limit: Option<usize>,
+    /// Splice units from a branch with the branch's indentation, instead of
+    /// re-indenting them to the file's style (see [`crate::indent`]).
+    pub keep_indentation: bool,
+    /// Where inserted units go.
+    pub insert_anchor: InsertAnchor,
+    /// Units (`kind::name`) a branch moved to another file (see
+    /// [`crate::extracted_units`]): their deletion wins over an edit.
+    pub moved: HashSet<String>,
+    /// The file is generated though it has no header saying so (e.g.
+    /// `linguist-generated` in `.gitattributes`; see [`crate::is_generated`]).
+    pub generated: bool,
+    /// B's commit is newer than A's (for [`crate::GeneratedMerge::TakeNewer`]).
+    pub b_is_newer: bool,
+    /// Heuristic resolutions less sure than this become conflicts.
+    pub min_confidence: Option<f64>,
+    reconcilers: Vec<(String, Box<dyn Reconciler>)>,
+    comparer: Option<Box<dyn UnitComparer>>,
+    formatter: Option<Box<dyn Formatter>>,
+    ast_cache: Option<Box<dyn AstCache>>,
+}
+
+impl MergeOptions {
+    pub fn new(parse: ParseOptions) -> Self {
+        MergeOptions { parse, ..Default::default() }
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Acknowledge conflicts by [`Conflict::id`].
+    pub fn with_suppressed<I, S>(mut self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.suppressed.extend(ids.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_resolutions(mut self, resolutions: Resolutions) -> Self {
+        self.resolutions = resolutions;
+        self
+    }
+
+    pub fn with_pruned_imports(mut self, prune: bool) -> Self {
+        self.prune_imports = prune;
+        self
+    }
+
+    /// Merge files over `limit` bytes textually instead of parsing them.
+    pub fn with_max_parse_bytes(mut self, limit: Option<usize>) -> Self {
+        self.max_parse_bytes = limit;
+        self
+    }
+
+    /// Merge files whose semantic merge would take more than `limit` bytes
+    /// of memory textually.
+    pub fn with_memory_limit(mut self, limit: Option<usize>) -> Self {
+        self.memory_limit = limit;
+        self
+    }
+
+    pub fn with_kept_indentation(mut self, keep: bool) -> Self {
+        self.keep_indentation = keep;
+        self
+    }
+
+    pub fn with_insert_anchor(mut self, anchor: InsertAnchor) -> Self {
+        self.insert_anchor = anchor;
+        self
+    }
+
+    /// Take the deletion of these units (`kind::name`) over the other
+    /// branch's edits, which [`crate::carry_moved_edits`] applies where
+    /// they went.
+    pub fn with_moved<I, S>(mut self, units: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.moved.extend(units.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_generated(mut self, generated: bool) -> Self {
+        self.generated = generated;
+        self
+    }
+
+    pub fn with_b_newer(mut self, newer: bool) -> Self {
+        self.b_is_newer = newer;
+        self
+    }
+
+    /// Report a heuristic resolution whose confidence (see
+    /// [`AutoResolution`]) is below `min` as a conflict instead, for a human
+    /// to review.
+    pub fn with_min_confidence(mut self, min: Option<f64>) -> Self {
+        self.min_confidence = min;
+        self
+    }
+
+    /// A conflict message for the resolution `note` describes, if its
+    /// `confidence` is too low to apply it.
+    pub(crate) fn doubt(&self, confidence: f64, note: &str) -> Option<String> {
+        let min = self.min_confidence.filter(|&min| confidence < min)?;
+        Some(format!("Held for review (confidence {confidence}, below the minimum {min}): {note}"))
+    }
+
+    /// Decide with `comparer` whether both branches made the same update or
+    /// insert (see [`crate::compare`]).
+    pub fn with_comparer(mut self, comparer: Box<dyn UnitComparer>) -> Self {
+        self.comparer = Some(comparer);
+        self
+    }
+
+    pub(crate) fn comparer(&self) -> Option<&dyn UnitComparer> {
+        self.comparer.as_deref()
+    }
+
+    /// Run merged files both branches changed through `formatter`.
+    pub fn with_formatter(mut self, formatter: Box<dyn Formatter>) -> Self {
+        self.formatter = Some(formatter);
+        self
+    }
+
+    pub(crate) fn formatter(&self) -> Option<&dyn Formatter> {
+        self.formatter.as_deref()
+    }
+
+    /// Look parsed inputs up in `cache` (and store new ones there).
+    pub fn with_ast_cache(mut self, cache: Box<dyn AstCache>) -> Self {
+        self.ast_cache = Some(cache);
+        self
+    }
+
+    pub(crate) fn ast_cache(&self) -> Option<&dyn AstCache> {
+        self.ast_cache.as_deref()
+    }
+
+    /// Register `reconciler` for units of `kind` (e.g. `"function_declaration"`).
+    /// Reconcilers registered for the same kind are tried in registration order.
+    pub fn with_reconciler(mut self, kind: impl Into<String>, reconciler: Box<dyn Reconciler>) -> Self {
+        self.reconcilers.push((kind.into(), reconciler));
+        self
+    }
+
+    fn custom_reconcilers<'s>(&'s self, kind: &'s str) -> impl Iterator<Item = &'s dyn Reconciler> + 's {
+        self.reconcilers.iter().filter(move |(k, _)| k == kind).map(|(_, r)| r.as_ref())
+    }
+}
+
+impl std::fmt::Debug for MergeOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MergeOptions")
+            .field("parse", &self.parse)
+            .field("path", &self.path)
+            .field("suppressed", &self.suppressed)
+            .field("resolutions", &self.resolutions.len())
+            .field("prune_imports", &self.prune_imports)
+            .field("max_parse_bytes", &self.max_parse_bytes)
+            .field("memory_limit", &self.memory_limit)
+            .field("keep_indentation", &self.keep_indentation)
+            .field("insert_anchor", &self.insert_anchor)
+            .field("moved", &self.moved)
+            .field("generated", &self.generated)
+            .field("b_is_newer", &self.b_is_newer)
+            .field("min_confidence", &self.min_confidence)
+            .field("reconcilers", &self.reconcilers.iter().map(|(k, _)| k).collect::<Vec<_>>())
+            .field("comparer", &self.comparer.is_some())
+            .field("formatter", &self.formatter.is_some())
+            .field("ast_cache", &self.ast_cache.is_some())
+            .finish()
+    }
 }
 
 /// Map (kind,name) -> (start,end) from Base
 fn index_base_ranges(base: &AstFile) -> HashMap<(String, String), (usize, usize)> {
-    let mut idx = HashMap::new();
-    for u in &base.units {
-        if let (kind, Some(name)) = (u.kind.clone(), u.name.clone()) {
-            idx.insert((kind, name), (u.start_byte, u.end_byte));
-        }
-    }
-    idx
+    base.keyed_units()
+        .into_iter()
+        .map(|(key, u)| (key, (u.start_byte, u.end_byte)))
+        .collect()
 }
 
 pub fn compose_top_level(base: &AstFile, ea: &[Edit], eb: &[Edit]) -> Result<MergeOutcome> {
+    compose_top_level_with(base, ea, eb, &MergeOptions::default())
+}
+
+/// Like [`compose_top_level`], consulting the file's merge policy before
+/// applying automatic resolutions.
+pub fn compose_top_level_with(base: &AstFile, ea: &[Edit], eb: &[Edit], opts: &MergeOptions) -> Result<MergeOutcome> {
+    compose(base, ea, eb, opts, Indent::detect(&base.code))
+}
+
+/// [`compose_top_level_with`], with inserted and updated units re-indented
+/// to `indent` (the merged file's style).
+pub(crate) fn compose<'e>(
+    base: &AstFile,
+    ea: &'e [Edit],
+    eb: &'e [Edit],
+    opts: &MergeOptions,
+    indent: Option<Indent>,
+) -> Result<MergeOutcome> {
+    // edit scripts are only copied where a payload changes (re-indented,
+    // renames followed), not for the thousands of units of a big file
+    let reindented = |edits: &'e [Edit]| -> Cow<'e, [Edit]> {
+        let mut edits = Cow::Borrowed(edits);
+        if let Some(to) = indent.filter(|_| !opts.keep_indentation) {
+            for i in 0..edits.len() {
+                if let Some(p) = edits[i].payload.as_deref().and_then(|p| reindent(p, to, base.lang)) {
+                    edits.to_mut()[i].payload = Some(p);
+                }
+            }
+        }
+        edits
+    };
+    let (mut ea, mut eb) = (reindented(ea), reindented(eb));
     let mut code = base.code.clone();
     let mut conflicts = Vec::new();
+    let mut warnings = Vec::new();
+    let mut auto = Vec::new();
+    let mut stats = MergeStats::default();
+    let path = opts.path.as_deref();
+
+    // Follow renames: one side's new and updated units that use a name the
+    // other side renamed get the new name (see `renames`)
+    let renames_in = |edits: &[Edit]| -> Vec<(String, String, String)> {
+        edits.iter().filter_map(|e| Some((e.unit_kind.clone(), e.renamed_from.clone()?, e.name.clone()))).collect()
+    };
+    let renamed_by = [(renames_in(&ea), true), (renames_in(&eb), false)];
+    let mut carried: Vec<(String, String)> = Vec::new(); // (from, to)
+    for (renamed, by_a) in &renamed_by {
+        let (renamer, user) = if *by_a { ("A", "B") } else { ("B", "A") };
+        let edits = if *by_a { &mut eb } else { &mut ea };
+        for (kind, from, to) in renamed {
+            // both sides deleted or renamed it: nothing to follow
+            let gone = |e: &Edit| {
+                e.unit_kind == *kind && ((e.kind == "delete" && e.name == *from) || e.renamed_from.as_ref() == Some(from))
+            };
+            if edits.iter().any(gone) {
+                continue;
+            }
+            let mut followed = false;
+            for i in 0..edits.len() {
+                let e = &edits[i];
+                if e.unit_kind == "import_statement" || (e.unit_kind == *kind && e.name == *from) {
+                    continue;
+                }
+                let uses = e.payload.as_deref().filter(|p| p.contains(from.as_str()));
+                let Some(renamed) = uses.and_then(|p| renames::rename_uses(p, base.lang, from, to)) else {
+                    continue;
+                };
+                let e = &mut edits.to_mut()[i];
+                let key = (e.unit_kind.clone(), e.name.clone());
+                let note = format!("{renamer} renamed {kind}::{from} to {to}; renamed {user}'s uses of it in {}::{}", key.0, key.1);
+                let hash = content_hash(&renamed);
+                if let Some(message) = opts.doubt(RENAME, &note) {
+                    conflicts.push(Conflict::new(path, "rename-use", Some(&key), (e.content_hash, Some(hash)), message));
+                    continue;
+                }
+                e.payload = Some(renamed);
+                e.content_hash = Some(hash);
+                warnings.push(note);
+                auto.push(AutoResolution::new(&key, "rename", RENAME));
+                followed = true;
+            }
+            if followed {
+                carried.push((from.clone(), to.clone()));
+            }
+        }
+    }
+    let (ea, eb) = (&ea[..], &eb[..]);
 
     // Collect edits
-    let mut inserts: HashSet<(String, String, String)> = HashSet::new(); // (kind,name,payload)
-    let mut updates_by_side: HashMap<(String, String), (Option<String>, Option<String>)> = HashMap::new(); // (kind,name) -> (A?, B?)
-    let mut deletes: HashSet<(String, String)> = HashSet::new();
+    // ordered collections throughout, so the output is reproducible
+    let mut inserts: Vec<(bool, Cow<Edit>)> = Vec::new(); // (from A?, edit), A's first
+    // (kind, name sans #n) -> indices in `inserts`, so matching an insert
+    // against the other branch's doesn't scan them all
+    let mut inserts_by_name: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    // (kind, name sans #n, token hash) -> copies inserted by (A, B); B's copies
+    // of A's inserts are dropped, even with other formatting
+    let mut seen_inserts: HashMap<(String, String, u64), (usize, usize)> = HashMap::new();
+    type Update<'e> = (&'e str, u64); // (payload, content hash)
+    let mut updates_by_side: BTreeMap<(String, String), (Option<Update>, Option<Update>)> = BTreeMap::new(); // (kind,name) -> (A?, B?)
+    let mut deletes: BTreeSet<(String, String)> = BTreeSet::new();
+    let mut deleted_by: HashMap<(String, String), (bool, bool)> = HashMap::new(); // (kind,name) -> (A?, B?)
+    let mut renamed_away: HashSet<(String, String)> = HashSet::new(); // base keys a rename deleted
+    let mut new_imports: HashMap<String, (Option<String>, Option<String>)> = HashMap::new(); // module -> (A?, B?)
+    // (kind, name sans #n) both branches inserted: only those need the
+    // (costly) fingerprint to tell copies apart
+    let inserted = |edits: &[Edit]| -> HashSet<(String, String)> {
+        edits.iter().filter(|e| matches!(e.kind.as_str(), "insert" | "rename")).map(|e| (e.unit_kind.clone(), without_occurrence(&e.name).to_string())).collect()
+    };
+    let inserted_by_both: HashSet<(String, String)> = inserted(ea).intersection(&inserted(eb)).cloned().collect();
 
-    let mut ingest = |edits: &[Edit], is_a: bool| {
+    for (edits, is_a) in [(ea, true), (eb, false)] {
         for e in edits {
             match e.kind.as_str() {
-                "insert" => {
+                "unmerged" => conflicts.push(Conflict::new(
+                    path,
+                    "unmerged",
+                    Some(&(e.unit_kind.clone(), e.name.clone())),
+                    (None, None),
+                    format!(
+                        "Syntax error around {}::{} in {}; kept the base version",
+                        e.unit_kind,
+                        e.name,
+                        if is_a { "A" } else { "B" }
+                    ),
+                )),
+                "insert" | "rename" => {
+                    if let Some(from) = &e.renamed_from {
+                        let key = (e.unit_kind.clone(), from.clone());
+                        let entry = deleted_by.entry(key.clone()).or_default();
+                        if is_a { entry.0 = true; } else { entry.1 = true; }
+                        deletes.insert(key.clone());
+                        renamed_away.insert(key);
+                    }
+                    if e.unit_kind == "import_statement"
+                        && let Some(p) = &e.payload
+                    {
+                        let entry = new_imports.entry(e.name.clone()).or_default();
+                        if is_a { entry.0 = Some(p.clone()); } else { entry.1 = Some(p.clone()); }
+                    }
+                    // both branches added a re-export of one module: one
+                    // statement with the names of both
+                    let name_key = (e.unit_kind.clone(), without_occurrence(&e.name).to_string());
+                    let same_name = inserts_by_name.get(&name_key).map_or(&[][..], Vec::as_slice);
+                    if !is_a
+                        && e.unit_kind == "export_statement"
+                        && let Some(p) = &e.payload
+                        && let Some(&i) = same_name.iter().find(|&&i| inserts[i].0 && inserts[i].1.name == e.name)
+                        && let Some(ours) = inserts[i].1.payload.as_deref().filter(|ours| ours != p)
+                    {
+                        let label = format!("{}::{}", e.unit_kind, e.name);
+                        match reexports::merge_reexports("", ours, p, &label, base.lang) {
+                            MemberMerge::Merged(merged) => {
+                                let insert = inserts[i].1.to_mut();
+                                insert.content_hash = Some(content_hash(&merged));
+                                insert.payload = Some(merged);
+                                stats.inserts_a -= 1;
+                                stats.reconciled += 1;
+                            }
+                            MemberMerge::Conflicts(found) => conflicts.extend(found.into_iter().map(|message| {
+                                let key = (e.unit_kind.clone(), e.name.clone());
+                                Conflict::new(path, "insert-insert", Some(&key), (inserts[i].1.content_hash, e.content_hash), message)
+                            })),
+                            MemberMerge::NotApplicable => {
+                                stats.inserts_b += 1;
+                                inserts_by_name.entry(name_key).or_default().push(inserts.len());
+                                inserts.push((false, Cow::Borrowed(e)));
+                            }
+                        }
+                        continue;
+                    }
                     if let Some(p) = &e.payload {
-                        inserts.insert((e.unit_kind.clone(), e.name.clone(), p.clone()));
+                        let same = if inserted_by_both.contains(&name_key) {
+                            opts.comparer().unwrap_or(&TokenComparer).fingerprint(p, base.lang)
+                        } else {
+                            0
+                        };
+                        let key = (name_key.0.clone(), name_key.1.clone(), same);
+                        let copies = seen_inserts.entry(key).or_default();
+                        if is_a {
+                            copies.0 += 1;
+                        } else {
+                            copies.1 += 1;
+                        }
+                        if is_a || copies.1 > copies.0 {
+                            if is_a { stats.inserts_a += 1; } else { stats.inserts_b += 1; }
+                            inserts_by_name.entry(name_key).or_default().push(inserts.len());
+                            inserts.push((is_a, Cow::Borrowed(e)));
+                        } else if same_name.iter().any(|&i| inserts[i].1.payload.as_deref() == Some(p)) {
+                            // A's copy, counted as A's, was both branches'
+                            stats.inserts_a -= 1;
+                            stats.same_changes += 1;
+                        } else {
+                            // kept A's copy: the target branch's formatting
+                            let key = (e.unit_kind.clone(), e.name.clone());
+                            let whitespace_only = same_name.iter().any(|&i| {
+                                inserts[i].0 && inserts[i].1.payload.as_deref().is_some_and(|x| content_hash(x) == content_hash(p))
+                            });
+                            let confidence = if whitespace_only { SAME_BUT_WHITESPACE } else { SAME_BUT_FORMATTING };
+                            let note = format!("Both branches added {}::{} with different formatting; kept A's", key.0, key.1);
+                            match opts.doubt(confidence, &note) {
+                                Some(message) => conflicts.push(Conflict::new(
+                                    path,
+                                    "insert-insert",
+                                    Some(&key),
+                                    (e.content_hash, Some(content_hash(p))),
+                                    message,
+                                )),
+                                None => {
+                                    warnings.push(note);
+                                    auto.push(AutoResolution::new(&key, "formatting", confidence));
+                                    stats.inserts_a -= 1;
+                                    stats.same_changes += 1;
+                                }
+                            }
+                        }
                     }
                 }
                 "update" => {
@@ -48,94 +604,376 @@ pub fn compose_top_level(base: &AstFile, ea: &[Edit], eb: &[Edit]) -> Result<Mer
                         .entry((e.unit_kind.clone(), e.name.clone()))
                         .or_insert((None, None));
                     if let Some(p) = &e.payload {
-                        if is_a { entry.0 = Some(p.clone()); } else { entry.1 = Some(p.clone()); }
+                        let update = Some((p.as_str(), e.content_hash.unwrap_or_else(|| content_hash(p))));
+                        if is_a { entry.0 = update; } else { entry.1 = update; }
                     }
                 }
-                "delete" => { deletes.insert((e.unit_kind.clone(), e.name.clone())); }
+                "delete" => {
+                    let key = (e.unit_kind.clone(), e.name.clone());
+                    let entry = deleted_by.entry(key.clone()).or_default();
+                    if is_a { entry.0 = true; } else { entry.1 = true; }
+                    deletes.insert(key);
+                }
                 _ => {}
             }
         }
+    }
+
+    // 0) a unit one side renamed, split into new ones or moved to another
+    //    file, and the other edited: carry the edit into the renamed unit or
+    //    the piece with its lines (or to the other file), else report it
+    // key -> (conflict kind, message) of a split or move whose edit stays
+    let mut unsettled: HashMap<(String, String), (&str, String)> = HashMap::new();
+    let base_units: HashMap<(String, String), &TopLevel> = base.keyed_units().into_iter().collect();
+    // (renamed by A?, kind, old name) -> the rename's index in `inserts`
+    let renamed_to: HashMap<(bool, String, String), usize> = (0..inserts.len())
+        .filter_map(|i| Some(((inserts[i].0, inserts[i].1.unit_kind.clone(), inserts[i].1.renamed_from.clone()?), i)))
+        .collect();
+    // each branch's inserts by their lines, built on the first unit it
+    // deleted and the other edited: the pieces a split may have made
+    let mut pieces_by_line: [Option<splits::PieceLines>; 2] = [None, None];
+    for key in &deletes {
+        let (deleted_by_a, edited) = match (deleted_by.get(key), updates_by_side.get(key)) {
+            (Some((true, false)), Some((None, Some((p, _))))) => (true, *p),
+            (Some((false, true)), Some((Some((p, _)), None))) => (false, *p),
+            _ => continue,
+        };
+        let (mover, editor) = if deleted_by_a { ("A", "B") } else { ("B", "A") };
+        let lines = &mut pieces_by_line[usize::from(deleted_by_a)];
+        if let Some(&i) = renamed_to.get(&(deleted_by_a, key.0.clone(), key.1.clone())) {
+            // the rename changed nothing but the name: the edit under the new one
+            let to = inserts[i].1.name.clone();
+            let followed = renames::rename_uses(edited, base.lang, &key.1, &to).unwrap_or_else(|| edited.to_string());
+            let note = format!("{mover} renamed {}::{} to {to}; applied {editor}'s edit of it there", key.0, key.1);
+            if let Some(message) = opts.doubt(RENAME, &note) {
+                unsettled.insert(key.clone(), ("delete-update", message));
+                continue;
+            }
+            if let Some(lines) = lines {
+                lines.add(i, &followed);
+            }
+            let insert = inserts[i].1.to_mut();
+            insert.content_hash = Some(content_hash(&followed));
+            insert.payload = Some(followed);
+            warnings.push(note);
+            auto.push(AutoResolution::new(key, "rename", RENAME));
+            updates_by_side.remove(key);
+            if deleted_by_a { stats.inserts_a -= 1; } else { stats.inserts_b -= 1; }
+            stats.reconciled += 1;
+            continue;
+        }
+        if opts.moved.contains(&format!("{}::{}", key.0, key.1)) {
+            let note = format!("{mover} moved {}::{} to another file; {editor}'s edit of it goes there", key.0, key.1);
+            match opts.doubt(MOVED, &note) {
+                Some(message) => {
+                    unsettled.insert(key.clone(), ("delete-update", message));
+                }
+                None => {
+                    warnings.push(note);
+                    auto.push(AutoResolution::new(key, "moved", MOVED));
+                    updates_by_side.remove(key);
+                }
+            }
+            continue;
+        }
+        let Some(u) = base_units.get(key) else { continue };
+        let original = &base.code[u.start_byte..u.end_byte];
+        let lines = lines.get_or_insert_with(|| {
+            let mut lines = splits::PieceLines::default();
+            for (i, (by_a, e)) in inserts.iter().enumerate() {
+                if *by_a == deleted_by_a && e.unit_kind != "import_statement" {
+                    lines.add(i, e.payload.as_deref().unwrap_or_default());
+                }
+            }
+            lines
+        });
+        let candidates = lines.sharing(original);
+        let payloads: Vec<&str> = candidates.iter().map(|&i| inserts[i].1.payload.as_deref().unwrap_or_default()).collect();
+        let Some(pieces) = splits::split_pieces(original, &payloads) else { continue };
+        let pieces: Vec<usize> = pieces.into_iter().map(|p| candidates[p]).collect();
+        let names: Vec<String> = pieces.iter().map(|&i| inserts[i].1.name.clone()).collect();
+        let mut texts: Vec<String> = pieces.iter().map(|&i| inserts[i].1.payload.clone().unwrap_or_default()).collect();
+        let split = format!("{mover} split {}::{} into {}", key.0, key.1, names.join(", "));
+        let Some(changed) = splits::carry_edit(original, edited, &mut texts) else {
+            let message = format!("{split}, and {editor} edited it; the edit fits none of them");
+            unsettled.insert(key.clone(), ("split-edit", message));
+            continue;
+        };
+        let into: Vec<&str> = changed.iter().map(|&p| names[p].as_str()).collect();
+        let note = format!("{split}; carried {editor}'s edit of it into {}", into.join(", "));
+        if let Some(message) = opts.doubt(SPLIT, &note) {
+            unsettled.insert(key.clone(), ("split-edit", message));
+            continue;
+        }
+        for (&i, text) in pieces.iter().zip(texts) {
+            lines.add(i, &text);
+            let insert = inserts[i].1.to_mut();
+            insert.content_hash = Some(content_hash(&text));
+            insert.payload = Some(text);
+        }
+        warnings.push(note);
+        auto.push(AutoResolution::new(key, "split", SPLIT));
+        updates_by_side.remove(key);
+    }
+
+    let inserts = canonical_insert_order(inserts, base);
+    let (imported, placed): (Vec<Cow<Edit>>, Vec<Cow<Edit>>) = inserts.into_iter().partition(|e| e.unit_kind == "import_statement");
+    let placed = match opts.insert_anchor {
+        InsertAnchor::Base => placed,
+        InsertAnchor::Nearest => chain_to_preceding(placed),
     };
-    ingest(ea, true);
-    ingest(eb, false);
+
+    let mut both_imported: Vec<&String> = new_imports
+        .iter()
+        .filter(|(_, (a, b))| a.is_some() && b.is_some() && a != b)
+        .map(|(module, _)| module)
+        .collect();
+    both_imported.sort();
+    for module in both_imported {
+        warnings.push(format!("Both branches added imports of \"{module}\" differently; kept both"));
+    }
 
     // 1) delete vs update => conflict
     for key in &deletes {
-        if let Some((pa, pb)) = updates_by_side.get(key) {
-            if pa.is_some() || pb.is_some() {
-                conflicts.push(format!("Deletion vs update on {}::{}", key.0, key.1));
-            }
+        if let Some((pa, pb)) = updates_by_side.get(key)
+            && (pa.is_some() || pb.is_some())
+        {
+            let hash = |u: &Option<Update>| u.as_ref().map(|(_, h)| *h);
+            let (kind, message) = match unsettled.remove(key) {
+                Some(found) => found,
+                None => ("delete-update", format!("Deletion vs update on {}::{}", key.0, key.1)),
+            };
+            conflicts.push(Conflict::new(path, kind, Some(key), (hash(pa), hash(pb)), message));
         }
     }
 
     // base ranges for splicing
     let base_idx = index_base_ranges(base);
 
-    #[derive(Clone)]
-    struct Patch { start: usize, end: usize, replacement: String }
     let mut patches: Vec<Patch> = Vec::new();
 
-    // 2) updates (with rename-aware reconcile for functions)
+    // 2) updates (with rename-aware reconcile for functions, member-level merges for containers)
+    let comparer = opts.comparer().unwrap_or(&TokenComparer);
+    let same_update = |a: &str, b: &str| comparer.fingerprint(a, base.lang) == comparer.fingerprint(b, base.lang);
     for (key, (pa, pb)) in &updates_by_side {
         if deletes.contains(key) { continue; }
         match (pa, pb) {
-            (Some(a_payload), Some(b_payload)) => {
-                if a_payload == b_payload {
-                    // identical update
-                    if let Some((s, e)) = base_idx.get(key) {
-                        patches.push(Patch { start: *s, end: *e, replacement: a_payload.clone() });
+            // the same change up to formatting: take A's
+            (Some((a_payload, ha)), Some((b_payload, hb))) if same_update(a_payload, b_payload) => {
+                if a_payload != b_payload {
+                    let confidence = formatting_confidence(a_payload, b_payload);
+                    let note = format!(
+                        "Both branches made the same change to {}::{} with different formatting; kept A's",
+                        key.0, key.1
+                    );
+                    if let Some(message) = opts.doubt(confidence, &note) {
+                        conflicts.push(Conflict::new(path, "update-update", Some(key), (Some(*ha), Some(*hb)), message));
+                        continue;
+                    }
+                    warnings.push(note);
+                    auto.push(AutoResolution::new(key, "formatting", confidence));
+                }
+                if let Some((s, e)) = base_idx.get(key) {
+                    patches.push(Patch { start: *s, end: *e, replacement: a_payload.to_string(), unit: Some(key.clone()) });
+                    stats.same_changes += 1;
+                }
+            }
+            (Some((a_payload, ha)), Some((b_payload, hb))) => {
+                if let Some((s, e)) = base_idx.get(key) {
+                    // base as it would be with the renames followed above: a
+                    // side that only followed one here changed nothing else
+                    let original = carried.iter().fold(base.code[*s..*e].to_string(), |text, (from, to)| {
+                        renames::rename_uses(&text, base.lang, from, to).unwrap_or(text)
+                    });
+                    if !carried.is_empty() && same_update(a_payload, &original) {
+                        patches.push(Patch { start: *s, end: *e, replacement: b_payload.to_string(), unit: Some(key.clone()) });
+                        stats.updates_b += 1;
+                        continue;
+                    }
+                    if !carried.is_empty() && same_update(b_payload, &original) {
+                        patches.push(Patch { start: *s, end: *e, replacement: a_payload.to_string(), unit: Some(key.clone()) });
+                        stats.updates_a += 1;
+                        continue;
                     }
-                } else if key.0 == "function_declaration" {
-                    if let Some(reconciled) = try_reconcile_param_rename(a_payload, b_payload) {
-                        if let Some((s, e)) = base_idx.get(key) {
-                            patches.push(Patch { start: *s, end: *e, replacement: reconciled });
+                    let mut notes = Notes::default();
+                    let merged = reconcile_update(key, &original, a_payload, b_payload, base.lang, opts, &mut notes);
+                    warnings.append(&mut notes.warnings);
+                    auto.append(&mut notes.auto_resolutions);
+                    match merged {
+                        Ok(merged) => {
+                            patches.push(Patch { start: *s, end: *e, replacement: merged, unit: Some(key.clone()) });
+                            stats.reconciled += 1;
                         }
-                    } else {
-                        conflicts.push(format!("Both branches updated {}::{} differently", key.0, key.1));
+                        Err(found) => conflicts.extend(
+                            found
+                                .into_iter()
+                                .map(|msg| Conflict::new(path, "update-update", Some(key), (Some(*ha), Some(*hb)), msg)),
+                        ),
                     }
-                } else {
-                    conflicts.push(format!("Both branches updated {}::{} differently", key.0, key.1));
                 }
             }
-            (Some(only), None) | (None, Some(only)) => {
+            (Some((only, _)), None) | (None, Some((only, _))) => {
                 if let Some((s, e)) = base_idx.get(key) {
-                    patches.push(Patch { start: *s, end: *e, replacement: only.clone() });
+                    patches.push(Patch { start: *s, end: *e, replacement: only.to_string(), unit: Some(key.clone()) });
+                    if pa.is_some() { stats.updates_a += 1; } else { stats.updates_b += 1; }
                 }
             }
             (None, None) => {}
         }
     }
 
-    // 3) apply patches (right→left)
-    patches.sort_by(|a, b| b.start.cmp(&a.start));
-    for p in patches {
-        if p.start <= p.end && p.end <= code.len() {
-            code.replace_range(p.start..p.end, &p.replacement);
+    // 3) deletions the other side left alone; declarators sharing a statement
+    //    are cut out of it, the statement itself goes once all of them are
+    let mut removed: HashSet<(usize, usize)> = HashSet::new();
+    for (key, u) in base.keyed_units() {
+        if !deletes.contains(&key) || updates_by_side.contains_key(&key) {
+            continue;
+        }
+        if opts.parse.policy.conflict_on_exported_delete && is_exported(base, u) {
+            conflicts.push(Conflict::new(
+                path,
+                "exported-delete",
+                Some(&key),
+                (None, None),
+                format!("Policy: deletion of exported {}::{}", key.0, key.1),
+            ));
+            continue;
+        }
+        match deleted_by.get(&key) {
+            // counted with the unit under its new name
+            _ if renamed_away.contains(&key) => {}
+            Some((true, true)) => stats.same_changes += 1,
+            Some((true, false)) => stats.deletes_a += 1,
+            _ => stats.deletes_b += 1,
+        }
+        removed.insert((u.start_byte, u.end_byte));
+    }
+    let is_removed = |u: &TopLevel| removed.contains(&(u.start_byte, u.end_byte));
+    let keys: HashMap<(usize, usize), (String, String)> =
+        base.keyed_units().into_iter().map(|(key, u)| ((u.start_byte, u.end_byte), key)).collect();
+    let unit_of = |u: &TopLevel| keys.get(&(u.start_byte, u.end_byte)).cloned();
+
+    // conflicts in source order (stable: a unit's own conflicts keep theirs)
+    let unit_starts: HashMap<String, usize> = base_idx.iter().map(|((k, n), (s, _))| (format!("{k}::{n}"), *s)).collect();
+    conflicts.sort_by_key(|c| c.unit.as_ref().and_then(|u| unit_starts.get(u)).copied().unwrap_or(usize::MAX));
+
+    // 3b) conflicts decided in a resolutions file: the unit becomes the chosen
+    //     version (an empty one deletes it)
+    conflicts::disambiguate(&mut conflicts);
+    let mut settled: HashSet<String> = HashSet::new();
+    for c in &conflicts {
+        let Some(resolution) = opts.resolutions.get(&c.id) else { continue };
+        let Some(unit) = c.unit.as_deref().filter(|unit| !settled.contains(*unit)) else { continue };
+        let Some((kind, name)) = unit.split_once("::") else { continue };
+        let key = (kind.to_string(), name.to_string());
+        let Some(&(start, end)) = base_idx.get(&key) else { continue };
+        if c.kind == "unmerged" && !matches!(resolution, Resolution::Text(_)) {
+            warnings.push(format!("Resolution of {} ignored: only `text` can resolve a syntax-error conflict", c.id));
+            continue;
+        }
+        let (update_a, update_b) = updates_by_side.get(&key).copied().unwrap_or_default();
+        let (deleted_a, deleted_b) = deleted_by.get(&key).copied().unwrap_or_default();
+        let version = |update: Option<Update>, deleted: bool| match update {
+            Some((payload, _)) => payload.to_string(),
+            None if deleted => String::new(),
+            None => base.code[start..end].to_string(),
+        };
+        let replacement = resolution.apply(&version(update_a, deleted_a), &version(update_b, deleted_b));
+        // units end at their last token; a `'''` text block usually ends in a newline
+        let replacement = replacement.trim_end_matches(['\n', '\r']).to_string();
+        let end = if replacement.is_empty() { line_end(&base.code, end) } else { end };
+        patches.push(Patch { start, end, replacement, unit: Some(key) });
+        settled.insert(unit.to_string());
+    }
+    let (resolved, unresolved): (Vec<Conflict>, Vec<Conflict>) =
+        conflicts.into_iter().partition(|c| c.unit.as_deref().is_some_and(|u| settled.contains(u)));
+    let mut conflicts = unresolved;
+    let mut statements_done = HashSet::new();
+    let mut declarators: HashMap<(usize, usize), Vec<&TopLevel>> = HashMap::new(); // statement -> its units
+    for u in &base.units {
+        if let Some(stmt) = u.statement {
+            declarators.entry(stmt).or_default().push(u);
+        }
+    }
+    for u in base.units.iter().filter(|u| is_removed(u)) {
+        let Some(stmt) = u.statement else {
+            let end = line_end(&base.code, u.end_byte);
+            patches.push(Patch { start: u.start_byte, end, replacement: String::new(), unit: unit_of(u) });
+            continue;
+        };
+        if !statements_done.insert(stmt) {
+            continue;
+        }
+        let shared = &declarators[&stmt];
+        if shared.iter().all(|v| is_removed(v)) {
+            let (end, unit) = (line_end(&base.code, stmt.1), unit_of(shared[0]));
+            patches.push(Patch { start: stmt.0, end, replacement: String::new(), unit });
+            continue;
+        }
+        let first_kept = shared.iter().position(|v| !is_removed(v)).unwrap_or_default();
+        for (i, v) in shared.iter().enumerate().filter(|(_, v)| is_removed(v)) {
+            // cut towards the first surviving declarator, taking one comma along
+            let (start, end) = if i < first_kept {
+                (v.start_byte, shared[i + 1].start_byte)
+            } else {
+                (shared[i - 1].end_byte, v.end_byte)
+            };
+            patches.push(Patch { start, end, replacement: String::new(), unit: unit_of(v) });
+        }
+    }
+
+    // 4) inserts go next to their neighbours from the contributing branch:
+    //    after the preceding base unit, else before the next, so both sides'
+    //    inserts interleave in base order (runs at one spot in canonical order)
+    let spacing = opts.parse.policy.spacing;
+    let position = |key: &Option<(String, String)>| {
+        let u = base_units.get(key.as_ref()?).filter(|u| !is_removed(u))?;
+        Some(u.statement.unwrap_or((u.start_byte, u.end_byte)))
+    };
+    let mut appended = Vec::new();
+    for e in &placed {
+        let payload = e.payload.as_deref().unwrap_or_default().trim_matches(['\r', '\n']).to_string();
+        if let Some((start, end)) = position(&e.after) {
+            let sep = separator(spacing, &base.code[start..end], &payload);
+            let unit = Some((e.unit_kind.clone(), e.name.clone()));
+            patches.push(Patch { start: end, end, replacement: format!("{sep}{payload}"), unit });
+        } else if let Some((start, end)) = position(&e.before) {
+            let sep = separator(spacing, &payload, &base.code[start..end]);
+            let unit = Some((e.unit_kind.clone(), e.name.clone()));
+            patches.push(Patch { start, end: start, replacement: format!("{payload}{sep}"), unit });
         } else {
-            conflicts.push("Internal splice range out of bounds".to_string());
+            appended.push(payload);
         }
     }
 
-    // 4) Append inserts (MVP)
-    for (_k, _n, payload) in &inserts {
-        code.push_str("\n\n");
+    // 5) apply patches
+    code = splice(&code, patches, path, &mut conflicts);
+
+    // 6) append inserts with no neighbour left in base, spaced by the
+    //    policy; imports are hoisted below instead
+    for payload in &appended {
+        code.truncate(code.trim_end_matches(['\r', '\n']).len());
+        if !code.is_empty() {
+            let last_line = code.rsplit('\n').next().unwrap_or_default();
+            code.push_str(&separator(spacing, last_line, payload));
+        }
         code.push_str(payload);
         code.push('\n');
     }
 
-    // 5) IMPORT UNION: pull all import lines from (a) current code and (b) inserted payloads,
+    // 7) IMPORT UNION: pull all import lines from (a) current code and (b) inserted payloads,
     //    de-dupe, and place them at the very top of the file.
 
     // (a) collect imports from the full merged code
     let mut all_imports: Vec<String> = Vec::new();
-    for line in code.lines() {
+    for line in code.split('\n') {
         let l = line.trim();
         if l.starts_with("import ") {
             all_imports.push(line.to_string());
         }
     }
     // (b) also scan inserted payloads in case they contain imports that weren’t captured
-    for (_k, _n, payload) in inserts {
+    for payload in imported.iter().chain(&placed).filter_map(|e| e.payload.as_deref()) {
         for line in payload.lines() {
             let l = line.trim();
             if l.starts_with("import ") {
@@ -154,7 +992,7 @@ pub fn compose_top_level(base: &AstFile, ea: &[Edit], eb: &[Edit]) -> Result<Mer
 
     // remove all existing import lines from the body
     let mut body_lines: Vec<&str> = Vec::new();
-    for line in code.lines() {
+    for line in code.split('\n') {
         if !line.trim().starts_with("import ") {
             body_lines.push(line);
         }
@@ -168,25 +1006,469 @@ pub fn compose_top_level(base: &AstFile, ea: &[Edit], eb: &[Edit]) -> Result<Mer
     } else {
         unique_imports.join("\n") + "\n"
     };
-    let trimmed_body = body.trim_start_matches('\n').to_string();
-    code = format!("{imports}\n{body}", imports = imports_block, body = trimmed_body);
+    if !imports_block.is_empty() {
+        let trimmed_body = body.trim_start_matches(['\r', '\n']).to_string();
+        code = format!("{imports}\n{body}", imports = imports_block, body = trimmed_body);
+    } else {
+        code = body;
+    }
+
+    // 8) optionally drop imports whose last users the merge removed
+    if opts.prune_imports {
+        let mut used_before = HashSet::new();
+        imports::used_identifiers(&base.code, base.lang, &mut used_before);
+        for payload in ea.iter().chain(eb).filter_map(|e| e.payload.as_deref()) {
+            imports::used_identifiers(payload, base.lang, &mut used_before);
+        }
+        let (pruned, notes) = imports::prune_unused(&code, base.lang, &used_before);
+        code = pruned;
+        warnings.extend(notes);
+    }
+
+    conflicts::disambiguate(&mut conflicts);
+    let suppressed = conflicts::split_suppressed(&mut conflicts, &opts.suppressed);
+    Ok(MergeOutcome {
+        merged_code: code,
+        conflicts,
+        suppressed,
+        resolved,
+        warnings,
+        auto_resolutions: auto,
+        stats,
+        ..MergeOutcome::default()
+    })
+}
+
+/// A replacement of `start..end` of base, made for `unit`.
+#[derive(Clone)]
+struct Patch {
+    start: usize,
+    end: usize,
+    replacement: String,
+    unit: Option<(String, String)>,
+}
+
+/// `code` with `patches` applied, left to right; at equal starts, zero-width
+/// inserts go before replacements, and inserts keep their canonical order. A
+/// patch out of range, or cutting into another one, is a bug: its unit stays
+/// as in base, with an `internal` conflict, rather than garbled.
+fn splice(code: &str, patches: Vec<Patch>, path: Option<&str>, conflicts: &mut Vec<Conflict>) -> String {
+    let (mut patches, invalid): (Vec<Patch>, Vec<Patch>) = patches
+        .into_iter()
+        .partition(|p| p.start <= p.end && code.is_char_boundary(p.start) && code.is_char_boundary(p.end));
+    for p in invalid {
+        let message = "Internal splice range out of bounds";
+        conflicts.push(Conflict::new(path, "internal", p.unit.as_ref(), (None, None), message));
+    }
+    patches.sort_by_key(|p| (p.start, p.end));
+    let mut overlapping = vec![false; patches.len()];
+    for (i, p) in patches.iter().enumerate() {
+        for (j, _) in patches.iter().enumerate().skip(i + 1).take_while(|(_, q)| q.start < p.end) {
+            (overlapping[i], overlapping[j]) = (true, true);
+        }
+    }
+    let mut spliced = String::with_capacity(code.len() + patches.iter().map(|p| p.replacement.len()).sum::<usize>());
+    let mut at = 0;
+    let mut clashed: Vec<Option<(String, String)>> = Vec::new();
+    for (p, overlaps) in patches.into_iter().zip(overlapping) {
+        if overlaps {
+            if !clashed.contains(&p.unit) {
+                let message = "Internal splice overlap; kept the base version";
+                conflicts.push(Conflict::new(path, "internal", p.unit.as_ref(), (None, None), message));
+                clashed.push(p.unit);
+            }
+            continue;
+        }
+        spliced.push_str(&code[at..p.start]);
+        spliced.push_str(&p.replacement);
+        at = p.end;
+    }
+    spliced.push_str(&code[at..]);
+    spliced
+}
+
+/// Line breaks between `above` and `below`, one unit the merge inserted:
+/// one more than the blank lines `spacing` asks for.
+fn separator(spacing: Spacing, above: &str, below: &str) -> String {
+    let blank = match spacing {
+        Spacing::Lines(n) => n,
+        Spacing::Auto => {
+            // one-line statements: not a block (`function f() {}`), comment or a unit's last line
+            let one_liner = |s: &str| {
+                !s.contains('\n') && !s.starts_with([' ', '\t', '}', ')', ']', '*', '/']) && !s.trim_end().ends_with('}')
+            };
+            let run = one_liner(above) && one_liner(below) && leading_keywords(above) == leading_keywords(below);
+            usize::from(!run)
+        }
+    };
+    "\n".repeat(blank + 1)
+}
 
-    Ok(MergeOutcome { merged_code: code, conflicts })
+/// `export const x = 1;` -> `export const`: the words up to the first that
+/// isn't a modifier; a call is classified by its callee (`app.use(a);` ->
+/// `app.use`).
+fn leading_keywords(line: &str) -> &str {
+    let mut end = 0;
+    for word in line.split(' ') {
+        if !matches!(word, "export" | "default" | "declare" | "async" | "abstract") {
+            end += word.find('(').unwrap_or(word.len());
+            break;
+        }
+        end += word.len() + 1;
+    }
+    &line[..end.min(line.len())]
+}
+
+/// Whether a base unit is exported (`export ...`, after any doc comment).
+fn is_exported(base: &AstFile, u: &TopLevel) -> bool {
+    let (start, end) = u.statement.unwrap_or((u.start_byte, u.end_byte));
+    let (_, code) = split_leading_comments(&base.code[start..end]);
+    code.starts_with("export ") || (code.starts_with('@') && code.contains("export "))
+}
+
+/// File-level fallback: take the only side that changed, else keep both
+/// versions between conflict markers and report `reason`.
+pub(crate) fn textual_merge(base: &str, a: &str, b: &str, reason: &str, opts: &MergeOptions) -> MergeOutcome {
+    let mut resolved = Vec::new();
+    let (merged_code, mut conflicts) = if a == b || b == base {
+        (a.to_string(), Vec::new())
+    } else if a == base {
+        (b.to_string(), Vec::new())
+    } else {
+        let hashes = (Some(content_hash(a)), Some(content_hash(b)));
+        let conflict = Conflict::new(opts.path.as_deref(), "textual", None, hashes, reason);
+        if let Some(resolution) = opts.resolutions.get(&conflict.id) {
+            resolved.push(conflict);
+            (resolution.apply(a, b), Vec::new())
+        } else {
+            let nl = |s: &str| if s.ends_with('\n') || s.is_empty() { "" } else { "\n" };
+            (format!("<<<<<<< A\n{a}{}=======\n{b}{}>>>>>>> B\n", nl(a), nl(b)), vec![conflict])
+        }
+    };
+    let suppressed = conflicts::split_suppressed(&mut conflicts, &opts.suppressed);
+    MergeOutcome { merged_code, conflicts, suppressed, resolved, textual: true, ..MergeOutcome::default() }
 }
 
-/// Very small heuristic: if both payloads look like the *same* function but the
-/// first parameter identifier differs, rewrite B to use A's param name and return it.
-/// This lets us keep B's body edits (e.g., punctuation) while adopting A's rename.
+/// One unit merged on its own: a one-sided change is taken, and divergent
+/// updates go through the same reconcilers as in a file merge. On conflict
+/// the unit stays as in base, like in a file.
+pub(crate) fn merge_unit(base: &str, a: &str, b: &str, kind: &str, lang: AstLanguage, opts: &MergeOptions) -> MergeOutcome {
+    if a == b || b == base {
+        let stats = MergeStats { updates_a: usize::from(a != base), ..MergeStats::default() };
+        return MergeOutcome { merged_code: a.to_string(), stats, ..MergeOutcome::default() };
+    }
+    if a == base {
+        return MergeOutcome { merged_code: b.to_string(), stats: MergeStats { updates_b: 1, ..MergeStats::default() }, ..MergeOutcome::default() };
+    }
+    // the unit's name, for messages and conflict IDs, as in its file
+    let name = crate::ast::parse_typescript_to_ast_with(base, lang, &opts.parse)
+        .ok()
+        .and_then(|file| file.keyed_units().into_iter().find(|((k, _), _)| k == kind).map(|((_, n), _)| n))
+        .unwrap_or_default();
+    let key = (kind.to_string(), name);
+    let mut notes = Notes::default();
+    let merged = reconcile_update(&key, base, a, b, lang, opts, &mut notes);
+    let Notes { warnings, auto_resolutions } = notes;
+    let found = match merged {
+        Ok(merged_code) => {
+            let stats = MergeStats { reconciled: 1, ..MergeStats::default() };
+            return MergeOutcome { merged_code, warnings, auto_resolutions, stats, ..MergeOutcome::default() };
+        }
+        Err(found) => found,
+    };
+    let hashes = (Some(content_hash(a)), Some(content_hash(b)));
+    let mut conflicts: Vec<Conflict> = found
+        .into_iter()
+        .map(|msg| Conflict::new(opts.path.as_deref(), "update-update", Some(&key), hashes, msg))
+        .collect();
+    conflicts::disambiguate(&mut conflicts);
+    let suppressed = conflicts::split_suppressed(&mut conflicts, &opts.suppressed);
+    let mut merged_code = base.to_string();
+    let mut resolved = Vec::new();
+    if let Some(resolution) = conflicts.iter().find_map(|c| opts.resolutions.get(&c.id)) {
+        merged_code = resolution.apply(a, b);
+        resolved = std::mem::take(&mut conflicts);
+    }
+    MergeOutcome { merged_code, conflicts, suppressed, resolved, warnings, ..MergeOutcome::default() }
+}
+
+/// `name` without the `#n` suffix [`AstFile::keyed_units`] gives repeats: the
+/// same statement added by both branches may land at different occurrences.
+fn without_occurrence(name: &str) -> &str {
+    match name.rsplit_once('#') {
+        Some((stem, n)) if !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()) => stem,
+        _ => name,
+    }
+}
+
+/// Order inserts so the result does not depend on which branch is A: by the
+/// base unit they follow, then per branch as runs (each in its branch's
+/// order), the runs sorted by their first insert's content hash.
+fn canonical_insert_order<'e>(inserts: Vec<(bool, Cow<'e, Edit>)>, base: &AstFile) -> Vec<Cow<'e, Edit>> {
+    let positions: HashMap<(String, String), usize> =
+        base.keyed_units().into_iter().enumerate().map(|(i, (key, _))| (key, i)).collect();
+    let anchor = |e: &Edit| e.after.as_ref().and_then(|k| positions.get(k)).copied();
+    // (hash, text) of each run's first insert; the text breaks hash ties
+    let mut run_keys: HashMap<(Option<usize>, bool), (u64, String)> = HashMap::new();
+    for (is_a, e) in &inserts {
+        run_keys
+            .entry((anchor(e), *is_a))
+            .or_insert_with(|| (e.content_hash.unwrap_or_default(), e.payload.clone().unwrap_or_default()));
+    }
+    let mut keyed: Vec<_> = inserts
+        .into_iter()
+        .enumerate()
+        .map(|(i, (is_a, e))| ((anchor(&e), run_keys[&(anchor(&e), is_a)].clone(), i), e))
+        .collect();
+    keyed.sort_by(|(x, _), (y, _)| x.cmp(y));
+    keyed.into_iter().map(|(_, e)| e).collect()
+}
+
+/// `inserts` (in canonical order) with every insert whose preceding unit is
+/// another insert moved right after it, taking its spot in base.
+fn chain_to_preceding(inserts: Vec<Cow<Edit>>) -> Vec<Cow<Edit>> {
+    let key = |e: &Edit| (e.unit_kind.clone(), e.name.clone());
+    let keys: HashSet<(String, String)> = inserts.iter().map(|e| key(e)).collect();
+    let chained = |e: &Edit| e.preceding.as_ref().is_some_and(|p| keys.contains(p) && *p != key(e));
+    // preceding insert -> the inserts chained to it
+    let mut followers: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for (j, e) in inserts.iter().enumerate() {
+        if let Some(p) = e.preceding.as_ref().filter(|_| chained(e)) {
+            followers.entry(p.clone()).or_default().push(j);
+        }
+    }
+    let mut out: Vec<Cow<Edit>> = Vec::with_capacity(inserts.len());
+    let mut done = vec![false; inserts.len()];
+    // roots in order, each followed by the inserts chained to it (depth first)
+    let mut stack: Vec<(usize, Option<usize>)> =
+        (0..inserts.len()).rev().filter(|&i| !chained(&inserts[i])).map(|i| (i, None)).collect();
+    // (once the stack runs dry, cycles of inserts preceding each other are
+    // left: start anywhere)
+    while let Some((i, parent)) = stack.pop().or_else(|| done.iter().position(|d| !d).map(|i| (i, None))) {
+        if std::mem::replace(&mut done[i], true) {
+            continue;
+        }
+        let mut e = inserts[i].clone();
+        if let Some(p) = parent {
+            let (after, before) = (out[p].after.clone(), out[p].before.clone());
+            let e = e.to_mut();
+            (e.after, e.before) = (after, before);
+        }
+        let k = key(&e);
+        out.push(e);
+        let at = out.len() - 1;
+        // by content, so which branch is A doesn't matter
+        let mut children: Vec<usize> =
+            followers.get(&k).map_or(&[][..], Vec::as_slice).iter().copied().filter(|&j| !done[j]).collect();
+        children.sort_by_key(|&j| (inserts[j].content_hash, inserts[j].payload.clone()));
+        stack.extend(children.into_iter().rev().map(|j| (j, Some(at))));
+    }
+    out
+}
+
+/// `end`, extended over the whitespace up to the next line's code (if the unit
+/// ends its line), so removed units don't leave blank lines behind.
+fn line_end(code: &str, end: usize) -> usize {
+    let rest = &code[end..];
+    let ws = rest.len() - rest.trim_start().len();
+    if rest[..ws].contains('\n') { end + ws } else { end }
+}
+
+/// Merge two updates of the same unit. Leading doc comments and code merge
+/// independently, so a doc-only edit on one side composes with a code edit on
+/// the other.
+pub(crate) fn reconcile_update(
+    key: &(String, String),
+    base: &str,
+    a: &str,
+    b: &str,
+    lang: AstLanguage,
+    opts: &MergeOptions,
+    notes: &mut Notes,
+) -> Result<String, Vec<String>> {
+    if a == b {
+        // identical update
+        return Ok(a.to_string());
+    }
+    if key.0 == IGNORED_REGION {
+        let mut keep = |side: &str, code: &str| {
+            notes.warnings.push(format!("Both branches changed the ignored region {}: kept {side}'s version; regenerate it", key.1));
+            Ok(code.to_string())
+        };
+        return match opts.parse.policy.generated {
+            GeneratedMerge::Skip => keep("A", a),
+            GeneratedMerge::TakeNewer if opts.b_is_newer => keep("B", b),
+            GeneratedMerge::TakeNewer => keep("A", a),
+            _ => Err(vec![format!("Both branches changed the ignored region {}", key.1)]),
+        };
+    }
+    let ((doc_o, code_o), (doc_a, code_a), (doc_b, code_b)) =
+        (split_leading_comments(base), split_leading_comments(a), split_leading_comments(b));
+
+    let mut conflicts = Vec::new();
+    let doc = match pick_frame(doc_o, doc_a, doc_b) {
+        Some(Side::A) => doc_a,
+        Some(Side::B) => doc_b,
+        None => {
+            conflicts.push(format!("Both branches updated the doc comment of {}::{} differently", key.0, key.1));
+            doc_a
+        }
+    };
+
+    // `export`/`declare` modifiers merge independently too
+    let ((mod_o, code_o), (mod_a, code_a), (mod_b, code_b)) =
+        (split_modifiers(code_o), split_modifiers(code_a), split_modifiers(code_b));
+    let modifiers = match pick_frame(mod_o, mod_a, mod_b) {
+        Some(Side::A) => mod_a,
+        Some(Side::B) => mod_b,
+        None => {
+            conflicts.push(format!("Both branches updated the modifiers of {}::{} differently", key.0, key.1));
+            mod_a
+        }
+    };
+
+    let code = match pick_frame(code_o, code_a, code_b) {
+        Some(Side::A) => Ok(code_a.to_string()),
+        Some(Side::B) => Ok(code_b.to_string()),
+        None => reconcile_code(key, code_o, code_a, code_b, lang, opts, notes),
+    };
+    match code {
+        Ok(code) if conflicts.is_empty() => Ok(format!("{doc}{modifiers}{code}")),
+        Ok(_) => Err(conflicts),
+        Err(found) => {
+            conflicts.extend(found);
+            Err(conflicts)
+        }
+    }
+}
+
+/// Split leading `export`/`default`/`declare` keywords (and the whitespace
+/// after them) from the declaration.
+fn split_modifiers(code: &str) -> (&str, &str) {
+    let mut rest = code;
+    while let Some(after) = ["export", "default", "declare"]
+        .iter()
+        .find_map(|kw| rest.strip_prefix(kw).filter(|r| r.starts_with(char::is_whitespace)))
+    {
+        rest = after.trim_start();
+    }
+    code.split_at(code.len() - rest.len())
+}
+
+/// Both sides changed a unit's code differently: try custom reconcilers, the
+/// rename heuristic, member-level reconcilers, then a line-level merge, else
+/// report a unit conflict.
+fn reconcile_code(
+    key: &(String, String),
+    base: &str,
+    a: &str,
+    b: &str,
+    lang: AstLanguage,
+    opts: &MergeOptions,
+    notes: &mut Notes,
+) -> Result<String, Vec<String>> {
+    let label = format!("{}::{}", key.0, key.1);
+    let custom = opts
+        .custom_reconcilers(&key.0)
+        .map(|r| r.reconcile(base, a, b, &label, lang))
+        .find(|m| !matches!(m, MemberMerge::NotApplicable));
+    if let Some(outcome) = custom {
+        return match outcome {
+            MemberMerge::Merged(merged) => Ok(merged),
+            MemberMerge::Conflicts(found) => Err(found),
+            MemberMerge::NotApplicable => unreachable!("filtered above"),
+        };
+    }
+    if key.0 == "function_declaration"
+        && let Some((reconciled, renamed_by)) = try_reconcile_param_rename(base, a, b)
+    {
+        if let Some(side) = renamed_by {
+            let (from, to) = if side == Side::A { ("A", "B") } else { ("B", "A") };
+            let note = format!("Applied {from}'s parameter rename to {to}'s edit of {label}");
+            if let Some(message) = opts.doubt(PARAM_RENAME, &note) {
+                return Err(vec![message]);
+            }
+            notes.warnings.push(note);
+            notes.auto_resolutions.push(AutoResolution::new(key, "param-rename", PARAM_RENAME));
+        }
+        return Ok(reconciled);
+    }
+    let outcome = match key.0.as_str() {
+        // a namespace's members are reconciled like the file's own units
+        "internal_module" | "module" | "global_declaration" => namespaces::merge_namespace(base, a, b, &label, lang, opts, notes),
+        kind => member_reconcilers(kind)
+            .iter()
+            .map(|merge| merge(base, a, b, &label, lang))
+            .find(|m| !matches!(m, MemberMerge::NotApplicable))
+            .unwrap_or(MemberMerge::NotApplicable),
+    };
+    match outcome {
+        MemberMerge::Merged(merged) => return Ok(merged),
+        // what a reconciler found diverging stays a conflict, even where the
+        // lines would merge (two enum members given the same value)
+        MemberMerge::Conflicts(found) => return Err(found),
+        MemberMerge::NotApplicable => {}
+    }
+    // edits of different lines, if merging them breaks no syntax
+    let broken = |code: &str| parse_tree(code, lang).map_or(true, |t| t.root_node().has_error());
+    let Some(merged) = diff3::merge_lines(base, a, b).filter(|m| !broken(m) || broken(base)) else {
+        return Err(vec![format!("Both branches updated {label} differently")]);
+    };
+    let note = format!("Merged both branches' edits of {label} line by line");
+    if let Some(message) = opts.doubt(LINE_MERGE, &note) {
+        return Err(vec![message]);
+    }
+    notes.warnings.push(note);
+    notes.auto_resolutions.push(AutoResolution::new(key, "line-merge", LINE_MERGE));
+    Ok(merged)
+}
+
+/// Member-level reconcilers for container-like and function-like unit kinds,
+/// tried in order until one applies.
+fn member_reconcilers(kind: &str) -> &'static [MemberReconciler] {
+    match kind {
+        "enum_declaration" | "interface_declaration" => &[members::merge_container],
+        "type_alias_declaration" => &[members::merge_type_alias],
+        "function_declaration" | "lexical_declaration" | "variable_declaration" => {
+            &[body::merge_switch_cases, jsx::merge_jsx, hooks::merge_hook_deps]
+        }
+        "class_declaration" => &[decorators::merge_decorators],
+        "export_statement" => &[reexports::merge_reexports],
+        "test_block" => &[specs::merge_test_block],
+        _ => &[],
+    }
+}
+
+/// Very small heuristic: if all three look like the *same* function and the
+/// first parameter was renamed (by one side, or identically by both), apply
+/// the rename to base and the other side, then take whichever side changed
+/// more than the rename. This keeps the other side's body edits (e.g.,
+/// punctuation) while adopting the rename, whichever branch made it. Returns
+/// the merge and the side whose rename was carried over (`None` if shared).
 ///
 /// Caveats: This is intentionally simple for an MVP.
-fn try_reconcile_param_rename(a: &str, b: &str) -> Option<String> {
-    // extract "function <name>(<param>..."  from both
+fn try_reconcile_param_rename(o: &str, a: &str, b: &str) -> Option<(String, Option<Side>)> {
+    // extract "function <name>(<param>..."  from all three
+    let (o_name, o_param) = parse_fn_name_and_first_param(o)?;
     let (a_name, a_param) = parse_fn_name_and_first_param(a)?;
     let (b_name, b_param) = parse_fn_name_and_first_param(b)?;
-    if a_name != b_name { return None; }          // not the same function
-    if a_param == b_param { return None; }        // no rename; real conflict
-    // replace b_param with a_param in b's payload (whole-word occurrences)
-    Some(replace_ident_whole_word(b, &b_param, &a_param))
+    if a_name != b_name || a_name != o_name { return None; } // not the same function
+    let (new, renamed_by) = match (a_param != o_param, b_param != o_param) {
+        (true, false) => (&a_param, Some(Side::A)),
+        (false, true) => (&b_param, Some(Side::B)),
+        (true, true) if a_param == b_param => (&a_param, None),
+        _ => return None, // no rename, or different renames: real conflict
+    };
+    // rename everywhere (whole-word occurrences), then a plain 3-way pick
+    let (o, a, b) = (
+        renames::replace_word(o, &o_param, new),
+        renames::replace_word(a, &a_param, new),
+        renames::replace_word(b, &b_param, new),
+    );
+    match pick_frame(o.as_str(), a.as_str(), b.as_str())? {
+        Side::A => Some((a, renamed_by)),
+        Side::B => Some((b, renamed_by)),
+    }
 }
 
 fn parse_fn_name_and_first_param(code: &str) -> Option<(String, String)> {
@@ -201,32 +1483,75 @@ fn parse_fn_name_and_first_param(code: &str) -> Option<(String, String)> {
     let name = rest[..paren].trim().to_string();
     // parameter segment up to ':' or ',' or ')'
     let after_paren = &rest[paren+1..];
-    let end = after_paren.find(|c: char| c == ':' || c == ',' || c == ')' ).unwrap_or(after_paren.len());
+    let end = after_paren.find([':', ',', ')']).unwrap_or(after_paren.len());
     let param = after_paren[..end].trim().to_string();
     if name.is_empty() || param.is_empty() { return None; }
     Some((name, param))
 }
 
-fn replace_ident_whole_word(haystack: &str, from: &str, to: &str) -> String {
-    // very small whole-word replacement (ASCII word chars)
-    // to avoid messing with substrings like 'username'
-    let mut out = String::with_capacity(haystack.len());
-    let bytes = haystack.as_bytes();
-    let mut i = 0;
-    while i < bytes.len() {
-        if i + from.len() <= bytes.len() && &haystack[i..i+from.len()] == from {
-            let before = if i == 0 { None } else { Some(bytes[i-1] as char) };
-            let after = if i + from.len() >= bytes.len() { None } else { Some(bytes[i+from.len()] as char) };
-            let is_word_left  = before.map(|c| c.is_alphanumeric() || c == '_').unwrap_or(false);
-            let is_word_right = after.map(|c| c.is_alphanumeric() || c == '_').unwrap_or(false);
-            if !is_word_left && !is_word_right {
-                out.push_str(to);
-                i += from.len();
-                continue;
-            }
-        }
-        out.push(bytes[i] as char);
-        i += 1;
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use crate::{AstLanguage, three_way_merge_top_level};
+
+    #[test]
+    fn split_units_take_the_other_branchs_edit() {
+        let base = "function processOrder(o) {\n  check(o.id);\n  check(o.total);\n  send(o);\n  log(o);\n}\n";
+        let a = "function validateOrder(o) {\n  check(o.id);\n  check(o.total);\n}\n\nfunction submitOrder(o) {\n  send(o);\n  log(o);\n}\n";
+        let b = "function processOrder(o) {\n  check(o.id);\n  check(o.total);\n  send(o, { retry: true });\n  log(o);\n}\n";
+        let out = three_way_merge_top_level(base, a, b, AstLanguage::TypeScript).unwrap();
+        assert!(out.conflicts.is_empty(), "{:?}", out.conflicts);
+        assert!(out.merged_code.contains("function submitOrder(o) {\n  send(o, { retry: true });"), "{}", out.merged_code);
     }
-    out
-}
\ No newline at end of file
+
+    #[test]
+    fn deletes_against_updates_conflict_unit_by_unit() {
+        let unit = |i: usize, op: &str| format!("export function f{i}(x: number) {{\n  return x {op} {i};\n}}\n\n");
+        let base: String = (0..200).map(|i| unit(i, "+")).collect();
+        let a: String = (0..200).filter(|i| i % 2 == 0).map(|i| unit(i, "+")).collect();
+        let b: String = (0..200).map(|i| unit(i, "*")).collect();
+        let out = three_way_merge_top_level(&base, &a, &b, AstLanguage::TypeScript).unwrap();
+        assert_eq!(out.conflicts.len(), 100);
+        assert!(out.conflicts.iter().all(|c| c.kind == "delete-update"));
+        assert_eq!(out.stats.updates_b, 100);
+    }
+
+    #[test]
+    fn member_conflicts_are_not_line_merged() {
+        let base = "enum E {\n  A = 1,\n  B = 2,\n}\n";
+        let a = "enum E {\n  A = 1,\n  B = 2,\n  C = 3,\n}\n";
+        let b = "enum E {\n  D = 3,\n  A = 1,\n  B = 2,\n}\n";
+        let out = three_way_merge_top_level(base, a, b, AstLanguage::TypeScript).unwrap();
+        let messages: Vec<_> = out.conflicts.iter().map(|c| c.message.as_str()).collect();
+        assert_eq!(messages, ["The merge gives enum_declaration::E::D and enum_declaration::E::C the same value, 3"]);
+        assert!(out.auto_resolutions.is_empty());
+    }
+
+    #[test]
+    fn overlapping_patches_are_internal_conflicts() {
+        use super::{Patch, splice};
+        let patch = |start, end, replacement: &str, name: &str| Patch {
+            start,
+            end,
+            replacement: replacement.to_string(),
+            unit: Some(("function_declaration".to_string(), name.to_string())),
+        };
+        let code = "function f() {}\nfunction g() {}\n// é\n";
+        let mut conflicts = Vec::new();
+        let patches = vec![
+            patch(0, 15, "function f() { return 1; }", "f"),
+            patch(9, 30, "", "g"),
+            patch(36, 37, "", "h"),
+            patch(32, 32, "// new\n", "k"),
+        ];
+        assert_eq!(splice(code, patches, Some("x.ts"), &mut conflicts), "function f() {}\nfunction g() {}\n// new\n// é\n");
+        let found: Vec<_> = conflicts.iter().map(|c| (c.kind.as_str(), c.unit.as_deref(), c.message.as_str())).collect();
+        assert_eq!(
+            found,
+            [
+                ("internal", Some("function_declaration::h"), "Internal splice range out of bounds"),
+                ("internal", Some("function_declaration::f"), "Internal splice overlap; kept the base version"),
+                ("internal", Some("function_declaration::g"), "Internal splice overlap; kept the base version"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! conflicts.rs
+//! Conflict records with stable IDs.
+//!
+//! An ID hashes the file path, the unit (`kind::name`), the conflict kind and
+//! the content hashes of both branches' versions, so re-running a merge of the
+//! same inputs reports the same IDs. That lets a `.sinkit.toml` acknowledge a
+//! known conflict once:
+//!
+//! ```toml
+//! [[suppress]]
+//! id = "3f0c9a1e7b2d4c65"
+//! note = "vendored; upstream resolves it"
+//! ```
+//!
+//! [`locate_conflicts`] adds where each conflict is in the merged file and
+//! what A and B have there, for editors to jump to and show both versions.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{AstFile, AstLanguage, ParseOptions, parse_typescript_to_ast_with};
+use crate::hash::stable_hash;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conflict {
+    /// Stable ID, 16 hex digits.
+    pub id: String,
+    /// `update-update`, `delete-update`, `split-edit`, `insert-insert`,
+    /// `rename-use`, `unmerged`, `exported-delete`, `textual`,
+    /// `delete-modify`, `binary`, `submodule`, `lockfile` or `internal`.
+    pub kind: String,
+    /// `kind::name` of the unit, if the conflict is about one.
+    pub unit: Option<String>,
+    pub message: String,
+}
+
+impl Conflict {
+    /// A conflict about `unit`, whose versions in A and B hash to `hashes`.
+    pub(crate) fn new(
+        path: Option<&str>,
+        kind: &str,
+        unit: Option<&(String, String)>,
+        hashes: (Option<u64>, Option<u64>),
+        message: impl Into<String>,
+    ) -> Self {
+        let unit = unit.map(|(k, n)| format!("{k}::{n}"));
+        let hash = |h: Option<u64>| h.map(|h| format!("{h:016x}")).unwrap_or_default();
+        let id = stable_hash(&[
+            path.unwrap_or(""),
+            kind,
+            unit.as_deref().unwrap_or(""),
+            &hash(hashes.0),
+            &hash(hashes.1),
+        ]);
+        Conflict { id: format!("{id:016x}"), kind: kind.to_string(), unit, message: message.into() }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Give repeated IDs (several conflicts inside one unit) an ordinal, so
+/// every ID in `conflicts` is unique.
+pub(crate) fn disambiguate(conflicts: &mut [Conflict]) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for c in conflicts {
+        let n = seen.entry(c.id.clone()).or_insert(0);
+        *n += 1;
+        if *n > 1 {
+            c.id = format!("{:016x}", stable_hash(&[&c.id, &n.to_string()]));
+        }
+    }
+}
+
+/// Move conflicts whose ID is in `suppressed` out of `conflicts`.
+pub(crate) fn split_suppressed(conflicts: &mut Vec<Conflict>, suppressed: &HashSet<String>) -> Vec<Conflict> {
+    let (hidden, kept) = std::mem::take(conflicts).into_iter().partition(|c| suppressed.contains(&c.id));
+    *conflicts = kept;
+    hidden
+}
+
+/// A [`Conflict`] with its place in the merged file: the unit's byte range
+/// and (1-based, inclusive) line range, and the unit's text in A and in B
+/// (`None` where that side deleted it). A conflict without a unit covers the
+/// whole file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictSite {
+    #[serde(flatten)]
+    pub conflict: Conflict,
+    pub bytes: Option<(usize, usize)>,
+    pub lines: Option<(usize, usize)>,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+/// Locate `conflicts` of a merge of `a` and `b` (`None`: deleted) into
+/// `merged` (the files are only parsed if a conflict is about a unit).
+pub fn locate_conflicts(
+    conflicts: &[Conflict],
+    merged: &str,
+    a: Option<&str>,
+    b: Option<&str>,
+    lang: AstLanguage,
+    opts: &ParseOptions,
+) -> Vec<ConflictSite> {
+    let parse = |code: &str| parse_typescript_to_ast_with(code, lang, opts).ok();
+    let files = conflicts
+        .iter()
+        .any(|c| c.unit.is_some())
+        .then(|| (parse(merged), a.and_then(parse), b.and_then(parse)));
+    let find = |file: &Option<AstFile>, unit: &str| {
+        let file = file.as_ref()?;
+        let (_, u) = file.keyed_units().into_iter().find(|((k, n), _)| format!("{k}::{n}") == unit)?;
+        Some((u.start_byte, u.end_byte))
+    };
+    let line = |at: usize| merged[..at.min(merged.len())].matches('\n').count() + 1;
+    conflicts
+        .iter()
+        .map(|c| {
+            let (bytes, a, b) = match (&c.unit, &files) {
+                (Some(unit), Some((in_merged, in_a, in_b))) => {
+                    let text = |file: &Option<AstFile>| {
+                        let (s, e) = find(file, unit)?;
+                        Some(file.as_ref()?.code[s..e].to_string())
+                    };
+                    (find(in_merged, unit), text(in_a), text(in_b))
+                }
+                _ => (Some((0, merged.len())), a.map(str::to_string), b.map(str::to_string)),
+            };
+            let lines = bytes.map(|(s, e)| (line(s), line(e.saturating_sub(1).max(s))));
+            ConflictSite { conflict: c.clone(), bytes, lines, a, b }
+        })
+        .collect()
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+    use crate::{AstLanguage, three_way_merge_top_level};
+
+    fn unit(name: &str) -> (String, String) {
+        ("function_declaration".to_string(), name.to_string())
+    }
+
+    #[test]
+    fn ids_hash_path_kind_unit_and_versions() {
+        let id = |path, kind, name, hashes| Conflict::new(path, kind, Some(&unit(name)), hashes, "m").id;
+        let first = id(Some("a.ts"), "update-update", "f", (Some(1), Some(2)));
+        assert_eq!(first.len(), 16);
+        assert!(first.chars().all(|c| c.is_ascii_hexdigit()));
+        // the message is not part of it
+        assert_eq!(Conflict::new(Some("a.ts"), "update-update", Some(&unit("f")), (Some(1), Some(2)), "other").id, first);
+        for other in [
+            id(Some("b.ts"), "update-update", "f", (Some(1), Some(2))),
+            id(Some("a.ts"), "delete-update", "f", (Some(1), Some(2))),
+            id(Some("a.ts"), "update-update", "g", (Some(1), Some(2))),
+            id(Some("a.ts"), "update-update", "f", (Some(1), Some(3))),
+            id(Some("a.ts"), "update-update", "f", (Some(2), Some(1))),
+            id(None, "update-update", "f", (Some(1), Some(2))),
+        ] {
+            assert_ne!(other, first);
+        }
+    }
+
+    #[test]
+    fn ids_survive_reruns_and_reordered_units() {
+        let f = |v: i32| format!("function f() {{\n  return {v};\n}}\n");
+        let g = |v: i32| format!("function g() {{\n  return {v};\n}}\n");
+        let ids = |fg: bool| {
+            let file = |x: i32, y: i32| if fg { format!("{}\n{}", f(x), g(y)) } else { format!("{}\n{}", g(y), f(x)) };
+            let out = three_way_merge_top_level(&file(0, 0), &file(1, 1), &file(2, 2), AstLanguage::TypeScript).unwrap();
+            let mut ids: Vec<_> = out.conflicts.into_iter().map(|c| (c.unit.unwrap(), c.id)).collect();
+            ids.sort();
+            ids
+        };
+        let first = ids(true);
+        assert_eq!(first.len(), 2);
+        assert_eq!(ids(true), first);
+        assert_eq!(ids(false), first);
+    }
+
+    #[test]
+    fn repeated_ids_get_ordinals() {
+        let conflict = |message: &str| Conflict::new(Some("a.ts"), "update-update", Some(&unit("f")), (None, None), message);
+        let mut conflicts = vec![conflict("one"), conflict("two"), conflict("three")];
+        let first = conflicts[0].id.clone();
+        disambiguate(&mut conflicts);
+        assert_eq!(conflicts[0].id, first);
+        let ids: HashSet<_> = conflicts.iter().map(|c| c.id.clone()).collect();
+        assert_eq!(ids.len(), 3);
+        // the same ordinals on the next run
+        let mut again = vec![conflict("one"), conflict("two"), conflict("three")];
+        disambiguate(&mut again);
+        assert_eq!(again, conflicts);
+    }
+
+    #[test]
+    fn suppressed_ids_move_out() {
+        let conflict = |name: &str| Conflict::new(Some("a.ts"), "update-update", Some(&unit(name)), (None, None), name);
+        let mut conflicts = vec![conflict("f"), conflict("g"), conflict("h")];
+        let suppressed: HashSet<String> = [conflicts[1].id.clone(), "0000000000000000".to_string()].into();
+        let hidden = split_suppressed(&mut conflicts, &suppressed);
+        assert_eq!(hidden, [conflict("g")]);
+        assert_eq!(conflicts, [conflict("f"), conflict("h")]);
+        assert!(split_suppressed(&mut conflicts, &HashSet::new()).is_empty());
+        assert_eq!(conflicts.len(), 2);
+    }
+}
@@ -0,0 +1,126 @@
+//! decorators.rs
+//! Decorator metadata merging for Angular/NestJS-style classes.
+//!
+//! Object arguments of a class's decorators (`@Component({...})`,
+//! `@Module({...})`) are aligned by position and merged key-by-key, so
+//! branches editing different metadata keys no longer conflict.
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, find_first, parse_tree};
+use crate::members::{MemberMerge, Side, pick_frame};
+use crate::objects::merge_object_text;
+
+/// Reconcile divergent updates to a decorated class by merging the object
+/// arguments of its decorators. Code outside those objects must not diverge.
+pub(crate) fn merge_decorators(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+    let (Some(o), Some(x), Some(y)) = (decorator_objects(base, lang), decorator_objects(a, lang), decorator_objects(b, lang)) else {
+        return MemberMerge::NotApplicable;
+    };
+    if o.is_empty() || o.len() != x.len() || o.len() != y.len() {
+        return MemberMerge::NotApplicable;
+    }
+
+    let (code, chosen) = match pick_frame(skeleton(base, &o), skeleton(a, &x), skeleton(b, &y)) {
+        Some(Side::A) => (a, &x),
+        Some(Side::B) => (b, &y),
+        None => return MemberMerge::NotApplicable,
+    };
+
+    let mut conflicts = Vec::new();
+    let mut out = code.to_string();
+    for i in (0..o.len()).rev() {
+        let obj_label = format!("{label}::@{}", o[i].0);
+        let (ro, rx, ry) = (o[i].1, x[i].1, y[i].1);
+        match merge_object_text(&base[ro.0..ro.1], &a[rx.0..rx.1], &b[ry.0..ry.1], &obj_label) {
+            Ok(merged) => out.replace_range(chosen[i].1.0..chosen[i].1.1, &merged),
+            Err(found) => conflicts.extend(found),
+        }
+    }
+
+    if conflicts.is_empty() {
+        MemberMerge::Merged(out)
+    } else {
+        conflicts.reverse();
+        MemberMerge::Conflicts(conflicts)
+    }
+}
+
+/// (decorator name, object argument range) for each class-level decorator.
+fn decorator_objects(code: &str, lang: AstLanguage) -> Option<Vec<(String, (usize, usize))>> {
+    let tree = parse_tree(code, lang).ok()?;
+    let class = find_first(tree.root_node(), "class_declaration")?;
+
+    let mut decorators = Vec::new();
+    let holders = [Some(class), class.parent().filter(|p| p.kind() == "export_statement")];
+    for holder in holders.into_iter().flatten() {
+        for i in 0..holder.child_count() {
+            if let Some(d) = holder.child(i).filter(|d| d.kind() == "decorator") {
+                decorators.push(d);
+            }
+        }
+    }
+    decorators.sort_by_key(|d| d.start_byte());
+
+    let mut out = Vec::new();
+    for d in decorators {
+        let Some(call) = d.named_child(0).filter(|c| c.kind() == "call_expression") else {
+            continue;
+        };
+        let name = call.child_by_field_name("function")?.utf8_text(code.as_bytes()).ok()?.to_string();
+        let args = call.child_by_field_name("arguments")?;
+        for j in 0..args.named_child_count() {
+            let arg: Node = args.named_child(j)?;
+            if arg.kind() == "object" {
+                out.push((name.clone(), (arg.start_byte(), arg.end_byte())));
+            }
+        }
+    }
+    Some(out)
+}
+
+fn skeleton(code: &str, objects: &[(String, (usize, usize))]) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut last = 0;
+    for (_, (s, e)) in objects {
+        out.push_str(&code[last..*s]);
+        out.push_str("{}");
+        last = *e;
+    }
+    out.push_str(&code[last..]);
+    out
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "@Component({\n  selector: 'app-cart',\n  templateUrl: './cart.html',\n})\nexport class Cart {}";
+
+    fn merge(base: &str, a: &str, b: &str) -> MemberMerge {
+        merge_decorators(base, a, b, "class_declaration::Cart", AstLanguage::TypeScript)
+    }
+
+    #[test]
+    fn different_metadata_keys_merge() {
+        let a = BASE.replace("  templateUrl", "  standalone: true,\n  templateUrl");
+        let b = BASE.replace("'./cart.html'", "'./cart.component.html'");
+        let merged = a.replace("'./cart.html'", "'./cart.component.html'");
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::Merged(merged));
+    }
+
+    #[test]
+    fn a_key_both_changed_conflicts() {
+        let (a, b) = (BASE.replace("app-cart", "app-basket"), BASE.replace("app-cart", "shop-cart"));
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::Conflicts(vec![
+                "Both branches updated class_declaration::Cart::@Component::selector differently".to_string()
+            ]));
+    }
+
+    #[test]
+    fn the_class_body_must_not_diverge() {
+        let a = BASE.replace("{}", "{ a = 1; }").replace("app-cart", "app-basket");
+        let b = BASE.replace("{}", "{ b = 1; }");
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::NotApplicable);
+    }
+}
@@ -4,90 +4,256 @@
 //!
 //! Edit kinds we emit for the MVP:
 //! - insert(kind,name,snippet)
-//! - update(kind,name,snippet)   (same unit exists, but byte range changed)
+//! - update(kind,name,snippet)   (same unit exists, but its text changed)
 //! - delete(kind,name)
+//! - rename(kind,name,snippet)   (a deleted unit inserted again under `name`, see `renames`)
+//! - unmerged(kind,name)          (tolerant mode: the unit changed, but overlaps a syntax error)
 
-use crate::ast::{AstFile, TopLevel};
+use crate::ast::{AstFile, ParseMode, ParseOptions, TopLevel, split_leading_comments};
+use crate::hash::content_hash;
+use crate::renames;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Edit {
-    pub kind: String,      // "insert" | "update" | "delete"
+    pub kind: String,      // "insert" | "update" | "delete" | "rename"
     pub unit_kind: String, // e.g. "function_declaration"
-    pub name: String,      // identifier ("" if unknown)
+    pub name: String,      // identifier, qualified by its scope (`User::save`; "" if unknown)
     pub payload: Option<String>, // code snippet for insert/update
+    /// [`content_hash`] of the payload.
+    #[serde(default)]
+    pub content_hash: Option<u64>,
+    /// Inserts: keys of the nearest units around the new one in `other` that
+    /// also exist in base, so the insert can be placed in context.
+    #[serde(default)]
+    pub after: Option<(String, String)>,
+    #[serde(default)]
+    pub before: Option<(String, String)>,
+    /// Inserts: key of the unit right before the new one in `other`, new or
+    /// not (see [`crate::InsertAnchor::Nearest`]).
+    #[serde(default)]
+    pub preceding: Option<(String, String)>,
+    /// Renames: the unit's name in base. A rename is placed like an insert
+    /// and stands for the deletion of the old unit too.
+    #[serde(default)]
+    pub renamed_from: Option<String>,
 }
 
-fn unit_key(u: &TopLevel) -> Option<(String, String)> {
-    // Use (kind, name) as the semantic identity for top-level units.
-    // If a unit has no name (rare at top-level), skip it for MVP.
-    Some((u.kind.clone(), u.name.clone()?))
+impl Edit {
+    fn new(kind: &str, (unit_kind, name): &(String, String), payload: Option<String>) -> Self {
+        Edit {
+            kind: kind.into(),
+            unit_kind: unit_kind.clone(),
+            name: name.clone(),
+            content_hash: payload.as_deref().map(content_hash),
+            payload,
+            after: None,
+            before: None,
+            preceding: None,
+            renamed_from: None,
+        }
+    }
 }
 
 /// Compute edits to go from base -> other at top level.
 pub fn diff_top_level(base: &AstFile, other: &AstFile) -> Vec<Edit> {
+    diff_top_level_with(base, other, &ParseOptions::default())
+}
+
+/// Like [`diff_top_level`]; with [`ParseMode::Tolerant`], units touching a
+/// syntax error on either side yield `unmerged` edits instead of
+/// inserts/updates/deletes.
+pub fn diff_top_level_with(base: &AstFile, other: &AstFile, opts: &ParseOptions) -> Vec<Edit> {
     use std::collections::{HashMap, HashSet};
 
-    let mut base_map: HashMap<(String, String), (usize, usize)> = HashMap::new();
-    for u in &base.units {
-        if let Some(key) = unit_key(u) {
-            base_map.insert(key, (u.start_byte, u.end_byte));
+    let tolerant = opts.mode == ParseMode::Tolerant;
+
+    // Units are identified by (kind, name [+ signature]), so overloads
+    // don't collide.
+    // Unnamed units (rare at top-level) are skipped for MVP.
+    let base_units = base.keyed_units();
+    let other_units = other.keyed_units();
+    let base_map: HashMap<&(String, String), &TopLevel> = base_units.iter().map(|(k, u)| (k, *u)).collect();
+    let other_map: HashMap<&(String, String), &TopLevel> = other_units.iter().map(|(k, u)| (k, *u)).collect();
+
+    // for inserts: the nearest units around each unit of OTHER that are in
+    // base, found in one pass each way rather than by a scan per insert
+    let in_base: Vec<bool> = other_units.iter().map(|(k, _)| base_map.contains_key(k)).collect();
+    let mut after = Vec::with_capacity(other_units.len());
+    let mut last = None;
+    for (i, &known) in in_base.iter().enumerate() {
+        after.push(last);
+        if known {
+            last = Some(i);
+        }
+    }
+    let mut before = vec![None; other_units.len()];
+    let mut next = None;
+    for (i, &known) in in_base.iter().enumerate().rev() {
+        before[i] = next;
+        if known {
+            next = Some(i);
         }
     }
 
-    let mut other_map: HashMap<(String, String), (usize, usize)> = HashMap::new();
-    for u in &other.units {
-        if let Some(key) = unit_key(u) {
-            other_map.insert(key, (u.start_byte, u.end_byte));
+    // renames: a unit gone from base paired with a new one that is it under
+    // another name (new key -> old key), when the pairing is unambiguous;
+    // only top-level units, whose uses name them unqualified
+    let undamaged = |file: &AstFile, u: &TopLevel| u.scope.is_empty() && (!tolerant || !file.overlaps_error(u));
+    let mut gone: HashMap<(&str, u64), Vec<usize>> = HashMap::new(); // (kind, nameless hash) -> base units
+    for (j, (key, bu)) in base_units.iter().enumerate() {
+        if !other_map.contains_key(key)
+            && !key.1.contains('#')
+            && undamaged(base, bu)
+            && let Some(hash) = renames::nameless_hash(base, bu)
+        {
+            gone.entry((&bu.kind, hash)).or_default().push(j);
+        }
+    }
+    let mut renamed: HashMap<&(String, String), &(String, String)> = HashMap::new();
+    let mut renamed_away: HashSet<&(String, String)> = HashSet::new();
+    if !gone.is_empty() {
+        // (a second unit of a name, `f#2`, has no name of its own to rename)
+        for (key, u) in other_units.iter().filter(|(k, u)| !base_map.contains_key(k) && !k.1.contains('#') && undamaged(other, u)) {
+            let Some(candidates) = renames::nameless_hash(other, u).and_then(|hash| gone.get(&(u.kind.as_str(), hash))) else {
+                continue;
+            };
+            let mut found = candidates.iter().map(|&j| &base_units[j]).filter(|(_, bu)| renames::is_rename(base, bu, other, u));
+            if let (Some((old, _)), None) = (found.next(), found.next())
+                && renamed_away.insert(old)
+            {
+                renamed.insert(key, old);
+            }
         }
     }
 
     let mut edits = Vec::new();
 
     // Inserts/Updates (units present in OTHER)
-    for u in &other.units {
-        if let Some((kind, name)) = unit_key(u) {
-            match base_map.get(&(kind.clone(), name.clone())) {
-                None => {
-                    // New unit inserted
-                    let snippet = &other.code[u.start_byte..u.end_byte];
-                    edits.push(Edit {
-                        kind: "insert".into(),
-                        unit_kind: kind,
-                        name,
-                        payload: Some(snippet.to_string()),
-                    });
-                }
-                Some((s, e)) => {
-                    // Unit existed in base; if byte range differs, call it an update (MVP)
-                    if *s != u.start_byte || *e != u.end_byte {
-                        let snippet = &other.code[u.start_byte..u.end_byte];
-                        edits.push(Edit {
-                            kind: "update".into(),
-                            unit_kind: kind,
-                            name,
-                            payload: Some(snippet.to_string()),
-                        });
-                    }
+    for (i, (key, u)) in other_units.iter().enumerate() {
+        let base_unit = base_map.get(key);
+        if tolerant && (other.overlaps_error(u) || base_unit.is_some_and(|bu| base.overlaps_error(bu))) {
+            let text = &other.code[u.start_byte..u.end_byte];
+            let unchanged = base_unit.is_some_and(|bu| base.code[bu.start_byte..bu.end_byte] == *text);
+            if !unchanged {
+                edits.push(Edit::new("unmerged", key, None));
+            }
+            continue;
+        }
+        match base_unit {
+            None => {
+                // New unit inserted
+                let key_at = |j: Option<usize>| j.map(|j: usize| other_units[j].0.clone());
+                let renamed_from = renamed.get(key).map(|(_, old)| old.clone());
+                let kind = if renamed_from.is_some() { "rename" } else { "insert" };
+                edits.push(Edit {
+                    after: key_at(after[i]),
+                    before: key_at(before[i]),
+                    preceding: i.checked_sub(1).map(|p| other_units[p].0.clone()),
+                    renamed_from,
+                    ..Edit::new(kind, key, Some(standalone_snippet(other, u)))
+                });
+            }
+            Some(bu) => {
+                if let Some(snippet) = update_payload(base, bu, other, u) {
+                    edits.push(Edit::new("update", key, Some(snippet)));
                 }
             }
         }
     }
 
     // Deletions (present in base but missing in OTHER)
-    let other_keys: HashSet<_> = other_map.keys().cloned().collect();
-    for u in &base.units {
-        if let Some(key) = unit_key(u) {
-            if !other_keys.contains(&key) {
-                edits.push(Edit {
-                    kind: "delete".into(),
-                    unit_kind: key.0,
-                    name: key.1,
-                    payload: None,
-                });
-            }
+    for (key, bu) in &base_units {
+        if other_map.contains_key(key) || renamed_away.contains(key) {
+            continue;
+        }
+        // a unit swallowed by error recovery is not a deletion
+        let damaged = base.overlaps_error(bu) || bu.name.as_deref().is_some_and(|n| other.error_mentions(n));
+        if tolerant && damaged {
+            edits.push(Edit::new("unmerged", key, None));
+        } else {
+            edits.push(Edit::new("delete", key, None));
         }
     }
 
     edits
-}
\ No newline at end of file
+}
+
+/// Text of `u` that can stand on its own: a declarator sharing a statement is
+/// re-wrapped as `const b = 2;`.
+fn standalone_snippet(file: &AstFile, u: &TopLevel) -> String {
+    match (u.statement, u.declarator) {
+        (Some(stmt), Some((s, e))) => format!("{} {};", statement_keyword(file, stmt), &file.code[s..e]),
+        _ => file.code[u.start_byte..u.end_byte].to_string(),
+    }
+}
+
+/// The replacement for base unit `bu` if `u` changed it, shaped to splice
+/// into `bu`'s range: when either side is a declarator of a shared statement,
+/// declarators are compared and the payload is a declarator or a statement
+/// to match `bu`.
+fn update_payload(base: &AstFile, bu: &TopLevel, other: &AstFile, u: &TopLevel) -> Option<String> {
+    if bu.statement.is_none() && u.statement.is_none() {
+        // Unit existed in base; if its text differs, call it an update. Byte
+        // ranges alone would flag every unit shifted by an edit above it.
+        // Exact-text hashes settle it without comparing (or copying) the
+        // text of units that didn't change.
+        let unchanged = bu.text_hash == u.text_hash && bu.end_byte - bu.start_byte == u.end_byte - u.start_byte;
+        return (!unchanged).then(|| other.code[u.start_byte..u.end_byte].to_string());
+    }
+
+    let declarator = |f: &AstFile, t: &TopLevel| t.declarator.map(|(s, e)| f.code[s..e].to_string());
+    let (old, new) = (declarator(base, bu)?, declarator(other, u)?);
+    if old == new {
+        return None;
+    }
+    match (bu.statement, u.statement) {
+        (Some(_), _) => Some(new),
+        // base statement held only this declarator: rewrite it, keeping its doc
+        (None, Some(stmt)) => {
+            let (doc, _) = split_leading_comments(&base.code[bu.start_byte..bu.end_byte]);
+            Some(format!("{doc}{} {new};", statement_keyword(other, stmt)))
+        }
+        (None, None) => None,
+    }
+}
+
+/// The statement's leading keywords up to its `const`/`let`/`var`
+/// (`export const`, `declare let`, ...).
+fn statement_keyword(file: &AstFile, (s, e): (usize, usize)) -> String {
+    let mut words = Vec::new();
+    for w in file.code[s..e].split_whitespace() {
+        words.push(w);
+        if matches!(w, "const" | "let" | "var") {
+            return words.join(" ");
+        }
+    }
+    "const".to_string()
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+    use crate::ast::{AstLanguage, parse_typescript_to_ast};
+
+    fn diff(base: &str, other: &str) -> Vec<Edit> {
+        let parse = |code| parse_typescript_to_ast(code, AstLanguage::TypeScript).unwrap();
+        diff_top_level(&parse(base), &parse(other))
+    }
+
+    #[test]
+    fn shifted_units_are_unchanged() {
+        let base = "function a() {}\n\nfunction b() {\n  return 1;\n}\n";
+        let other = "const x = 1;\n\nfunction a() {}\n\nfunction b() {\n  return 1;\n}\n";
+        let edits = diff(base, other);
+        assert_eq!(edits.iter().map(|e| (e.kind.as_str(), e.name.as_str())).collect::<Vec<_>>(), [("insert", "x")]);
+    }
+
+    #[test]
+    fn whitespace_only_changes_are_updates() {
+        let edits = diff("function b() {\n  return 1;\n}\n", "function b() {\n    return 1;\n}\n");
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].kind, "update");
+        assert_eq!(edits[0].payload.as_deref(), Some("function b() {\n    return 1;\n}"));
+    }
+}
@@ -0,0 +1,167 @@
+//! diff3.rs
+//! Line-level three-way merge of a unit both branches updated.
+//!
+//! The last resort before a unit conflict: each branch's edit is diffed
+//! line by line against base, and edits of different lines merge. Edits of
+//! the same or adjacent lines don't (as with `git merge-file`), unless both
+//! branches made the same one.
+
+use std::ops::Range;
+
+/// A run of base lines `old` replaced by `new` (line breaks included).
+pub(crate) struct Hunk<'t> {
+    pub(crate) old: Range<usize>,
+    pub(crate) new: Vec<&'t str>,
+}
+
+/// One step of an edit from one list of lines to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// The next line of both is the same.
+    Keep,
+    /// The next line of the new list is added.
+    Insert,
+    /// The next line of the old list is removed.
+    Delete,
+}
+
+/// The edits from `base` to `a` and to `b` merged; `None` if they overlap.
+pub(crate) fn merge_lines(base: &str, a: &str, b: &str) -> Option<String> {
+    let o: Vec<&str> = base.split_inclusive('\n').collect();
+    let (x, y): (Vec<&str>, Vec<&str>) = (a.split_inclusive('\n').collect(), b.split_inclusive('\n').collect());
+    let (ha, hb) = (hunks(&o, &x, |p, q| p == q), hunks(&o, &y, |p, q| p == q));
+    let mut out: Vec<&str> = Vec::new();
+    // base line, next hunk of A, of B
+    let (mut i, mut p, mut q) = (0, 0, 0);
+    while p < ha.len() || q < hb.len() {
+        // the hunks touching the earliest one, from either side
+        let lo = match (ha.get(p), hb.get(q)) {
+            (Some(x), Some(y)) => x.old.start.min(y.old.start),
+            (Some(x), None) => x.old.start,
+            (None, Some(y)) => y.old.start,
+            (None, None) => unreachable!("loop condition"),
+        };
+        let (from_a, from_b, mut hi) = (p, q, lo);
+        loop {
+            let before = (p, q);
+            while let Some(h) = ha.get(p).filter(|h| h.old.start <= hi) {
+                hi = hi.max(h.old.end);
+                p += 1;
+            }
+            while let Some(h) = hb.get(q).filter(|h| h.old.start <= hi) {
+                hi = hi.max(h.old.end);
+                q += 1;
+            }
+            if (p, q) == before {
+                break;
+            }
+        }
+        out.extend(&o[i..lo]);
+        let (x, y) = (replay(&o, lo..hi, &ha[from_a..p]), replay(&o, lo..hi, &hb[from_b..q]));
+        match (from_a < p, from_b < q) {
+            (true, false) => out.extend(x),
+            (false, true) => out.extend(y),
+            _ if x == y => out.extend(x),
+            _ => return None,
+        }
+        i = hi;
+    }
+    out.extend(&o[i..]);
+    Some(out.concat())
+}
+
+/// Base lines `range` with `hunks` (all within it) applied.
+fn replay<'t>(o: &[&'t str], range: Range<usize>, hunks: &[Hunk<'t>]) -> Vec<&'t str> {
+    let mut out = Vec::new();
+    let mut at = range.start;
+    for h in hunks {
+        out.extend(&o[at..h.old.start]);
+        out.extend(&h.new);
+        at = h.old.end;
+    }
+    out.extend(&o[at..range.end]);
+    out
+}
+
+/// Hunks from `old` to `new`, lines compared by `same` (see [`lcs_steps`]).
+pub(crate) fn hunks<'t>(old: &[&str], new: &[&'t str], same: impl Fn(&str, &str) -> bool) -> Vec<Hunk<'t>> {
+    let mut out: Vec<Hunk> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut open: Option<Hunk> = None;
+    for step in lcs_steps(old, new, same) {
+        if step == Step::Keep {
+            out.extend(open.take());
+            (i, j) = (i + 1, j + 1);
+            continue;
+        }
+        let hunk = open.get_or_insert(Hunk { old: i..i, new: Vec::new() });
+        if step == Step::Insert {
+            hunk.new.push(new[j]);
+            j += 1;
+        } else {
+            hunk.old.end = i + 1;
+            i += 1;
+        }
+    }
+    out.extend(open);
+    out
+}
+
+/// The steps from `old` to `new` along a longest common subsequence of
+/// lines, compared by `same`; within a change, additions come first.
+pub(crate) fn lcs_steps(old: &[&str], new: &[&str], same: impl Fn(&str, &str) -> bool) -> Vec<Step> {
+    let (n, m) = (old.len(), new.len());
+    // lcs[i][j]: common lines of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if same(old[i], new[j]) { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let mut out = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && same(old[i], new[j]) {
+            out.push(Step::Keep);
+            (i, j) = (i + 1, j + 1);
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push(Step::Insert);
+            j += 1;
+        } else {
+            out.push(Step::Delete);
+            i += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "one\ntwo\nthree\nfour\nfive\n";
+
+    #[test]
+    fn edits_of_different_lines_merge() {
+        let (a, b) = (BASE.replace("one", "ONE"), BASE.replace("five", "FIVE").replace("three\n", ""));
+        assert_eq!(merge_lines(BASE, &a, &b).as_deref(), Some("ONE\ntwo\nfour\nFIVE\n"));
+    }
+
+    #[test]
+    fn the_same_edit_on_both_merges() {
+        let a = BASE.replace("two", "TWO");
+        assert_eq!(merge_lines(BASE, &a, &a).as_deref(), Some(a.as_str()));
+    }
+
+    #[test]
+    fn edits_of_the_same_or_adjacent_lines_dont() {
+        assert_eq!(merge_lines(BASE, &BASE.replace("two", "2"), &BASE.replace("two", "II")), None);
+        assert_eq!(merge_lines(BASE, &BASE.replace("two", "2"), &BASE.replace("three", "3")), None);
+    }
+
+    #[test]
+    fn lcs_steps_align_equal_lines() {
+        let steps = lcs_steps(&["a", "b", "c"], &["a", "x", "c"], |p, q| p == q);
+        assert_eq!(steps, [Step::Keep, Step::Insert, Step::Delete, Step::Keep]);
+    }
+}
@@ -0,0 +1,181 @@
+//! encoding.rs
+//! Text encodings of merge inputs.
+//!
+//! Merging works on UTF-8 strings. Files in another encoding are decoded
+//! first ([`Encoding::detect`] guesses it: a byte order mark, else valid
+//! UTF-8, else the NUL pattern of UTF-16, else Latin-1) and the merge is
+//! encoded back the same way, so a Latin-1 or UTF-16 file round-trips byte
+//! for byte where nothing changed.
+
+use anyhow::{Result, bail};
+
+/// A text encoding; `bom`: the text starts with a byte order mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8 { bom: bool },
+    Utf16Le { bom: bool },
+    Utf16Be { bom: bool },
+    /// ISO-8859-1: every byte is the code point of the same value.
+    Latin1,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Utf8 { bom: false }
+    }
+}
+
+impl Encoding {
+    /// Best guess at the encoding of `bytes`.
+    pub fn detect(bytes: &[u8]) -> Self {
+        match bytes {
+            [0xef, 0xbb, 0xbf, ..] => return Encoding::Utf8 { bom: true },
+            [0xff, 0xfe, ..] => return Encoding::Utf16Le { bom: true },
+            [0xfe, 0xff, ..] => return Encoding::Utf16Be { bom: true },
+            _ => {}
+        }
+        if std::str::from_utf8(bytes).is_ok() {
+            return Encoding::Utf8 { bom: false };
+        }
+        // mostly-ASCII UTF-16 has a NUL in every other byte
+        let sample = &bytes[..bytes.len().min(4096) & !1];
+        let zeros = |offset: usize| sample.iter().skip(offset).step_by(2).filter(|&&b| b == 0).count();
+        let pairs = sample.len() / 2;
+        if pairs > 0 && zeros(1) * 2 > pairs && zeros(0) * 8 < pairs {
+            Encoding::Utf16Le { bom: false }
+        } else if pairs > 0 && zeros(0) * 2 > pairs && zeros(1) * 8 < pairs {
+            Encoding::Utf16Be { bom: false }
+        } else {
+            Encoding::Latin1
+        }
+    }
+
+    /// The encoding of a name as `.gitattributes`' `working-tree-encoding`
+    /// takes it (`UTF-16LE-BOM`, `ISO-8859-1`, ...); `UTF-16` is little endian
+    /// with a BOM.
+    pub fn for_label(label: &str) -> Option<Self> {
+        Some(match label.to_ascii_uppercase().replace('_', "-").as_str() {
+            "UTF-8" | "UTF8" => Encoding::Utf8 { bom: false },
+            "UTF-8-BOM" => Encoding::Utf8 { bom: true },
+            "UTF-16" | "UTF-16LE-BOM" => Encoding::Utf16Le { bom: true },
+            "UTF-16LE" => Encoding::Utf16Le { bom: false },
+            "UTF-16BE" => Encoding::Utf16Be { bom: false },
+            "UTF-16BE-BOM" => Encoding::Utf16Be { bom: true },
+            "ISO-8859-1" | "LATIN1" | "LATIN-1" | "L1" => Encoding::Latin1,
+            _ => return None,
+        })
+    }
+
+    /// The encoding of a merge of versions in these: a branch that changed
+    /// the encoding wins, like any one-sided change (A if both did).
+    pub fn merged(base: Self, a: Self, b: Self) -> Self {
+        if a == base { b } else { a }
+    }
+
+    /// `bytes` as text (without the BOM); malformed sequences become U+FFFD.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 { .. } => {
+                String::from_utf8_lossy(bytes.strip_prefix(&[0xef, 0xbb, 0xbf]).unwrap_or(bytes)).into_owned()
+            }
+            Encoding::Utf16Le { .. } => utf16(bytes.strip_prefix(&[0xff, 0xfe]).unwrap_or(bytes), u16::from_le_bytes),
+            Encoding::Utf16Be { .. } => utf16(bytes.strip_prefix(&[0xfe, 0xff]).unwrap_or(bytes), u16::from_be_bytes),
+            Encoding::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+        }
+    }
+
+    /// `text` in this encoding; an error if it has characters the encoding
+    /// can't represent.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>> {
+        let bom: &[u8] = match self {
+            Encoding::Utf8 { bom: true } => &[0xef, 0xbb, 0xbf],
+            Encoding::Utf16Le { bom: true } => &[0xff, 0xfe],
+            Encoding::Utf16Be { bom: true } => &[0xfe, 0xff],
+            _ => &[],
+        };
+        let mut out = bom.to_vec();
+        match self {
+            Encoding::Utf8 { .. } => out.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le { .. } => out.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+            Encoding::Utf16Be { .. } => out.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+            Encoding::Latin1 => {
+                for c in text.chars() {
+                    let Ok(b) = u8::try_from(u32::from(c)) else { bail!("{c:?} has no Latin-1 encoding") };
+                    out.push(b);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes.chunks(2).map(|c| unit([c[0], c.get(1).copied().unwrap_or(0)])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// `bytes` as text, in the encoding [`Encoding::detect`] finds.
+pub fn decode_text(bytes: &[u8]) -> (String, Encoding) {
+    let encoding = Encoding::detect(bytes);
+    (encoding.decode(bytes), encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "export const café = 'naïve';\n";
+
+    #[test]
+    fn detects_boms_utf16_and_latin1() {
+        let utf16le: Vec<u8> = TEXT.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let utf16be: Vec<u8> = TEXT.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(Encoding::detect(b"\xef\xbb\xbfa"), Encoding::Utf8 { bom: true });
+        assert_eq!(Encoding::detect(b"\xff\xfea\x00"), Encoding::Utf16Le { bom: true });
+        assert_eq!(Encoding::detect(b"\xfe\xff\x00a"), Encoding::Utf16Be { bom: true });
+        assert_eq!(Encoding::detect(TEXT.as_bytes()), Encoding::Utf8 { bom: false });
+        assert_eq!(Encoding::detect(&utf16le), Encoding::Utf16Le { bom: false });
+        assert_eq!(Encoding::detect(&utf16be), Encoding::Utf16Be { bom: false });
+        assert_eq!(Encoding::detect(b"caf\xe9\n"), Encoding::Latin1);
+        assert_eq!(Encoding::detect(b""), Encoding::Utf8 { bom: false });
+    }
+
+    #[test]
+    fn every_encoding_round_trips() {
+        for encoding in [
+            Encoding::Utf8 { bom: false },
+            Encoding::Utf8 { bom: true },
+            Encoding::Utf16Le { bom: false },
+            Encoding::Utf16Le { bom: true },
+            Encoding::Utf16Be { bom: false },
+            Encoding::Utf16Be { bom: true },
+            Encoding::Latin1,
+        ] {
+            let bytes = encoding.encode(TEXT).unwrap();
+            assert_eq!(decode_text(&bytes), (TEXT.to_string(), encoding), "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn latin1_rejects_what_it_cant_encode() {
+        assert!(Encoding::Latin1.encode("€").is_err());
+        assert_eq!(Encoding::Latin1.encode("ÿ").unwrap(), [0xff]);
+    }
+
+    #[test]
+    fn malformed_input_decodes_lossily() {
+        assert_eq!(Encoding::Utf8 { bom: false }.decode(b"a\xffb"), "a\u{fffd}b");
+        assert_eq!(Encoding::Utf16Le { bom: false }.decode(b"a\x00\x00\xd8"), "a\u{fffd}");
+    }
+
+    #[test]
+    fn labels_and_merges() {
+        assert_eq!(Encoding::for_label("utf-16"), Some(Encoding::Utf16Le { bom: true }));
+        assert_eq!(Encoding::for_label("UTF_16BE"), Some(Encoding::Utf16Be { bom: false }));
+        assert_eq!(Encoding::for_label("latin1"), Some(Encoding::Latin1));
+        assert_eq!(Encoding::for_label("SHIFT-JIS"), None);
+        let (utf8, latin1) = (Encoding::default(), Encoding::Latin1);
+        assert_eq!(Encoding::merged(utf8, utf8, latin1), latin1);
+        assert_eq!(Encoding::merged(utf8, latin1, Encoding::Utf16Le { bom: true }), latin1);
+    }
+}
@@ -0,0 +1,83 @@
+//! formatter.rs
+//! Post-merge formatting.
+//!
+//! Inserted and spliced payloads keep their branch's formatting; a
+//! [`Formatter`] registered with [`crate::MergeOptions::with_formatter`] runs
+//! over every file both branches changed, so the result matches the repo's
+//! style. [`CommandFormatter`] pipes the file through an external tool
+//! (`prettier --stdin-filepath {path}`).
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+
+/// Reformats merged code. `path` is the file's repo-relative path, if known.
+pub trait Formatter: Send + Sync {
+    fn format(&self, path: Option<&str>, code: &str) -> Result<String>;
+}
+
+impl<F> Formatter for F
+where
+    F: Fn(Option<&str>, &str) -> Result<String> + Send + Sync,
+{
+    fn format(&self, path: Option<&str>, code: &str) -> Result<String> {
+        self(path, code)
+    }
+}
+
+/// Runs a shell command with the code on stdin and takes its stdout; `{path}`
+/// in the command is replaced by the (shell-quoted) file path. The command
+/// runs as is: take it from the user, not from a repo's config (see
+/// [`crate::Policy::without_commands`]).
+#[derive(Debug, Clone)]
+pub struct CommandFormatter {
+    command: String,
+    dir: Option<PathBuf>,
+}
+
+impl CommandFormatter {
+    pub fn new(command: impl Into<String>) -> Self {
+        CommandFormatter { command: command.into(), dir: None }
+    }
+
+    /// Run in `dir` (e.g. the repo root, so the tool finds its config).
+    pub fn in_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+}
+
+impl Formatter for CommandFormatter {
+    fn format(&self, path: Option<&str>, code: &str) -> Result<String> {
+        run_command(&self.command, path, self.dir.as_deref(), code)
+    }
+}
+
+/// Run `command` through `sh` in `dir` with `input` on stdin, `{path}`
+/// replaced by the shell-quoted `path`; its stdout.
+pub(crate) fn run_command(command: &str, path: Option<&str>, dir: Option<&Path>, input: &str) -> Result<String> {
+    let quoted = format!("'{}'", path.unwrap_or("").replace('\'', r"'\''"));
+    let command = command.replace("{path}", &quoted);
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(&command).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let mut child = cmd.spawn().with_context(|| format!("running `{command}`"))?;
+    // feed stdin from a thread so a chatty command can't deadlock on a full stdout pipe
+    let mut stdin = child.stdin.take().context("command stdin")?;
+    let input = input.to_string();
+    let feeder = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let out = child.wait_with_output().with_context(|| format!("running `{command}`"))?;
+    let _ = feeder.join();
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        match stderr.trim() {
+            "" => bail!("`{command}` failed ({})", out.status),
+            stderr => bail!("`{command}` failed: {stderr}"),
+        }
+    }
+    String::from_utf8(out.stdout).with_context(|| format!("`{command}` printed invalid UTF-8"))
+}
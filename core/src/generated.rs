@@ -0,0 +1,28 @@
+//! generated.rs
+//! Generated files, recognized by their header.
+//!
+//! Generators mark their output in a comment at the top (`// @generated`,
+//! `/* AUTO-GENERATED */`, Go's `Code generated ... DO NOT EDIT.`); a
+//! semantic merge of two runs only produces conflicts nobody should resolve
+//! by hand, so such files merge by their [`crate::GeneratedMerge`] policy.
+//! Files marked elsewhere (`linguist-generated` in `.gitattributes`) are
+//! flagged with [`crate::MergeOptions::with_generated`].
+
+/// Comment lines at the top of a file a marker may be on.
+const HEADER_LINES: usize = 10;
+
+const MARKERS: [&str; 5] = ["@generated", "auto-generated", "autogenerated", "do not edit", "code generated by"];
+
+/// Whether `code` starts with a generated-code header: a marker in the
+/// comments before the first line of code.
+pub fn is_generated(code: &str) -> bool {
+    code.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .take(HEADER_LINES)
+        .take_while(|l| ["//", "/*", "*", "#!"].iter().any(|p| l.starts_with(p)))
+        .any(|l| {
+            let l = l.to_ascii_lowercase();
+            MARKERS.iter().any(|m| l.contains(m))
+        })
+}
@@ -0,0 +1,167 @@
+//! grammars.rs
+//! Tree-sitter grammars loaded at runtime, for languages sinkit doesn't
+//! bundle: a grammar built as a shared library (`tree-sitter build`, or
+//! `cc -shared -fPIC src/parser.c src/scanner.c`) is mapped to extensions
+//! in `.sinkit.toml`, with the node kinds that make up its units:
+//!
+//! ```toml
+//! [grammars.elixir]
+//! path = "tools/grammars/libtree-sitter-elixir.so"   # relative to the repo
+//! extensions = [".ex", ".exs"]
+//! units = ["call"]                      # root children merged by name
+//! # symbol = "tree_sitter_elixir"       # the default
+//! ```
+//!
+//! Every other root child is a unit too, keyed by its kind and first line,
+//! like a side-effect statement in TypeScript; comments right above a unit
+//! belong to it. The TypeScript-specific reconcilers (imports, members,
+//! JSX, ...) don't apply: divergent edits of one unit conflict.
+//!
+//! A loaded grammar is an [`AstLanguage::Runtime`], one per library (by
+//! canonical path), language function and unit kinds. Grammars compiled to
+//! wasm need tree-sitter's wasm runtime, which the tree-sitter this crate
+//! builds against (0.20) doesn't have.
+//!
+//! Loading a library runs its code: only load grammars from a trusted
+//! `.sinkit.toml` (see [`crate::Policy::without_commands`]).
+
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Result, bail};
+use tree_sitter::Language;
+
+use crate::ast::AstLanguage;
+use crate::hash::bytes_hash;
+
+/// A grammar to load, as configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrammarSpec {
+    /// The language's name (`elixir`).
+    pub name: String,
+    /// The shared library.
+    pub path: String,
+    /// Extensions merged with it (`.ex`).
+    pub extensions: Vec<String>,
+    /// Kinds of root children that are named units.
+    pub units: Vec<String>,
+    /// The library's language function; `tree_sitter_<name>` by default.
+    pub symbol: Option<String>,
+}
+
+struct Loaded {
+    /// The library (canonical path) and language function it came from.
+    library: (PathBuf, String),
+    language: Language,
+    units: Vec<String>,
+    /// Name, unit kinds and a hash of the library: what the grammar is
+    /// across processes, unlike its index.
+    fingerprint: String,
+}
+
+/// Every grammar loaded so far; an [`AstLanguage::Runtime`] is an index.
+/// Libraries stay loaded for the life of the process.
+static LOADED: Mutex<Vec<Loaded>> = Mutex::new(Vec::new());
+
+/// Load the grammar `spec` describes (a relative `path` is resolved against
+/// `root`), or find it loaded already: the same library, language function
+/// and unit kinds, whatever it's named. Two specs with one name and
+/// different libraries are two grammars.
+pub fn load_grammar(spec: &GrammarSpec, root: &Path) -> Result<AstLanguage> {
+    let path = root.join(&spec.path);
+    if path.extension().is_some_and(|e| e == "wasm") {
+        bail!(
+            "grammar {}: wasm grammars aren't supported by tree-sitter 0.20; build {} as a shared library",
+            spec.name,
+            spec.path
+        );
+    }
+    let path = path.canonicalize().map_err(|e| anyhow::anyhow!("grammar {}: {}: {e}", spec.name, path.display()))?;
+    let symbol = spec.symbol.clone().unwrap_or_else(|| format!("tree_sitter_{}", spec.name.replace('-', "_")));
+    let library = (path, symbol);
+    let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(id) = loaded.iter().position(|g| g.library == library && g.units == spec.units) {
+        return Ok(AstLanguage::Runtime(id as u16));
+    }
+    let (path, symbol) = &library;
+    let language = open(path, symbol).map_err(|e| anyhow::anyhow!("grammar {}: {e:#}", spec.name))?;
+    let hash = std::fs::read(path).map_or(0, |bytes| bytes_hash(&bytes));
+    let fingerprint = format!("{} {symbol} {:?} {hash:016x}", spec.name, spec.units);
+    loaded.push(Loaded { library, language, units: spec.units.clone(), fingerprint });
+    Ok(AstLanguage::Runtime((loaded.len() - 1) as u16))
+}
+
+/// The tree-sitter language of the loaded grammar `id`.
+pub(crate) fn language(id: u16) -> Result<Language> {
+    let loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    match loaded.get(usize::from(id)) {
+        Some(grammar) => Ok(grammar.language),
+        None => bail!("no grammar loaded as Runtime({id})"),
+    }
+}
+
+/// What the loaded grammar `id` is, for keys that outlive the process (see
+/// [`crate::cache`]).
+pub(crate) fn fingerprint(id: u16) -> Option<String> {
+    let loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    loaded.get(usize::from(id)).map(|g| g.fingerprint.clone())
+}
+
+/// The unit kinds of the loaded grammar `id`.
+pub(crate) fn unit_kinds(id: u16) -> Vec<String> {
+    let loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+    loaded.get(usize::from(id)).map(|g| g.units.clone()).unwrap_or_default()
+}
+
+#[cfg(unix)]
+unsafe extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *const c_char;
+}
+
+#[cfg(unix)]
+fn open(path: &Path, symbol: &str) -> Result<Language> {
+    const RTLD_NOW: c_int = 2;
+    let error = || {
+        // SAFETY: dlerror returns null or a NUL-terminated message
+        let message = unsafe { dlerror() };
+        if message.is_null() {
+            "unknown error".to_string()
+        } else {
+            unsafe { std::ffi::CStr::from_ptr(message) }.to_string_lossy().into_owned()
+        }
+    };
+    let c_path = CString::new(path.to_string_lossy().as_bytes())?;
+    let c_symbol = CString::new(symbol)?;
+    // SAFETY: both strings are NUL-terminated; the handle is never closed,
+    // so the language it returns stays valid
+    let handle = unsafe { dlopen(c_path.as_ptr(), RTLD_NOW) };
+    if handle.is_null() {
+        bail!("loading {}: {}", path.display(), error());
+    }
+    let function = unsafe { dlsym(handle, c_symbol.as_ptr()) };
+    if function.is_null() {
+        bail!("{} has no {symbol}: {}", path.display(), error());
+    }
+    // SAFETY: tree-sitter grammars export `const TSLanguage *tree_sitter_<name>(void)`,
+    // which `Language` wraps transparently
+    let function: unsafe extern "C" fn() -> Language = unsafe { std::mem::transmute(function) };
+    let language = unsafe { function() };
+    if !(tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION..=tree_sitter::LANGUAGE_VERSION).contains(&language.version()) {
+        bail!(
+            "{} was generated for tree-sitter ABI {}, this build reads {} to {}",
+            path.display(),
+            language.version(),
+            tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION,
+            tree_sitter::LANGUAGE_VERSION
+        );
+    }
+    Ok(language)
+}
+
+#[cfg(not(unix))]
+fn open(path: &Path, _symbol: &str) -> Result<Language> {
+    bail!("loading {}: runtime grammars are only supported on unix", path.display())
+}
@@ -0,0 +1,124 @@
+//! hash.rs
+//! Stable content hashes for unit text.
+//!
+//! FNV-1a (64-bit) over whitespace-normalized text: unlike `std`'s hasher it
+//! is stable across runs, platforms and versions, so hashes can be persisted
+//! and compared between processes. Reindenting or reflowing a unit does not
+//! change its hash. [`token_hash`] goes further and ignores all formatting,
+//! quote style included.
+
+use crate::ast::{AstLanguage, WalkControl};
+
+const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Hash of `text` with whitespace runs collapsed to one space and trimmed.
+pub fn content_hash(text: &str) -> u64 {
+    let mut h = OFFSET;
+    let mut feed = |bytes: &[u8]| {
+        for b in bytes {
+            h ^= u64::from(*b);
+            h = h.wrapping_mul(PRIME);
+        }
+    };
+    for (i, word) in text.split_whitespace().enumerate() {
+        if i > 0 {
+            feed(b" ");
+        }
+        feed(word.as_bytes());
+    }
+    h
+}
+
+/// FNV-1a over raw bytes, for content that isn't text.
+pub(crate) fn bytes_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(OFFSET, |h, b| (h ^ u64::from(*b)).wrapping_mul(PRIME))
+}
+
+/// FNV-1a over `parts`, each terminated by a NUL byte (so `["ab", "c"]` and
+/// `["a", "bc"]` differ). For IDs derived from several fields.
+pub(crate) fn stable_hash(parts: &[&str]) -> u64 {
+    let mut h = OFFSET;
+    for part in parts {
+        for b in part.as_bytes().iter().chain([&0u8]) {
+            h ^= u64::from(*b);
+            h = h.wrapping_mul(PRIME);
+        }
+    }
+    h
+}
+
+/// Hash of the tokens of `text`, ignoring formatting: whitespace, optional
+/// semicolons (where automatic semicolon insertion would put them, or
+/// wouldn't need one) and trailing commas, and quote style (`'a'`, `"a"` and `` `a` `` are the
+/// same string). String, template and regex literals and comments count as
+/// one token each, so spacing inside them still matters. Falls back to
+/// [`content_hash`] when `text` does not parse cleanly.
+pub fn token_hash(text: &str, lang: AstLanguage) -> u64 {
+    tokens_hash(text, lang, false)
+}
+
+/// [`token_hash`], optionally keeping semicolons and trailing commas.
+pub(crate) fn tokens_hash(text: &str, lang: AstLanguage, punctuation: bool) -> u64 {
+    let Ok(file) = crate::parse_typescript_to_ast(text, lang) else { return content_hash(text) };
+    if file.has_errors() {
+        return content_hash(text);
+    }
+    let mut tokens: Vec<String> = Vec::new();
+    file.walk(|node, _| {
+        // statements are told apart by where they start, not by semicolons,
+        // so `a;\n(b)` (two statements) still differs from `a\n(b)` (a call)
+        if !punctuation
+            && node.is_named()
+            && node.parent().is_some_and(|p| {
+                matches!(p.kind(), "program" | "statement_block" | "class_body" | "switch_case" | "switch_default")
+            })
+        {
+            tokens.push(";".to_string());
+        }
+        let atomic = matches!(node.kind(), "string" | "template_string" | "regex" | "comment");
+        if node.child_count() > 0 && !atomic {
+            return WalkControl::Continue;
+        }
+        let token = &text[node.start_byte()..node.end_byte()];
+        let plain_template = node.kind() == "template_string"
+            && !(0..node.named_child_count()).any(|i| node.named_child(i).is_some_and(|c| c.kind() == "template_substitution"));
+        match token {
+            "" => {}
+            ";" if !punctuation => {}
+            ")" | "]" | "}" | ">" if !punctuation && tokens.last().is_some_and(|t| t == ",") => {
+                tokens.pop();
+                tokens.push(token.to_string());
+            }
+            _ if node.kind() == "string" || plain_template => tokens.push(string_token(token)),
+            // comments may be reflowed
+            _ if node.kind() == "comment" => tokens.push(token.split_whitespace().collect::<Vec<_>>().join(" ")),
+            _ => tokens.push(token.to_string()),
+        }
+        WalkControl::SkipChildren
+    });
+    stable_hash(&tokens.iter().map(String::as_str).collect::<Vec<_>>())
+}
+
+/// A string literal (or template without substitutions) in one quote style:
+/// escaped quotes unescaped, other escapes as written.
+fn string_token(literal: &str) -> String {
+    let inner = literal.get(1..literal.len().saturating_sub(1)).unwrap_or_default();
+    let mut out = String::from("\"");
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(quote @ ('\'' | '"' | '`')) => out.push(quote),
+            escaped => {
+                out.push('\\');
+                out.extend(escaped);
+            }
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,143 @@
+//! hooks.rs
+//! React hook dependency-array merging (TS/TSX).
+//!
+//! Hook calls (`useEffect`, `useMemo`, `useCallback`, ...) are aligned by
+//! position — React requires a stable hook order — and their trailing
+//! dependency arrays merged as order-preserving unions.
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, parse_tree};
+use crate::members::{Member, MemberMerge, Side, commented_items, merge_members, normalize_ws, pick_frame, render_array};
+
+/// A hook call's dependency array: its elements and `[ ... ]` byte range.
+struct DepArray {
+    deps: Vec<Member>,
+    range: (usize, usize),
+}
+
+/// Reconcile divergent updates to a component/custom hook by merging hook
+/// dependency arrays. Code outside the arrays must not diverge.
+pub(crate) fn merge_hook_deps(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+    let (Some(o), Some(x), Some(y)) = (dep_arrays(base, lang), dep_arrays(a, lang), dep_arrays(b, lang)) else {
+        return MemberMerge::NotApplicable;
+    };
+    if o.is_empty() || o.len() != x.len() || o.len() != y.len() {
+        return MemberMerge::NotApplicable;
+    }
+
+    let (code, chosen) = match pick_frame(skeleton(base, &o), skeleton(a, &x), skeleton(b, &y)) {
+        Some(Side::A) => (a, &x),
+        Some(Side::B) => (b, &y),
+        None => return MemberMerge::NotApplicable,
+    };
+
+    let mut conflicts = Vec::new();
+    let mut out = code.to_string();
+    for i in (0..o.len()).rev() {
+        let deps_label = format!("{label}::deps#{i}");
+        match merge_members(&o[i].deps, &x[i].deps, &y[i].deps, &deps_label) {
+            Ok(merged) => {
+                let (s, e) = chosen[i].range;
+                out.replace_range(s..e, &render_array(&code[s..e], &merged));
+            }
+            Err(found) => conflicts.extend(found),
+        }
+    }
+
+    if conflicts.is_empty() {
+        MemberMerge::Merged(out)
+    } else {
+        conflicts.reverse();
+        MemberMerge::Conflicts(conflicts)
+    }
+}
+
+/// Dependency arrays of all hook calls in a payload, in source order.
+fn dep_arrays(code: &str, lang: AstLanguage) -> Option<Vec<DepArray>> {
+    let tree = parse_tree(code, lang).ok()?;
+    let mut arrays = Vec::new();
+    collect_dep_arrays(tree.root_node(), code, &mut arrays);
+    arrays.sort_by_key(|n| n.start_byte());
+
+    let mut out = Vec::new();
+    for arr in arrays {
+        let (items, closing) = commented_items(arr, code)?;
+        let mut deps = Vec::new();
+        for (d, text) in items {
+            let key = normalize_ws(d.utf8_text(code.as_bytes()).ok()?);
+            deps.push(Member { key, text });
+        }
+        deps.extend(closing);
+        out.push(DepArray { deps, range: (arr.start_byte(), arr.end_byte()) });
+    }
+    Some(out)
+}
+
+fn collect_dep_arrays<'t>(node: Node<'t>, code: &str, out: &mut Vec<Node<'t>>) {
+    if node.kind() == "call_expression"
+        && let Some(callee) = node.child_by_field_name("function")
+        && is_hook_name(callee.utf8_text(code.as_bytes()).unwrap_or(""))
+        && let Some(args) = node.child_by_field_name("arguments")
+        && let Some(last) = args.named_child(args.named_child_count().saturating_sub(1))
+        && last.kind() == "array"
+        && args.named_child_count() > 1
+    {
+        out.push(last);
+    }
+    for i in 0..node.child_count() {
+        if let Some(c) = node.child(i) {
+            collect_dep_arrays(c, code, out);
+        }
+    }
+}
+
+/// `useEffect`, `React.useMemo`, `useCustomThing`, ...
+fn is_hook_name(callee: &str) -> bool {
+    let name = callee.rsplit('.').next().unwrap_or(callee);
+    name.strip_prefix("use")
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| c.is_ascii_uppercase())
+}
+
+fn skeleton(code: &str, arrays: &[DepArray]) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut last = 0;
+    for arr in arrays {
+        out.push_str(&code[last..arr.range.0]);
+        out.push_str("[]");
+        last = arr.range.1;
+    }
+    out.push_str(&code[last..]);
+    out
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "function Cart({ id }) {\n  useEffect(() => {\n    load(id);\n  }, [id]);\n  return null;\n}";
+
+    fn merge(base: &str, a: &str, b: &str) -> MemberMerge {
+        merge_hook_deps(base, a, b, "function_declaration::Cart", AstLanguage::Tsx)
+    }
+
+    #[test]
+    fn dependencies_both_branches_added_unite() {
+        let (a, b) = (BASE.replace("[id]", "[id, user]"), BASE.replace("[id]", "[id, locale]"));
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::Merged(BASE.replace("[id]", "[id, user, locale]")));
+    }
+
+    #[test]
+    fn a_removed_dependency_stays_removed() {
+        let (a, b) = (BASE.replace("[id]", "[]"), BASE.replace("[id]", "[id, user]"));
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::Merged(BASE.replace("[id]", "[user]")));
+    }
+
+    #[test]
+    fn the_effect_body_must_not_diverge() {
+        let a = BASE.replace("load(id)", "fetch(id)").replace("[id]", "[id, user]");
+        let b = BASE.replace("load(id)", "get(id)");
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::NotApplicable);
+    }
+}
@@ -0,0 +1,109 @@
+//! imports.rs
+//! Pruning imports a merge left without users.
+//!
+//! When both branches delete the last user of an import, the import union
+//! keeps the import and the merged file fails `no-unused-imports`. With
+//! [`crate::MergeOptions::with_pruned_imports`], bindings that were used in
+//! base or in a branch's edits but no longer are in the merged file get
+//! dropped; bindings nobody used (intentionally unused, side-effect imports)
+//! are left alone.
+
+use std::collections::HashSet;
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, parse_tree};
+
+/// Identifiers referenced outside import statements. Shadowing bindings count
+/// too, which errs on the side of keeping an import.
+pub(crate) fn used_identifiers(code: &str, lang: AstLanguage, out: &mut HashSet<String>) {
+    let Ok(tree) = parse_tree(code, lang) else { return };
+    collect_uses(tree.root_node(), code, out);
+}
+
+fn collect_uses(node: Node, code: &str, out: &mut HashSet<String>) {
+    match node.kind() {
+        "import_statement" => return,
+        "identifier" | "type_identifier" | "shorthand_property_identifier" => {
+            out.insert(code[node.start_byte()..node.end_byte()].to_string());
+        }
+        _ => {}
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_uses(child, code, out);
+    }
+}
+
+/// Drop import bindings of `code` that `used_before` has but `code` no longer
+/// uses; an import left with no bindings goes entirely. Returns the new code
+/// and a note per dropped binding.
+pub(crate) fn prune_unused(code: &str, lang: AstLanguage, used_before: &HashSet<String>) -> (String, Vec<String>) {
+    let tree = match parse_tree(code, lang) {
+        Ok(tree) if !tree.root_node().has_error() => tree,
+        _ => return (code.to_string(), Vec::new()),
+    };
+    let mut used = HashSet::new();
+    collect_uses(tree.root_node(), code, &mut used);
+    let orphaned = |name: &str| used_before.contains(name) && !used.contains(name);
+
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+    let mut notes = Vec::new();
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    for stmt in root.children(&mut cursor).filter(|n| n.kind() == "import_statement") {
+        let Some(clause) = stmt.children(&mut stmt.walk()).find(|n| n.kind() == "import_clause") else { continue };
+        let text = |n: Node| &code[n.start_byte()..n.end_byte()];
+        let module = stmt.child_by_field_name("source").map_or("", text);
+
+        let mut default = None;
+        let mut namespace = None;
+        let mut named: Vec<&str> = Vec::new();
+        let mut dropped = Vec::new();
+        for part in clause.children(&mut clause.walk()) {
+            match part.kind() {
+                "identifier" => {
+                    let name = text(part);
+                    if orphaned(name) { dropped.push(name) } else { default = Some(name) }
+                }
+                "namespace_import" => {
+                    let name = part.children(&mut part.walk()).find(|n| n.kind() == "identifier").map_or("", text);
+                    if orphaned(name) { dropped.push(name) } else { namespace = Some(text(part)) }
+                }
+                "named_imports" => {
+                    for spec in part.children(&mut part.walk()).filter(|n| n.kind() == "import_specifier") {
+                        let local = spec.child_by_field_name("alias").or_else(|| spec.child_by_field_name("name"));
+                        let name = local.map_or("", text);
+                        if orphaned(name) { dropped.push(name) } else { named.push(text(spec)) }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if dropped.is_empty() {
+            continue;
+        }
+        notes.extend(dropped.iter().map(|name| format!("Removed import of `{name}` from {module}, unused after the merge")));
+
+        let (start, end) = (stmt.start_byte(), stmt.end_byte());
+        let mut bindings: Vec<String> = default.into_iter().chain(namespace).map(str::to_string).collect();
+        if !named.is_empty() {
+            bindings.push(format!("{{ {} }}", named.join(", ")));
+        }
+        if bindings.is_empty() {
+            // the whole line goes
+            let end = code[end..].find('\n').map_or(code.len(), |i| end + i + 1);
+            edits.push((start, end, String::new()));
+        } else {
+            let keyword = if text(stmt).starts_with("import type ") { "import type" } else { "import" };
+            let semi = if text(stmt).ends_with(';') { ";" } else { "" };
+            edits.push((start, end, format!("{keyword} {} from {module}{semi}", bindings.join(", "))));
+        }
+    }
+
+    let mut out = code.to_string();
+    for (start, end, replacement) in edits.into_iter().rev() {
+        out.replace_range(start..end, &replacement);
+    }
+    (out, notes)
+}
@@ -0,0 +1,123 @@
+//! indent.rs
+//! Indentation style of a file, and re-indenting spliced payloads to it.
+//!
+//! A unit a branch inserted or updated carries the branch's indentation; in a
+//! file indented with tabs, a payload from an editor set to four spaces
+//! would otherwise be spliced in as is. Payloads are re-indented level for
+//! level (alignment spaces past the last level are kept) before composing.
+//! Lines inside template literals (by the parse, not by counting backticks,
+//! which strings and comments hold too) are left alone: their whitespace is
+//! data.
+
+use std::ops::Range;
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, parse_tree};
+
+/// How a file indents one level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Indent {
+    Tabs,
+    Spaces(usize),
+}
+
+impl Indent {
+    /// The style most indented lines of `code` use; `None` if no line is
+    /// indented.
+    pub(crate) fn detect(code: &str) -> Option<Self> {
+        let (mut tabs, mut spaces) = (0usize, 0usize);
+        let mut widths = [0usize; 9]; // how often each step up (in spaces) occurs
+        let mut previous = 0usize;
+        for line in code.lines() {
+            let content = line.trim_start_matches([' ', '\t']);
+            // blank lines and ` * ` block comment continuations say nothing
+            if content.is_empty() || content.starts_with('*') {
+                continue;
+            }
+            let leading = &line[..line.len() - content.len()];
+            if leading.starts_with('\t') {
+                tabs += 1;
+                continue;
+            }
+            let width = leading.len();
+            if width > 0 && !leading.contains('\t') {
+                spaces += 1;
+            }
+            if width > previous && width - previous < widths.len() {
+                widths[width - previous] += 1;
+            }
+            previous = width;
+        }
+        if tabs == 0 && spaces == 0 {
+            return None;
+        }
+        if tabs > spaces {
+            return Some(Indent::Tabs);
+        }
+        // the most common step up; ties go to the narrower
+        let (width, _) = widths.iter().enumerate().skip(1).rev().filter(|(_, n)| **n > 0).max_by_key(|(_, n)| **n)?;
+        Some(Indent::Spaces(width))
+    }
+
+    /// The style of a merge of files in these: base's, unless both branches
+    /// moved to the same new one (one large insert in another style doesn't
+    /// make the file's); without base's, a branch's (A's if they differ).
+    pub(crate) fn merged(base: Option<Self>, a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (base, a, b) {
+            (_, Some(a), Some(b)) if a == b => Some(a),
+            (Some(base), _, _) => Some(base),
+            (None, a, b) => a.or(b),
+        }
+    }
+
+    fn level(self) -> String {
+        match self {
+            Indent::Tabs => "\t".to_string(),
+            Indent::Spaces(n) => " ".repeat(n),
+        }
+    }
+}
+
+/// `payload` indented `to`'s way instead of its own; `None` if it already
+/// is, or doesn't parse.
+pub(crate) fn reindent(payload: &str, to: Indent, lang: AstLanguage) -> Option<String> {
+    let from = Indent::detect(payload).filter(|&from| from != to)?;
+    let tree = parse_tree(payload, lang).ok()?;
+    let mut templates = Vec::new();
+    collect_templates(tree.root_node(), &mut templates);
+    let mut out = String::with_capacity(payload.len());
+    let mut at = 0;
+    for line in payload.split_inclusive('\n') {
+        let content = line.trim_start_matches([' ', '\t']);
+        let in_template = templates.iter().any(|t| t.start < at && at < t.end);
+        at += line.len();
+        if in_template || content.trim_end().is_empty() {
+            out.push_str(line);
+        } else {
+            let leading = &line[..line.len() - content.len()];
+            let tabs = leading.chars().filter(|&c| c == '\t').count();
+            let spaces = leading.len() - tabs;
+            let (levels, align) = match from {
+                Indent::Tabs => (tabs, spaces),
+                Indent::Spaces(n) => (tabs + spaces / n, spaces % n),
+            };
+            out.push_str(&to.level().repeat(levels));
+            out.push_str(&" ".repeat(align));
+            out.push_str(content);
+        }
+    }
+    Some(out)
+}
+
+/// Byte ranges of the template literals under `node`.
+fn collect_templates(node: Node, out: &mut Vec<Range<usize>>) {
+    if node.kind() == "template_string" {
+        out.push(node.start_byte()..node.end_byte());
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_templates(child, out);
+    }
+}
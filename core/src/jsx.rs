@@ -0,0 +1,339 @@
+//! jsx.rs
+//! JSX element-aware merging for React components (TSX only).
+//!
+//! The outermost JSX trees of a component are aligned by position; inside them,
+//! attributes are keyed by name and child elements by tag + `key`/`id`
+//! attribute (or occurrence), recursively. One branch adding a prop while the
+//! other adds a sibling element therefore merges instead of conflicting.
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, find_first, parse_tree};
+use crate::members::{Member, MemberMerge, Side, merge_members, merge_members_with, normalize_ws, pick_frame};
+
+const JSX_ROOTS: [&str; 2] = ["jsx_element", "jsx_self_closing_element"];
+
+/// A parsed JSX element, with ranges relative to its own text.
+struct Element {
+    tag: String,
+    attrs: Vec<Member>,
+    /// Range between the tag name and the closing `>`/`/>` of the opening tag.
+    attrs_range: (usize, usize),
+    /// Children and the range between opening and closing tags (`None` if self-closing).
+    children: Option<(Vec<Member>, (usize, usize))>,
+    /// Every child starts on its own line, so children can be re-laid out safely.
+    linewise: bool,
+}
+
+/// Reconcile divergent updates to a component by merging its returned JSX.
+/// Code outside the JSX trees must not diverge.
+pub(crate) fn merge_jsx(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+    if lang != AstLanguage::Tsx {
+        return MemberMerge::NotApplicable;
+    }
+    let (Some(o), Some(x), Some(y)) = (jsx_roots(base), jsx_roots(a), jsx_roots(b)) else {
+        return MemberMerge::NotApplicable;
+    };
+    if o.is_empty() || o.len() != x.len() || o.len() != y.len() {
+        return MemberMerge::NotApplicable;
+    }
+
+    let (code, chosen) = match pick_frame(skeleton(base, &o), skeleton(a, &x), skeleton(b, &y)) {
+        Some(Side::A) => (a, &x),
+        Some(Side::B) => (b, &y),
+        None => return MemberMerge::NotApplicable,
+    };
+
+    let mut conflicts = Vec::new();
+    let mut out = code.to_string();
+    for i in (0..o.len()).rev() {
+        let (oi, xi, yi) = (&base[o[i].0..o[i].1], &a[x[i].0..x[i].1], &b[y[i].0..y[i].1]);
+        match merge_element(oi, xi, yi, label) {
+            Ok(merged) => out.replace_range(chosen[i].0..chosen[i].1, &merged),
+            Err(found) => conflicts.extend(found),
+        }
+    }
+
+    if conflicts.is_empty() {
+        MemberMerge::Merged(out)
+    } else {
+        conflicts.reverse();
+        MemberMerge::Conflicts(conflicts)
+    }
+}
+
+/// 3-way merge of one JSX element's text.
+fn merge_element(o: &str, x: &str, y: &str, label: &str) -> Result<String, Vec<String>> {
+    if x == y || y == o {
+        return Ok(x.to_string());
+    }
+    if x == o {
+        return Ok(y.to_string());
+    }
+
+    let conflict = || vec![format!("Both branches updated {label} differently")];
+    let (Some(eo), Some(ex), Some(ey)) = (parse_element(o), parse_element(x), parse_element(y)) else {
+        return Err(conflict());
+    };
+    if eo.tag != ex.tag || eo.tag != ey.tag || eo.children.is_some() != ex.children.is_some()
+        || ex.children.is_some() != ey.children.is_some()
+    {
+        return Err(conflict());
+    }
+    let label = format!("{label}<{}>", eo.tag);
+
+    let mut conflicts = Vec::new();
+    let attrs = merge_members(&eo.attrs, &ex.attrs, &ey.attrs, &label);
+
+    // Children: take one side's region when only it changed; otherwise merge
+    // element-by-element, which needs one child per line on every side.
+    let children = match (&eo.children, &ex.children, &ey.children) {
+        (Some((co, ro)), Some((cx, rx)), Some((cy, ry))) => {
+            let (to, tx, ty) = (&o[ro.0..ro.1], &x[rx.0..rx.1], &y[ry.0..ry.1]);
+            if tx == ty || ty == to {
+                Some(Ok(tx.to_string()))
+            } else if tx == to {
+                Some(Ok(ty.to_string()))
+            } else if eo.linewise && ex.linewise && ey.linewise {
+                let merged = merge_members_with(co, cx, cy, &label, |_, o, x, y| merge_element(o, x, y, &label).ok());
+                Some(merged.map(|ms| render_children(tx, &ms)))
+            } else {
+                Some(Err(vec![format!("Both branches updated children of {label} differently")]))
+            }
+        }
+        _ => None,
+    };
+
+    // Render into A's element text, right→left.
+    let mut out = x.to_string();
+    if let Some(result) = children {
+        match result {
+            Ok(text) => {
+                let (s, e) = ex.children.as_ref().map(|(_, r)| *r).unwrap_or_default();
+                out.replace_range(s..e, &text);
+            }
+            Err(found) => conflicts.extend(found),
+        }
+    }
+    match attrs {
+        Ok(ms) => {
+            let (s, e) = ex.attrs_range;
+            out.replace_range(s..e, &render_attrs(&x[s..e], &ms));
+        }
+        Err(found) => conflicts.extend(found),
+    }
+
+    if conflicts.is_empty() { Ok(out) } else { Err(conflicts) }
+}
+
+/// Byte ranges of the outermost JSX elements in a payload.
+fn jsx_roots(code: &str) -> Option<Vec<(usize, usize)>> {
+    let tree = parse_tree(code, AstLanguage::Tsx).ok()?;
+    let mut out = Vec::new();
+    collect_roots(tree.root_node(), &mut out);
+    Some(out)
+}
+
+fn collect_roots(node: Node, out: &mut Vec<(usize, usize)>) {
+    if JSX_ROOTS.contains(&node.kind()) {
+        out.push((node.start_byte(), node.end_byte()));
+        return;
+    }
+    for i in 0..node.child_count() {
+        if let Some(c) = node.child(i) {
+            collect_roots(c, out);
+        }
+    }
+}
+
+fn skeleton(code: &str, roots: &[(usize, usize)]) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut last = 0;
+    for (s, e) in roots {
+        out.push_str(&code[last..*s]);
+        out.push_str("<></>");
+        last = *e;
+    }
+    out.push_str(&code[last..]);
+    out
+}
+
+/// Parse a standalone JSX element snippet.
+fn parse_element(code: &str) -> Option<Element> {
+    let tree = parse_tree(code, AstLanguage::Tsx).ok()?;
+    let root = tree.root_node();
+    let node = JSX_ROOTS
+        .iter()
+        .filter_map(|k| find_first(root, k))
+        .min_by_key(|n| n.start_byte())?;
+
+    let (open, children) = if node.kind() == "jsx_element" {
+        let open = node.child_by_field_name("open_tag").or_else(|| node.named_child(0))?;
+        let close = node
+            .child_by_field_name("close_tag")
+            .or_else(|| node.named_child(node.named_child_count().checked_sub(1)?))?;
+        (open, Some((open.end_byte(), close.start_byte())))
+    } else {
+        (node, None)
+    };
+
+    let name = open.child_by_field_name("name");
+    let tag = match name {
+        Some(n) => n.utf8_text(code.as_bytes()).ok()?.to_string(),
+        None => String::new(), // fragment
+    };
+
+    let mut attrs = Vec::new();
+    for i in 0..open.named_child_count() {
+        let c = open.named_child(i)?;
+        if Some(c) == name {
+            continue;
+        }
+        let key = match c.kind() {
+            "jsx_attribute" => c.named_child(0)?.utf8_text(code.as_bytes()).ok()?.to_string(),
+            _ => normalize_ws(c.utf8_text(code.as_bytes()).ok()?),
+        };
+        attrs.push(Member { key, text: c.utf8_text(code.as_bytes()).ok()?.to_string() });
+    }
+    let attrs_start = name.map_or(open.start_byte() + 1, |n| n.end_byte());
+    let closer = open.child(open.child_count().checked_sub(1)?)?;
+    let attrs_range = (attrs_start, closer.start_byte());
+
+    let mut linewise = true;
+    let children = match children {
+        None => None,
+        Some((s, e)) => {
+            let mut ms = Vec::new();
+            let mut seen: std::collections::HashMap<String, usize> = Default::default();
+            for i in 0..node.named_child_count() {
+                let c = node.named_child(i)?;
+                if c.start_byte() < s || c.end_byte() > e {
+                    continue;
+                }
+                let text = c.utf8_text(code.as_bytes()).ok()?;
+                if text.trim().is_empty() {
+                    continue;
+                }
+                let line_start = code[..c.start_byte()].rfind('\n').map_or(0, |p| p + 1);
+                if line_start <= s || !code[line_start..c.start_byte()].trim().is_empty() {
+                    linewise = false;
+                }
+                let base_key = child_key(&c, code)?;
+                let n = seen.entry(base_key.clone()).or_insert(0);
+                let key = format!("{base_key}#{n}");
+                *n += 1;
+                ms.push(Member { key, text: text.to_string() });
+            }
+            Some((ms, (s, e)))
+        }
+    };
+
+    Some(Element { tag, attrs, attrs_range, children, linewise })
+}
+
+/// Identity of a child: tag plus `key`/`id` attribute for elements, normalized
+/// text for text and expression children.
+fn child_key(node: &Node, code: &str) -> Option<String> {
+    let open = match node.kind() {
+        "jsx_element" => node.child_by_field_name("open_tag").or_else(|| node.named_child(0))?,
+        "jsx_self_closing_element" => *node,
+        _ => return Some(normalize_ws(node.utf8_text(code.as_bytes()).ok()?)),
+    };
+    let tag = open
+        .child_by_field_name("name")
+        .and_then(|n| n.utf8_text(code.as_bytes()).ok())
+        .unwrap_or("");
+    for attr_name in ["key", "id"] {
+        for i in 0..open.named_child_count() {
+            let c = open.named_child(i)?;
+            if c.kind() == "jsx_attribute"
+                && c.named_child(0).and_then(|n| n.utf8_text(code.as_bytes()).ok()) == Some(attr_name)
+            {
+                let value = c.named_child(1).and_then(|v| v.utf8_text(code.as_bytes()).ok()).unwrap_or("");
+                return Some(format!("<{tag} {attr_name}={value}>"));
+            }
+        }
+    }
+    Some(format!("<{tag}>"))
+}
+
+/// Render attributes into the region after the tag name, keeping one-per-line
+/// layout when the original used it.
+fn render_attrs(original: &str, attrs: &[Member]) -> String {
+    if attrs.is_empty() {
+        return if original.ends_with(' ') { " ".to_string() } else { String::new() };
+    }
+    let texts: Vec<&str> = attrs.iter().map(|m| m.text.as_str()).collect();
+    if !original.contains('\n') {
+        let tail = if original.ends_with(' ') { " " } else { "" };
+        return format!(" {}{tail}", texts.join(" "));
+    }
+    let indent = original
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .unwrap_or("  ");
+    let closing = original.rsplit('\n').next().unwrap_or("");
+    let mut out = String::new();
+    for t in texts {
+        out.push('\n');
+        out.push_str(indent);
+        out.push_str(t);
+    }
+    out.push('\n');
+    out.push_str(closing);
+    out
+}
+
+/// Render children one per line, mimicking the original region's indentation.
+fn render_children(original: &str, children: &[Member]) -> String {
+    let indent = original
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .unwrap_or("  ");
+    let closing = original.rsplit('\n').next().unwrap_or("");
+    let mut out = String::new();
+    for m in children {
+        out.push('\n');
+        out.push_str(indent);
+        out.push_str(m.text.trim());
+    }
+    out.push('\n');
+    out.push_str(closing);
+    out
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "function Cart() {\n  return (\n    <ul className=\"cart\">\n      <li key=\"a\">A</li>\n    </ul>\n  );\n}";
+
+    fn merge(base: &str, a: &str, b: &str) -> MemberMerge {
+        merge_jsx(base, a, b, "function_declaration::Cart", AstLanguage::Tsx)
+    }
+
+    #[test]
+    fn a_prop_and_a_sibling_element_merge() {
+        let a = BASE.replace("className=\"cart\"", "className=\"cart\" role=\"list\"");
+        let b = BASE.replace("      <li key=\"a\">A</li>\n", "      <li key=\"a\">A</li>\n      <li key=\"b\">B</li>\n");
+        let merged = a.replace("      <li key=\"a\">A</li>\n", "      <li key=\"a\">A</li>\n      <li key=\"b\">B</li>\n");
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::Merged(merged));
+    }
+
+    #[test]
+    fn a_prop_both_changed_conflicts() {
+        let (a, b) = (BASE.replace("\"cart\"", "\"basket\""), BASE.replace("\"cart\"", "\"cart open\""));
+        assert_eq!(merge(BASE, &a, &b), MemberMerge::Conflicts(vec![
+                "Both branches updated function_declaration::Cart<ul>::className differently".to_string()
+            ]));
+    }
+
+    #[test]
+    fn typescript_files_have_no_jsx_merge() {
+        let b = BASE.replace("className=\"cart\"", "id=\"cart\"");
+        let a = BASE.replace("<li key=\"a\">A</li>", "<li key=\"a\">Z</li>");
+        assert_eq!(merge_jsx(BASE, &a, &b, "function_declaration::Cart", AstLanguage::TypeScript), MemberMerge::NotApplicable);
+    }
+}
@@ -0,0 +1,275 @@
+//! keyed.rs
+//! Data files as maps of keyed members, for structural three-way merges.
+//!
+//! Pretty-printed JSON objects and block-style YAML maps split into one
+//! [`Member`] per key, whose text is the key and its value as written (so
+//! unchanged members come out byte for byte), and render back in the layout
+//! they came in: the members' indentation and blank lines between them.
+//! Comments above a YAML key belong to it; a YAML list may sit at its
+//! parent key's indentation. Flow-style YAML is a value like any other.
+//! [`merge_keyed`] merges member lists, asking what to keep where both
+//! branches changed a member.
+
+use crate::compose::{MergeOptions, MergeOutcome};
+use crate::conflicts::Conflict;
+use crate::members::{Member, merge_members};
+use crate::resolutions::Resolution;
+
+/// How a data file is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Syntax {
+    Json,
+    Yaml,
+}
+
+impl Syntax {
+    /// The syntax of the document `code`: JSON if it's an object.
+    pub(crate) fn of(code: &str) -> Self {
+        if code.trim_start().starts_with('{') { Syntax::Json } else { Syntax::Yaml }
+    }
+}
+
+/// A map's members and layout.
+#[derive(Default)]
+pub(crate) struct Map {
+    pub(crate) members: Vec<Member>,
+    /// Indentation of the members (JSON; `None`: no members).
+    indent: Option<String>,
+    /// Indentation of the closing brace (JSON).
+    close: String,
+    /// Blank lines above the first key (YAML).
+    lead: bool,
+    /// Blank lines between keys (YAML).
+    spaced: bool,
+}
+
+impl Map {
+    /// A whole document.
+    pub(crate) fn document(syntax: Syntax, code: &str) -> Option<Self> {
+        match syntax {
+            Syntax::Json => json_object(code.trim()),
+            Syntax::Yaml => yaml_map(code),
+        }
+    }
+
+    /// The map a member holds (`None` if it holds anything else), and the
+    /// text before it: `"key": ` or the `key:` line.
+    pub(crate) fn of_member(syntax: Syntax, member: &str) -> Option<(&str, Self)> {
+        match syntax {
+            Syntax::Json => {
+                let value = json_value(member)?;
+                Some((&member[..member.len() - value.len()], json_object(value)?))
+            }
+            Syntax::Yaml => {
+                // a bare `key:` is taken for an empty map
+                let (head, rest) = member.split_once('\n').unwrap_or((member, ""));
+                head.trim_end().ends_with(':').then_some(())?;
+                Some((head, yaml_map(rest)?))
+            }
+        }
+    }
+}
+
+/// `members` rendered like the first of `like` that has any; `head` is
+/// what [`Map::of_member`] returned, or empty for a document (to which the
+/// caller adds the file's last line break).
+pub(crate) fn render(syntax: Syntax, head: &str, members: &[Member], like: [&Map; 3]) -> String {
+    let map = like.iter().find(|m| !m.members.is_empty()).unwrap_or(&like[0]);
+    match syntax {
+        Syntax::Json => {
+            let Some(indent) = like.iter().find_map(|m| m.indent.as_deref()).filter(|_| !members.is_empty()) else {
+                return format!("{head}{{}}");
+            };
+            let close = like.iter().find(|m| m.indent.is_some()).map_or("", |m| m.close.as_str());
+            let lines: Vec<String> = members.iter().map(|m| format!("{indent}{}", m.text)).collect();
+            format!("{head}{{\n{}\n{close}}}", lines.join(",\n"))
+        }
+        Syntax::Yaml if members.is_empty() && !head.is_empty() => format!("{} {{}}", head.trim_end()),
+        Syntax::Yaml => {
+            let body: Vec<&str> = members.iter().map(|m| m.text.as_str()).collect();
+            let lead = if map.lead { "\n" } else { "" };
+            let nl = if head.is_empty() { "" } else { "\n" };
+            format!("{head}{nl}{lead}{}", body.join(if map.spaced { "\n\n" } else { "\n" }))
+        }
+    }
+}
+
+/// Merge member lists: one-sided changes are taken, and for a member both
+/// branches changed (or one removed and the other changed),
+/// `settle(key, base, a, b)` says what stays (`None`: nothing). Order
+/// follows A, with B's additions after the member they follow in B.
+pub(crate) fn merge_keyed(
+    base: &[Member],
+    mut a: Vec<Member>,
+    mut b: Vec<Member>,
+    label: &str,
+    mut settle: impl FnMut(&str, Option<&str>, Option<&str>, Option<&str>) -> Option<String>,
+) -> Vec<Member> {
+    let mut keys: Vec<String> = Vec::new();
+    for m in a.iter().chain(&b).chain(base) {
+        if !keys.contains(&m.key) {
+            keys.push(m.key.clone());
+        }
+    }
+    let text = |ms: &[Member], key: &str| ms.iter().find(|m| m.key == key).map(|m| m.text.clone());
+    for key in keys {
+        let (o, x, y) = (text(base, &key), text(&a, &key), text(&b, &key));
+        if x == y || x == o || y == o {
+            continue;
+        }
+        let pick = settle(&key, o.as_deref(), x.as_deref(), y.as_deref());
+        set(&mut a, &b, &key, pick.clone());
+        set(&mut b, &a, &key, pick);
+    }
+    // nothing diverges any more
+    merge_members(base, &a, &b, label).unwrap_or(a)
+}
+
+/// Put `key`'s `value` into `list` (`None`: take it out); a new key goes
+/// after the one it follows in `other`.
+fn set(list: &mut Vec<Member>, other: &[Member], key: &str, value: Option<String>) {
+    let at = list.iter().position(|m| m.key == key);
+    match (at, value) {
+        (Some(i), Some(text)) => list[i].text = text,
+        (Some(i), None) => {
+            list.remove(i);
+        }
+        (None, Some(text)) => {
+            let after = other
+                .iter()
+                .position(|m| m.key == key)
+                .and_then(|i| i.checked_sub(1))
+                .and_then(|p| list.iter().position(|m| m.key == other[p].key));
+            list.insert(after.map_or(0, |p| p + 1), Member { key: key.to_string(), text });
+        }
+        (None, None) => {}
+    }
+}
+
+/// Settle `conflict` over a member (`a`, `b`: its versions) with the
+/// resolution given for it, if any (`a`, `b` or a replacement text; an
+/// empty one removes the member); else record it, and `keep` stays.
+pub(crate) fn resolve(
+    conflict: Conflict,
+    a: Option<&str>,
+    b: Option<&str>,
+    keep: Option<&str>,
+    opts: &MergeOptions,
+    out: &mut MergeOutcome,
+) -> Option<String> {
+    let pick = match opts.resolutions.get(&conflict.id) {
+        Some(Resolution::A) => a,
+        Some(Resolution::B) => b,
+        Some(Resolution::Text(text)) => Some(text.as_str()).filter(|t| !t.trim().is_empty()),
+        Some(Resolution::Union) => {
+            out.warnings.push(format!("Resolution of {} ignored: a data file's entry takes \"a\", \"b\" or a text", conflict.id));
+            out.conflicts.push(conflict);
+            return keep.map(str::to_string);
+        }
+        None => {
+            out.conflicts.push(conflict);
+            return keep.map(str::to_string);
+        }
+    };
+    out.resolved.push(conflict);
+    pick.map(str::to_string)
+}
+
+fn json_object(value: &str) -> Option<Map> {
+    let inner = value.strip_prefix('{')?.strip_suffix('}')?;
+    let mut members = Vec::new();
+    let (mut depth, mut in_string, mut escaped, mut start) = (0usize, false, false, 0);
+    for (i, c) in inner.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                members.push(json_member(&inner[start..i])?);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if in_string || depth != 0 {
+        return None;
+    }
+    if !inner[start..].trim().is_empty() {
+        members.push(json_member(&inner[start..])?);
+    }
+    let line_indent = |text: &str| text.rsplit('\n').next().unwrap_or("").to_string();
+    let indent = members.first().map(|_| line_indent(&inner[..inner.len() - inner.trim_start().len()]));
+    let close = line_indent(&inner[inner.trim_end().len()..]);
+    Some(Map { members, indent, close, ..Map::default() })
+}
+
+fn json_member(text: &str) -> Option<Member> {
+    let text = text.trim();
+    let key = text.strip_prefix('"')?.split('"').next()?.to_string();
+    Some(Member { key, text: text.to_string() })
+}
+
+/// The value of a `"key": value` member.
+fn json_value(member: &str) -> Option<&str> {
+    let key_end = member.strip_prefix('"')?.find('"')? + 2;
+    Some(member[key_end..].trim_start().strip_prefix(':')?.trim_start())
+}
+
+fn yaml_map(code: &str) -> Option<Map> {
+    let lines: Vec<&str> = code.lines().collect();
+    let first = lines.iter().position(|l| !l.trim().is_empty());
+    let mut map = Map { lead: first.is_some_and(|i| i > 0), ..Map::default() };
+    let Some(first) = first else { return Some(map) };
+    let indent = lines[first].len() - lines[first].trim_start().len();
+    // the lines of the key being read, and comments waiting for the next
+    let (mut current, mut comments): (Vec<&str>, Vec<&str>) = (Vec::new(), Vec::new());
+    for line in &lines[first..] {
+        let depth = line.len() - line.trim_start().len();
+        let item = line.trim_start().starts_with("- ") || line.trim() == "-";
+        if depth == indent && line.trim_start().starts_with('#') {
+            comments.push(line);
+            continue;
+        }
+        if line.trim().is_empty() || depth > indent || (item && !current.is_empty()) {
+            current.append(&mut comments);
+            current.push(line);
+            continue;
+        }
+        if depth < indent {
+            return None;
+        }
+        if current.last().is_some_and(|l| l.trim().is_empty()) {
+            map.spaced = true;
+        }
+        yaml_member(&mut current, &mut map)?;
+        current.append(&mut comments);
+        current.push(line);
+    }
+    current.append(&mut comments);
+    yaml_member(&mut current, &mut map)?;
+    Some(map)
+}
+
+/// Add the key whose lines are `current` to `map`; `None` if it has it.
+fn yaml_member(current: &mut Vec<&str>, map: &mut Map) -> Option<()> {
+    let blank = current.iter().rev().take_while(|l| l.trim().is_empty()).count();
+    let Some(head) = current.iter().find(|l| !l.trim_start().starts_with('#')) else {
+        // only comments: they stay, after the last key
+        if let (Some(last), false) = (map.members.last_mut(), current.is_empty()) {
+            last.text = format!("{}\n{}", last.text, current.join("\n"));
+        }
+        current.clear();
+        return Some(());
+    };
+    let head = head.trim();
+    let key = head.strip_suffix(':').or_else(|| head.split_once(": ").map(|(k, _)| k)).unwrap_or(head);
+    if map.members.iter().any(|m| m.key == key) {
+        return None;
+    }
+    map.members.push(Member { key: key.to_string(), text: current[..current.len() - blank].join("\n") });
+    current.clear();
+    Some(())
+}
@@ -1,14 +1,89 @@
 //! sink_core: Step 2 adds diff + compose, and exposes a 3-way merge helper.
 
 mod ast;
+mod parsers;
 mod diff;
+mod diff3;
 mod compose;
+mod members;
+mod body;
+mod jsx;
+mod hooks;
+mod specs;
+mod objects;
+mod decorators;
+mod namespaces;
+mod imports;
+mod reexports;
+mod barrels;
+mod keyed;
+mod lockfiles;
+mod openapi;
+mod properties;
+mod formatter;
+mod generated;
+mod grammars;
+mod compare;
+mod markers;
+mod hash;
+mod policy;
+mod queries;
+mod regions;
+mod conflicts;
+mod clusters;
+mod resolutions;
+mod bundles;
+mod resolver;
+mod batch;
+mod cache;
+#[cfg(feature = "binary")]
+mod binary;
+mod encoding;
+mod indent;
+mod splits;
+mod moves;
+mod renames;
+#[cfg(feature = "async")]
+mod tasks;
+pub mod testing;
 
-pub use ast::{AstFile, TopLevel, parse_typescript_to_ast, AstLanguage};
-pub use diff::{Edit, diff_top_level};
-pub use compose::{MergeOutcome, compose_top_level};
+pub use ast::{
+    AST_SCHEMA_VERSION, AstFile, TopLevel, parse_typescript_to_ast, parse_typescript_to_ast_with, AstLanguage, ParseMode, ParseOptions,
+    SyntaxError, SyntaxErrorPolicy, WalkControl,
+};
+/// The tree-sitter version backing [`AstFile::walk`] and [`AstFile::node_at`].
+pub use tree_sitter;
+pub use diff::{Edit, diff_top_level, diff_top_level_with};
+pub use compose::{AutoResolution, InsertAnchor, MergeOptions, MergeOutcome, MergeStats, Reconciler, compose_top_level, compose_top_level_with};
+pub use formatter::{CommandFormatter, Formatter};
+pub use compare::{ExactComparer, QuoteComparer, TokenComparer, UnitComparer, WhitespaceComparer};
+pub use markers::{ConflictSides, split_conflict_markers};
+pub use members::MemberMerge;
+pub use lockfiles::LockfileFormat;
+pub use policy::{Adapter, BarrelOrder, FilePolicy, GeneratedMerge, LockfileMerge, Policy, Spacing, glob_match};
+pub use generated::is_generated;
+pub use grammars::{GrammarSpec, load_grammar};
+pub use queries::UnitQuery;
+pub use conflicts::{Conflict, ConflictSite, locate_conflicts};
+pub use clusters::{ConflictCluster, cluster_conflicts};
+pub use resolutions::{Resolution, Resolutions};
+pub use bundles::{ConflictBundle, conflict_bundles};
+pub use resolver::{CommandResolver, ConflictResolver};
+pub use hash::{content_hash, token_hash};
+pub use batch::{FileSource, MergeJob, merge_batch, merge_batch_with, merge_tree, merge_tree_with};
+pub use cache::{AstCache, DirAstCache};
+#[cfg(feature = "binary")]
+pub use binary::{ast_from_bytes, ast_to_bytes, edits_from_bytes, edits_to_bytes};
+pub use encoding::{Encoding, decode_text};
+pub use moves::{carry_moved_edits, extracted_units};
+#[cfg(feature = "async")]
+pub use tasks::{
+    AsyncFileSource, MergeTask, SourceFuture, merge_batch_async, merge_tree_async, parse_typescript_to_ast_async,
+    three_way_merge_async, three_way_merge_top_level_async,
+};
 
 use anyhow::*;
+use indent::Indent;
 
 /// High-level 3-way merge helper for a single file (top-level only, MVP).
 pub fn three_way_merge_top_level(
@@ -17,16 +92,360 @@ pub fn three_way_merge_top_level(
     b_code: &str,
     lang: AstLanguage,
 ) -> Result<MergeOutcome> {
+    three_way_merge_top_level_with(base_code, a_code, b_code, lang, &ParseOptions::default())
+}
+
+/// Like [`three_way_merge_top_level`], with explicit unit-collection options
+/// (e.g. [`ParseOptions::for_path`] for spec files).
+pub fn three_way_merge_top_level_with(
+    base_code: &str,
+    a_code: &str,
+    b_code: &str,
+    lang: AstLanguage,
+    opts: &ParseOptions,
+) -> Result<MergeOutcome> {
+    three_way_merge(base_code, a_code, b_code, lang, &MergeOptions::new(opts.clone()))
+}
+
+/// The full 3-way merge pipeline, with [`MergeOptions`] (custom reconcilers, ...).
+pub fn three_way_merge(
+    base_code: &str,
+    a_code: &str,
+    b_code: &str,
+    lang: AstLanguage,
+    merge_opts: &MergeOptions,
+) -> Result<MergeOutcome> {
+    let mut out = merge_file(base_code, a_code, b_code, lang, merge_opts)?;
+
+    // Post-merge formatting, for files both sides changed and without conflict markers
+    let both_changed = a_code != base_code && b_code != base_code && a_code != b_code;
+    let markers = out.conflicts.iter().any(|c| c.kind == "textual");
+    if let Some(formatter) = merge_opts.formatter()
+        && both_changed
+        && !markers
+    {
+        let code = std::mem::take(&mut out.merged_code);
+        out.merged_code = formatter.format(merge_opts.path.as_deref(), &code).unwrap_or_else(|e| {
+            out.warnings.push(format!("Formatter failed, kept the unformatted merge: {e:#}"));
+            code
+        });
+    }
+    Ok(out)
+}
+
+/// Merge a single top-level unit (a function, class, ...) of `kind` (its
+/// tree-sitter kind, e.g. `function_declaration`) from its three versions,
+/// e.g. to resolve one conflicted hunk in an editor. Conflicts leave the base
+/// version.
+pub fn merge_unit(base: &str, a: &str, b: &str, kind: &str, lang: AstLanguage) -> MergeOutcome {
+    merge_unit_with(base, a, b, kind, lang, &MergeOptions::default())
+}
+
+/// Like [`merge_unit`], with [`MergeOptions`] (reconcilers, resolutions, the
+/// file's path for conflict IDs, ...).
+pub fn merge_unit_with(
+    base: &str,
+    a: &str,
+    b: &str,
+    kind: &str,
+    lang: AstLanguage,
+    merge_opts: &MergeOptions,
+) -> MergeOutcome {
+    compose::merge_unit(base, a, b, kind, lang, merge_opts)
+}
+
+/// Rough peak memory of a semantic merge of these versions: trees, unit
+/// lists and snippets come to about 60 bytes per input byte (measured on
+/// generated code).
+pub(crate) fn merge_memory(base_code: &str, a_code: &str, b_code: &str) -> usize {
+    60 * (base_code.len() + a_code.len() + b_code.len())
+}
+
+/// Entry-level merge of a lockfile (see [`LockfileFormat`]): packages added
+/// on either side are kept, and one both branches changed is settled as the
+/// file's policy says ([`LockfileMerge`]), else is a `lockfile` conflict
+/// leaving A's entry. [`three_way_merge`] does this for paths named like a
+/// lockfile.
+pub fn merge_lockfile(format: LockfileFormat, base: &str, a: &str, b: &str, merge_opts: &MergeOptions) -> MergeOutcome {
+    lockfiles::merge_lockfile(format, base, a, b, merge_opts)
+}
+
+/// File-level merge of a symlink (git mode 120000), whose blob is its target
+/// path: the same target is kept, a side that retargeted it is taken, and two
+/// different targets are a `textual` conflict. Targets are never merged as
+/// text.
+pub fn merge_symlink(base: &str, a: &str, b: &str, merge_opts: &MergeOptions) -> MergeOutcome {
+    compose::textual_merge(base, a, b, "Both branches changed the symlink differently", merge_opts)
+}
+
+/// Commit-level merge of a submodule (gitlink, mode 160000) pointer: the same
+/// commit is kept, a one-sided bump is taken, and when both branches moved it
+/// a fast-forward takes the newer commit (`is_ancestor(x, y)`: `x` is an
+/// ancestor of `y`). Divergent commits are a `submodule` conflict, leaving
+/// A's commit. `merged_code` is the resulting commit ID.
+pub fn merge_submodule(
+    base: &str,
+    a: &str,
+    b: &str,
+    is_ancestor: impl Fn(&str, &str) -> bool,
+    merge_opts: &MergeOptions,
+) -> MergeOutcome {
+    let mut out = MergeOutcome::default();
+    let short = |oid: &str| oid[..oid.len().min(12)].to_string();
+    out.merged_code = if a == b || b == base {
+        a.to_string()
+    } else if a == base {
+        b.to_string()
+    } else if is_ancestor(a, b) {
+        out.warnings.push(format!("Fast-forwarded the submodule to B's {}", short(b)));
+        b.to_string()
+    } else if is_ancestor(b, a) {
+        out.warnings.push(format!("Fast-forwarded the submodule to A's {}", short(a)));
+        a.to_string()
+    } else {
+        let message = format!("Both branches moved the submodule, to divergent commits {} and {}", short(a), short(b));
+        let hashes = (Some(content_hash(a)), Some(content_hash(b)));
+        let conflict = Conflict::new(merge_opts.path.as_deref(), "submodule", None, hashes, message);
+        match merge_opts.resolutions.get(&conflict.id) {
+            Some(resolution @ (Resolution::A | Resolution::B)) => {
+                out.resolved.push(conflict);
+                resolution.apply(a, b)
+            }
+            resolution => {
+                if resolution.is_some() {
+                    out.warnings.push(format!(
+                        "Resolution of {} ignored: only \"a\" or \"b\" can resolve a submodule conflict",
+                        conflict.id
+                    ));
+                }
+                out.conflicts.push(conflict);
+                a.to_string()
+            }
+        }
+    };
+    out.suppressed = conflicts::split_suppressed(&mut out.conflicts, &merge_opts.suppressed);
+    out
+}
+
+/// File-level merge of a file at least one branch deleted (`None`). If the
+/// other branch deleted it too or left it alone, the merge deletes it
+/// ([`MergeOutcome::deleted`]); if it modified it, that's a `delete-modify`
+/// conflict, leaving the modified version. A resolution choosing the deleted
+/// side (or empty text) deletes the file.
+pub fn merge_deleted(base: &str, a: Option<&str>, b: Option<&str>, merge_opts: &MergeOptions) -> MergeOutcome {
+    let mut out = MergeOutcome { textual: true, ..MergeOutcome::default() };
+    let (modified, message) = match (a, b) {
+        (Some(a), None) if a != base => (a, "Deleted in B, modified in A"),
+        (None, Some(b)) if b != base => (b, "Deleted in A, modified in B"),
+        _ => {
+            out.deleted = true;
+            return out;
+        }
+    };
+    let hashes = (a.map(content_hash), b.map(content_hash));
+    let conflict = Conflict::new(merge_opts.path.as_deref(), "delete-modify", None, hashes, message);
+    if let Some(resolution) = merge_opts.resolutions.get(&conflict.id) {
+        out.merged_code = resolution.apply(a.unwrap_or(""), b.unwrap_or(""));
+        out.deleted = out.merged_code.is_empty();
+        out.resolved.push(conflict);
+        return out;
+    }
+    out.merged_code = modified.to_string();
+    out.conflicts.push(conflict);
+    out.suppressed = conflicts::split_suppressed(&mut out.conflicts, &merge_opts.suppressed);
+    out
+}
+
+/// Whether `bytes` look binary: a NUL byte in the first 8000, git's rule.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(8000).any(|&b| b == 0)
+}
+
+/// File-level merge of a binary file (`None`: deleted), compared byte for
+/// byte and never merged: an identical or one-sided change is taken, and two
+/// different changes are a `binary` conflict (`delete-modify` if one side
+/// deleted it), leaving A's version, or B's if A deleted the file. Returns the
+/// outcome (`merged_code` stays empty) and the bytes of the version the merge
+/// is; with `None`, the merge deletes the file. Only "a" and "b" can resolve
+/// the conflict.
+pub fn merge_binary<'v>(
+    base: &'v [u8],
+    a: Option<&'v [u8]>,
+    b: Option<&'v [u8]>,
+    merge_opts: &MergeOptions,
+) -> (MergeOutcome, Option<&'v [u8]>) {
+    let mut out = MergeOutcome { textual: true, ..MergeOutcome::default() };
+    let merged = if a == b || b == Some(base) {
+        a
+    } else if a == Some(base) {
+        b
+    } else {
+        let (kind, message) = match (a, b) {
+            (Some(_), Some(_)) => ("binary", "Both branches changed the binary file differently"),
+            (None, _) => ("delete-modify", "Deleted in A, modified in B"),
+            (_, None) => ("delete-modify", "Deleted in B, modified in A"),
+        };
+        let hashes = (a.map(hash::bytes_hash), b.map(hash::bytes_hash));
+        let conflict = Conflict::new(merge_opts.path.as_deref(), kind, None, hashes, message);
+        match merge_opts.resolutions.get(&conflict.id) {
+            Some(Resolution::A) => {
+                out.resolved.push(conflict);
+                a
+            }
+            Some(Resolution::B) => {
+                out.resolved.push(conflict);
+                b
+            }
+            resolution => {
+                if resolution.is_some() {
+                    out.warnings.push(format!(
+                        "Resolution of {} ignored: only \"a\" or \"b\" can resolve a binary file conflict",
+                        conflict.id
+                    ));
+                }
+                out.conflicts.push(conflict);
+                a.or(b)
+            }
+        }
+    };
+    out.suppressed = conflicts::split_suppressed(&mut out.conflicts, &merge_opts.suppressed);
+    out.deleted = merged.is_none();
+    (out, merged)
+}
+
+fn merge_file(
+    base_code: &str,
+    a_code: &str,
+    b_code: &str,
+    lang: AstLanguage,
+    merge_opts: &MergeOptions,
+) -> Result<MergeOutcome> {
+    let opts = &merge_opts.parse;
+    // 0) Policy: file-level rules come before any semantic work
+    let both_changed = a_code != base_code && b_code != base_code && a_code != b_code;
+    // lockfiles: packages, not text (and often marked generated)
+    if let Some(format) = merge_opts.path.as_deref().and_then(LockfileFormat::for_path)
+        && opts.policy.lockfile != LockfileMerge::Textual
+    {
+        return Ok(lockfiles::merge_lockfile(format, base_code, a_code, b_code, merge_opts));
+    }
+    // generated files: merging two runs of a generator helps nobody
+    if opts.policy.generated != GeneratedMerge::Semantic
+        && (merge_opts.generated || [base_code, a_code, b_code].into_iter().any(is_generated))
+    {
+        let keep = |code: &str, warning: &str| MergeOutcome {
+            merged_code: code.to_string(),
+            warnings: vec![warning.to_string()],
+            textual: true,
+            ..MergeOutcome::default()
+        };
+        return Ok(match opts.policy.generated {
+            GeneratedMerge::Skip if both_changed => {
+                keep(a_code, "Generated file both branches changed: kept A's version; regenerate it")
+            }
+            GeneratedMerge::TakeNewer if both_changed && merge_opts.b_is_newer => {
+                keep(b_code, "Generated file both branches changed: took B's, the newer version; regenerate it")
+            }
+            GeneratedMerge::TakeNewer if both_changed => {
+                keep(a_code, "Generated file both branches changed: took A's, the newer version; regenerate it")
+            }
+            _ => compose::textual_merge(base_code, a_code, b_code, "Generated file; both branches changed it", merge_opts),
+        });
+    }
+    if opts.policy.textual {
+        return Ok(compose::textual_merge(base_code, a_code, b_code, "Policy: textual merge only; both branches changed the file", merge_opts));
+    }
+    if opts.policy.never_auto_merge && both_changed {
+        return Ok(compose::textual_merge(base_code, a_code, b_code, "Policy: never auto-merge; both branches changed the file", merge_opts));
+    }
+    // Huge (generated) files would hold three trees plus snippets in memory
+    let largest = base_code.len().max(a_code.len()).max(b_code.len());
+    let needed = merge_memory(base_code, a_code, b_code);
+    let over = match (merge_opts.max_parse_bytes, merge_opts.memory_limit) {
+        (Some(limit), _) if largest > limit => Some(format!("{largest} bytes is over the {limit}-byte parse limit")),
+        (_, Some(limit)) if needed > limit => {
+            Some(format!("the merge would take about {needed} bytes, over the {limit}-byte memory limit"))
+        }
+        _ => None,
+    };
+    if let Some(why) = over {
+        let mut out = compose::textual_merge(base_code, a_code, b_code, "File too large to merge semantically; both branches changed it", merge_opts);
+        if both_changed {
+            out.warnings.push(format!("Merged textually: {why}"));
+        }
+        return Ok(out);
+    }
+
+    if opts.policy.openapi {
+        return Ok(openapi::merge_openapi(base_code, a_code, b_code, merge_opts));
+    }
+    if opts.policy.properties {
+        return Ok(properties::merge_properties(base_code, a_code, b_code, merge_opts));
+    }
+    let mut warnings = Vec::new();
+    if let Some(order) = opts.policy.barrel
+        && both_changed
+    {
+        match barrels::merge_barrel(base_code, a_code, b_code, order, lang) {
+            Some(std::result::Result::Ok(merged_code)) => return Ok(MergeOutcome { merged_code, ..MergeOutcome::default() }),
+            Some(Err(found)) => {
+                let reason = format!("Barrel file: {}", found.join("; "));
+                return Ok(compose::textual_merge(base_code, a_code, b_code, &reason, merge_opts));
+            }
+            None => warnings.push(
+                "Policy: merge = \"barrel\", but the file isn't one (it has more than re-exports, or one twice); merged as usual"
+                    .to_string(),
+            ),
+        }
+    }
+
     // 1) Parse
-    let t0 = parse_typescript_to_ast(base_code, lang)?;
-    let ta = parse_typescript_to_ast(a_code, lang)?;
-    let tb = parse_typescript_to_ast(b_code, lang)?;
+    let cache = merge_opts.ast_cache();
+    let t0 = cache::parse_cached(base_code, lang, opts, cache)?;
+    let ta = cache::parse_cached(a_code, lang, opts, cache)?;
+    let tb = cache::parse_cached(b_code, lang, opts, cache)?;
+
+    // Units next to recovered syntax errors can produce garbage diffs
+    let syntax_errors: Vec<String> = [("base", &t0), ("A", &ta), ("B", &tb)]
+        .iter()
+        .flat_map(|(side, t)| t.errors.iter().map(move |e| format!("{side}: {e}")))
+        .collect();
+    if !syntax_errors.is_empty() && opts.mode == ParseMode::Strict {
+        match opts.on_syntax_error {
+            SyntaxErrorPolicy::Fail => bail!("syntax errors in merge inputs: {}", syntax_errors.join(", ")),
+            SyntaxErrorPolicy::Textual => {
+                let reason = "Both branches changed a file with syntax errors; merged textually";
+                let mut out = compose::textual_merge(base_code, a_code, b_code, reason, merge_opts);
+                out.syntax_errors = syntax_errors;
+                return Ok(out);
+            }
+            SyntaxErrorPolicy::Report => {}
+        }
+    }
+
+    // One side unchanged, or both made the same change: nothing to merge
+    if a_code == b_code || b_code == base_code || a_code == base_code {
+        let merged = if a_code == base_code { b_code } else { a_code };
+        return Ok(MergeOutcome { merged_code: merged.to_string(), syntax_errors, ..MergeOutcome::default() });
+    }
+
+    // what a branch's stray ERROR region holds would be lost unit by unit
+    if let Some((side, e)) = [("A", &ta), ("B", &tb)].into_iter().find_map(|(side, t)| Some((side, t.stray_errors().next()?))) {
+        let reason = format!("{side} has a syntax error outside every unit ({e}); merged textually");
+        let mut out = compose::textual_merge(base_code, a_code, b_code, &reason, merge_opts);
+        out.syntax_errors = syntax_errors;
+        return Ok(out);
+    }
 
     // 2) Diff (Base->A and Base->B)
-    let ea = diff::diff_top_level(&t0, &ta);
-    let eb = diff::diff_top_level(&t0, &tb);
+    let ea = diff::diff_top_level_with(&t0, &ta, opts);
+    let eb = diff::diff_top_level_with(&t0, &tb, opts);
 
     // 3) Compose
-    let out = compose::compose_top_level(&t0, &ea, &eb)?;
+    // the file keeps base's style unless both branches re-indented it alike
+    let indent = Indent::merged(Indent::detect(base_code), Indent::detect(a_code), Indent::detect(b_code));
+    let mut out = compose::compose(&t0, &ea, &eb, merge_opts, indent)?;
+    out.syntax_errors = syntax_errors;
+    out.warnings.splice(0..0, warnings);
     Ok(out)
 }
\ No newline at end of file
@@ -0,0 +1,435 @@
+//! lockfiles.rs
+//! Merging package-manager lockfiles entry by entry.
+//!
+//! Lockfiles conflict on almost every merge and are usually thrown away and
+//! regenerated. Most of those conflicts are two branches adding or bumping
+//! different packages, so `package-lock.json` (and `npm-shrinkwrap.json`),
+//! `yarn.lock` and `pnpm-lock.yaml` merge as keyed entries instead of text:
+//!
+//! - npm: JSON objects, member by member; a package entry (one with
+//!   `resolved`, `integrity` or `link`) is one unit, the root package's
+//!   dependency maps merge key by key.
+//! - yarn (v1 and berry): one unit per specifier (`lodash@^4.17.20`), so a
+//!   branch adding a specifier to an entry the other bumped still merges;
+//!   entries are regrouped by body and sorted as yarn writes them.
+//! - pnpm: the YAML sections, nested maps key by key down to the leaves.
+//!
+//! Entries added on one side are kept, removed ones dropped. One both
+//! branches changed goes to the higher version with
+//! [`LockfileMerge::HigherVersion`] (an auto-resolution, `higher-version`),
+//! and is a `lockfile` conflict with [`LockfileMerge::Regenerate`], as is
+//! anything no version settles (a removed entry the other side changed).
+//! Conflicts keep A's entry and ask for the lockfile to be regenerated; a
+//! resolution choosing `a` or `b` (or giving the entry's text) settles one.
+//! A lockfile that doesn't parse merges textually.
+
+use crate::compose::{AutoResolution, MergeOptions, MergeOutcome, textual_merge};
+use crate::conflicts::{self, Conflict};
+use crate::hash::content_hash;
+use crate::keyed::{Map, Syntax, merge_keyed, render, resolve};
+use crate::members::{Member, Side, pick_frame};
+use crate::policy::LockfileMerge;
+
+/// Confidence that the higher of two versions both branches moved a
+/// package to is the one to keep.
+const HIGHER_VERSION: f64 = 0.7;
+
+/// The lockfile formats merged entry by entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockfileFormat {
+    /// `package-lock.json`, `npm-shrinkwrap.json`
+    Npm,
+    /// `yarn.lock`, v1 or berry
+    Yarn,
+    /// `pnpm-lock.yaml`
+    Pnpm,
+}
+
+impl LockfileFormat {
+    /// The format of the lockfile at `path`, by its file name.
+    pub fn for_path(path: &str) -> Option<Self> {
+        match path.rsplit('/').next().unwrap_or(path) {
+            "package-lock.json" | "npm-shrinkwrap.json" => Some(LockfileFormat::Npm),
+            "yarn.lock" => Some(LockfileFormat::Yarn),
+            "pnpm-lock.yaml" => Some(LockfileFormat::Pnpm),
+            _ => None,
+        }
+    }
+
+    fn install(self) -> &'static str {
+        match self {
+            LockfileFormat::Npm => "npm install",
+            LockfileFormat::Yarn => "yarn install",
+            LockfileFormat::Pnpm => "pnpm install",
+        }
+    }
+}
+
+/// Merge three versions of a lockfile.
+pub(crate) fn merge_lockfile(format: LockfileFormat, base: &str, a: &str, b: &str, opts: &MergeOptions) -> MergeOutcome {
+    if a == b || b == base || a == base {
+        let merged_code = if a == base { b } else { a };
+        return MergeOutcome { merged_code: merged_code.to_string(), ..MergeOutcome::default() };
+    }
+    let mut m = Merger {
+        format,
+        opts,
+        regenerate: opts.parse.policy.lockfile == LockfileMerge::Regenerate,
+        held: None,
+        out: MergeOutcome::default(),
+    };
+    let merged = match format {
+        LockfileFormat::Npm => m.merge_npm(base, a, b),
+        LockfileFormat::Yarn => m.merge_yarn(base, a, b),
+        LockfileFormat::Pnpm => m.merge_pnpm(base, a, b),
+    };
+    let Some(merged) = merged else {
+        return textual_merge(base, a, b, "Lockfile that doesn't parse; both branches changed it", opts);
+    };
+    let mut out = m.out;
+    out.merged_code = merged;
+    if !out.auto_resolutions.is_empty() {
+        out.warnings.push(format!(
+            "Took the higher version of {} lockfile entries both branches changed; run `{}` to check the result",
+            out.auto_resolutions.len(),
+            format.install()
+        ));
+    }
+    out.suppressed = conflicts::split_suppressed(&mut out.conflicts, &opts.suppressed);
+    out
+}
+
+struct Merger<'m> {
+    format: LockfileFormat,
+    opts: &'m MergeOptions,
+    regenerate: bool,
+    /// Why the entry last merged was held for review instead.
+    held: Option<String>,
+    out: MergeOutcome,
+}
+
+impl Merger<'_> {
+    /// Merge one map's entries (`at`: the keys of the maps around it).
+    fn merge_entries(&mut self, at: &str, o: &[Member], a: Vec<Member>, b: Vec<Member>) -> Vec<Member> {
+        merge_keyed(o, a, b, at, |key, o, a, b| {
+            // the root package of a package-lock is `""`
+            let shown = if key.is_empty() { "\"\"" } else { key };
+            let name = if at.is_empty() { shown.to_string() } else { format!("{at} > {shown}") };
+            let merged = match (a, b) {
+                (Some(x), Some(y)) => self.merge_entry(&name, o.unwrap_or(""), x, y),
+                _ => None,
+            };
+            merged.or_else(|| self.conflict(&name, a, b))
+        })
+    }
+
+    /// An entry both branches changed: merged, if it's a map, else the
+    /// higher version; `None` if neither settles it.
+    fn merge_entry(&mut self, name: &str, o: &str, a: &str, b: &str) -> Option<String> {
+        let nested = match self.format {
+            LockfileFormat::Npm => self.merge_map(Syntax::Json, name, o, a, b),
+            LockfileFormat::Pnpm => self.merge_map(Syntax::Yaml, name, o, a, b),
+            LockfileFormat::Yarn => None,
+        };
+        if nested.is_some() || self.regenerate {
+            return nested;
+        }
+        let (x, y) = (version(a)?, version(b)?);
+        if x == y {
+            return None;
+        }
+        let (higher, side) = if x > y { (a, "A") } else { (b, "B") };
+        let note = format!("both branches changed the lockfile entry {name}; took {side}'s, the higher version");
+        if let Some(held) = self.opts.doubt(HIGHER_VERSION, &note) {
+            self.held = Some(held);
+            return None;
+        }
+        self.out.auto_resolutions.push(AutoResolution {
+            unit: format!("entry::{name}"),
+            heuristic: "higher-version".to_string(),
+            confidence: HIGHER_VERSION,
+        });
+        Some(higher.to_string())
+    }
+
+    /// A `lockfile` conflict on `name`: what stays, A's version unless a
+    /// resolution says otherwise.
+    fn conflict(&mut self, name: &str, a: Option<&str>, b: Option<&str>) -> Option<String> {
+        let what = match (a, b) {
+            (Some(_), Some(_)) => format!("Both branches changed the lockfile entry {name} differently"),
+            _ => format!("The lockfile entry {name} was removed on one branch and changed on the other"),
+        };
+        let message = match self.held.take() {
+            Some(held) => held,
+            None => format!("{what}; regenerate the lockfile (`{}`)", self.format.install()),
+        };
+        let unit = ("entry".to_string(), name.to_string());
+        let hashes = (a.map(content_hash), b.map(content_hash));
+        let conflict = Conflict::new(self.opts.path.as_deref(), "lockfile", Some(&unit), hashes, message);
+        resolve(conflict, a, b, a, self.opts, &mut self.out)
+    }
+
+    // npm
+
+    fn merge_npm(&mut self, base: &str, a: &str, b: &str) -> Option<String> {
+        let (o, x, y) = (Map::document(Syntax::Json, base)?, Map::document(Syntax::Json, a)?, Map::document(Syntax::Json, b)?);
+        let members = self.merge_entries("", &o.members, x.members.clone(), y.members.clone());
+        Some(format!("{}{}", render(Syntax::Json, "", &members, [&x, &y, &o]), &a[a.trim_end().len()..]))
+    }
+
+    /// An entry both branches changed that holds a map (and isn't a
+    /// package), merged key by key.
+    fn merge_map(&mut self, syntax: Syntax, name: &str, o: &str, a: &str, b: &str) -> Option<String> {
+        let ((head, x), (_, y)) = (Map::of_member(syntax, a)?, Map::of_member(syntax, b)?);
+        let o = if o.is_empty() { Some(Map::default()) } else { Map::of_member(syntax, o).map(|(_, o)| o) }?;
+        let package = |m: &Map| m.members.iter().any(|m| ["resolved", "integrity", "link"].contains(&m.key.as_str()));
+        if syntax == Syntax::Json && [&o, &x, &y].into_iter().any(package) {
+            return None;
+        }
+        let members = self.merge_entries(name, &o.members, x.members.clone(), y.members.clone());
+        Some(render(syntax, head, &members, [&x, &y, &o]))
+    }
+
+    // yarn
+
+    fn merge_yarn(&mut self, base: &str, a: &str, b: &str) -> Option<String> {
+        let (o, x, y) = (yarn_lock(base)?, yarn_lock(a)?, yarn_lock(b)?);
+        let head = match pick_frame(&o.head, &x.head, &y.head) {
+            Some(Side::B) => &y.head,
+            _ => &x.head,
+        };
+        let entries = self.merge_entries("", &o.entries, x.entries.clone(), y.entries.clone());
+        // specifiers resolving the same way share an entry
+        let mut groups: Vec<(Vec<&str>, &str)> = Vec::new();
+        for e in &entries {
+            match groups.iter_mut().find(|(_, body)| *body == e.text) {
+                Some((specs, _)) => specs.push(&e.key),
+                None => groups.push((vec![&e.key], &e.text)),
+            }
+        }
+        let berry = [&o, &x, &y].iter().any(|l| l.entries.iter().any(|e| e.key == "__metadata"));
+        let quoted = |spec: &str| [&x, &y, &o].iter().find_map(|l| l.quoted.iter().find(|(s, _)| s == spec).map(|(_, q)| *q));
+        let mut blocks: Vec<(String, String)> = groups
+            .into_iter()
+            .map(|(mut specs, body)| {
+                specs.sort_unstable();
+                let header = if berry {
+                    let joined = specs.join(", ");
+                    if specs.iter().any(|s| quoted(s).unwrap_or(true)) { format!("\"{joined}\"") } else { joined }
+                } else {
+                    specs
+                        .iter()
+                        .map(|s| if quoted(s).unwrap_or(false) { format!("\"{s}\"") } else { s.to_string() })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                (specs.join(", "), format!("{header}:\n{body}"))
+            })
+            .collect();
+        blocks.sort_by(|(p, _), (q, _)| (p != "__metadata", p).cmp(&(q != "__metadata", q)));
+        let body: Vec<String> = blocks.into_iter().map(|(_, block)| block).collect();
+        Some(format!("{head}{}\n", body.join("\n\n")))
+    }
+
+    // pnpm
+
+    fn merge_pnpm(&mut self, base: &str, a: &str, b: &str) -> Option<String> {
+        let (o, x, y) = (Map::document(Syntax::Yaml, base)?, Map::document(Syntax::Yaml, a)?, Map::document(Syntax::Yaml, b)?);
+        let members = self.merge_entries("", &o.members, x.members.clone(), y.members.clone());
+        Some(format!("{}\n", render(Syntax::Yaml, "", &members, [&x, &y, &o])))
+    }
+}
+
+/// The version an entry pins (its `version` field, or its own value for a
+/// `"name": "^1.2.3"` leaf), as numbers to compare.
+fn version(entry: &str) -> Option<Vec<u64>> {
+    let field = ["\"version\": \"", "version \"", "version: "]
+        .iter()
+        .find_map(|pat| entry.find(pat).map(|at| &entry[at + pat.len()..]))
+        .or_else(|| entry.lines().next()?.split_once(": ").map(|(_, v)| v))?;
+    // a range (`^1.2.3`) or a quoted version, not a hash or a path
+    let field = field.trim_start_matches(['"', '\'', '^', '~', '>', '<', '=', 'v', ' ']);
+    if !field.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let numbers: Vec<u64> = field
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()?
+        .split('.')
+        .map_while(|n| n.parse().ok())
+        .collect();
+    (!numbers.is_empty()).then_some(numbers)
+}
+
+/// A `yarn.lock` split into one entry per specifier.
+struct YarnLock {
+    /// Comments (and blank lines) above the first entry.
+    head: String,
+    /// Entry bodies keyed by specifier.
+    entries: Vec<Member>,
+    /// Whether each specifier was quoted.
+    quoted: Vec<(String, bool)>,
+}
+
+fn yarn_lock(code: &str) -> Option<YarnLock> {
+    let mut lock = YarnLock { head: String::new(), entries: Vec::new(), quoted: Vec::new() };
+    let mut lines = code.lines().peekable();
+    while let Some(line) = lines.next_if(|l| l.trim().is_empty() || l.starts_with('#')) {
+        lock.head.push_str(line);
+        lock.head.push('\n');
+    }
+    while let Some(header) = lines.next() {
+        if header.trim().is_empty() {
+            continue;
+        }
+        let header = header.strip_suffix(':')?;
+        if header.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let mut body = Vec::new();
+        while let Some(line) = lines.next_if(|l| l.starts_with(char::is_whitespace) || l.trim().is_empty()) {
+            body.push(line);
+        }
+        while body.last().is_some_and(|l| l.trim().is_empty()) {
+            body.pop();
+        }
+        let body = body.join("\n");
+        for spec in header.split(", ") {
+            let key = spec.trim_matches('"').to_string();
+            if lock.entries.iter().any(|e| e.key == key) {
+                return None;
+            }
+            lock.quoted.push((key.clone(), spec.starts_with('"') || spec.ends_with('"')));
+            lock.entries.push(Member { key, text: body.clone() });
+        }
+    }
+    Some(lock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NPM: &str = r#"{
+  "name": "app",
+  "lockfileVersion": 3,
+  "packages": {
+    "": {
+      "dependencies": {
+        "lodash": "^4.17.20"
+      }
+    },
+    "node_modules/lodash": {
+      "version": "4.17.20",
+      "resolved": "https://registry.npmjs.org/lodash/-/lodash-4.17.20.tgz"
+    }
+  }
+}
+"#;
+
+    fn units(out: &MergeOutcome) -> Vec<(&str, Option<&str>)> {
+        out.conflicts.iter().map(|c| (c.kind.as_str(), c.unit.as_deref())).collect()
+    }
+
+    /// `lock` with `name` at `version` added to the root package and `packages`.
+    fn npm_with(lock: &str, name: &str, version: &str) -> String {
+        let dependency = format!("        \"lodash\": \"^4.17.20\",\n        \"{name}\": \"^{version}\"\n");
+        let package = format!(
+            concat!(
+                "    \"node_modules/{name}\": {{\n",
+                "      \"version\": \"{version}\",\n",
+                "      \"resolved\": \"https://registry.npmjs.org/{name}/-/{name}-{version}.tgz\"\n",
+                "    }},\n    \"node_modules/lodash\"",
+            ),
+            name = name,
+            version = version,
+        );
+        lock.replace("        \"lodash\": \"^4.17.20\"\n", &dependency).replacen("    \"node_modules/lodash\"", &package, 1)
+    }
+
+    fn bump_lodash(lock: &str, version: &str) -> String {
+        lock.replace("4.17.20", version)
+    }
+
+    #[test]
+    fn for_path_reads_the_file_name() {
+        assert_eq!(LockfileFormat::for_path("web/package-lock.json"), Some(LockfileFormat::Npm));
+        assert_eq!(LockfileFormat::for_path("yarn.lock"), Some(LockfileFormat::Yarn));
+        assert_eq!(LockfileFormat::for_path("pnpm-lock.yaml"), Some(LockfileFormat::Pnpm));
+        assert_eq!(LockfileFormat::for_path("yarn.lock.bak"), None);
+    }
+
+    #[test]
+    fn npm_packages_added_on_both_branches_merge() {
+        let (a, b) = (npm_with(NPM, "axios", "1.6.0"), npm_with(NPM, "chalk", "5.3.0"));
+        let out = merge_lockfile(LockfileFormat::Npm, NPM, &a, &b, &MergeOptions::default());
+        assert!(out.conflicts.is_empty() && !out.textual, "{:?}", out.conflicts);
+        for name in ["\"axios\": \"^1.6.0\"", "\"chalk\": \"^5.3.0\"", "\"node_modules/axios\"", "\"node_modules/chalk\""] {
+            assert!(out.merged_code.contains(name), "{name} missing from\n{}", out.merged_code);
+        }
+        assert!(serde_json::from_str::<serde_json::Value>(&out.merged_code).is_ok(), "{}", out.merged_code);
+    }
+
+    #[test]
+    fn npm_bumps_on_both_branches_take_the_higher_version() {
+        let (a, b) = (bump_lodash(NPM, "4.17.21"), bump_lodash(NPM, "4.17.30"));
+        let out = merge_lockfile(LockfileFormat::Npm, NPM, &a, &b, &MergeOptions::default());
+        assert!(out.conflicts.is_empty(), "{:?}", out.conflicts);
+        assert_eq!(out.merged_code, b);
+        let heuristics: Vec<_> = out.auto_resolutions.iter().map(|r| r.heuristic.as_str()).collect();
+        assert_eq!(heuristics, ["higher-version", "higher-version"]);
+        assert_eq!(out.warnings.len(), 1);
+    }
+
+    #[test]
+    fn regenerate_makes_bumps_on_both_branches_conflicts() {
+        let mut opts = MergeOptions::default().with_path("package-lock.json");
+        opts.parse.policy.lockfile = LockfileMerge::Regenerate;
+        let (a, b) = (bump_lodash(NPM, "4.17.21"), bump_lodash(NPM, "4.17.30"));
+        let out = merge_lockfile(LockfileFormat::Npm, NPM, &a, &b, &opts);
+        assert_eq!(
+            units(&out),
+            [
+                ("lockfile", Some("entry::packages > \"\" > dependencies > lodash")),
+                ("lockfile", Some("entry::packages > node_modules/lodash")),
+            ]
+        );
+        assert_eq!(out.merged_code, a);
+    }
+
+    #[test]
+    fn yarn_entries_merge_per_specifier() {
+        let base = "# yarn lockfile v1\n\n\nlodash@^4.17.20:\n  version \"4.17.20\"\n";
+        let a = "# yarn lockfile v1\n\n\nlodash@^4.17.20:\n  version \"4.17.21\"\n\naxios@^1.6.0:\n  version \"1.6.0\"\n";
+        let b = "# yarn lockfile v1\n\n\nlodash@^4.17.20, lodash@^4.17.21:\n  version \"4.17.20\"\n";
+        let out = merge_lockfile(LockfileFormat::Yarn, base, a, b, &MergeOptions::default());
+        assert!(out.conflicts.is_empty(), "{:?}", out.conflicts);
+        assert_eq!(
+            out.merged_code,
+            concat!(
+                "# yarn lockfile v1\n\n\n",
+                "axios@^1.6.0:\n  version \"1.6.0\"\n\n",
+                "lodash@^4.17.20:\n  version \"4.17.21\"\n\n",
+                "lodash@^4.17.21:\n  version \"4.17.20\"\n",
+            )
+        );
+    }
+
+    #[test]
+    fn pnpm_sections_merge_key_by_key() {
+        let base = "lockfileVersion: '9.0'\n\npackages:\n\n  lodash@4.17.20:\n    resolution: {integrity: sha512-a}\n";
+        let a = format!("{base}\n  axios@1.6.0:\n    resolution: {{integrity: sha512-b}}\n");
+        let b = base.replace("lockfileVersion: '9.0'", "lockfileVersion: '9.1'");
+        let out = merge_lockfile(LockfileFormat::Pnpm, base, &a, &b, &MergeOptions::default());
+        assert!(out.conflicts.is_empty() && !out.textual, "{:?}", out.conflicts);
+        assert!(out.merged_code.starts_with("lockfileVersion: '9.1'"), "{}", out.merged_code);
+        assert!(out.merged_code.contains("axios@1.6.0:") && out.merged_code.contains("lodash@4.17.20:"), "{}", out.merged_code);
+    }
+
+    #[test]
+    fn a_lockfile_that_doesnt_parse_merges_textually() {
+        let out = merge_lockfile(LockfileFormat::Npm, NPM, "{", &bump_lodash(NPM, "4.17.21"), &MergeOptions::default());
+        assert!(out.textual);
+        assert_eq!(units(&out), [("textual", None)]);
+    }
+}
@@ -0,0 +1,171 @@
+//! markers.rs
+//! Reading conflicted files back into their three versions.
+//!
+//! jj keeps unresolved conflicts in the working copy as marked-up files, and
+//! so does git mid-merge. To resolve one semantically, the base and both
+//! sides are rebuilt from the markers and merged from scratch. Supported:
+//!
+//! - jj's "diff" style (`%%%%%%%` base-to-side diffs, `+++++++` side contents)
+//!   and "snapshot" style (`-------` base, `+++++++` sides);
+//! - git's `diff3`/`zdiff3` style (`|||||||` base section). Plain `merge`
+//!   style has no base and can't be merged.
+
+use anyhow::{Result, anyhow, bail};
+
+/// The three versions a conflicted file was made of.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConflictSides {
+    pub base: String,
+    pub a: String,
+    pub b: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    /// git's first side, before any other marker.
+    Leading,
+    /// A `%%%%%%%` diff from base to a side; `true` if it also carries the
+    /// base (the first one does).
+    Diff(bool),
+    Side,
+    Base,
+}
+
+/// Rebuild base and both sides from `text`'s conflict markers; `Ok(None)` if
+/// it has none. Conflicts with more than two sides or without a base are
+/// errors.
+pub fn split_conflict_markers(text: &str) -> Result<Option<ConflictSides>> {
+    let mut out = ConflictSides::default();
+    let mut found = 0usize;
+    // inside a conflict: marker length, current section, base, sides
+    let mut open: Option<(usize, Section, Option<String>, Vec<String>)> = None;
+    for line in text.split_inclusive('\n') {
+        let Some((len, section, base, sides)) = &mut open else {
+            match marker(line, '<') {
+                Some(len) => {
+                    found += 1;
+                    open = Some((len, Section::Leading, None, vec![String::new()]));
+                }
+                None => {
+                    out.base.push_str(line);
+                    out.a.push_str(line);
+                    out.b.push_str(line);
+                }
+            }
+            continue;
+        };
+        let is = |c: char| marker(line, c) == Some(*len);
+        if is('>') {
+            let (base, sides) = (base.take(), std::mem::take(sides));
+            open = None;
+            let Some(base) = base else {
+                bail!("conflict {found} has no base version (use jj's markers or git's diff3 style)");
+            };
+            let [a, b] = <[String; 2]>::try_from(sides).map_err(|sides| {
+                anyhow!("conflict {found} has {} sides; only two-sided conflicts can be merged", sides.len())
+            })?;
+            out.base.push_str(&base);
+            out.a.push_str(&a);
+            out.b.push_str(&b);
+        } else if is('%') {
+            next_side(*section, sides);
+            *section = Section::Diff(base.is_none());
+            base.get_or_insert_default();
+        } else if is('\\') {
+            // continuation of a `%%%%%%%` header ("to: side #1")
+        } else if is('+') {
+            next_side(*section, sides);
+            *section = Section::Side;
+        } else if is('-') || is('|') {
+            *section = Section::Base;
+            *base = Some(String::new());
+        } else if is('=') {
+            *section = Section::Side;
+            sides.push(String::new());
+        } else {
+            let side = sides.last_mut().expect("a conflict has a side");
+            match *section {
+                Section::Leading | Section::Side => side.push_str(line),
+                Section::Base => base.get_or_insert_default().push_str(line),
+                Section::Diff(with_base) => {
+                    let (in_base, in_side, rest) = match line.split_at_checked(1) {
+                        Some(("-", rest)) => (true, false, rest),
+                        Some(("+", rest)) => (false, true, rest),
+                        Some((" ", rest)) => (true, true, rest),
+                        _ => (true, true, line),
+                    };
+                    if in_base && with_base {
+                        base.get_or_insert_default().push_str(rest);
+                    }
+                    if in_side {
+                        side.push_str(rest);
+                    }
+                }
+            }
+        }
+    }
+    if open.is_some() {
+        bail!("conflict {found} is not closed");
+    }
+    Ok((found > 0).then_some(out))
+}
+
+/// Start another side. jj's sections all follow a header, so the leading
+/// side git's style starts with is dropped.
+fn next_side(section: Section, sides: &mut Vec<String>) {
+    if section == Section::Leading {
+        sides.clear();
+    }
+    sides.push(String::new());
+}
+
+/// Length of a conflict marker of `c`s (7 or more, then the end of the line
+/// or a space) at the start of `line`.
+fn marker(line: &str, c: char) -> Option<usize> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let len = line.chars().take_while(|&x| x == c).count();
+    let rest = &line[len * c.len_utf8()..];
+    (len >= 7 && (rest.is_empty() || rest.starts_with(' '))).then_some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sides(base: &str, a: &str, b: &str) -> Option<ConflictSides> {
+        Some(ConflictSides { base: base.to_string(), a: a.to_string(), b: b.to_string() })
+    }
+
+    #[test]
+    fn git_diff3_style() {
+        let text = "x\n<<<<<<< ours\na\n||||||| base\no\n=======\nb\n>>>>>>> theirs\ny\n";
+        assert_eq!(split_conflict_markers(text).unwrap(), sides("x\no\ny\n", "x\na\ny\n", "x\nb\ny\n"));
+    }
+
+    #[test]
+    fn jj_snapshot_and_diff_styles() {
+        let snapshot = concat!(
+            "<<<<<<< Conflict 1 of 1\n",
+            "+++++++ Contents of side #1\na\n",
+            "------- Contents of base\no\n",
+            "+++++++ Contents of side #2\nb\n",
+            ">>>>>>> Conflict 1 of 1 ends\n",
+        );
+        assert_eq!(split_conflict_markers(snapshot).unwrap(), sides("o\n", "a\n", "b\n"));
+        let diff = concat!(
+            "<<<<<<< Conflict 1 of 1\n",
+            "%%%%%%% Changes from base to side #1\n-o\n+a\n keep\n",
+            "+++++++ Contents of side #2\nb\nkeep\n",
+            ">>>>>>> Conflict 1 of 1 ends\n",
+        );
+        assert_eq!(split_conflict_markers(diff).unwrap(), sides("o\nkeep\n", "a\nkeep\n", "b\nkeep\n"));
+    }
+
+    #[test]
+    fn unmarked_and_unmergeable_files() {
+        assert_eq!(split_conflict_markers("no conflict\n").unwrap(), None);
+        // git's plain `merge` style has no base
+        assert!(split_conflict_markers("<<<<<<< ours\na\n=======\nb\n>>>>>>> theirs\n").is_err());
+        assert!(split_conflict_markers("<<<<<<< ours\na\n||||||| base\no\n=======\nb\n").is_err());
+    }
+}
@@ -0,0 +1,672 @@
+//! members.rs
+//! Member-level 3-way merging for container units (enum members, interface
+//! properties and methods, union/intersection alias operands).
+//!
+//! When both branches update the same container differently, we split the
+//! three payloads into keyed members, merge the member lists, and re-render the
+//! container from A's payload. Only members that both sides changed
+//! incompatibly are reported as conflicts, and, in enums, members whose
+//! values the merge changes or makes collide.
+
+use std::collections::{HashMap, HashSet};
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, find_first, parse_tree};
+
+/// One keyed member of a container body (e.g. `Green = "g"` keyed by `Green`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Member {
+    pub key: String,
+    pub text: String,
+}
+
+/// Signature shared by member-level reconcilers: (base, a, b, label, lang).
+pub(crate) type MemberReconciler = fn(&str, &str, &str, &str, AstLanguage) -> MemberMerge;
+
+/// Result of a member-level (or custom, see [`crate::Reconciler`]) reconcile attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemberMerge {
+    /// Re-rendered container payload.
+    Merged(String),
+    /// Member-level conflict reasons.
+    Conflicts(Vec<String>),
+    /// Payloads could not be split into members; caller falls back to a unit conflict.
+    NotApplicable,
+}
+
+/// Where a member's separator goes when comments follow it (see
+/// [`commented_items`]): before them, not after.
+const SEP_SLOT: char = '\u{1}';
+
+/// A body's items with their text, and the member closing it (see [`commented_items`]).
+pub(crate) type CommentedItems<'t> = (Vec<(Node<'t>, String)>, Option<Member>);
+
+/// Key of the member holding the comments that close a body, below its last
+/// item; rendered last, without a separator.
+const CLOSING: &str = "//";
+
+/// The items of the body `node` (its named children other than comments),
+/// each with its text in `code` widened over its comments: those on the lines
+/// right above it and those after it on its line. The comments left before
+/// the end of the body come as a member of their own. Re-rendered with
+/// [`render_body`] or [`render_array`], the comments stay where they were.
+pub(crate) fn commented_items<'t>(node: Node<'t>, code: &str) -> Option<CommentedItems<'t>> {
+    // item, start (leading comments included), end (trailing ones), separator
+    let mut items: Vec<(Node, usize, usize, Option<Node>)> = Vec::new();
+    let mut doc: Option<(usize, usize)> = None;
+    for i in 0..node.child_count() {
+        let c = node.child(i)?;
+        if c.kind() == "comment" {
+            match items.last_mut() {
+                Some(last) if doc.is_none() && !code[last.2..c.start_byte()].contains('\n') => last.2 = c.end_byte(),
+                _ => doc = Some((doc.map_or(c.start_byte(), |d| d.0), c.end_byte())),
+            }
+        } else if !c.is_named() {
+            if let Some(last) = items.last_mut().filter(|l| l.3.is_none() && l.2 == l.0.end_byte()) {
+                last.3 = matches!(c.kind(), "," | ";").then_some(c);
+            }
+        } else {
+            let start = doc.take().map_or(c.start_byte(), |d| d.0);
+            items.push((c, start, c.end_byte(), None));
+        }
+    }
+    let closing = doc.map(|(start, end)| Member { key: CLOSING.to_string(), text: code[start..end].to_string() });
+    let items = items
+        .into_iter()
+        .map(|(item, start, end, sep)| {
+            let text = &code[start..item.end_byte()];
+            if end == item.end_byte() {
+                return (item, text.to_string());
+            }
+            let after = match sep {
+                Some(s) => format!("{}{}", &code[item.end_byte()..s.start_byte()], &code[s.end_byte()..end]),
+                None => code[item.end_byte()..end].to_string(),
+            };
+            (item, format!("{text}{SEP_SLOT}{after}"))
+        })
+        .collect();
+    Some((items, closing))
+}
+
+/// A member's text split ahead of the comments following it, if any (see
+/// [`commented_items`]).
+pub(crate) fn split_trailing(text: &str) -> (&str, &str) {
+    text.split_at(text.find(SEP_SLOT).unwrap_or(text.len()))
+}
+
+/// A member's text with `sep` after it, ahead of the comments following it.
+fn with_sep(text: &str, sep: Option<char>) -> String {
+    let sep = sep.map(String::from).unwrap_or_default();
+    match text.split_once(SEP_SLOT) {
+        Some((code, comments)) => format!("{code}{sep}{comments}"),
+        None => format!("{text}{sep}"),
+    }
+}
+
+/// Whether a member's comments run to the end of its line (or stand on
+/// lines of their own), keeping what follows it off that line.
+fn ends_line(m: &Member) -> bool {
+    m.key == CLOSING || m.text.split_once(SEP_SLOT).is_some_and(|(_, c)| c.contains("//") || c.contains('\n'))
+}
+
+/// Whether the last member in `inner` is followed by `sep`, line comments aside.
+fn ends_with_sep(inner: &str, sep: char) -> bool {
+    inner
+        .lines()
+        .map(|l| l.split_once(" //").map_or(l, |(code, _)| code).trim())
+        .rfind(|l| !l.is_empty() && !l.starts_with("//"))
+        .is_some_and(|l| l.ends_with(sep))
+}
+
+/// Branch whose payload frames the merged members.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Side {
+    A,
+    B,
+}
+
+/// Decide whose surrounding text (everything outside the merged members) to
+/// keep: the side that changed it, or A when neither/both changed it identically.
+/// `None` means both sides changed the frame differently.
+pub(crate) fn pick_frame<T: PartialEq>(o: T, x: T, y: T) -> Option<Side> {
+    if x == y || y == o {
+        Some(Side::A)
+    } else if x == o {
+        Some(Side::B)
+    } else {
+        None
+    }
+}
+
+/// Merge keyed member lists. Ordering follows A, with B's additions placed after
+/// the member that precedes them in B (after any of A's additions at that spot).
+/// `label` prefixes conflict messages, e.g. `enum_declaration::Color`.
+pub(crate) fn merge_members(
+    base: &[Member],
+    a: &[Member],
+    b: &[Member],
+    label: &str,
+) -> Result<Vec<Member>, Vec<String>> {
+    merge_members_with(base, a, b, label, |_, _, _, _| None)
+}
+
+/// Like [`merge_members`], but a member updated differently on both sides is
+/// first handed to `both_changed(key, base, a, b)`, which may merge it recursively.
+pub(crate) fn merge_members_with(
+    base: &[Member],
+    a: &[Member],
+    b: &[Member],
+    label: &str,
+    mut both_changed: impl FnMut(&str, &str, &str, &str) -> Option<String>,
+) -> Result<Vec<Member>, Vec<String>> {
+    let index = |ms: &[Member]| -> HashMap<String, String> {
+        ms.iter().map(|m| (m.key.clone(), m.text.clone())).collect()
+    };
+    let (base_map, a_map, b_map) = (index(base), index(a), index(b));
+
+    let mut conflicts = Vec::new();
+    let mut resolved: HashMap<String, Option<String>> = HashMap::new();
+
+    let mut all_keys: Vec<&String> = Vec::new();
+    let mut seen = HashSet::new();
+    for m in base.iter().chain(a).chain(b) {
+        if seen.insert(&m.key) {
+            all_keys.push(&m.key);
+        }
+    }
+
+    for key in all_keys {
+        let (o, x, y) = (base_map.get(key), a_map.get(key), b_map.get(key));
+        let pick = if x == y {
+            x.cloned()
+        } else if x == o {
+            y.cloned()
+        } else if y == o {
+            x.cloned()
+        } else if let (Some(o), Some(x), Some(y)) = (o, x, y)
+            && let Some(merged) = both_changed(key, o, x, y)
+        {
+            Some(merged)
+        } else {
+            conflicts.push(match (o, x, y) {
+                (None, _, _) => format!("Both branches added {label}::{key} differently"),
+                (Some(_), Some(_), Some(_)) => {
+                    format!("Both branches updated {label}::{key} differently")
+                }
+                _ => format!("Deletion vs update on {label}::{key}"),
+            });
+            continue;
+        };
+        resolved.insert(key.clone(), pick);
+    }
+    if !conflicts.is_empty() {
+        return Err(conflicts);
+    }
+
+    // Order: A's sequence, then splice in B-only additions by their B predecessor.
+    let mut order: Vec<String> = a.iter().map(|m| m.key.clone()).collect();
+    for (i, m) in b.iter().enumerate() {
+        if base_map.contains_key(&m.key) || a_map.contains_key(&m.key) {
+            continue;
+        }
+        let mut pos = match i.checked_sub(1) {
+            None => 0,
+            Some(p) => order
+                .iter()
+                .position(|k| *k == b[p].key)
+                .map_or(order.len(), |at| at + 1),
+        };
+        // keep A's own additions at the same anchor ahead of B's
+        while pos < order.len() && !base_map.contains_key(&order[pos]) {
+            pos += 1;
+        }
+        order.insert(pos, m.key.clone());
+    }
+
+    Ok(order
+        .into_iter()
+        .filter_map(|key| {
+            let text = resolved.get(&key).cloned().flatten()?;
+            Some(Member { key, text })
+        })
+        .collect())
+}
+
+/// A container payload split into its keyed members.
+struct Split {
+    members: Vec<Member>,
+    /// Byte range of the `{ ... }` body (or aliased type) within the payload.
+    body: (usize, usize),
+    /// Separator used between members, if any (`,` for enums, `;`/`,`/none for interfaces).
+    sep: Option<char>,
+}
+
+impl Split {
+    /// Payload text outside the body (keyword, name, heritage clauses, ...).
+    fn frame<'c>(&self, code: &'c str) -> (&'c str, &'c str) {
+        (&code[..self.body.0], &code[self.body.1..])
+    }
+}
+
+/// Reconcile divergent updates to an `enum` or `interface` declaration
+/// member-by-member. The header (name, `extends`, `const`, ...) must not
+/// diverge; the merged body is rendered into whichever side changed it.
+pub(crate) fn merge_container(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+    let (Some(o), Some(x), Some(y)) = (
+        split_container(base, lang),
+        split_container(a, lang),
+        split_container(b, lang),
+    ) else {
+        return MemberMerge::NotApplicable;
+    };
+
+    let (code, split) = match pick_frame(o.frame(base), x.frame(a), y.frame(b)) {
+        Some(Side::A) => (a, &x),
+        Some(Side::B) => (b, &y),
+        None => {
+            return MemberMerge::Conflicts(vec![format!(
+                "Both branches updated the declaration of {label} differently"
+            )]);
+        }
+    };
+
+    match merge_members(&o.members, &x.members, &y.members, label) {
+        Ok(merged) => {
+            let (s, e) = split.body;
+            let body = render_body(&code[s..e], &merged, split.sep);
+            let mut out = code.to_string();
+            out.replace_range(s..e, &body);
+            match enum_value_conflicts([base, a, b, &out], label, lang) {
+                conflicts if conflicts.is_empty() => MemberMerge::Merged(out),
+                conflicts => MemberMerge::Conflicts(conflicts),
+            }
+        }
+        Err(conflicts) => MemberMerge::Conflicts(conflicts),
+    }
+}
+
+/// What merging an enum's member lists did to the members' values, given
+/// `[base, a, b, merged]`: a value that isn't the one the branches gave the
+/// member (an insertion moving the implicit values after it), or two members
+/// with the same value that no branch gave it to both. Empty for interfaces,
+/// and for values sinkit can't work out (computed ones, and the implicit
+/// values after them).
+fn enum_value_conflicts(codes: [&str; 4], label: &str, lang: AstLanguage) -> Vec<String> {
+    let [Some(o), Some(x), Some(y), Some(merged)] = codes.map(|code| enum_values(code, lang)) else {
+        return Vec::new();
+    };
+    let value = |values: &[(String, Option<String>)], key: &str| {
+        values.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+    };
+    let mut conflicts = Vec::new();
+    for (key, got) in &merged {
+        let Some(got) = got else { continue };
+        let (o, x, y) = (value(&o, key), value(&x, key), value(&y, key));
+        // the member's value as the branches left it (absent: not theirs)
+        let wanted = if x == y || y == o { x } else if x == o { y } else { None };
+        if let Some(Some(wanted)) = wanted
+            && wanted != *got
+        {
+            conflicts.push(format!("The merge moves the value of {label}::{key} from {wanted} to {got}"));
+        }
+    }
+    for (i, (key, v)) in merged.iter().enumerate() {
+        let Some(v) = v else { continue };
+        for (other, _) in merged[i + 1..].iter().filter(|(_, w)| w.as_ref() == Some(v)) {
+            let alike = |values: &[(String, Option<String>)]| {
+                let v = Some(Some(v.clone()));
+                value(values, key) == v && value(values, other) == v
+            };
+            if !alike(&x) && !alike(&y) {
+                conflicts.push(format!("The merge gives {label}::{key} and {label}::{other} the same value, {v}"));
+            }
+        }
+    }
+    conflicts
+}
+
+/// The members of the enum in `code` with their values (numbers as
+/// written by `{}`, strings quoted), `None` where they aren't literals.
+fn enum_values(code: &str, lang: AstLanguage) -> Option<Vec<(String, Option<String>)>> {
+    let tree = parse_tree(code, lang).ok()?;
+    let body = find_first(tree.root_node(), "enum_declaration")?.child_by_field_name("body")?;
+    let mut values = Vec::new();
+    // the implicit value of the next member
+    let mut next = Some(0.0);
+    for i in 0..body.named_child_count() {
+        let m = body.named_child(i)?;
+        let (name, value) = match m.kind() {
+            "comment" => continue,
+            "enum_assignment" => {
+                let value = m.child_by_field_name("value")?;
+                let text = value.utf8_text(code.as_bytes()).ok()?;
+                next = None;
+                let value = match value.kind() {
+                    "string" => Some(format!("{:?}", text.trim_matches(&['"', '\''][..]))),
+                    _ => {
+                        next = number(text).map(|n| n + 1.0);
+                        number(text).map(|n| n.to_string())
+                    }
+                };
+                (m.child_by_field_name("name")?, value)
+            }
+            _ => {
+                let value = next.map(|n| n.to_string());
+                next = next.map(|n| n + 1.0);
+                (m, value)
+            }
+        };
+        values.push((member_key(&name, code)?, value));
+    }
+    Some(values)
+}
+
+/// A numeric literal's value (`-1`, `0x10`, `1_000`, ...).
+fn number(text: &str) -> Option<f64> {
+    let text = text.replace('_', "");
+    let (negative, text) = match text.trim().strip_prefix('-') {
+        Some(rest) => (true, rest.trim().to_string()),
+        None => (false, text.trim().to_string()),
+    };
+    let radix = |prefix: [&str; 2]| prefix.iter().find_map(|p| text.strip_prefix(p));
+    let value = if let Some(hex) = radix(["0x", "0X"]) {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(octal) = radix(["0o", "0O"]) {
+        i64::from_str_radix(octal, 8).ok()? as f64
+    } else if let Some(binary) = radix(["0b", "0B"]) {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else {
+        text.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Split an enum/interface payload into keyed members.
+fn split_container(code: &str, lang: AstLanguage) -> Option<Split> {
+    let tree = parse_tree(code, lang).ok()?;
+    let root = tree.root_node();
+    let decl = find_first(root, "enum_declaration").or_else(|| find_first(root, "interface_declaration"))?;
+    let body = decl
+        .child_by_field_name("body")
+        .or_else(|| find_first(decl, "interface_body"))?;
+
+    let sep = (0..body.child_count())
+        .filter_map(|i| body.child(i))
+        .rfind(|c| matches!(c.kind(), "," | ";"))
+        .and_then(|c| c.kind().chars().next());
+    let mut members = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let (items, closing) = commented_items(body, code)?;
+    for (m, text) in items {
+        let base_key = match m.kind() {
+            "property_identifier" | "string" => member_key(&m, code)?,
+            _ => match m.child_by_field_name("name") {
+                Some(n) => member_key(&n, code)?,
+                // index/call/construct signatures have no name; key them by kind
+                None => m.kind().to_string(),
+            },
+        };
+        // overloads and repeated signatures share a name; keep them distinct
+        let n = seen.entry(base_key.clone()).or_insert(0);
+        let key = if *n == 0 { base_key } else { format!("{base_key}#{n}") };
+        *n += 1;
+        members.push(Member { key, text });
+    }
+    members.extend(closing);
+    Some(Split {
+        members,
+        body: (body.start_byte(), body.end_byte()),
+        sep,
+    })
+}
+
+fn member_key(node: &Node, code: &str) -> Option<String> {
+    let raw = node.utf8_text(code.as_bytes()).ok()?;
+    Some(raw.trim_matches(&['"', '\''][..]).to_string())
+}
+
+/// Reconcile divergent updates to a `type X = A | B` (or `A & B`) alias by
+/// merging the union/intersection members. Members are keyed by their text.
+pub(crate) fn merge_type_alias(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+    let (Some(o), Some(x), Some(y)) = (
+        split_alias(base, lang),
+        split_alias(a, lang),
+        split_alias(b, lang),
+    ) else {
+        return MemberMerge::NotApplicable;
+    };
+
+    // All sides must agree on the operator (a single-member alias has none yet).
+    let mut ops = [o.sep, x.sep, y.sep].into_iter().flatten();
+    let Some(op) = ops.next() else {
+        return MemberMerge::NotApplicable;
+    };
+    if ops.any(|c| c != op) {
+        return MemberMerge::NotApplicable;
+    }
+
+    let (code, split) = match pick_frame(o.frame(base), x.frame(a), y.frame(b)) {
+        Some(Side::A) => (a, &x),
+        Some(Side::B) => (b, &y),
+        None => {
+            return MemberMerge::Conflicts(vec![format!(
+                "Both branches updated the declaration of {label} differently"
+            )]);
+        }
+    };
+
+    match merge_members(&o.members, &x.members, &y.members, label) {
+        Ok(merged) => {
+            let (s, e) = split.body;
+            let value = render_type_operands(&code[s..e], &merged, op);
+            let mut out = code.to_string();
+            out.replace_range(s..e, &value);
+            MemberMerge::Merged(out)
+        }
+        Err(conflicts) => MemberMerge::Conflicts(conflicts),
+    }
+}
+
+/// Split a type alias into its flattened union/intersection operands. `body`
+/// is the range of the aliased type and `sep` the operator (`|`/`&`), if any.
+fn split_alias(code: &str, lang: AstLanguage) -> Option<Split> {
+    let tree = parse_tree(code, lang).ok()?;
+    let decl = find_first(tree.root_node(), "type_alias_declaration")?;
+    let value = decl.child_by_field_name("value")?;
+
+    let sep = match value.kind() {
+        "union_type" => Some('|'),
+        "intersection_type" => Some('&'),
+        _ => None,
+    };
+    let mut operands = Vec::new();
+    collect_operands(value, value.kind(), &mut operands);
+
+    let mut members = Vec::new();
+    for n in operands {
+        let text = n.utf8_text(code.as_bytes()).ok()?.to_string();
+        members.push(Member { key: normalize_ws(&text), text });
+    }
+    Some(Split {
+        members,
+        body: (value.start_byte(), value.end_byte()),
+        sep,
+    })
+}
+
+/// Flatten the left-nested `union_type`/`intersection_type` chain.
+fn collect_operands<'t>(node: Node<'t>, op_kind: &str, out: &mut Vec<Node<'t>>) {
+    if node.kind() != op_kind || !matches!(op_kind, "union_type" | "intersection_type") {
+        out.push(node);
+        return;
+    }
+    for i in 0..node.named_child_count() {
+        if let Some(c) = node.named_child(i) {
+            collect_operands(c, op_kind, out);
+        }
+    }
+}
+
+pub(crate) fn normalize_ws(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Render union/intersection operands, keeping the one-per-line leading-operator
+/// layout (`\n  | 'a'\n  | 'b'`) when the original used it.
+fn render_type_operands(original: &str, members: &[Member], op: char) -> String {
+    let texts: Vec<&str> = members.iter().map(|m| m.text.as_str()).collect();
+    if !original.contains('\n') {
+        return texts.join(&format!(" {op} "));
+    }
+    let indent = original
+        .lines()
+        .last()
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .unwrap_or("  ");
+    let joined = texts.join(&format!("\n{indent}{op} "));
+    if original.starts_with(op) {
+        format!("{op} {joined}")
+    } else {
+        joined
+    }
+}
+
+/// Re-render a `{ ... }` body holding `members`, mimicking the layout of
+/// `original` (single-line vs one-member-per-line, indentation, trailing separator).
+/// With no separator, multi-line members are separated by newlines only.
+pub(crate) fn render_body(original: &str, members: &[Member], sep: Option<char>) -> String {
+    if members.is_empty() {
+        return "{}".to_string();
+    }
+    let inner = original
+        .strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .unwrap_or("");
+    let trailing_sep = sep.is_some_and(|c| ends_with_sep(inner, c));
+    let one_line = !original.contains('\n');
+    if one_line && !members.iter().any(ends_line) {
+        let sep = sep.unwrap_or(';');
+        let last = members.len() - 1;
+        let texts: Vec<String> = members
+            .iter()
+            .enumerate()
+            .map(|(i, m)| with_sep(&m.text, Some(sep).filter(|_| i < last || trailing_sep)))
+            .collect();
+        return format!("{{ {} }}", texts.join(" "));
+    }
+
+    let indent = inner
+        .lines()
+        .find(|l| !l.trim().is_empty() && !one_line)
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .unwrap_or("  ");
+    let closing = original
+        .lines()
+        .last()
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .unwrap_or("");
+
+    // statement-like bodies separated by blank lines keep that spacing
+    let spaced = sep.is_none() && inner.trim().contains("\n\n");
+
+    let items = members.iter().filter(|m| m.key != CLOSING).count();
+    let mut out = String::from("{\n");
+    for (i, m) in members.iter().enumerate() {
+        if spaced && i > 0 {
+            out.push('\n');
+        }
+        out.push_str(indent);
+        let last = i + 1 >= items && !trailing_sep;
+        out.push_str(&with_sep(&m.text, sep.filter(|_| !last && m.key != CLOSING)));
+        out.push('\n');
+    }
+    out.push_str(closing);
+    out.push('}');
+    out
+}
+
+/// Render `[a, b]`, keeping one-per-line layout (and trailing comma) if used.
+pub(crate) fn render_array(original: &str, elements: &[Member]) -> String {
+    let one_line = !original.contains('\n');
+    if one_line && !elements.iter().any(ends_line) {
+        let last = elements.len().saturating_sub(1);
+        let texts: Vec<String> = elements
+            .iter()
+            .enumerate()
+            .map(|(i, m)| with_sep(&m.text, Some(',').filter(|_| i < last)))
+            .collect();
+        return format!("[{}]", texts.join(" "));
+    }
+    let inner = original.trim_start_matches('[').trim_end_matches(']');
+    let trailing = ends_with_sep(inner, ',');
+    let indent = inner
+        .lines()
+        .find(|l| !l.trim().is_empty() && !one_line)
+        .map(|l| &l[..l.len() - l.trim_start().len()])
+        .unwrap_or("  ");
+    let closing = match one_line {
+        true => "",
+        false => original.rsplit('\n').next().unwrap_or("]").trim_end_matches(']'),
+    };
+    let mut out = String::from("[");
+    let items = elements.iter().filter(|m| m.key != CLOSING).count();
+    for (i, m) in elements.iter().enumerate() {
+        out.push('\n');
+        out.push_str(indent);
+        let last = i + 1 >= items && !trailing;
+        out.push_str(&with_sep(&m.text, Some(',').filter(|_| !last && m.key != CLOSING)));
+    }
+    out.push('\n');
+    out.push_str(closing);
+    out.push(']');
+    out
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, a: &str, b: &str) -> MemberMerge {
+        merge_container(base, a, b, "enum_declaration::E", AstLanguage::TypeScript)
+    }
+
+    #[test]
+    fn appended_members_merge() {
+        let out = merge("enum E { A = 1, B = 2 }", "enum E { A = 1, B = 2, C = 3 }", "enum E { A = 1, B = 2, D = 4 }");
+        assert_eq!(out, MemberMerge::Merged("enum E { A = 1, B = 2, C = 3, D = 4 }".to_string()));
+    }
+
+    #[test]
+    fn colliding_explicit_values_conflict() {
+        let out = merge("enum E { A = 1, B = 2 }", "enum E { A = 1, B = 2, C = 3 }", "enum E { A = 1, B = 2, D = 3 }");
+        let MemberMerge::Conflicts(conflicts) = out else { panic!("merged: {out:?}") };
+        assert_eq!(conflicts, ["The merge gives enum_declaration::E::C and enum_declaration::E::D the same value, 3"]);
+    }
+
+    #[test]
+    fn aliases_a_branch_wrote_merge() {
+        let out = merge("enum E { A = 1 }", "enum E { A = 1, First = 1 }", "enum E { A = 1, B = 2 }");
+        assert_eq!(out, MemberMerge::Merged("enum E { A = 1, First = 1, B = 2 }".to_string()));
+    }
+
+    #[test]
+    fn shifted_implicit_values_conflict() {
+        let out = merge("enum E { A, B }", "enum E { A, B, C }", "enum E { A, X, B }");
+        let MemberMerge::Conflicts(conflicts) = out else { panic!("merged: {out:?}") };
+        assert_eq!(conflicts, ["The merge moves the value of enum_declaration::E::C from 2 to 3"]);
+    }
+
+    #[test]
+    fn implicit_values_after_explicit_ones_merge() {
+        let out = merge("enum E { A = 10 }", "enum E { A = 10, B }", "enum E { Z = -1, A = 10 }");
+        assert_eq!(out, MemberMerge::Merged("enum E { Z = -1, A = 10, B }".to_string()));
+    }
+
+    #[test]
+    fn string_values_collide_too() {
+        let out = merge("enum E { A = \"a\" }", "enum E { A = \"a\", B = 'b' }", "enum E { A = \"a\", C = \"b\" }");
+        let MemberMerge::Conflicts(conflicts) = out else { panic!("merged: {out:?}") };
+        assert_eq!(conflicts, ["The merge gives enum_declaration::E::B and enum_declaration::E::C the same value, \"b\""]);
+    }
+}
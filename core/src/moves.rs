@@ -0,0 +1,80 @@
+//! moves.rs
+//! Units extracted to a new file: one branch moves units out of a file into
+//! one it adds, the other edits them where they were.
+//!
+//! [`extracted_units`] recognizes the move; merging the old file with the
+//! units listed in [`MergeOptions::with_moved`] takes the deletion, and
+//! [`carry_moved_edits`] applies the other branch's edits in the new file.
+
+use anyhow::Result;
+
+use crate::ast::{AstLanguage, parse_typescript_to_ast_with};
+use crate::compose::{self, MergeOptions, MergeOutcome};
+
+/// Units of `base` (a file) that a branch deleted from it (`side` is its
+/// version) and has in `new_file`, one it added, by kind and name; empty
+/// unless they make up at least half of the new file's units (imports
+/// aside), i.e. the file was extracted rather than merely shares a name.
+pub fn extracted_units(
+    base: &str,
+    side: &str,
+    new_file: &str,
+    lang: AstLanguage,
+    merge_opts: &MergeOptions,
+) -> Result<Vec<(String, String)>> {
+    let parse = |code: &str| parse_typescript_to_ast_with(code, lang, &merge_opts.parse);
+    let (base, side, new_file) = (parse(base)?, parse(side)?, parse(new_file)?);
+    let remaining: Vec<_> = side.keyed_units().into_iter().map(|(k, _)| k).collect();
+    let added: Vec<_> =
+        new_file.keyed_units().into_iter().map(|(k, _)| k).filter(|(kind, _)| kind != "import_statement").collect();
+    let moved: Vec<(String, String)> = base
+        .keyed_units()
+        .into_iter()
+        .map(|(k, _)| k)
+        .filter(|k| k.0 != "import_statement" && !remaining.contains(k) && added.contains(k))
+        .collect();
+    Ok(if moved.len() * 2 >= added.len() { moved } else { Vec::new() })
+}
+
+/// `new_file` with the edits `edited` (the other branch's version of the
+/// file the `units` were moved out of) made to them since `base`, each unit
+/// merged like [`crate::merge_unit`]. A unit whose edit conflicts with how
+/// the move changed it stays as moved, with the conflict reported.
+pub fn carry_moved_edits(
+    base: &str,
+    edited: &str,
+    new_file: &str,
+    units: &[(String, String)],
+    lang: AstLanguage,
+    merge_opts: &MergeOptions,
+) -> Result<MergeOutcome> {
+    let parse = |code: &str| parse_typescript_to_ast_with(code, lang, &merge_opts.parse);
+    let (base, edited) = (parse(base)?, parse(edited)?);
+    let text = |file: &crate::AstFile, key: &(String, String)| {
+        let (_, u) = file.keyed_units().into_iter().find(|(k, _)| k == key)?;
+        Some(file.code[u.start_byte..u.end_byte].to_string())
+    };
+    let mut out = MergeOutcome { merged_code: new_file.to_string(), ..MergeOutcome::default() };
+    for key in units {
+        let (Some(original), Some(changed)) = (text(&base, key), text(&edited, key)) else { continue };
+        if original == changed {
+            continue;
+        }
+        // the unit as it is now, after the units carried before it
+        let current = parse(&out.merged_code)?;
+        let Some((_, u)) = current.keyed_units().into_iter().find(|(k, _)| k == key) else { continue };
+        let (start, end) = (u.start_byte, u.end_byte);
+        let moved = &current.code[start..end];
+        let res = compose::merge_unit(&original, moved, &changed, &key.0, lang, merge_opts);
+        if res.conflicts.is_empty() {
+            out.merged_code.replace_range(start..end, &res.merged_code);
+            out.warnings.push(format!("Applied the edit of {}::{} made where it was before the move", key.0, key.1));
+        }
+        out.warnings.extend(res.warnings);
+        out.conflicts.extend(res.conflicts);
+        out.suppressed.extend(res.suppressed);
+        out.resolved.extend(res.resolved);
+        out.auto_resolutions.extend(res.auto_resolutions);
+    }
+    Ok(out)
+}
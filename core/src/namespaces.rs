@@ -0,0 +1,136 @@
+//! namespaces.rs
+//! Namespace and module declarations as container units.
+//!
+//! `namespace Api { ... }`, `declare module "x" { ... }` and `declare global
+//! { ... }` bodies are merged
+//! statement-by-statement. Declarations are keyed like top-level units
+//! (`kind::name`) and reconciled recursively with the same rules as the file's
+//! top level; leading comments travel with the statement they precede.
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, UNIT_KINDS, extract_unit_name, parse_tree};
+use crate::compose::{MergeOptions, Notes, reconcile_update};
+use crate::members::{Member, MemberMerge, Side, merge_members_with, normalize_ws, pick_frame, render_body};
+
+/// Reconcile divergent updates to a namespace/module unit, its members with
+/// the file's `opts`. What reconciling them finds goes to `notes` if the
+/// namespace merges.
+pub(crate) fn merge_namespace(
+    base: &str,
+    a: &str,
+    b: &str,
+    label: &str,
+    lang: AstLanguage,
+    opts: &MergeOptions,
+    notes: &mut Notes,
+) -> MemberMerge {
+    let (Some(o), Some(x), Some(y)) = (split_module(base, lang), split_module(a, lang), split_module(b, lang)) else {
+        return MemberMerge::NotApplicable;
+    };
+
+    let frame = |code: &str, range: (usize, usize)| format!("{}{{}}{}", &code[..range.0], &code[range.1..]);
+    let (code, range) = match pick_frame(frame(base, o.1), frame(a, x.1), frame(b, y.1)) {
+        Some(Side::A) => (a, x.1),
+        Some(Side::B) => (b, y.1),
+        None => {
+            return MemberMerge::Conflicts(vec![format!(
+                "Both branches updated the declaration of {label} differently"
+            )]);
+        }
+    };
+
+    // members are reconciled under their qualified name (`Api::get`, or
+    // `Api::V1::get` a level down), so notes on two namespaces' `get` differ
+    let scope = label.split_once("::").map_or(label, |(_, name)| name);
+    let mut found = Notes::default();
+    let merged = merge_members_with(&o.0, &x.0, &y.0, label, |key, o, x, y| {
+        let (kind, name) = key.split_once("::")?;
+        reconcile_update(&(kind.to_string(), format!("{scope}::{name}")), o, x, y, lang, opts, &mut found).ok()
+    });
+    match merged {
+        Ok(statements) => {
+            notes.warnings.extend(found.warnings);
+            notes.auto_resolutions.extend(found.auto_resolutions);
+            let mut out = code.to_string();
+            out.replace_range(range.0..range.1, &render_body(&code[range.0..range.1], &statements, None));
+            MemberMerge::Merged(out)
+        }
+        Err(conflicts) => MemberMerge::Conflicts(conflicts),
+    }
+}
+
+/// Keyed body statements of the first namespace/module in `code`, plus the
+/// byte range of its `{ ... }` body.
+fn split_module(code: &str, lang: AstLanguage) -> Option<(Vec<Member>, (usize, usize))> {
+    let tree = parse_tree(code, lang).ok()?;
+    let module = find_module(tree.root_node())?;
+    let body = module.child_by_field_name("body").or_else(|| {
+        (0..module.named_child_count())
+            .filter_map(|i| module.named_child(i))
+            .find(|c| c.kind() == "statement_block")
+    })?;
+
+    let mut statements = Vec::new();
+    let mut doc_start: Option<usize> = None;
+    for i in 0..body.named_child_count() {
+        let st = body.named_child(i)?;
+        if st.kind() == "comment" {
+            doc_start.get_or_insert(st.start_byte());
+            continue;
+        }
+        let start = doc_start.take().unwrap_or(st.start_byte());
+        statements.push(Member {
+            key: statement_key(&st, code)?,
+            text: code[start..st.end_byte()].to_string(),
+        });
+    }
+    Some((statements, (body.start_byte(), body.end_byte())))
+}
+
+fn find_module(node: Node) -> Option<Node> {
+    let is_global = || node.kind() == "ambient_declaration" && node.child(1).is_some_and(|c| c.kind() == "global");
+    if matches!(node.kind(), "internal_module" | "module") || is_global() {
+        return Some(node);
+    }
+    (0..node.named_child_count())
+        .filter_map(|i| node.named_child(i))
+        .find_map(find_module)
+}
+
+/// `kind::name` for declarations (looking through `export`/`declare`), else
+/// the statement's normalized text.
+fn statement_key(st: &Node, code: &str) -> Option<String> {
+    let decl = match st.kind() {
+        "export_statement" | "expression_statement" | "ambient_declaration" => (0..st.named_child_count())
+            .filter_map(|i| st.named_child(i))
+            .find(|c| UNIT_KINDS.contains(&c.kind()) || matches!(c.kind(), "internal_module" | "module")),
+        k if UNIT_KINDS.contains(&k) => Some(*st),
+        _ => None,
+    };
+    if let Some(decl) = decl
+        && let Some(name) = extract_unit_name(&decl, code)
+    {
+        return Some(format!("{}::{name}", decl.kind()));
+    }
+    Some(normalize_ws(st.utf8_text(code.as_bytes()).ok()?))
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use crate::{AstLanguage, three_way_merge_top_level};
+
+    #[test]
+    fn members_are_reported_under_their_namespace() {
+        let ns = |get: &str, put: &str| {
+            format!("namespace Api {{\n  export function get() {{\n    {get}\n    return 1;\n    {put}\n  }}\n  export const v = 1;\n}}\n")
+        };
+        let base = ns("a();", "b();");
+        let a = ns("a(1);", "b();");
+        let b = ns("a();", "b(2);").replace("v = 1", "v = 2");
+        let out = three_way_merge_top_level(&base, &a, &b, AstLanguage::TypeScript).unwrap();
+        assert!(out.conflicts.is_empty(), "{:?}", out.conflicts);
+        let units: Vec<&str> = out.auto_resolutions.iter().map(|r| r.unit.as_str()).collect();
+        assert_eq!(units, ["function_declaration::Api::get"]);
+    }
+}
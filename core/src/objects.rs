@@ -0,0 +1,138 @@
+//! objects.rs
+//! Key-by-key 3-way merging of object literals (config-style objects), with
+//! order-preserving unions for array values.
+//!
+//! `{ selector: "app", imports: [A] }` edited on both sides merges per key;
+//! when both sides change the same key, nested objects recurse and arrays
+//! union their elements.
+
+use crate::ast::{AstLanguage, parse_tree};
+use crate::members::{
+    Member, Side, commented_items, merge_members, merge_members_with, normalize_ws, pick_frame, render_array, render_body, split_trailing,
+};
+
+/// 3-way merge of an object literal's text.
+pub(crate) fn merge_object_text(o: &str, x: &str, y: &str, label: &str) -> Result<String, Vec<String>> {
+    if x == y || y == o {
+        return Ok(x.to_string());
+    }
+    if x == o {
+        return Ok(y.to_string());
+    }
+    let (Some(po), Some(px), Some(py)) = (split_literal(o, "object"), split_literal(x, "object"), split_literal(y, "object")) else {
+        return Err(vec![format!("Both branches updated {label} differently")]);
+    };
+    let merged = merge_members_with(&po, &px, &py, label, |_, o, x, y| merge_pair(o, x, y, label))?;
+    Ok(render_body(x, &merged, Some(',')))
+}
+
+/// 3-way merge of an array literal's text as an order-preserving union.
+pub(crate) fn merge_array_text(o: &str, x: &str, y: &str, label: &str) -> Result<String, Vec<String>> {
+    if x == y || y == o {
+        return Ok(x.to_string());
+    }
+    if x == o {
+        return Ok(y.to_string());
+    }
+    let (Some(po), Some(px), Some(py)) = (split_literal(o, "array"), split_literal(x, "array"), split_literal(y, "array")) else {
+        return Err(vec![format!("Both branches updated {label} differently")]);
+    };
+    let merged = merge_members(&po, &px, &py, label)?;
+    Ok(render_array(x, &merged))
+}
+
+/// Both sides changed the same `key: value` pair: recurse into object or array values.
+fn merge_pair(o: &str, x: &str, y: &str, label: &str) -> Option<String> {
+    let ((co, o, to), (cx, x, tx), (cy, y, ty)) = (peel(o)?, peel(x)?, peel(y)?);
+    // comments both sides changed don't merge
+    let (above, after) = match pick_frame((co, to), (cx, tx), (cy, ty))? {
+        Side::A => (cx, tx),
+        Side::B => (cy, ty),
+    };
+    let (ko, vo) = o.split_once(':')?;
+    let (kx, vx) = x.split_once(':')?;
+    let (ky, vy) = y.split_once(':')?;
+    if ko.trim() != kx.trim() || kx.trim() != ky.trim() {
+        return None;
+    }
+    let (vo, vx, vy) = (vo.trim(), vx.trim(), vy.trim());
+    let label = format!("{label}::{}", kx.trim());
+    let merged = if vx.starts_with('{') && vy.starts_with('{') && vo.starts_with('{') {
+        merge_object_text(vo, vx, vy, &label).ok()?
+    } else if vx.starts_with('[') && vy.starts_with('[') && vo.starts_with('[') {
+        merge_array_text(vo, vx, vy, &label).ok()?
+    } else {
+        return None;
+    };
+    Some(format!("{above}{kx}: {merged}{after}"))
+}
+
+/// A pair's text split into the comments above it, the pair and the
+/// comments after it.
+fn peel(text: &str) -> Option<(&str, &str, &str)> {
+    let (head, after) = split_trailing(text);
+    let wrapped = format!("({{{head}}})");
+    let tree = parse_tree(&wrapped, AstLanguage::TypeScript).ok()?;
+    let object = tree.root_node().named_child(0)?.named_child(0)?.named_child(0)?;
+    let pair = (0..object.named_child_count())
+        .filter_map(|i| object.named_child(i))
+        .find(|n| n.kind() != "comment")?;
+    // past the `({` wrapping it
+    let (above, pair) = head.split_at(pair.start_byte().checked_sub(2)?);
+    Some((above, pair, after))
+}
+
+/// Split an object (`kind = "object"`) or array literal into keyed members.
+/// Object members are keyed by property name, array elements by their text.
+fn split_literal(text: &str, kind: &str) -> Option<Vec<Member>> {
+    // parenthesize so `{ ... }` parses as an expression, not a block
+    let wrapped = format!("({text})");
+    let tree = parse_tree(&wrapped, AstLanguage::TypeScript).ok()?;
+    let node = tree.root_node().named_child(0)?.named_child(0)?.named_child(0)?;
+    if node.kind() != kind || node.has_error() {
+        return None;
+    }
+
+    let (items, closing) = commented_items(node, &wrapped)?;
+    let mut out = Vec::new();
+    for (c, member_text) in items {
+        let key = match c.kind() {
+            "pair" => {
+                let k = c.child_by_field_name("key")?.utf8_text(wrapped.as_bytes()).ok()?;
+                k.trim_matches(&['"', '\''][..]).to_string()
+            }
+            _ => normalize_ws(c.utf8_text(wrapped.as_bytes()).ok()?),
+        };
+        out.push(Member { key, text: member_text });
+    }
+    out.extend(closing);
+    Some(out)
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "{\n  selector: 'app',\n  imports: [A],\n}";
+
+    #[test]
+    fn different_keys_merge() {
+        let a = BASE.replace("'app'", "'app-root'");
+        let b = BASE.replace("  imports", "  standalone: true,\n  imports");
+        assert_eq!(merge_object_text(BASE, &a, &b, "config").unwrap(), b.replace("'app'", "'app-root'"));
+    }
+
+    #[test]
+    fn arrays_under_one_key_unite() {
+        let (a, b) = (BASE.replace("[A]", "[A, B]"), BASE.replace("[A]", "[A, C]"));
+        assert_eq!(merge_object_text(BASE, &a, &b, "config").unwrap(), BASE.replace("[A]", "[A, B, C]"));
+        assert_eq!(merge_array_text("[1, 2]", "[1, 2, 3]", "[0, 1, 2]", "list").unwrap(), "[0, 1, 2, 3]");
+    }
+
+    #[test]
+    fn a_key_both_changed_conflicts() {
+        let (a, b) = (BASE.replace("'app'", "'x'"), BASE.replace("'app'", "'y'"));
+        assert_eq!(merge_object_text(BASE, &a, &b, "config").unwrap_err(), ["Both branches updated config::selector differently"]);
+        assert!(merge_object_text("f()", "g()", "h()", "config").is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! openapi.rs
+//! Merging OpenAPI (and Swagger 2) documents by operation and schema.
+//!
+//! A spec file every team adds endpoints to conflicts like a barrel file.
+//! Files of the `openapi` adapter ([`crate::Adapter::OpenApi`]) merge by
+//! their structure, in JSON or YAML alike: `paths` (and `webhooks`) by path
+//! and, within one, by operation (`get`, `post`, ...); `components` by
+//! section and name (`definitions` in Swagger 2). Other top-level keys
+//! (`info`, `servers`, `tags`, ...) and each operation and schema are
+//! merged whole. Endpoints and schemas added on different branches merge;
+//! an operation or schema both changed differently, or removed on one and
+//! changed on the other, is a conflict of its own (`update-update`,
+//! `insert-insert` or `delete-update`), e.g. on `operation::GET /pets`,
+//! and keeps its base version (A's, if both added it). A document that
+//! doesn't parse merges textually.
+
+use crate::compose::{MergeOptions, MergeOutcome, textual_merge};
+use crate::conflicts::{self, Conflict};
+use crate::hash::content_hash;
+use crate::keyed::{Map, Syntax, merge_keyed, render, resolve};
+use crate::members::Member;
+
+const METHODS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+/// Merge three versions of an OpenAPI document.
+pub(crate) fn merge_openapi(base: &str, a: &str, b: &str, opts: &MergeOptions) -> MergeOutcome {
+    if a == b || b == base || a == base {
+        let merged_code = if a == base { b } else { a };
+        return MergeOutcome { merged_code: merged_code.to_string(), ..MergeOutcome::default() };
+    }
+    let syntax = Syntax::of(a);
+    let (Some(o), Some(x), Some(y)) =
+        (Map::document(syntax, base), Map::document(syntax, a), Map::document(syntax, b))
+    else {
+        return textual_merge(base, a, b, "OpenAPI document that doesn't parse; both branches changed it", opts);
+    };
+    let mut m = Merger { syntax, opts, out: MergeOutcome::default() };
+    let members = m.merge_level(&[], &o.members, x.members.clone(), y.members.clone());
+    let end = match syntax {
+        Syntax::Json => &a[a.trim_end().len()..],
+        Syntax::Yaml => "\n",
+    };
+    let mut out = m.out;
+    out.merged_code = format!("{}{end}", render(syntax, "", &members, [&x, &y, &o]));
+    out.suppressed = conflicts::split_suppressed(&mut out.conflicts, &opts.suppressed);
+    out
+}
+
+struct Merger<'m> {
+    syntax: Syntax,
+    opts: &'m MergeOptions,
+    out: MergeOutcome,
+}
+
+impl Merger<'_> {
+    /// Merge the members of the map at `at` (its keys from the top).
+    fn merge_level(&mut self, at: &[&str], o: &[Member], a: Vec<Member>, b: Vec<Member>) -> Vec<Member> {
+        let label = at.join(" > ");
+        merge_keyed(o, a, b, &label, |key, o, a, b| {
+            let path: Vec<&str> = at.iter().copied().chain([key]).collect();
+            if let (Some(x), Some(y)) = (a, b)
+                && nests(&path)
+                && let Some(merged) = self.merge_map(&path, o.unwrap_or(""), x, y)
+            {
+                return Some(merged);
+            }
+            self.conflict(&path, o, a, b)
+        })
+    }
+
+    /// A member both branches changed that holds a map, merged key by key.
+    fn merge_map(&mut self, at: &[&str], o: &str, a: &str, b: &str) -> Option<String> {
+        let ((head, x), (_, y)) = (Map::of_member(self.syntax, a)?, Map::of_member(self.syntax, b)?);
+        let o = if o.is_empty() { Some(Map::default()) } else { Map::of_member(self.syntax, o).map(|(_, o)| o) }?;
+        let members = self.merge_level(at, &o.members, x.members.clone(), y.members.clone());
+        Some(render(self.syntax, head, &members, [&x, &y, &o]))
+    }
+
+    /// A conflict on the member at `at`; its base version stays, or A's.
+    fn conflict(&mut self, at: &[&str], o: Option<&str>, a: Option<&str>, b: Option<&str>) -> Option<String> {
+        let unit = unit(at);
+        let what = format!("{} {}", unit.0, unit.1);
+        let (kind, message) = match (o, a, b) {
+            (None, _, _) => ("insert-insert", format!("Both branches added {what} differently")),
+            (Some(_), Some(_), Some(_)) => ("update-update", format!("Both branches changed {what} differently")),
+            _ => ("delete-update", format!("One branch removed {what} and the other changed it")),
+        };
+        let hashes = (a.map(content_hash), b.map(content_hash));
+        let conflict = Conflict::new(self.opts.path.as_deref(), kind, Some(&unit), hashes, message);
+        resolve(conflict, a, b, o.or(a), self.opts, &mut self.out)
+    }
+}
+
+/// Whether the member at `at` merges key by key: the maps of paths,
+/// operations and components.
+fn nests(at: &[&str]) -> bool {
+    matches!(
+        at,
+        ["paths" | "webhooks" | "components" | "definitions"] | ["paths" | "webhooks" | "components", _]
+    )
+}
+
+/// The unit the member at `at` is: an operation, a schema, ...
+fn unit(at: &[&str]) -> (String, String) {
+    let bare = |key: &str| key.trim_matches(['"', '\'']).to_string();
+    let (kind, name) = match at {
+        ["paths" | "webhooks", path, method] if METHODS.contains(method) => {
+            ("operation", format!("{} {}", method.to_uppercase(), bare(path)))
+        }
+        ["paths" | "webhooks", path, key] => ("path", format!("{} {}", bare(path), bare(key))),
+        ["paths" | "webhooks", path] => ("path", bare(path)),
+        ["components", "schemas", name] | ["definitions", name] => ("schema", bare(name)),
+        ["components", section, name] => ("component", format!("{}/{}", bare(section), bare(name))),
+        _ => ("key", at.iter().map(|k| bare(k)).collect::<Vec<_>>().join(" > ")),
+    };
+    (kind.to_string(), name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, a: &str, b: &str) -> MergeOutcome {
+        merge_openapi(base, a, b, &MergeOptions::default().with_path("openapi.yaml"))
+    }
+
+    fn units(out: &MergeOutcome) -> Vec<(&str, Option<&str>)> {
+        out.conflicts.iter().map(|c| (c.kind.as_str(), c.unit.as_deref())).collect()
+    }
+
+    const BASE: &str = "\
+openapi: 3.0.0
+info:
+  title: Pets
+paths:
+  /pets:
+    get:
+      summary: List pets
+components:
+  schemas:
+    Pet:
+      type: object
+";
+
+    #[test]
+    fn endpoints_and_schemas_from_both_branches_merge() {
+        let a = BASE.replace("components:", "  /owners:\n    get:\n      summary: List owners\ncomponents:");
+        let b = format!("{BASE}    Owner:\n      type: object\n").replace(
+            "      summary: List pets\n",
+            "      summary: List pets\n    post:\n      summary: Add a pet\n",
+        );
+        let out = merge(BASE, &a, &b);
+        assert!(out.conflicts.is_empty() && !out.textual, "{:?}", out.conflicts);
+        for part in ["  /pets:\n    get:\n      summary: List pets\n    post:\n", "  /owners:\n", "    Owner:\n"] {
+            assert!(out.merged_code.contains(part), "{part:?} missing from\n{}", out.merged_code);
+        }
+    }
+
+    #[test]
+    fn an_operation_both_changed_conflicts() {
+        let a = BASE.replace("List pets", "List all pets");
+        let b = BASE.replace("List pets", "List some pets").replace("title: Pets", "title: Pet store");
+        let out = merge(BASE, &a, &b);
+        assert_eq!(units(&out), [("update-update", Some("operation::GET /pets"))]);
+        assert!(out.merged_code.contains("summary: List pets") && out.merged_code.contains("title: Pet store"));
+    }
+
+    #[test]
+    fn a_removed_schema_the_other_changed_conflicts() {
+        let a = BASE.replace("    Pet:\n      type: object\n", "    Animal:\n      type: object\n");
+        let b = BASE.replace("      type: object\n", "      type: object\n      required: [name]\n");
+        let out = merge(BASE, &a, &b);
+        assert_eq!(units(&out), [("delete-update", Some("schema::Pet"))]);
+    }
+
+    #[test]
+    fn json_documents_merge_too() {
+        let base = "{\n  \"openapi\": \"3.0.0\",\n  \"paths\": {\n    \"/pets\": {}\n  }\n}\n";
+        let a = base.replace("\"/pets\": {}", "\"/pets\": {},\n    \"/owners\": {}");
+        let b = base.replace("\"openapi\": \"3.0.0\"", "\"openapi\": \"3.1.0\"");
+        let out = merge(base, &a, &b);
+        assert!(out.conflicts.is_empty(), "{:?}", out.conflicts);
+        assert_eq!(out.merged_code, a.replace("3.0.0", "3.1.0"));
+    }
+
+    #[test]
+    fn a_document_that_doesnt_parse_merges_textually() {
+        let out = merge("{\n  \"paths\": {}\n}\n", "{\n  \"paths\": {\n", "{\n  \"paths\": {},\n  \"info\": {}\n}\n");
+        assert!(out.textual);
+        assert_eq!(units(&out), [("textual", None)]);
+    }
+}
@@ -0,0 +1,54 @@
+//! parsers.rs
+//! A process-wide pool of tree-sitter parsers.
+//!
+//! A merge parses each version several times over (units, then member,
+//! body and import reconcilers), and creating a parser and setting its
+//! language is a large part of a small parse. Parsers are `Send` but not
+//! `Sync`, so each parse checks one out of the pool for its language, and
+//! puts it back afterwards: concurrent callers (servers, [`crate::merge_batch`])
+//! each get their own, and nobody pays construction twice.
+
+use std::sync::Mutex;
+
+use anyhow::{Result, anyhow};
+use tree_sitter::{Parser, Tree};
+
+use crate::ast::{AstLanguage, ts_language};
+
+/// Idle parsers kept per language; more are made on demand and dropped
+/// when the pool is full.
+const MAX_IDLE: usize = 64;
+
+/// Idle parsers, TypeScript, TSX, then the runtime grammars.
+static POOL: Mutex<Vec<Vec<Parser>>> = Mutex::new(Vec::new());
+
+fn slot(lang: AstLanguage) -> usize {
+    match lang {
+        AstLanguage::TypeScript => 0,
+        AstLanguage::Tsx => 1,
+        AstLanguage::Runtime(id) => 2 + usize::from(id),
+    }
+}
+
+/// Parse `code` with a pooled parser for `lang`.
+pub(crate) fn parse(code: &str, lang: AstLanguage) -> Result<Tree> {
+    let idle = POOL.lock().unwrap_or_else(|e| e.into_inner()).get_mut(slot(lang)).and_then(Vec::pop);
+    let mut parser = match idle {
+        Some(parser) => parser,
+        None => {
+            let mut parser = Parser::new();
+            parser.set_language(ts_language(lang)?).map_err(|e| anyhow!("failed to set the {lang:?} grammar: {e}"))?;
+            parser
+        }
+    };
+    let tree = parser.parse(code, None);
+    parser.reset();
+    let mut pool = POOL.lock().unwrap_or_else(|e| e.into_inner());
+    if pool.len() <= slot(lang) {
+        pool.resize_with(slot(lang) + 1, Vec::new);
+    }
+    if pool[slot(lang)].len() < MAX_IDLE {
+        pool[slot(lang)].push(parser);
+    }
+    tree.ok_or_else(|| anyhow!("tree-sitter parse returned None"))
+}
@@ -0,0 +1,686 @@
+//! policy.rs
+//! Per-path merge policy rules from `.sinkit.toml`.
+//!
+//! ```toml
+//! [[rule]]
+//! path = "src/billing/**"
+//! never_auto_merge = true          # both sides changed → file-level conflict
+//!
+//! [[rule]]
+//! path = "**/*.generated.ts"
+//! merge = "textual"                # no semantic merging
+//!
+//! [[rule]]
+//! path = "**/index.ts"
+//! merge = "barrel"                 # re-exports only, see `barrels`
+//! barrel_order = "sorted"          # by module path; default "keep"
+//!
+//! [[rule]]
+//! path = "**/package-lock.json"
+//! lockfile = "regenerate"          # conflict on any entry both changed;
+//!                                  # default "higher-version"
+//!
+//! [[rule]]
+//! path = "src/api/**"
+//! conflict_on_exported_delete = true
+//!
+//! [[rule]]
+//! path = "src/legacy/**"
+//! blank_lines = 2                  # around inserted units; default "auto"
+//!
+//! [[rule]]
+//! path = "src/proto/**"
+//! generated = "take-newer"         # generated files; default "textual"
+//!
+//! [[suppress]]
+//! id = "3f0c9a1e7b2d4c65"            # a conflict ID, see `Conflict`
+//! note = "vendored; upstream resolves it"
+//!
+//! [extensions]                       # on top of `.ts` and `.tsx`
+//! ".mts" = "typescript"
+//! ".ts.erb" = "typescript"
+//! ".gql" = "text"                    # no grammar: merged textually
+//! ".oas.yaml" = "openapi"            # by path, operation and schema
+//! ".cfg" = "properties"              # by key, like `.env` and `.ini`
+//!
+//! [limits]
+//! max_parse_bytes = 5_000_000        # larger files merge textually
+//! memory_limit = 2_000_000_000       # so do merges estimated above this
+//!
+//! [resolve]
+//! cmd = "./my-resolver"              # asked about every conflict left
+//!
+//! [grammars.elixir]                  # loaded at runtime, see `grammars`
+//! path = "grammars/libtree-sitter-elixir.so"
+//! extensions = [".ex", ".exs"]
+//! units = ["call"]
+//! query = "grammars/elixir-units.scm"   # instead of `units`
+//!
+//! [queries]                          # unit queries, see `queries`
+//! typescript = "tools/units.scm"
+//! ```
+//!
+//! Every rule whose glob matches a path applies; later rules override the
+//! settings they mention. The longest matching extension picks the adapter.
+//!
+//! A subdirectory (a package in a monorepo) can have a `.sinkit.toml` of its
+//! own, a profile for its subtree ([`Policy::with_profile`]). Like nested
+//! `.gitignore`s, its rule paths are relative to its directory, and its rules
+//! apply after those of the files above it; its extensions win over theirs
+//! for the same suffix. Suppressed IDs add up. `[limits]`, `[resolve]`,
+//! `[grammars]` and `[queries]` are read from the root file only.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+
+use crate::ast::AstLanguage;
+use crate::grammars::{GrammarSpec, load_grammar};
+use crate::lockfiles::LockfileFormat;
+use crate::queries::UnitQuery;
+use toml::{Table, Value};
+
+/// How files with a given extension are merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adapter {
+    /// Parsed with this grammar and merged unit by unit.
+    Ast(AstLanguage),
+    /// Merged textually: the one changed side, else a file-level conflict.
+    Text,
+    /// An OpenAPI document, merged by path, operation and schema.
+    OpenApi,
+    /// A `.env`, `.properties` or INI file, merged key by key.
+    Properties,
+}
+
+impl Adapter {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "typescript" => Some(Adapter::Ast(AstLanguage::TypeScript)),
+            "tsx" => Some(Adapter::Ast(AstLanguage::Tsx)),
+            "text" => Some(Adapter::Text),
+            "openapi" => Some(Adapter::OpenApi),
+            "properties" => Some(Adapter::Properties),
+            _ => None,
+        }
+    }
+}
+
+/// Extensions of the grammars compiled in (see the `lang-*` cargo features).
+#[cfg(feature = "lang-typescript")]
+const DEFAULT_EXTENSIONS: [(&str, Adapter); 2] =
+    [(".ts", Adapter::Ast(AstLanguage::TypeScript)), (".tsx", Adapter::Ast(AstLanguage::Tsx))];
+#[cfg(not(feature = "lang-typescript"))]
+const DEFAULT_EXTENSIONS: [(&str, Adapter); 0] = [];
+
+/// OpenAPI documents by their usual names.
+const OPENAPI_NAMES: [&str; 6] =
+    ["openapi.yaml", "openapi.yml", "openapi.json", "swagger.yaml", "swagger.yml", "swagger.json"];
+
+/// Key-value files by extension, besides `.env` and `.env.local`, ...
+const PROPERTIES_EXTENSIONS: [&str; 3] = [".env", ".properties", ".ini"];
+
+/// Settings for one file, combined from all matching rules.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilePolicy {
+    /// Never resolve a file both branches changed automatically.
+    pub never_auto_merge: bool,
+    /// Merge textually only: take the one changed side, else conflict.
+    pub textual: bool,
+    /// Merge as an OpenAPI document ([`Adapter::OpenApi`]).
+    pub openapi: bool,
+    /// Merge key by key ([`Adapter::Properties`]).
+    pub properties: bool,
+    /// Report a conflict instead of applying a deletion of an exported unit.
+    pub conflict_on_exported_delete: bool,
+    /// Blank lines between a unit the merge inserts and its neighbours.
+    pub spacing: Spacing,
+    /// How the file merges if it's generated (see [`crate::is_generated`]).
+    pub generated: GeneratedMerge,
+    /// Merge as a barrel file (re-exports only), its lines in this order.
+    pub barrel: Option<BarrelOrder>,
+    /// How a lockfile's entries both branches changed are settled.
+    pub lockfile: LockfileMerge,
+    /// Picks the file's units instead of the built-in collection (see
+    /// [`crate::UnitQuery`]).
+    pub unit_query: Option<Arc<UnitQuery>>,
+}
+
+/// How generated files both branches changed are merged: their conflicts
+/// mean nothing, the fix is to run the generator again.
+/// [Ignored regions](crate::regions) both changed merge the same way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GeneratedMerge {
+    /// Textually: the one changed side, else a file-level conflict.
+    #[default]
+    Textual,
+    /// Not at all: A's version stays, with a warning to regenerate the file.
+    Skip,
+    /// The version of the branch whose commit is newer (see
+    /// [`crate::MergeOptions::with_b_newer`]).
+    TakeNewer,
+    /// Like any other file.
+    Semantic,
+}
+
+/// Where the re-exports of a merged barrel file go.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BarrelOrder {
+    /// Where the branches put them: A's order, with B's additions after the
+    /// line they follow in B.
+    #[default]
+    Keep,
+    /// By module path, the names each one re-exports sorted too.
+    Sorted,
+}
+
+/// How lockfiles (see [`crate::LockfileFormat`]) merge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockfileMerge {
+    /// Entry by entry; an entry both branches changed gets the higher
+    /// version of the two.
+    #[default]
+    HigherVersion,
+    /// Entry by entry; an entry both branches changed is a conflict, to
+    /// settle by regenerating the lockfile.
+    Regenerate,
+    /// Like any text file.
+    Textual,
+}
+
+/// Blank lines around inserted units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Spacing {
+    /// Like prettier leaves hand-written code: a blank line around
+    /// declarations, none within a run of one-line statements of the same
+    /// kind (`import`s, `const x = 1;`, type aliases).
+    #[default]
+    Auto,
+    /// Always this many.
+    Lines(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: String,
+    never_auto_merge: Option<bool>,
+    textual: Option<bool>,
+    barrel: Option<bool>,
+    barrel_order: Option<BarrelOrder>,
+    conflict_on_exported_delete: Option<bool>,
+    spacing: Option<Spacing>,
+    generated: Option<GeneratedMerge>,
+    lockfile: Option<LockfileMerge>,
+}
+
+/// The `[[rule]]` and `[[suppress]]` lists and `[extensions]` table of a
+/// `.sinkit.toml`, with the profiles of subdirectories.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+    suppressed: Vec<String>,
+    extensions: Vec<(String, Adapter)>,
+    max_parse_bytes: Option<usize>,
+    memory_limit: Option<usize>,
+    resolve_cmd: Option<String>,
+    grammars: Vec<GrammarSpec>,
+    /// (language, query file) pairs, until [`Policy::with_grammars`]
+    /// compiles them into `unit_queries`.
+    query_files: Vec<(String, String)>,
+    unit_queries: Vec<Arc<UnitQuery>>,
+    /// Profiles of subdirectories (repo-relative), shallowest first.
+    profiles: Vec<(String, Policy)>,
+}
+
+impl Policy {
+    /// Read `path`; a missing file is an empty policy.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).with_context(|| format!("reading {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    /// Parse the policy out of a `.sinkit.toml` document (other sections are ignored).
+    pub fn parse(text: &str) -> Result<Self> {
+        Self::from_table(&toml::from_str(text)?)
+    }
+
+    pub(crate) fn from_table(doc: &Table) -> Result<Self> {
+        let mut rules = Vec::new();
+        let entries = match doc.get("rule") {
+            None => &[][..],
+            Some(v) => v.as_array().context("`rule` must be an array of tables ([[rule]])")?.as_slice(),
+        };
+        for (i, entry) in entries.iter().enumerate() {
+            let t = entry.as_table().context("`rule` must be an array of tables ([[rule]])")?;
+            let flag = |key: &str| -> Result<Option<bool>> {
+                t.get(key)
+                    .map(|v| v.as_bool().with_context(|| format!("rule #{}: `{key}` must be a boolean", i + 1)))
+                    .transpose()
+            };
+            let (textual, barrel) = match t.get("merge").map(Value::as_str) {
+                None => (None, None),
+                Some(Some("textual")) => (Some(true), Some(false)),
+                Some(Some("semantic")) => (Some(false), Some(false)),
+                Some(Some("barrel")) => (Some(false), Some(true)),
+                Some(_) => bail!("rule #{}: `merge` must be \"semantic\", \"textual\" or \"barrel\"", i + 1),
+            };
+            let barrel_order = match t.get("barrel_order").map(Value::as_str) {
+                None => None,
+                Some(Some("keep")) => Some(BarrelOrder::Keep),
+                Some(Some("sorted")) => Some(BarrelOrder::Sorted),
+                Some(_) => bail!("rule #{}: `barrel_order` must be \"keep\" or \"sorted\"", i + 1),
+            };
+            let spacing = match t.get("blank_lines") {
+                None => None,
+                Some(v) if v.as_str() == Some("auto") => Some(Spacing::Auto),
+                Some(v) => match v.as_integer().and_then(|n| usize::try_from(n).ok()) {
+                    Some(n) => Some(Spacing::Lines(n)),
+                    None => bail!("rule #{}: `blank_lines` must be \"auto\" or a non-negative integer", i + 1),
+                },
+            };
+            let generated = match t.get("generated").map(Value::as_str) {
+                None => None,
+                Some(Some("textual")) => Some(GeneratedMerge::Textual),
+                Some(Some("skip")) => Some(GeneratedMerge::Skip),
+                Some(Some("take-newer")) => Some(GeneratedMerge::TakeNewer),
+                Some(Some("semantic")) => Some(GeneratedMerge::Semantic),
+                Some(_) => bail!(
+                    "rule #{}: `generated` must be \"textual\", \"skip\", \"take-newer\" or \"semantic\"",
+                    i + 1
+                ),
+            };
+            let lockfile = match t.get("lockfile").map(Value::as_str) {
+                None => None,
+                Some(Some("higher-version")) => Some(LockfileMerge::HigherVersion),
+                Some(Some("regenerate")) => Some(LockfileMerge::Regenerate),
+                Some(Some("textual")) => Some(LockfileMerge::Textual),
+                Some(_) => bail!(
+                    "rule #{}: `lockfile` must be \"higher-version\", \"regenerate\" or \"textual\"",
+                    i + 1
+                ),
+            };
+            rules.push(Rule {
+                glob: t
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .with_context(|| format!("rule #{}: missing `path`", i + 1))?
+                    .to_string(),
+                never_auto_merge: flag("never_auto_merge")?,
+                textual,
+                barrel,
+                barrel_order,
+                conflict_on_exported_delete: flag("conflict_on_exported_delete")?,
+                spacing,
+                generated,
+                lockfile,
+            });
+        }
+        let mut suppressed = Vec::new();
+        let entries = match doc.get("suppress") {
+            None => &[][..],
+            Some(v) => v.as_array().context("`suppress` must be an array of tables ([[suppress]])")?.as_slice(),
+        };
+        for (i, entry) in entries.iter().enumerate() {
+            let id = entry
+                .as_table()
+                .and_then(|t| t.get("id"))
+                .and_then(Value::as_str)
+                .with_context(|| format!("suppress #{}: missing `id`", i + 1))?;
+            suppressed.push(id.to_string());
+        }
+        let mut extensions = Vec::new();
+        if let Some(v) = doc.get("extensions") {
+            let t = v.as_table().context("`extensions` must be a table ([extensions])")?;
+            for (ext, adapter) in t {
+                if !ext.starts_with('.') {
+                    bail!("extensions: `{ext}` must start with a dot");
+                }
+                let adapter = adapter.as_str().and_then(Adapter::parse).with_context(|| {
+                    format!("extensions: `{ext}` must map to \"typescript\", \"tsx\", \"text\", \"openapi\" or \"properties\"")
+                })?;
+                extensions.push((ext.clone(), adapter));
+            }
+        }
+        let (mut max_parse_bytes, mut memory_limit) = (None, None);
+        if let Some(v) = doc.get("limits") {
+            let t = v.as_table().context("`limits` must be a table ([limits])")?;
+            let bytes = |key: &str| -> Result<Option<usize>> {
+                t.get(key)
+                    .map(|v| {
+                        v.as_integer()
+                            .and_then(|i| usize::try_from(i).ok())
+                            .with_context(|| format!("limits: `{key}` must be a non-negative integer"))
+                    })
+                    .transpose()
+            };
+            max_parse_bytes = bytes("max_parse_bytes")?;
+            memory_limit = bytes("memory_limit")?;
+        }
+        let mut resolve_cmd = None;
+        if let Some(v) = doc.get("resolve") {
+            let t = v.as_table().context("`resolve` must be a table ([resolve])")?;
+            resolve_cmd = t
+                .get("cmd")
+                .map(|v| v.as_str().map(str::to_string).context("resolve: `cmd` must be a string"))
+                .transpose()?;
+        }
+        let mut grammars = Vec::new();
+        let mut query_files = Vec::new();
+        if let Some(v) = doc.get("queries") {
+            let t = v.as_table().context("`queries` must be a table ([queries])")?;
+            for (lang, path) in t {
+                let path = path.as_str().with_context(|| format!("queries: `{lang}` must be a file path"))?;
+                query_files.push((lang.clone(), path.to_string()));
+            }
+        }
+        if let Some(v) = doc.get("grammars") {
+            let t = v.as_table().context("`grammars` must be a table ([grammars.<name>])")?;
+            for (name, spec) in t {
+                let spec = spec.as_table().with_context(|| format!("grammars: `{name}` must be a table"))?;
+                let string = |key: &str| -> Result<Option<String>> {
+                    spec.get(key)
+                        .map(|v| v.as_str().map(str::to_string).with_context(|| format!("grammars.{name}: `{key}` must be a string")))
+                        .transpose()
+                };
+                let strings = |key: &str| -> Result<Vec<String>> {
+                    let Some(v) = spec.get(key) else { return Ok(Vec::new()) };
+                    let items = v.as_array().map(|a| a.iter().map(Value::as_str).collect::<Option<Vec<_>>>());
+                    let items = items.flatten().with_context(|| format!("grammars.{name}: `{key}` must be a list of strings"))?;
+                    Ok(items.into_iter().map(str::to_string).collect())
+                };
+                if let Some(query) = string("query")? {
+                    query_files.push((name.clone(), query));
+                }
+                let extensions = strings("extensions")?;
+                if let Some(ext) = extensions.iter().find(|e| !e.starts_with('.')) {
+                    bail!("grammars.{name}: extension `{ext}` must start with a dot");
+                }
+                grammars.push(GrammarSpec {
+                    name: name.clone(),
+                    path: string("path")?.with_context(|| format!("grammars.{name}: missing `path`"))?,
+                    extensions,
+                    units: strings("units")?,
+                    symbol: string("symbol")?,
+                });
+            }
+        }
+        Ok(Policy {
+            rules,
+            suppressed,
+            extensions,
+            max_parse_bytes,
+            memory_limit,
+            resolve_cmd,
+            grammars,
+            query_files,
+            unit_queries: Vec::new(),
+            profiles: Vec::new(),
+        })
+    }
+
+    /// Load the `[grammars]` and compile the `[queries]` (paths relative to
+    /// `root`, the directory of the `.sinkit.toml`), and merge the grammars'
+    /// extensions with them. Without a `root` (a `.sinkit.toml` read from a
+    /// commit, whose files aren't on disk), relative paths are an error.
+    pub fn with_grammars(mut self, root: Option<&Path>) -> Result<Self> {
+        let mut paths = self.grammars.iter().map(|g| &g.path).chain(self.query_files.iter().map(|(_, path)| path));
+        if root.is_none()
+            && let Some(path) = paths.find(|path| Path::new(path).is_relative())
+        {
+            bail!("{path}: a relative grammar or query path needs a work tree to resolve it in; make it absolute");
+        }
+        let root = root.unwrap_or(Path::new(""));
+        let mut loaded = Vec::new();
+        for spec in &self.grammars {
+            let lang = load_grammar(spec, root)?;
+            self.extensions.extend(spec.extensions.iter().map(|ext| (ext.clone(), Adapter::Ast(lang))));
+            loaded.push((spec.name.as_str(), lang));
+        }
+        for (name, path) in &self.query_files {
+            let lang = match Adapter::parse(name) {
+                Some(Adapter::Ast(lang)) => lang,
+                _ => match loaded.iter().find(|(n, _)| n == name) {
+                    Some(&(_, lang)) => lang,
+                    None => bail!("queries: no language `{name}` (\"typescript\", \"tsx\" or a [grammars] name)"),
+                },
+            };
+            let source = std::fs::read_to_string(root.join(path)).with_context(|| format!("reading {path}"))?;
+            let query = UnitQuery::new(lang, &source).with_context(|| format!("query {path}"))?;
+            self.unit_queries.push(Arc::new(query));
+        }
+        Ok(self)
+    }
+
+    /// The grammars to load at runtime (see [`Policy::with_grammars`]).
+    pub fn grammars(&self) -> &[GrammarSpec] {
+        &self.grammars
+    }
+
+    /// Apply `profile`, the `.sinkit.toml` of the repo-relative directory
+    /// `dir`, to the paths under it, on top of this policy (and the profiles
+    /// of directories above `dir`).
+    pub fn with_profile(mut self, dir: &str, mut profile: Policy) -> Self {
+        let dir = dir.trim_matches('/');
+        if dir.is_empty() {
+            return self;
+        }
+        self.suppressed.append(&mut profile.suppressed);
+        for (sub, nested) in std::mem::take(&mut profile.profiles) {
+            self.profiles.push((format!("{dir}/{sub}"), nested));
+        }
+        self.profiles.push((dir.to_string(), profile));
+        self.profiles.sort_by_key(|(dir, _)| dir.matches('/').count());
+        self
+    }
+
+    /// This policy and the profiles of directories above `path`, shallowest
+    /// first, each with the path relative to its directory.
+    fn levels<'p>(&'p self, path: &'p str) -> impl DoubleEndedIterator<Item = (&'p Policy, &'p str)> {
+        let profiles = self.profiles.iter().filter_map(move |(dir, profile)| {
+            Some((profile, path.strip_prefix(dir.as_str())?.strip_prefix('/')?))
+        });
+        std::iter::once((self, path)).chain(profiles)
+    }
+
+    /// The adapter for a repo-relative `path`, or `None` if sinkit doesn't
+    /// handle its extension. Unless an extension says otherwise, files
+    /// named `openapi.yaml`, `swagger.json`, ... are OpenAPI documents,
+    /// `.env`, `.properties` and `.ini` files key-value files, and lockfiles
+    /// text (merged as lockfiles, see [`crate::LockfileFormat`]).
+    pub fn adapter_for(&self, path: &str) -> Option<Adapter> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let configured = self.levels(path).rev().flat_map(|(p, _)| p.extensions.iter().map(|(e, a)| (e.as_str(), *a)));
+        // configured entries come first (the deepest profile's first), so
+        // they win ties with the defaults
+        let mut best: Option<(&str, Adapter)> = None;
+        for (ext, adapter) in configured.chain(DEFAULT_EXTENSIONS) {
+            if name.ends_with(ext) && name.len() > ext.len() && best.is_none_or(|(b, _)| ext.len() > b.len()) {
+                best = Some((ext, adapter));
+            }
+        }
+        best.map(|(_, adapter)| adapter)
+            .or_else(|| OPENAPI_NAMES.contains(&name).then_some(Adapter::OpenApi))
+            .or_else(|| {
+                let ext = PROPERTIES_EXTENSIONS.iter().any(|e| name.ends_with(e) && name.len() > e.len());
+                (ext || name == ".env" || name.starts_with(".env.")).then_some(Adapter::Properties)
+            })
+            .or_else(|| LockfileFormat::for_path(name).map(|_| Adapter::Text))
+    }
+
+    /// Size above which files merge textually (see
+    /// [`crate::MergeOptions::with_max_parse_bytes`]).
+    pub fn max_parse_bytes(&self) -> Option<usize> {
+        self.max_parse_bytes
+    }
+
+    /// Memory a merge may take (see [`crate::MergeOptions::with_memory_limit`]).
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    /// The command asked to resolve conflicts (see [`crate::CommandResolver`]).
+    pub fn resolve_cmd(&self) -> Option<&str> {
+        self.resolve_cmd.as_deref()
+    }
+
+    /// Whether the policy runs code of its own: a `[resolve] cmd`, or
+    /// `[grammars]` (shared libraries, loaded into the process).
+    pub fn has_commands(&self) -> bool {
+        self.resolve_cmd.is_some() || !self.grammars.is_empty()
+    }
+
+    /// The policy without its commands (see [`Policy::has_commands`]), for a
+    /// `.sinkit.toml` that doesn't come from the user: one read from a
+    /// branch can be pushed by anyone.
+    pub fn without_commands(mut self) -> Self {
+        self.resolve_cmd = None;
+        // queries for the bundled languages stay; those of the grammars go
+        let grammars = std::mem::take(&mut self.grammars);
+        self.query_files.retain(|(lang, _)| !grammars.iter().any(|g| &g.name == lang));
+        self
+    }
+
+    /// IDs of acknowledged conflicts (see [`crate::Conflict`]).
+    pub fn suppressed(&self) -> &[String] {
+        &self.suppressed
+    }
+
+    /// Settings for a repo-relative `path`; files mapped to
+    /// [`Adapter::Text`] merge textually, to [`Adapter::OpenApi`] as
+    /// OpenAPI documents, to [`Adapter::Properties`] key by key.
+    pub fn for_path(&self, path: &str) -> FilePolicy {
+        let adapter = self.adapter_for(path);
+        let mut out = FilePolicy {
+            textual: adapter == Some(Adapter::Text),
+            openapi: adapter == Some(Adapter::OpenApi),
+            properties: adapter == Some(Adapter::Properties),
+            ..FilePolicy::default()
+        };
+        let rules = self.levels(path).flat_map(|(p, rel)| p.rules.iter().filter(move |r| glob_match(&r.glob, rel)));
+        let (mut barrel, mut order) = (false, BarrelOrder::default());
+        for rule in rules {
+            out.never_auto_merge = rule.never_auto_merge.unwrap_or(out.never_auto_merge);
+            out.textual = rule.textual.unwrap_or(out.textual);
+            barrel = rule.barrel.unwrap_or(barrel);
+            order = rule.barrel_order.unwrap_or(order);
+            out.conflict_on_exported_delete =
+                rule.conflict_on_exported_delete.unwrap_or(out.conflict_on_exported_delete);
+            out.spacing = rule.spacing.unwrap_or(out.spacing);
+            out.generated = rule.generated.unwrap_or(out.generated);
+            out.lockfile = rule.lockfile.unwrap_or(out.lockfile);
+        }
+        out.barrel = barrel.then_some(order);
+        if let Some(Adapter::Ast(lang)) = self.adapter_for(path) {
+            out.unit_query = self.unit_queries.iter().rev().find(|q| q.lang() == lang).cloned();
+        }
+        out
+    }
+}
+
+/// Gitignore-flavoured glob: `*` and `?` stay within a path segment, `**`
+/// spans segments. A pattern without `/` matches the file name anywhere.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    if !pattern.contains('/') {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        return match_from(pattern.as_bytes(), name.as_bytes());
+    }
+    match_from(pattern.trim_start_matches('/').as_bytes(), path.as_bytes())
+}
+
+fn match_from(p: &[u8], s: &[u8]) -> bool {
+    match p {
+        [] => s.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => (0..=s.len()).any(|i| (i == 0 || s[i - 1] == b'/') && match_from(rest, &s[i..])),
+        [b'*', b'*', rest @ ..] => (0..=s.len()).any(|i| match_from(rest, &s[i..])),
+        [b'*', rest @ ..] => (0..=s.len())
+            .take_while(|&i| i == 0 || s[i - 1] != b'/')
+            .any(|i| match_from(rest, &s[i..])),
+        [b'?', rest @ ..] => s.first().is_some_and(|&c| c != b'/') && match_from(rest, &s[1..]),
+        [c, rest @ ..] => s.first() == Some(c) && match_from(rest, &s[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_grammar_paths_need_a_root() {
+        let policy = Policy::parse("[grammars.elixir]\npath = \"grammars/elixir.so\"\nextensions = [\".ex\"]\n").unwrap();
+        let err = policy.with_grammars(None).unwrap_err().to_string();
+        assert!(err.contains("grammars/elixir.so") && err.contains("work tree"), "{err}");
+    }
+
+    #[test]
+    fn relative_query_paths_need_a_root() {
+        let policy = Policy::parse("[queries]\ntypescript = \"units.scm\"\n").unwrap();
+        assert!(policy.with_grammars(None).is_err());
+    }
+
+    #[test]
+    fn grammars_resolve_against_the_root() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let policy = Policy::parse("[grammars.elixir]\npath = \"no/such/elixir.so\"\nextensions = [\".ex\"]\n").unwrap();
+        let err = format!("{:#}", policy.with_grammars(Some(root)).unwrap_err());
+        assert!(err.contains(&root.join("no/such/elixir.so").display().to_string()), "{err}");
+    }
+
+    #[test]
+    fn strings_unquote_and_unescape() {
+        let policy = Policy::parse(concat!(
+            "[resolve]\n",
+            "cmd = \"printf \\\"%s\\\\n\\\" caf\\u00e9\\t# not a comment\"\n",
+            "[queries]\n",
+            "\"type script\" = 'C:\\queries\\units.scm'\n",
+        ))
+        .unwrap();
+        assert_eq!(policy.resolve_cmd.as_deref(), Some("printf \"%s\\n\" café\t# not a comment"));
+        assert_eq!(policy.query_files, [("type script".to_string(), "C:\\queries\\units.scm".to_string())]);
+    }
+
+    #[test]
+    fn arrays_and_tables() {
+        let policy = Policy::parse(concat!(
+            "grammars.elixir.path = \"/opt/elixir.so\"\n",
+            "[grammars.lua]\n",
+            "path = \"/opt/lua.so\"\n",
+            "extensions = [\n  \".lua\", # scripts\n  '.luau',\n]\n",
+            "units = []\n",
+            "[[rule]]\npath = \"src/billing/**\"\nnever_auto_merge = true\n",
+            "[[rule]]\npath = \"**/*.generated.ts\"\nmerge = \"textual\"\nblank_lines = 2\n",
+            "[[suppress]]\nid = \"0123456789abcdef\"\n",
+        ))
+        .unwrap();
+        let grammars: Vec<_> =
+            policy.grammars.iter().map(|g| (g.name.as_str(), g.path.as_str(), g.extensions.clone())).collect();
+        assert_eq!(
+            grammars,
+            [("elixir", "/opt/elixir.so", vec![]), ("lua", "/opt/lua.so", vec![".lua".to_string(), ".luau".to_string()])]
+        );
+        assert_eq!(policy.rules.len(), 2);
+        assert_eq!((policy.rules[0].glob.as_str(), policy.rules[0].never_auto_merge), ("src/billing/**", Some(true)));
+        assert_eq!((policy.rules[1].textual, policy.rules[1].spacing), (Some(true), Some(Spacing::Lines(2))));
+        assert_eq!(policy.suppressed, ["0123456789abcdef"]);
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        for text in [
+            "[resolve]\ncmd = \"unterminated\n",
+            "[resolve]\ncmd = 'a'\ncmd = 'b'\n",
+            "[limits]\nmax_parse_bytes = 1.5\n",
+            "[[rule]]\npath = \"a\"\nblank_lines = -1\n",
+            "rule = \"src/**\"\n",
+            "[[rule]]\nmerge = \"textual\"\n",
+            "[extensions]\nts = \"typescript\"\n",
+            "[grammars.lua]\npath = \"/opt/lua.so\"\nextensions = [\".lua\", 1]\n",
+            "[rule\npath = \"a\"\n",
+        ] {
+            assert!(Policy::parse(text).is_err(), "accepted {text:?}");
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! properties.rs
+//! Merging `.env`, Java `.properties` and INI files key by key.
+//!
+//! Each `KEY=value` line (`KEY: value` and `KEY value` in `.properties`,
+//! `export KEY=value` in `.env`) is a unit keyed by its key, within its
+//! `[section]` in INI files, along with the comments and blank lines
+//! directly above it. Keys both branches added merge as a union; a key whose
+//! value both changed differently, or that one removed while the other
+//! changed it, is a conflict of its own (`update-update`, `insert-insert` or
+//! `delete-update` on `key::NAME`, `key::section.NAME` in a section) and
+//! keeps its base value (A's, if both added it). Comments and blank lines
+//! stay where base has them unless a branch changed them. Values may run
+//! over several lines with a trailing `\` or an open `"` quote. A file with
+//! a key twice in one section merges textually.
+
+use crate::compose::{MergeOptions, MergeOutcome, textual_merge};
+use crate::conflicts::{self, Conflict};
+use crate::hash::content_hash;
+use crate::keyed::{merge_keyed, resolve};
+use crate::members::{Member, Side, pick_frame};
+
+/// Merge three versions of a key-value file.
+pub(crate) fn merge_properties(base: &str, a: &str, b: &str, opts: &MergeOptions) -> MergeOutcome {
+    if a == b || b == base || a == base {
+        let merged_code = if a == base { b } else { a };
+        return MergeOutcome { merged_code: merged_code.to_string(), ..MergeOutcome::default() };
+    }
+    let (Some(o), Some(x), Some(y)) = (parse(base), parse(a), parse(b)) else {
+        return textual_merge(base, a, b, "Key-value file with a key twice; both branches changed it", opts);
+    };
+    let mut out = MergeOutcome::default();
+    let members = merge_units(&o.members, x.members.clone(), y.members.clone(), "", opts, &mut out);
+    // comments at the end aren't worth a conflict: A's, if both changed them
+    let trailer = match pick_frame(&o.trailer, &x.trailer, &y.trailer) {
+        Some(Side::B) => &y.trailer,
+        _ => &x.trailer,
+    };
+    let mut lines: Vec<&str> = members.iter().map(|m| m.text.as_str()).collect();
+    if !trailer.is_empty() {
+        lines.push(trailer);
+    }
+    let mut merged = lines.join("\n");
+    if a.ends_with('\n') {
+        merged.push('\n');
+    }
+    out.merged_code = if a.contains("\r\n") { merged.replace('\n', "\r\n") } else { merged };
+    out.suppressed = conflicts::split_suppressed(&mut out.conflicts, &opts.suppressed);
+    out
+}
+
+/// Merge keys (or, at the top, keys and sections) of the section `section`.
+fn merge_units(
+    base: &[Member],
+    a: Vec<Member>,
+    b: Vec<Member>,
+    section: &str,
+    opts: &MergeOptions,
+    out: &mut MergeOutcome,
+) -> Vec<Member> {
+    merge_keyed(base, a, b, section, |key, o, a, b| {
+        if let (Some(x), Some(y)) = (a, b) {
+            let merged = match key.strip_prefix('[') {
+                Some(_) => merge_section(key, o.unwrap_or(""), x, y, opts, out),
+                None => merge_line(o.unwrap_or(""), x, y),
+            };
+            if merged.is_some() {
+                return merged;
+            }
+        }
+        let unit = match key.strip_prefix('[') {
+            Some(name) => ("section".to_string(), name.trim_end_matches(']').to_string()),
+            None if section.is_empty() => ("key".to_string(), key.to_string()),
+            None => ("key".to_string(), format!("{section}.{key}")),
+        };
+        let what = format!("{} {}", unit.0, unit.1);
+        let (kind, message) = match (o, a, b) {
+            (None, _, _) => ("insert-insert", format!("Both branches added {what} differently")),
+            (Some(_), Some(_), Some(_)) => ("update-update", format!("Both branches changed {what} differently")),
+            _ => ("delete-update", format!("One branch removed {what} and the other changed it")),
+        };
+        let hashes = (a.map(content_hash), b.map(content_hash));
+        let conflict = Conflict::new(opts.path.as_deref(), kind, Some(&unit), hashes, message);
+        resolve(conflict, a, b, o.or(a), opts, out)
+    })
+}
+
+/// A key both branches changed: their changes, if one changed its comments
+/// and the other its value.
+fn merge_line(base: &str, a: &str, b: &str) -> Option<String> {
+    let (co, lo) = split_trivia(base);
+    let ((ca, la), (cb, lb)) = (split_trivia(a), split_trivia(b));
+    let comments = match pick_frame(co, ca, cb)? {
+        Side::A => ca,
+        Side::B => cb,
+    };
+    let line = match pick_frame(lo, la, lb)? {
+        Side::A => la,
+        Side::B => lb,
+    };
+    Some(format!("{comments}{line}"))
+}
+
+/// A section both branches changed, merged key by key.
+fn merge_section(key: &str, base: &str, a: &str, b: &str, opts: &MergeOptions, out: &mut MergeOutcome) -> Option<String> {
+    let (ho, o) = if base.is_empty() { ("", Vec::new()) } else { section(base)? };
+    let ((ha, x), (hb, y)) = (section(a)?, section(b)?);
+    let head = match pick_frame(ho, ha, hb)? {
+        Side::A => ha,
+        Side::B => hb,
+    };
+    let name = key.trim_start_matches('[').trim_end_matches(']');
+    let members = merge_units(&o, x, y, name, opts, out);
+    let body: Vec<&str> = members.iter().map(|m| m.text.as_str()).collect();
+    Some(if body.is_empty() { head.to_string() } else { format!("{head}\n{}", body.join("\n")) })
+}
+
+/// A unit's comments and blank lines (with their line breaks), and its line.
+fn split_trivia(unit: &str) -> (&str, &str) {
+    let mut at = 0;
+    for line in unit.split_inclusive('\n') {
+        if !is_trivia(line) {
+            break;
+        }
+        at += line.len();
+    }
+    unit.split_at(at)
+}
+
+/// A section unit's head (comments and `[name]` line) and its keys.
+fn section(unit: &str) -> Option<(&str, Vec<Member>)> {
+    let (trivia, rest) = split_trivia(unit);
+    let header_end = trivia.len() + rest.find('\n').unwrap_or(rest.len());
+    let body: Vec<&str> = unit[header_end..].lines().skip(1).collect();
+    let (members, trailer) = units(&body)?;
+    trailer.is_empty().then_some((&unit[..header_end], members))
+}
+
+struct Parsed {
+    /// Keys above the first section, then the sections.
+    members: Vec<Member>,
+    /// Comments and blank lines below the last key.
+    trailer: String,
+}
+
+fn parse(code: &str) -> Option<Parsed> {
+    let lines: Vec<&str> = code.lines().collect();
+    let headers: Vec<usize> = (0..lines.len()).filter(|&i| is_header(lines[i])).collect();
+    let first = headers.first().copied().unwrap_or(lines.len());
+    let (mut members, mut pending) = units(&lines[..first])?;
+    for (n, &start) in headers.iter().enumerate() {
+        let end = headers.get(n + 1).copied().unwrap_or(lines.len());
+        let (keys, trailer) = units(&lines[start + 1..end])?;
+        let key = lines[start].trim().to_string();
+        if members.iter().any(|m| m.key == key) {
+            return None;
+        }
+        // comments below a section's last key go with the next header
+        pending.push(lines[start]);
+        pending.extend(keys.iter().map(|m| m.text.as_str()));
+        members.push(Member { key, text: pending.join("\n") });
+        pending = trailer;
+    }
+    Some(Parsed { members, trailer: pending.join("\n") })
+}
+
+/// The keys in `lines`, each with the comments and blank lines above it,
+/// and the lines below the last one.
+fn units<'c>(lines: &[&'c str]) -> Option<(Vec<Member>, Vec<&'c str>)> {
+    let mut members: Vec<Member> = Vec::new();
+    let mut pending: Vec<&str> = Vec::new();
+    // the last key's value goes on: after a `\`, or in an open quote
+    let (mut escaped, mut quoted) = (false, false);
+    for &line in lines {
+        if let Some(last) = members.last_mut().filter(|_| escaped || quoted) {
+            last.text.push('\n');
+            last.text.push_str(line);
+            if quoted {
+                quoted = quotes(line).is_multiple_of(2);
+            } else {
+                escaped = ends_escaped(line);
+            }
+            continue;
+        }
+        if is_trivia(line) {
+            pending.push(line);
+            continue;
+        }
+        let key = key_of(line);
+        if members.iter().any(|m| m.key == key) {
+            return None;
+        }
+        pending.push(line);
+        members.push(Member { key, text: pending.join("\n") });
+        pending.clear();
+        escaped = ends_escaped(line);
+        let value = line.split_once(['=', ':']).map_or("", |(_, v)| v.trim_start());
+        quoted = !escaped && value.starts_with('"') && quotes(value) % 2 == 1;
+    }
+    Some((members, pending))
+}
+
+/// Whether `line` ends in an odd number of backslashes.
+fn ends_escaped(line: &str) -> bool {
+    (line.len() - line.trim_end_matches('\\').len()) % 2 == 1
+}
+
+/// The double quotes in `text` that aren't escaped.
+fn quotes(text: &str) -> usize {
+    text.matches('"').count() - text.matches("\\\"").count()
+}
+
+fn is_trivia(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with(['#', ';', '!'])
+}
+
+fn is_header(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('[') && line.ends_with(']')
+}
+
+/// The key of a `KEY=value` (`KEY: value`, `KEY value`) line.
+fn key_of(line: &str) -> String {
+    let line = line.trim_start();
+    let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+    let end = line.find(['=', ':', ' ', '\t']).unwrap_or(line.len());
+    line[..end].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, a: &str, b: &str) -> MergeOutcome {
+        merge_properties(base, a, b, &MergeOptions::default().with_path(".env"))
+    }
+
+    fn units(out: &MergeOutcome) -> Vec<(&str, Option<&str>)> {
+        out.conflicts.iter().map(|c| (c.kind.as_str(), c.unit.as_deref())).collect()
+    }
+
+    #[test]
+    fn keys_added_on_both_branches_merge() {
+        let out = merge("A=1\nB=2\n", "A=1\nB=2\nC=3\n", "export D=4\nA=1\nB=2\n");
+        assert!(out.conflicts.is_empty() && !out.textual, "{:?}", out.conflicts);
+        assert_eq!(out.merged_code, "export D=4\nA=1\nB=2\nC=3\n");
+    }
+
+    #[test]
+    fn a_comment_and_a_value_change_merge() {
+        let out = merge("# port\nPORT=80\n", "# the port to serve on\nPORT=80\n", "# port\nPORT=8080\n");
+        assert_eq!(out.merged_code, "# the port to serve on\nPORT=8080\n");
+    }
+
+    #[test]
+    fn divergent_values_conflict_and_keep_base() {
+        let out = merge("HOST=a\nPORT=80\n", "HOST=b\nPORT=80\n", "HOST=c\nPORT=81\n");
+        assert_eq!(units(&out), [("update-update", Some("key::HOST"))]);
+        assert_eq!(out.merged_code, "HOST=a\nPORT=81\n");
+        let out = merge("HOST=a\n", "", "HOST=c\n");
+        assert_eq!(units(&out), [("delete-update", Some("key::HOST"))]);
+    }
+
+    #[test]
+    fn ini_sections_merge_key_by_key() {
+        let base = "[db]\nhost = a\n\n[cache]\nttl = 1\n";
+        let a = "[db]\nhost = a\nport = 5432\n\n[cache]\nttl = 2\n";
+        let b = "[db]\nhost = b\n\n[cache]\nttl = 3\n\n[log]\nlevel = info\n";
+        let out = merge(base, a, b);
+        assert_eq!(units(&out), [("update-update", Some("key::cache.ttl"))]);
+        assert_eq!(out.merged_code, "[db]\nhost = b\nport = 5432\n\n[cache]\nttl = 1\n\n[log]\nlevel = info\n");
+    }
+
+    #[test]
+    fn continued_values_and_line_endings() {
+        let base = "a = one \\\n  two\r\nb = \"x\r\ny\"\r\n";
+        let a = "a = one \\\n  three\r\nb = \"x\r\ny\"\r\n";
+        let b = "a = one \\\n  two\r\nb = \"x\r\ny\"\r\nc = 3\r\n";
+        let out = merge(base, a, b);
+        assert!(out.conflicts.is_empty(), "{:?}", out.conflicts);
+        assert_eq!(out.merged_code, "a = one \\\r\n  three\r\nb = \"x\r\ny\"\r\nc = 3\r\n");
+    }
+
+    #[test]
+    fn a_repeated_key_merges_textually() {
+        let out = merge("A=1\nA=2\n", "A=1\nA=3\n", "A=1\nA=2\nB=1\n");
+        assert!(out.textual);
+        assert_eq!(units(&out), [("textual", None)]);
+    }
+}
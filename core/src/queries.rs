@@ -0,0 +1,106 @@
+//! queries.rs
+//! Unit extraction by tree-sitter query: which nodes are a language's units,
+//! and what names them, as data instead of code.
+//!
+//! ```scheme
+//! ; a unit per function or class, named by its name
+//! (function_declaration name: (identifier) @name) @unit
+//! (class_declaration name: (type_identifier) @name) @unit
+//! ```
+//!
+//! Every `@unit` capture is a unit of its node's kind, keyed by the text of
+//! the match's `@name` (else by its first line); units nested in one already
+//! taken are left out. A query replaces the built-in collection for its
+//! language: root nodes without a unit still merge as units keyed by kind
+//! and first line, and one holding a single unit (`export function f`) is
+//! that unit's range, comments right above included. Queries are set per
+//! language in `.sinkit.toml` (see [`crate::Policy`]):
+//!
+//! Nodes captured `@ignore` are left out of semantic merging: the root
+//! statements holding them are ignored regions (see `regions`).
+//!
+//! ```toml
+//! [queries]
+//! typescript = "tools/units.scm"
+//!
+//! [grammars.elixir]
+//! path = "tools/libtree-sitter-elixir.so"
+//! query = "tools/elixir-units.scm"
+//! ```
+
+use std::fmt;
+
+use anyhow::{Context, Result, anyhow};
+use tree_sitter::{Node, Query, QueryCursor, Tree};
+
+use crate::ast::{AstLanguage, ts_language};
+
+/// A compiled unit query.
+pub struct UnitQuery {
+    lang: AstLanguage,
+    source: String,
+    query: Query,
+    unit: u32,
+    name: Option<u32>,
+    ignore: Option<u32>,
+}
+
+impl UnitQuery {
+    /// Compile `source` for `lang`; it must capture `@unit`.
+    pub fn new(lang: AstLanguage, source: &str) -> Result<Self> {
+        let query = Query::new(ts_language(lang)?, source).map_err(|e| anyhow!("{e}"))?;
+        let unit = query.capture_index_for_name("unit").context("the query captures no @unit")?;
+        let name = query.capture_index_for_name("name");
+        let ignore = query.capture_index_for_name("ignore");
+        Ok(UnitQuery { lang, source: source.to_string(), query, unit, name, ignore })
+    }
+
+    /// The language the query was compiled for.
+    pub fn lang(&self) -> AstLanguage {
+        self.lang
+    }
+
+    /// The unit nodes in `tree`, with their names, in source order.
+    pub(crate) fn units<'t>(&self, tree: &'t Tree, code: &str) -> Vec<(Node<'t>, Option<String>)> {
+        let mut cursor = QueryCursor::new();
+        let mut found: Vec<(Node<'t>, Option<String>)> = Vec::new();
+        for m in cursor.matches(&self.query, tree.root_node(), code.as_bytes()) {
+            let capture = |index: Option<u32>| m.captures.iter().find(|c| Some(c.index) == index).map(|c| c.node);
+            let Some(unit) = capture(Some(self.unit)) else { continue };
+            let name = capture(self.name).and_then(|n| n.utf8_text(code.as_bytes()).ok()).map(str::to_string);
+            found.push((unit, name));
+        }
+        found.sort_by_key(|(n, _)| (n.start_byte(), std::cmp::Reverse(n.end_byte())));
+        let mut out: Vec<(Node<'t>, Option<String>)> = Vec::new();
+        for (node, name) in found {
+            if out.last().is_none_or(|(last, _)| node.start_byte() >= last.end_byte()) {
+                out.push((node, name));
+            }
+        }
+        out
+    }
+
+    /// The nodes in `tree` captured `@ignore`.
+    pub(crate) fn ignored<'t>(&self, tree: &'t Tree, code: &str) -> Vec<Node<'t>> {
+        let Some(ignore) = self.ignore else { return Vec::new() };
+        let mut cursor = QueryCursor::new();
+        cursor
+            .captures(&self.query, tree.root_node(), code.as_bytes())
+            .filter_map(|(m, i)| (m.captures[i].index == ignore).then_some(m.captures[i].node))
+            .collect()
+    }
+}
+
+impl fmt::Debug for UnitQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnitQuery").field("lang", &self.lang).field("source", &self.source).finish()
+    }
+}
+
+impl PartialEq for UnitQuery {
+    fn eq(&self, other: &Self) -> bool {
+        self.lang == other.lang && self.source == other.source
+    }
+}
+
+impl Eq for UnitQuery {}
@@ -0,0 +1,209 @@
+//! reexports.rs
+//! Merging `export ... from "module"` statements.
+//!
+//! Re-exports are units keyed by their module, like imports. Both branches
+//! adding names to the same one is the usual barrel-file conflict: the
+//! specifier lists merge as sets keyed by exported name, like enum members.
+//! `export *` re-exports every named export of the module, so a branch that
+//! widened a list to `export *` wins over names the other added, except the
+//! ones `*` leaves out (`default` and aliases), which follow in a named
+//! re-export of their own. `export * as ns` and `export type { ... }` only
+//! merge with their own kind.
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, parse_tree};
+use crate::members::{Member, MemberMerge, merge_members, render_body};
+
+/// What a re-export statement exports.
+#[derive(PartialEq)]
+enum Clause {
+    /// `export * from`
+    Star,
+    /// `export * as ns from`
+    Namespace(String),
+    /// `export { ... } from` (`export type { ... } from` if `type_only`),
+    /// with its specifiers and the byte range of `{ ... }`.
+    Named { type_only: bool, specs: Vec<Member>, braces: (usize, usize) },
+}
+
+struct Reexport<'c> {
+    code: &'c str,
+    clause: Clause,
+}
+
+/// Reconcile divergent updates of a re-export (an empty `base`: both
+/// branches added one of the same module).
+pub(crate) fn merge_reexports(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+    // updates come with their `export` split off (see `reconcile_update`):
+    // parse them with it, and hand the merge back without it
+    let bare = !a.starts_with("export");
+    let full = |code: &str| if bare && !code.trim().is_empty() { format!("export {code}") } else { code.to_string() };
+    match merge(&full(base), &full(a), &full(b), label, lang) {
+        MemberMerge::Merged(merged) if bare => {
+            MemberMerge::Merged(merged.strip_prefix("export ").unwrap_or(&merged).to_string())
+        }
+        outcome => outcome,
+    }
+}
+
+fn merge(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+    let (Some(x), Some(y)) = (parse(a, lang), parse(b, lang)) else {
+        return MemberMerge::NotApplicable;
+    };
+    let o = if base.trim().is_empty() {
+        Reexport { code: "", clause: Clause::Named { type_only: false, specs: Vec::new(), braces: (0, 0) } }
+    } else {
+        match parse(base, lang) {
+            Some(o) => o,
+            None => return MemberMerge::NotApplicable,
+        }
+    };
+    let base_specs: &[Member] = match &o.clause {
+        Clause::Named { specs, .. } => specs,
+        _ => &[],
+    };
+    match (&x.clause, &y.clause) {
+        (Clause::Named { type_only: tx, specs: sx, braces }, Clause::Named { type_only: ty, specs: sy, .. })
+            if tx == ty && matches!(o.clause, Clause::Named { type_only, .. } if type_only == *tx || o.code.is_empty()) =>
+        {
+            match merge_members(base_specs, sx, sy, label) {
+                Ok(merged) => MemberMerge::Merged(with_specs(&x, *braces, &merged)),
+                Err(found) => MemberMerge::Conflicts(found),
+            }
+        }
+        (Clause::Star, Clause::Named { type_only: false, specs, braces }) => {
+            MemberMerge::Merged(widened(x.code, &y, *braces, specs, base_specs))
+        }
+        (Clause::Named { type_only: false, specs, braces }, Clause::Star) => {
+            MemberMerge::Merged(widened(y.code, &x, *braces, specs, base_specs))
+        }
+        (x_clause, y_clause) if x_clause == y_clause => MemberMerge::Merged(x.code.to_string()),
+        _ => MemberMerge::Conflicts(vec![format!("Both branches changed the re-export {label} differently")]),
+    }
+}
+
+/// `star` (an `export *`), then the names `named` added that `*` doesn't
+/// cover, re-exported as in `named`.
+fn widened(star: &str, named: &Reexport, braces: (usize, usize), specs: &[Member], base: &[Member]) -> String {
+    let uncovered: Vec<Member> = specs
+        .iter()
+        .filter(|m| !base.iter().any(|b| b.key == m.key) && (m.key == "default" || m.key != local_name(&m.text)))
+        .cloned()
+        .collect();
+    if uncovered.is_empty() {
+        return star.to_string();
+    }
+    format!("{star}\n{}", with_specs(named, braces, &uncovered))
+}
+
+/// `r` with the specifiers in `braces` replaced by `specs`.
+fn with_specs(r: &Reexport, (start, end): (usize, usize), specs: &[Member]) -> String {
+    format!("{}{}{}", &r.code[..start], render_body(&r.code[start..end], specs, Some(',')), &r.code[end..])
+}
+
+/// The names the re-exports in `code` export (none for `export *`).
+pub(crate) fn exported_names(code: &str, lang: AstLanguage) -> Vec<String> {
+    let Ok(tree) = parse_tree(code, lang) else { return Vec::new() };
+    let root = tree.root_node();
+    (0..root.named_child_count())
+        .filter_map(|i| root.named_child(i))
+        .filter_map(|stmt| clause(code, stmt))
+        .flat_map(|clause| match clause {
+            Clause::Star => Vec::new(),
+            Clause::Namespace(ns) => vec![ns.strip_prefix("*as").unwrap_or(&ns).to_string()],
+            Clause::Named { specs, .. } => specs.into_iter().map(|m| m.key).collect(),
+        })
+        .collect()
+}
+
+/// `code` with the specifiers of its `export { ... } from` sorted by
+/// exported name; `None` if they already are (or it has none).
+pub(crate) fn sort_specifiers(code: &str, lang: AstLanguage) -> Option<String> {
+    let r = parse(code, lang)?;
+    let Clause::Named { specs, braces, .. } = &r.clause else { return None };
+    let mut sorted = specs.clone();
+    sorted.sort_by(|p, q| p.key.cmp(&q.key));
+    (sorted != *specs).then(|| with_specs(&r, *braces, &sorted))
+}
+
+fn parse<'c>(code: &'c str, lang: AstLanguage) -> Option<Reexport<'c>> {
+    let tree = parse_tree(code, lang).ok()?;
+    let root = tree.root_node();
+    let clause = (0..root.named_child_count()).filter_map(|i| root.named_child(i)).find_map(|n| clause(code, n))?;
+    Some(Reexport { code, clause })
+}
+
+/// The clause of `stmt`, if it's a re-export.
+fn clause(code: &str, stmt: Node) -> Option<Clause> {
+    if stmt.kind() != "export_statement" || stmt.child_by_field_name("source").is_none() {
+        return None;
+    }
+    let text = |n: Node| &code[n.start_byte()..n.end_byte()];
+    let children: Vec<Node> = (0..stmt.child_count()).filter_map(|i| stmt.child(i)).collect();
+    let clause = if let Some(ns) = children.iter().find(|c| c.kind() == "namespace_export") {
+        Clause::Namespace(text(*ns).split_whitespace().collect())
+    } else if let Some(clause) = children.iter().find(|c| c.kind() == "export_clause") {
+        let specs = (0..clause.named_child_count())
+            .filter_map(|i| clause.named_child(i))
+            .filter(|s| s.kind() == "export_specifier")
+            .map(|s| {
+                let spec = text(s).split_whitespace().collect::<Vec<_>>().join(" ");
+                Member { key: exported_name(&spec).to_string(), text: spec }
+            })
+            .collect();
+        let type_only = children.iter().any(|c| c.kind() == "type");
+        Clause::Named { type_only, specs, braces: (clause.start_byte(), clause.end_byte()) }
+    } else if children.iter().any(|c| c.kind() == "*") {
+        Clause::Star
+    } else {
+        return None;
+    };
+    Some(clause)
+}
+
+/// `b` in `a as b`, else the name itself.
+fn exported_name(spec: &str) -> &str {
+    spec.rsplit_once(" as ").map_or(spec, |(_, alias)| alias).trim_start_matches("type ")
+}
+
+/// `a` in `a as b`, else the name itself.
+fn local_name(spec: &str) -> &str {
+    spec.split_once(" as ").map_or(spec, |(local, _)| local).trim_start_matches("type ")
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+
+    fn merge(base: &str, a: &str, b: &str) -> MemberMerge {
+        merge_reexports(base, a, b, "export_statement::./cart", AstLanguage::TypeScript)
+    }
+
+    #[test]
+    fn names_both_branches_added_unite() {
+        let out = merge("export { Cart } from './cart';", "export { Cart, Item } from './cart';", "export { Cart, empty } from './cart';");
+        assert_eq!(out, MemberMerge::Merged("export { Cart, Item, empty } from './cart';".to_string()));
+    }
+
+    #[test]
+    fn star_wins_over_added_names_but_keeps_aliases() {
+        let b = "export { Cart, Item as Line, default as cart } from './cart';";
+        let out = merge("export { Cart } from './cart';", "export * from './cart';", b);
+        let merged = "export * from './cart';\nexport { Item as Line, default as cart } from './cart';";
+        assert_eq!(out, MemberMerge::Merged(merged.to_string()));
+    }
+
+    #[test]
+    fn a_name_both_pointed_elsewhere_conflicts() {
+        let out = merge("export { Cart } from './cart';", "export { Basket as Cart } from './cart';", "export { Trolley as Cart } from './cart';");
+        let conflicts = vec!["Both branches updated export_statement::./cart::Cart differently".to_string()];
+        assert_eq!(out, MemberMerge::Conflicts(conflicts));
+    }
+
+    #[test]
+    fn helpers_read_exported_names() {
+        assert_eq!(exported_names("export { a, b as c } from './x';", AstLanguage::TypeScript), ["a", "c"]);
+        assert_eq!(sort_specifiers("export { b, a } from './x';", AstLanguage::TypeScript).as_deref(), Some("export { a, b } from './x';"));
+    }
+}
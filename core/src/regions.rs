@@ -0,0 +1,163 @@
+//! regions.rs
+//! Ignored regions: blocks of a hand-written file that are really generated
+//! (an embedded codegen block, a vendored snippet), fenced with sentinel
+//! comments or captured `@ignore` by the file's [`crate::UnitQuery`]:
+//!
+//! ```ts
+//! // sinkit:ignore-start routes
+//! export const routes = { ... };
+//! export type Route = keyof typeof routes;
+//! // sinkit:ignore-end
+//! ```
+//!
+//! A region is one opaque unit of kind `ignored_region`, keyed by the label
+//! after the start marker (else `#1`, `#2`, ... in file order), so nothing
+//! inside it is diffed or reconciled. One branch's change to it is taken;
+//! changes on both merge like a generated file, by the file's
+//! [`crate::GeneratedMerge`] policy (`semantic` turns regions off).
+
+use tree_sitter::{Node, Tree};
+
+use crate::ast::TopLevel;
+
+/// The kind of an ignored region's unit.
+pub(crate) const IGNORED_REGION: &str = "ignored_region";
+
+const START: &str = "sinkit:ignore-start";
+const END: &str = "sinkit:ignore-end";
+
+/// The `(start, end, label)` of the regions fenced by sentinel comments at
+/// the top level of `tree`; a start marker without an end is ignored.
+pub(crate) fn fenced(tree: &Tree, code: &str) -> Vec<(usize, usize, Option<String>)> {
+    let root = tree.root_node();
+    let mut out = Vec::new();
+    let mut open: Option<(usize, Option<String>)> = None;
+    for ch in (0..root.named_child_count()).filter_map(|i| root.named_child(i)) {
+        if !ch.kind().contains("comment") {
+            continue;
+        }
+        let text = &code[ch.start_byte()..ch.end_byte()];
+        if let Some((_, rest)) = text.split_once(START) {
+            let label = rest.trim().trim_end_matches("*/").trim();
+            open = Some((ch.start_byte(), (!label.is_empty()).then(|| label.to_string())));
+        } else if text.contains(END)
+            && let Some((start, label)) = open.take()
+        {
+            out.push((start, ch.end_byte(), label));
+        }
+    }
+    out
+}
+
+/// The ranges of the root children holding `nodes`.
+pub(crate) fn enclosing(tree: &Tree, nodes: &[Node]) -> Vec<(usize, usize, Option<String>)> {
+    let root = tree.root_node();
+    let mut out: Vec<(usize, usize, Option<String>)> = Vec::new();
+    for n in nodes {
+        let Some(ch) = (0..root.child_count())
+            .filter_map(|i| root.child(i))
+            .find(|ch| ch.start_byte() <= n.start_byte() && n.end_byte() <= ch.end_byte())
+        else {
+            continue;
+        };
+        if !out.iter().any(|(s, _, _)| *s == ch.start_byte()) {
+            out.push((ch.start_byte(), ch.end_byte(), None));
+        }
+    }
+    out
+}
+
+/// Replace the units inside `regions` with one unit per region. A unit
+/// straddling a region's end (its doc comment being the end marker) starts
+/// after it.
+pub(crate) fn apply(units: &mut Vec<TopLevel>, code: &str, mut regions: Vec<(usize, usize, Option<String>)>) {
+    if regions.is_empty() {
+        return;
+    }
+    regions.sort_by_key(|r| r.0);
+    // overlapping regions (a fence around a captured node) are one
+    regions.dedup_by(|next, prev| {
+        let overlaps = next.0 < prev.1;
+        if overlaps {
+            prev.1 = prev.1.max(next.1);
+            prev.2 = prev.2.take().or(next.2.take());
+        }
+        overlaps
+    });
+    units.retain_mut(|u| {
+        let Some((_, end, _)) = regions.iter().find(|(s, e, _)| u.start_byte < *e && *s < u.end_byte) else {
+            return true;
+        };
+        if u.end_byte <= *end || u.inner.is_some_and(|(s, _)| s < *end) {
+            return false;
+        }
+        u.start_byte = end + (code[*end..].len() - code[*end..].trim_start().len());
+        true
+    });
+    for (i, (start, end, label)) in regions.into_iter().enumerate() {
+        let name = label.unwrap_or_else(|| format!("#{}", i + 1));
+        units.push(TopLevel { inner: Some((start, end)), ..TopLevel::new(IGNORED_REGION, Some(name), start, end) });
+    }
+    units.sort_by_key(|u| u.start_byte);
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use crate::{AstLanguage, parse_typescript_to_ast, three_way_merge_top_level};
+
+    const BASE: &str = concat!(
+        "export const a = 1;\n",
+        "// sinkit:ignore-start routes\n",
+        "export const routes = { home: \"/\" };\n",
+        "export type Route = keyof typeof routes;\n",
+        "// sinkit:ignore-end\n",
+        "export const b = 2;\n",
+    );
+
+    fn units(code: &str) -> Vec<String> {
+        let file = parse_typescript_to_ast(code, AstLanguage::TypeScript).unwrap();
+        file.keyed_units().into_iter().map(|((kind, name), _)| format!("{kind}::{name}")).collect()
+    }
+
+    #[test]
+    fn a_fence_is_one_unit_by_its_label() {
+        let unlabeled = "/* sinkit:ignore-start */\nexport const x = 1;\n/* sinkit:ignore-end */\n";
+        assert_eq!(
+            units(&format!("{BASE}{unlabeled}")),
+            ["lexical_declaration::a", "ignored_region::routes", "lexical_declaration::b", "ignored_region::#2"]
+        );
+    }
+
+    #[test]
+    fn one_branchs_edit_inside_a_region_is_taken() {
+        let a = BASE.replace("export const b = 2;", "export const b = 3;");
+        let b = BASE.replace("home: \"/\"", "home: \"/\", about: \"/about\"");
+        let out = three_way_merge_top_level(BASE, &a, &b, AstLanguage::TypeScript).unwrap();
+        assert!(out.conflicts.is_empty(), "{:?}", out.conflicts);
+        assert_eq!(out.merged_code, b.replace("export const b = 2;", "export const b = 3;"));
+    }
+
+    #[test]
+    fn edits_on_both_sides_of_a_region_conflict() {
+        // different declarations, which would merge outside the fence
+        let a = BASE.replace("home: \"/\"", "home: \"/home\"");
+        let b = BASE.replace("keyof typeof routes", "(keyof typeof routes) | \"404\"");
+        let out = three_way_merge_top_level(BASE, &a, &b, AstLanguage::TypeScript).unwrap();
+        let found: Vec<_> = out.conflicts.iter().map(|c| (c.kind.as_str(), c.unit.as_deref())).collect();
+        assert_eq!(found, [("update-update", Some("ignored_region::routes"))]);
+    }
+
+    #[test]
+    fn a_start_without_an_end_fences_nothing() {
+        let open = BASE.replace("// sinkit:ignore-end\n", "");
+        assert_eq!(
+            units(&open),
+            ["lexical_declaration::a", "lexical_declaration::routes", "type_alias_declaration::Route", "lexical_declaration::b"]
+        );
+        let a = open.replace("home: \"/\"", "home: \"/home\"");
+        let b = open.replace("keyof typeof routes", "(keyof typeof routes) | \"404\"");
+        let out = three_way_merge_top_level(&open, &a, &b, AstLanguage::TypeScript).unwrap();
+        assert!(out.conflicts.is_empty(), "{:?}", out.conflicts);
+        assert_eq!(out.merged_code, b.replace("home: \"/\"", "home: \"/home\""));
+    }
+}
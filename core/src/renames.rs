@@ -0,0 +1,158 @@
+//! renames.rs
+//! Units a branch renamed, and the other branch's uses of the old name.
+//!
+//! A top-level unit deleted from base and one inserted of the same kind are
+//! a rename when the new one is the old one with its name replaced (as a
+//! whole word) and nothing else changed, up to whitespace; the diff reports
+//! the pair as one `rename` edit. When composing, the other branch's new and
+//! updated units that still use the old name get the new one: identifiers
+//! only, so property names (`x.foo`), strings and comments keep theirs.
+//! Locals shadowing the old name are renamed along with it.
+
+use tree_sitter::Node;
+
+use crate::ast::{AstFile, AstLanguage, TopLevel, parse_tree};
+use crate::hash::content_hash;
+
+/// Hash of `u`'s text without its name: equal for a unit and the unit
+/// renamed, so candidates for [`is_rename`] can be looked up.
+pub(crate) fn nameless_hash(file: &AstFile, u: &TopLevel) -> Option<u64> {
+    Some(content_hash(&replace_word(&file.code[u.start_byte..u.end_byte], u.name.as_deref()?, "")))
+}
+
+/// Whether `u` of `other` is `bu` of `base` renamed.
+pub(crate) fn is_rename(base: &AstFile, bu: &TopLevel, other: &AstFile, u: &TopLevel) -> bool {
+    let (Some(old), Some(new)) = (&bu.name, &u.name) else { return false };
+    // top-level units known by a plain name, whose key is that name
+    let plain = |t: &TopLevel| t.signature.is_none();
+    if bu.kind != u.kind || old == new || !plain(bu) || !plain(u) {
+        return false;
+    }
+    let renamed = replace_word(&base.code[bu.start_byte..bu.end_byte], old, new);
+    content_hash(&renamed) == content_hash(&other.code[u.start_byte..u.end_byte])
+}
+
+/// `code` with its uses of `from` renamed to `to`; `None` if it has none.
+pub(crate) fn rename_uses(code: &str, lang: AstLanguage, from: &str, to: &str) -> Option<String> {
+    let tree = parse_tree(code, lang).ok()?;
+    let mut uses = Vec::new();
+    collect_uses(tree.root_node(), code, from, &mut uses);
+    if uses.is_empty() {
+        return None;
+    }
+    let mut out = code.to_string();
+    for (start, end, shorthand) in uses.into_iter().rev() {
+        // `{ foo }` keeps its key: `{ foo: bar }`
+        let replacement = if shorthand { format!("{from}: {to}") } else { to.to_string() };
+        out.replace_range(start..end, &replacement);
+    }
+    Some(out)
+}
+
+fn collect_uses(node: Node, code: &str, name: &str, out: &mut Vec<(usize, usize, bool)>) {
+    let kind = node.kind();
+    if matches!(kind, "identifier" | "type_identifier" | "shorthand_property_identifier")
+        && &code[node.start_byte()..node.end_byte()] == name
+    {
+        out.push((node.start_byte(), node.end_byte(), kind == "shorthand_property_identifier"));
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_uses(child, code, name, out);
+    }
+}
+
+/// `haystack` with whole-word occurrences of `from` replaced by `to`, so
+/// `user` leaves `username` alone.
+pub(crate) fn replace_word(haystack: &str, from: &str, to: &str) -> String {
+    let mut out = String::with_capacity(haystack.len());
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$');
+    let mut i = 0;
+    while let Some(c) = haystack[i..].chars().next() {
+        if haystack[i..].starts_with(from)
+            && !is_word(haystack[..i].chars().next_back())
+            && !is_word(haystack[i + from.len()..].chars().next())
+        {
+            out.push_str(to);
+            i += from.len();
+            continue;
+        }
+        out.push(c);
+        i += c.len_utf8();
+    }
+    out
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use super::*;
+    use crate::parse_typescript_to_ast;
+
+    fn file(code: &str) -> AstFile {
+        parse_typescript_to_ast(code, AstLanguage::TypeScript).unwrap()
+    }
+
+    /// Whether the one unit of `other` is the one unit of `base` renamed.
+    fn renamed(base: &str, other: &str) -> bool {
+        let (base, other) = (file(base), file(other));
+        is_rename(&base, &base.units[0], &other, &other.units[0])
+    }
+
+    #[test]
+    fn words_are_replaced_whole() {
+        assert_eq!(
+            replace_word("load(loader, _load, $load, load2) + load", "load", "fetch"),
+            "fetch(loader, _load, $load, load2) + fetch"
+        );
+        assert_eq!(replace_word("é load é", "load", ""), "é  é");
+    }
+
+    #[test]
+    fn nameless_hashes_match_across_names_only() {
+        let hash = |code: &str| {
+            let file = file(code);
+            nameless_hash(&file, &file.units[0])
+        };
+        let load = hash("function load(x) {\n  return x;\n}\n");
+        assert!(load.is_some());
+        assert_eq!(hash("function fetch(x) {\n  return x;\n}\n"), load);
+        assert_ne!(hash("function fetch(x) {\n  return x + 1;\n}\n"), load);
+    }
+
+    #[test]
+    fn a_unit_with_only_its_name_changed_is_a_rename() {
+        assert!(renamed("function load(x) {\n  return x;\n}\n", "function fetch(x) {\n  return x;\n}\n"));
+        assert!(renamed("function load(x) {\n  return load(x - 1);\n}\n", "function fetch(x) {\n  return fetch(x - 1);\n}\n"));
+    }
+
+    #[test]
+    fn other_changes_are_not_renames() {
+        // the body changed too
+        assert!(!renamed("function load(x) {\n  return x;\n}\n", "function fetch(x) {\n  return x + 1;\n}\n"));
+        // the same body, but a recursive call still to the old name
+        assert!(!renamed("function load(x) {\n  return load(x - 1);\n}\n", "function fetch(x) {\n  return load(x - 1);\n}\n"));
+        // the same body in another kind of unit
+        assert!(!renamed("function load() {}\n", "class fetch {}\n"));
+        assert!(!renamed("function load(x) {\n  return x;\n}\n", "function load(x) {\n  return x;\n}\n"));
+    }
+
+    #[test]
+    fn uses_are_identifiers_only() {
+        let code = "const y = load(x) + obj.load;\nconst s = \"load\"; // load\nconst z = { load };\n";
+        assert_eq!(
+            rename_uses(code, AstLanguage::TypeScript, "load", "fetch").unwrap(),
+            "const y = fetch(x) + obj.load;\nconst s = \"load\"; // load\nconst z = { load: fetch };\n"
+        );
+        assert_eq!(rename_uses("const s = \"load\"; // load\n", AstLanguage::TypeScript, "load", "fetch"), None);
+    }
+
+    #[test]
+    fn shadowing_locals_are_renamed_along() {
+        let code = "function g(load) {\n  return load(1);\n}\n";
+        assert_eq!(
+            rename_uses(code, AstLanguage::TypeScript, "load", "fetch").unwrap(),
+            "function g(fetch) {\n  return fetch(1);\n}\n"
+        );
+    }
+}
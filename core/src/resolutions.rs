@@ -0,0 +1,254 @@
+//! resolutions.rs
+//! Non-interactive conflict resolution through a `resolutions.toml`.
+//!
+//! A merge with conflicts can write a skeleton ([`Resolutions::skeleton`])
+//! listing every conflict by ID. Reviewers fill in a choice per entry and the
+//! merge is re-run with the file ([`crate::MergeOptions::with_resolutions`]):
+//!
+//! ```toml
+//! [[conflict]]
+//! id = "3864013143c3ef18"
+//! file = "src/user.ts"
+//! unit = "class_declaration::User"
+//! reason = "Both branches updated class_declaration::User differently"
+//! choose = "b"                       # "a", "b" or "union"
+//!
+//! [[conflict]]
+//! id = "9a4410d2c07be3f1"
+//! text = '''
+//! export const retries = 5;
+//! '''                                # replaces the unit (or file) outright
+//! ```
+//!
+//! A choice settles the whole unit the conflict is about; entries left empty
+//! stay conflicts. A directory of answered conflict bundles (see
+//! [`crate::conflict_bundles`]) loads the same way.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use toml::{Table, Value};
+
+use crate::conflicts::Conflict;
+
+/// How to settle one conflict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Take A's version.
+    A,
+    /// Take B's version.
+    B,
+    /// Keep A's version followed by B's.
+    Union,
+    /// Replace with this text.
+    Text(String),
+}
+
+impl Resolution {
+    /// A `choose` value: `"a"`, `"b"` or `"union"`.
+    pub(crate) fn choice(name: &str) -> Option<Self> {
+        match name {
+            "a" => Some(Resolution::A),
+            "b" => Some(Resolution::B),
+            "union" => Some(Resolution::Union),
+            _ => None,
+        }
+    }
+
+    /// Apply to the two versions of a unit (or file); `""` is a deletion.
+    pub(crate) fn apply(&self, a: &str, b: &str) -> String {
+        match self {
+            Resolution::A => a.to_string(),
+            Resolution::B => b.to_string(),
+            Resolution::Union if b.is_empty() || a == b => a.to_string(),
+            Resolution::Union if a.is_empty() => b.to_string(),
+            Resolution::Union => {
+                let sep = if a.ends_with('\n') { "\n" } else { "\n\n" };
+                format!("{a}{sep}{b}")
+            }
+            Resolution::Text(text) => text.clone(),
+        }
+    }
+}
+
+/// Decisions from a `resolutions.toml`, by conflict ID.
+#[derive(Debug, Clone, Default)]
+pub struct Resolutions {
+    by_id: BTreeMap<String, Resolution>,
+}
+
+impl Resolutions {
+    /// Read a `resolutions.toml`, or a directory of conflict bundles.
+    pub fn load(path: &Path) -> Result<Self> {
+        if path.is_dir() {
+            let mut by_id = BTreeMap::new();
+            for bundle in crate::bundles::read_dir(path)? {
+                if let Some(resolution) = bundle.resolution()? {
+                    by_id.insert(bundle.conflict.id, resolution);
+                }
+            }
+            return Ok(Resolutions { by_id });
+        }
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("reading {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let doc: Table = toml::from_str(text)?;
+        let entries = match doc.get("conflict") {
+            None => &[][..],
+            Some(v) => v.as_array().context("`conflict` must be an array of tables ([[conflict]])")?.as_slice(),
+        };
+        let mut by_id = BTreeMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let t = entry.as_table().context("`conflict` must be an array of tables ([[conflict]])")?;
+            let id = t
+                .get("id")
+                .and_then(Value::as_str)
+                .with_context(|| format!("conflict #{}: missing `id`", i + 1))?;
+            let resolution = match (t.get("text"), t.get("choose").map(Value::as_str)) {
+                (Some(text), _) => Resolution::Text(
+                    text.as_str().with_context(|| format!("conflict #{}: `text` must be a string", i + 1))?.to_string(),
+                ),
+                (None, None | Some(Some(""))) => continue,
+                (None, Some(Some(choice))) if let Some(resolution) = Resolution::choice(choice) => resolution,
+                (None, Some(_)) => bail!("conflict #{}: `choose` must be \"a\", \"b\" or \"union\"", i + 1),
+            };
+            by_id.insert(id.to_string(), resolution);
+        }
+        Ok(Resolutions { by_id })
+    }
+
+    /// Settle conflict `id` with `resolution` (over any earlier decision).
+    pub fn insert(&mut self, id: impl Into<String>, resolution: Resolution) {
+        self.by_id.insert(id.into(), resolution);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Resolution> {
+        self.by_id.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_id.is_empty()
+    }
+
+    /// A `resolutions.toml` recording how `(file, conflict, resolution)`
+    /// were settled, to re-run the merge with.
+    pub fn record<'c>(entries: impl IntoIterator<Item = (&'c str, &'c Conflict, &'c Resolution)>) -> String {
+        let conflict = entries
+            .into_iter()
+            .map(|(file, c, resolution)| {
+                let (choose, text) = match resolution {
+                    Resolution::A => (Some("a"), None),
+                    Resolution::B => (Some("b"), None),
+                    Resolution::Union => (Some("union"), None),
+                    Resolution::Text(text) => (None, Some(text.as_str())),
+                };
+                Entry { id: &c.id, file, unit: c.unit.as_deref(), reason: &c.message, choose, text }
+            })
+            .collect();
+        write(&Document { conflict })
+    }
+
+    /// A `resolutions.toml` listing `(file, conflict)` pairs with empty choices.
+    pub fn skeleton<'c>(conflicts: impl IntoIterator<Item = (&'c str, &'c Conflict)>) -> String {
+        let conflict = conflicts
+            .into_iter()
+            .map(|(file, c)| Entry {
+                id: &c.id,
+                file,
+                unit: c.unit.as_deref(),
+                reason: &c.message,
+                choose: Some(""),
+                text: None,
+            })
+            .collect();
+        let mut out = String::from(
+            "# Set `choose` to \"a\", \"b\" or \"union\" (or replace it with `text = '''...'''`)\n\
+             # for each conflict, then re-run with `--resolutions <this file>`.\n\n",
+        );
+        out.push_str(&write(&Document { conflict }));
+        out
+    }
+}
+
+/// The on-disk shape of a `resolutions.toml`.
+#[derive(Serialize)]
+struct Document<'c> {
+    conflict: Vec<Entry<'c>>,
+}
+
+#[derive(Serialize)]
+struct Entry<'c> {
+    id: &'c str,
+    file: &'c str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<&'c str>,
+    reason: &'c str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    choose: Option<&'c str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<&'c str>,
+}
+
+fn write(doc: &Document) -> String {
+    if doc.conflict.is_empty() {
+        return String::new();
+    }
+    // multi-line strings (replacement text) come out as `'''` blocks
+    toml::to_string_pretty(doc).expect("a resolutions document always serializes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflict(id: &str, unit: Option<&str>) -> Conflict {
+        Conflict {
+            id: id.to_string(),
+            kind: "update-update".to_string(),
+            unit: unit.map(str::to_string),
+            message: "Both branches updated \"it\" differently".to_string(),
+        }
+    }
+
+    #[test]
+    fn record_round_trips() {
+        let (a, b, c) = (conflict("a1", Some("class_declaration::User")), conflict("b2", None), conflict("c3", None));
+        let text = Resolution::Text("const s = '''\\n\";\r\nlet t = \"\u{7}\";\n".to_string());
+        let out =
+            Resolutions::record([("src/a.ts", &a, &Resolution::B), ("src/b.ts", &b, &text), ("c.ts", &c, &Resolution::Union)]);
+        let parsed = Resolutions::parse(&out).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed.get("a1"), Some(&Resolution::B));
+        assert_eq!(parsed.get("b2"), Some(&text));
+        assert_eq!(parsed.get("c3"), Some(&Resolution::Union));
+    }
+
+    #[test]
+    fn skeleton_parses_to_nothing_settled() {
+        let out = Resolutions::skeleton([("src/a.ts", &conflict("a1", Some("function_declaration::f")))]);
+        assert!(out.starts_with("# Set `choose`"), "{out}");
+        assert!(out.contains("choose = \"\""), "{out}");
+        assert!(Resolutions::parse(&out).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_entries() {
+        for text in [
+            "[[conflict]]\nchoose = \"a\"\n",
+            "[[conflict]]\nid = \"a1\"\nchoose = \"c\"\n",
+            "[[conflict]]\nid = \"a1\"\ntext = 3\n",
+            "[conflict]\nid = \"a1\"\n",
+            "[[conflict]]\nid = \"a1\" choose = \"a\"\n",
+        ] {
+            assert!(Resolutions::parse(text).is_err(), "accepted {text:?}");
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! resolver.rs
+//! External conflict resolvers.
+//!
+//! A [`ConflictResolver`] is asked about each conflict a merge leaves, as a
+//! [`ConflictBundle`], and may answer with a [`Resolution`]; the merge is
+//! then re-run with the answers like with a `resolutions.toml`, and
+//! unanswered conflicts stay. [`CommandResolver`] runs a command (`cmd` in
+//! `.sinkit.toml`'s `[resolve]`) with the bundle's JSON on stdin and reads
+//! the answer from its stdout: `{"choose": "a"}` (`"b"`, `"union"`),
+//! `{"text": "..."}`, or nothing to leave the conflict.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::bundles::ConflictBundle;
+use crate::formatter::run_command;
+use crate::resolutions::Resolution;
+
+/// Settles conflicts, or declines to (`None`).
+pub trait ConflictResolver: Send + Sync {
+    fn resolve(&self, bundle: &ConflictBundle) -> Result<Option<Resolution>>;
+}
+
+impl<F> ConflictResolver for F
+where
+    F: Fn(&ConflictBundle) -> Result<Option<Resolution>> + Send + Sync,
+{
+    fn resolve(&self, bundle: &ConflictBundle) -> Result<Option<Resolution>> {
+        self(bundle)
+    }
+}
+
+/// Runs a shell command per conflict; `{path}` in the command is replaced by
+/// the (shell-quoted) file path. Like [`crate::CommandFormatter`]'s, the
+/// command must come from a trusted source.
+#[derive(Debug, Clone)]
+pub struct CommandResolver {
+    command: String,
+    dir: Option<PathBuf>,
+}
+
+impl CommandResolver {
+    pub fn new(command: impl Into<String>) -> Self {
+        CommandResolver { command: command.into(), dir: None }
+    }
+
+    /// Run in `dir` (e.g. the repo root).
+    pub fn in_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct Answer {
+    #[serde(default)]
+    choose: Option<String>,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+impl ConflictResolver for CommandResolver {
+    fn resolve(&self, bundle: &ConflictBundle) -> Result<Option<Resolution>> {
+        let input = serde_json::to_string(bundle)?;
+        let out = run_command(&self.command, Some(&bundle.path), self.dir.as_deref(), &input)?;
+        if out.trim().is_empty() {
+            return Ok(None);
+        }
+        let answer: Answer = serde_json::from_str(&out).with_context(|| format!("`{}` printed no answer", self.command))?;
+        ConflictBundle { choose: answer.choose, text: answer.text, ..bundle.clone() }.resolution()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AstLanguage, Conflict};
+
+    fn bundle() -> ConflictBundle {
+        let unit = ("function_declaration".to_string(), "f".to_string());
+        ConflictBundle {
+            path: "src/it's here.ts".to_string(),
+            conflict: Conflict::new(Some("src/it's here.ts"), "update-update", Some(&unit), (Some(1), Some(2)), "m"),
+            language: AstLanguage::TypeScript,
+            base: Some("function f() {}".to_string()),
+            a: Some("function f() { a(); }".to_string()),
+            b: Some("function f() { b(); }".to_string()),
+            before: String::new(),
+            after: String::new(),
+            imports: Vec::new(),
+            choose: None,
+            text: None,
+        }
+    }
+
+    /// What a resolver running `script` answers for [`bundle`].
+    fn answer(script: &str) -> Result<Option<Resolution>> {
+        CommandResolver::new(script).resolve(&bundle())
+    }
+
+    #[test]
+    fn the_command_reads_the_bundle_in_its_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = CommandResolver::new("cat > bundle.json; printf %s {path} > path.txt; echo '{\"choose\": \"b\"}'");
+        assert_eq!(resolver.in_dir(dir.path()).resolve(&bundle()).unwrap(), Some(Resolution::B));
+        let sent: ConflictBundle = serde_json::from_str(&std::fs::read_to_string(dir.path().join("bundle.json")).unwrap()).unwrap();
+        assert_eq!(sent, bundle());
+        assert_eq!(std::fs::read_to_string(dir.path().join("path.txt")).unwrap(), "src/it's here.ts");
+    }
+
+    #[test]
+    fn replies_are_choices_text_or_nothing() {
+        assert_eq!(answer("echo '{\"choose\": \"union\"}'").unwrap(), Some(Resolution::Union));
+        assert_eq!(
+            answer("echo '{\"text\": \"function f() { a(); b(); }\"}'").unwrap(),
+            Some(Resolution::Text("function f() { a(); b(); }".to_string()))
+        );
+        // text wins over a choice
+        assert_eq!(answer("echo '{\"choose\": \"a\", \"text\": \"\"}'").unwrap(), Some(Resolution::Text(String::new())));
+        for declined in ["true", "echo", "echo '{}'", "echo '{\"choose\": \"\"}'"] {
+            assert_eq!(answer(declined).unwrap(), None, "{declined}");
+        }
+    }
+
+    #[test]
+    fn a_failing_command_is_an_error() {
+        let err = answer("cat > /dev/null; echo 'no model' >&2; exit 3").unwrap_err();
+        assert!(format!("{err:#}").contains("failed: no model"), "{err:#}");
+        let err = answer("exit 1").unwrap_err();
+        assert!(format!("{err:#}").contains("failed (exit status: 1)"), "{err:#}");
+    }
+
+    #[test]
+    fn malformed_replies_are_errors() {
+        let err = answer("echo 'take b'").unwrap_err();
+        assert!(format!("{err:#}").contains("printed no answer"), "{err:#}");
+        let err = answer("echo '{\"choose\": \"both\"}'").unwrap_err();
+        assert!(format!("{err:#}").contains("`choose` must be \"a\", \"b\" or \"union\""), "{err:#}");
+    }
+}
@@ -0,0 +1,92 @@
+//! specs.rs
+//! Test-block granularity for spec files.
+//!
+//! `describe(...)` blocks are merged statement-by-statement: nested
+//! `describe`/`it`/`test` calls are keyed by callee + title (recursively),
+//! other statements (`beforeEach`, setup code) by their normalized text.
+
+use tree_sitter::Node;
+
+use crate::ast::{AstLanguage, parse_tree, test_call_title};
+use crate::members::{Member, MemberMerge, Side, commented_items, merge_members_with, normalize_ws, pick_frame, render_body};
+
+/// A test call's callback body: its statements and `{ ... }` byte range.
+struct Block {
+    statements: Vec<Member>,
+    range: (usize, usize),
+}
+
+/// Reconcile divergent updates to a top-level `test_block` unit.
+pub(crate) fn merge_test_block(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> MemberMerge {
+    match merge_block_text(base, a, b, label, lang) {
+        Some(Ok(merged)) => MemberMerge::Merged(merged),
+        Some(Err(conflicts)) => MemberMerge::Conflicts(conflicts),
+        None => MemberMerge::NotApplicable,
+    }
+}
+
+fn merge_block_text(base: &str, a: &str, b: &str, label: &str, lang: AstLanguage) -> Option<Result<String, Vec<String>>> {
+    let (o, x, y) = (callback_body(base, lang)?, callback_body(a, lang)?, callback_body(b, lang)?);
+
+    let frame = |code: &str, blk: &Block| format!("{}{{}}{}", &code[..blk.range.0], &code[blk.range.1..]);
+    let (code, chosen) = match pick_frame(frame(base, &o), frame(a, &x), frame(b, &y)) {
+        Some(Side::A) => (a, &x),
+        Some(Side::B) => (b, &y),
+        // title or callback signature changed on both sides
+        None => return None,
+    };
+
+    let merged = merge_members_with(&o.statements, &x.statements, &y.statements, label, |_, o, x, y| {
+        match merge_block_text(o, x, y, label, lang) {
+            Some(Ok(text)) => Some(text),
+            _ => None,
+        }
+    });
+    Some(merged.map(|statements| {
+        let (s, e) = chosen.range;
+        let mut out = code.to_string();
+        out.replace_range(s..e, &render_body(&code[s..e], &statements, None));
+        out
+    }))
+}
+
+/// Find the test call in a statement snippet and split its callback body.
+fn callback_body(code: &str, lang: AstLanguage) -> Option<Block> {
+    let tree = parse_tree(code, lang).ok()?;
+    // past the comments above the call
+    let root = tree.root_node();
+    let stmt = (0..root.named_child_count())
+        .filter_map(|i| root.named_child(i))
+        .find(|n| n.kind() != "comment")?;
+    let call = stmt.named_child(0)?;
+    test_call_title(&call, code)?;
+
+    let args = call.child_by_field_name("arguments")?;
+    let callback = (0..args.named_child_count())
+        .filter_map(|i| args.named_child(i))
+        .find(|n| matches!(n.kind(), "arrow_function" | "function" | "function_expression"))?;
+    let body = callback.child_by_field_name("body")?;
+    if body.kind() != "statement_block" {
+        return None;
+    }
+
+    let (items, closing) = commented_items(body, code)?;
+    let mut statements = Vec::new();
+    for (st, text) in items {
+        statements.push(Member { key: statement_key(&st, code)?, text });
+    }
+    statements.extend(closing);
+    Some(Block {
+        statements,
+        range: (body.start_byte(), body.end_byte()),
+    })
+}
+
+fn statement_key(st: &Node, code: &str) -> Option<String> {
+    if st.kind() == "expression_statement"
+        && let Some((callee, title)) = st.named_child(0).and_then(|c| test_call_title(&c, code))
+    {
+        return Some(format!("{callee}({title})"));
+    }
+    Some(normalize_ws(st.utf8_text(code.as_bytes()).ok()?))
+}
@@ -0,0 +1,175 @@
+//! splits.rs
+//! Function splits: one branch replaces a unit with new ones that share its
+//! lines (`processOrder` → `validateOrder` + `submitOrder`) while the other
+//! branch edits it.
+//!
+//! The edit is diffed line by line and every hunk is carried into the one
+//! new unit that has the lines it touches (or, for added lines, the line
+//! before them). When a hunk fits nowhere, or in more than one place, the
+//! merge reports a `split-edit` conflict instead of `delete-update`.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::diff3::hunks;
+
+/// Share of the original's body lines the pieces must have between them.
+const MIN_COVERAGE: f64 = 0.6;
+
+/// The `pieces` (indices) `original` looks split into: at least two that
+/// share its lines, and together have most of its body.
+pub(crate) fn split_pieces(original: &str, pieces: &[&str]) -> Option<Vec<usize>> {
+    let body = body_lines(original);
+    let piece_lines: Vec<Vec<&str>> = pieces.iter().map(|p| p.lines().map(str::trim).collect()).collect();
+    let sharing: Vec<usize> =
+        (0..pieces.len()).filter(|&i| body.iter().any(|b| piece_lines[i].contains(b))).collect();
+    let covered = body.iter().filter(|b| sharing.iter().any(|&i| piece_lines[i].contains(b))).count();
+    (sharing.len() >= 2 && covered as f64 >= MIN_COVERAGE * body.len() as f64).then_some(sharing)
+}
+
+/// Pieces by their (trimmed, non-trivial) lines, so looking for what a unit
+/// was split into only visits the pieces that share its lines.
+#[derive(Default)]
+pub(crate) struct PieceLines(HashMap<String, BTreeSet<usize>>);
+
+impl PieceLines {
+    /// Index piece `id`'s lines; again after it changed (lines it lost
+    /// still point to it, which only widens [`PieceLines::sharing`]).
+    pub(crate) fn add(&mut self, id: usize, piece: &str) {
+        for line in piece.lines().map(str::trim).filter(|l| !is_trivial(l)) {
+            self.0.entry(line.to_string()).or_default().insert(id);
+        }
+    }
+
+    /// The pieces with a body line of `original`, in order: all of those
+    /// [`split_pieces`] could pick.
+    pub(crate) fn sharing(&self, original: &str) -> Vec<usize> {
+        let found: BTreeSet<usize> = body_lines(original).iter().filter_map(|l| self.0.get(*l)).flatten().copied().collect();
+        found.into_iter().collect()
+    }
+}
+
+/// Lines of a unit between its first and last, trimmed, without blank
+/// lines and lone brackets (which say nothing about where code went).
+fn body_lines(unit: &str) -> Vec<&str> {
+    let lines: Vec<&str> = unit.lines().map(str::trim).collect();
+    let inner = if lines.len() > 2 { &lines[1..lines.len() - 1] } else { &[][..] };
+    inner.iter().copied().filter(|l| !is_trivial(l)).collect()
+}
+
+fn is_trivial(line: &str) -> bool {
+    line.chars().all(|c| c.is_whitespace() || matches!(c, '{' | '}' | '(' | ')' | '[' | ']' | ';' | ','))
+}
+
+/// Carry the change from `original` to `edited` into `pieces`; the indices
+/// of the pieces it changed, or `None` (and `pieces` untouched) if some
+/// hunk doesn't fit exactly one place.
+pub(crate) fn carry_edit(original: &str, edited: &str, pieces: &mut [String]) -> Option<Vec<usize>> {
+    let old: Vec<&str> = original.lines().collect();
+    let new: Vec<&str> = edited.lines().collect();
+    let hunks = hunks(&old, &new, |x, y| x.trim() == y.trim());
+    let piece_lines: Vec<Vec<&str>> = pieces.iter().map(|p| p.lines().collect()).collect();
+
+    // (piece, line range, replacement lines) for each hunk
+    let mut edits: Vec<(usize, std::ops::Range<usize>, Vec<String>)> = Vec::new();
+    for hunk in &hunks {
+        // (piece, lines replaced, a line of the original and where the piece has it)
+        let (piece, range, reference, matched) = if hunk.old.is_empty() {
+            // added lines go after a copy of the line before them (or before
+            // a copy of the line after them)
+            let before = hunk.old.start.checked_sub(1).map(|i| (i, 1)).filter(|(i, _)| !is_trivial(old[*i]));
+            let after = old.get(hunk.old.start).filter(|l| !is_trivial(l)).map(|_| (hunk.old.start, 0));
+            let (line, offset) = before.or(after)?;
+            let (piece, at) = find_unique(&piece_lines, &old[line..line + 1])?;
+            (piece, at + offset..at + offset, line, at)
+        } else {
+            let lines = &old[hunk.old.clone()];
+            if lines.iter().all(|l| is_trivial(l)) {
+                return None;
+            }
+            let (piece, at) = find_unique(&piece_lines, lines)?;
+            (piece, at..at + lines.len(), hunk.old.start, at)
+        };
+        // keep the piece's indentation: shift lines by the difference
+        let indent = |l: &str| l.len() - l.trim_start().len();
+        let from = &old[reference][..indent(old[reference])];
+        let target = piece_lines[piece][matched];
+        let to = &target[..indent(target)];
+        let replacement =
+            hunk.new.iter().map(|l| l.strip_prefix(from).map_or_else(|| l.to_string(), |rest| format!("{to}{rest}"))).collect();
+        edits.push((piece, range, replacement));
+    }
+    // hunks must not land on the same lines
+    edits.sort_by_key(|(piece, range, _)| (*piece, range.start, range.end));
+    if edits.windows(2).any(|w| w[0].0 == w[1].0 && w[0].1.end > w[1].1.start) {
+        return None;
+    }
+
+    let mut changed = Vec::new();
+    for (piece, range, replacement) in edits.into_iter().rev() {
+        let mut lines: Vec<String> = pieces[piece].lines().map(str::to_string).collect();
+        lines.splice(range, replacement);
+        let trailing = if pieces[piece].ends_with('\n') { "\n" } else { "" };
+        pieces[piece] = lines.join("\n") + trailing;
+        if changed.last() != Some(&piece) {
+            changed.push(piece);
+        }
+    }
+    changed.reverse();
+    Some(changed)
+}
+
+/// Where `lines` occur in exactly one piece, once (trimmed comparison).
+fn find_unique(pieces: &[Vec<&str>], lines: &[&str]) -> Option<(usize, usize)> {
+    let mut found = None;
+    for (p, piece) in pieces.iter().enumerate() {
+        for at in 0..(piece.len() + 1).saturating_sub(lines.len()) {
+            if piece[at..at + lines.len()].iter().zip(lines).all(|(x, y)| x.trim() == y.trim()) {
+                if found.is_some() {
+                    return None;
+                }
+                found = Some((p, at));
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "function processOrder(order) {\n  check(order.items);\n  check(order.total);\n  send(order);\n  log(order.id);\n}";
+    const VALIDATE: &str = "function validateOrder(order) {\n  check(order.items);\n  check(order.total);\n}";
+    const SUBMIT: &str = "function submitOrder(order) {\n  send(order);\n  log(order.id);\n}";
+
+    #[test]
+    fn pieces_sharing_most_lines_are_a_split() {
+        let other = "function unrelated() {\n  return 1;\n}";
+        assert_eq!(split_pieces(ORIGINAL, &[other, VALIDATE, SUBMIT]), Some(vec![1, 2]));
+        assert_eq!(split_pieces(ORIGINAL, &[VALIDATE, other]), None);
+        let mut lines = PieceLines::default();
+        for (id, piece) in [other, VALIDATE, SUBMIT].into_iter().enumerate() {
+            lines.add(id, piece);
+        }
+        assert_eq!(lines.sharing(ORIGINAL), [1, 2]);
+    }
+
+    #[test]
+    fn edits_go_to_the_piece_with_their_lines() {
+        let edited = ORIGINAL
+            .replace("log(order.id);", "log(order.id, order.total);")
+            .replace("  check(order.total);\n", "  check(order.total);\n  check(order.user);\n");
+        let mut pieces = [VALIDATE.to_string(), SUBMIT.to_string()];
+        assert_eq!(carry_edit(ORIGINAL, &edited, &mut pieces), Some(vec![0, 1]));
+        assert_eq!(pieces[0], VALIDATE.replace("  check(order.total);\n", "  check(order.total);\n  check(order.user);\n"));
+        assert_eq!(pieces[1], SUBMIT.replace("log(order.id);", "log(order.id, order.total);"));
+    }
+
+    #[test]
+    fn an_edit_that_fits_nowhere_leaves_the_pieces() {
+        let edited = ORIGINAL.replace("function processOrder(order) {", "async function processOrder(order) {");
+        let mut pieces = [VALIDATE.to_string(), SUBMIT.to_string()];
+        assert_eq!(carry_edit(ORIGINAL, &edited, &mut pieces), None);
+        assert_eq!(pieces, [VALIDATE, SUBMIT]);
+    }
+}
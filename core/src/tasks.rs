@@ -0,0 +1,180 @@
+//! tasks.rs
+//! Async entry points for servers (the `async` feature).
+//!
+//! Merging is CPU work; awaited on an async runtime's worker threads it would
+//! stall every other task. The `_async` functions hand the merge to tokio's
+//! blocking pool ([`tokio::task::spawn_blocking`]) and return a
+//! [`MergeTask`] to await, so they must be called from within a tokio
+//! runtime.
+//!
+//! [`merge_tree_async`] merges files read through an [`AsyncFileSource`]
+//! (object storage, HTTP): it awaits the versions on the caller's runtime
+//! and merges each file on the blocking pool as soon as they're in, within
+//! the policy's memory limit for all of them, as [`crate::merge_batch`]
+//! does.
+
+use std::future::Future;
+use std::panic;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use anyhow::Result;
+use tokio::task::JoinHandle;
+
+use crate::ast::{AstFile, AstLanguage, ParseOptions};
+use crate::batch::{Budget, MergeJob, merge_batch, merge_versions};
+use crate::compose::{MergeOptions, MergeOutcome};
+use crate::policy::Policy;
+
+/// A merge running on the blocking pool; resolves to its result. A panic in
+/// the merge resumes where the task is awaited.
+pub struct MergeTask<T> {
+    handle: JoinHandle<T>,
+}
+
+impl<T: Send + 'static> MergeTask<T> {
+    fn spawn(work: impl FnOnce() -> T + Send + 'static) -> Self {
+        MergeTask { handle: tokio::task::spawn_blocking(work) }
+    }
+}
+
+impl<T> Future for MergeTask<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(&mut self.handle).poll(cx).map(|result| match result {
+            Ok(value) => value,
+            Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+            // blocking tasks only end early when the runtime shuts down
+            Err(e) => panic!("merge task {e}"),
+        })
+    }
+}
+
+/// [`crate::three_way_merge_top_level`], off the async runtime.
+pub fn three_way_merge_top_level_async(
+    base_code: String,
+    a_code: String,
+    b_code: String,
+    lang: AstLanguage,
+) -> MergeTask<Result<MergeOutcome>> {
+    MergeTask::spawn(move || crate::three_way_merge_top_level(&base_code, &a_code, &b_code, lang))
+}
+
+/// [`crate::three_way_merge`], off the async runtime.
+pub fn three_way_merge_async(
+    base_code: String,
+    a_code: String,
+    b_code: String,
+    lang: AstLanguage,
+    merge_opts: MergeOptions,
+) -> MergeTask<Result<MergeOutcome>> {
+    MergeTask::spawn(move || crate::three_way_merge(&base_code, &a_code, &b_code, lang, &merge_opts))
+}
+
+/// [`merge_batch`], off the async runtime.
+pub fn merge_batch_async(jobs: Vec<MergeJob>, policy: Policy) -> MergeTask<Vec<Result<MergeOutcome>>> {
+    MergeTask::spawn(move || merge_batch(&jobs, &policy))
+}
+
+/// Unit collection with [`ParseOptions`], off the async runtime.
+pub fn parse_typescript_to_ast_async(
+    code: String,
+    lang: AstLanguage,
+    opts: ParseOptions,
+) -> MergeTask<Result<AstFile>> {
+    MergeTask::spawn(move || crate::parse_typescript_to_ast_with(&code, lang, &opts))
+}
+
+/// What an [`AsyncFileSource`] returns.
+pub type SourceFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Files by revision, read asynchronously, for [`merge_tree_async`].
+pub trait AsyncFileSource {
+    /// `path` at `rev`, or `None` if the revision has no such file.
+    fn read<'a>(&'a self, rev: &'a str, path: &'a str) -> SourceFuture<'a, Option<String>>;
+}
+
+/// Merge `paths` between the revisions `base`, `a` and `b` of `source` on
+/// the blocking pool; the outcomes line up with `paths`. A file a branch
+/// deleted merges with [`crate::merge_deleted`] (deleted, or a
+/// `delete-modify` conflict); one a branch added merges against an empty
+/// base. Fails if reading a version does.
+pub async fn merge_tree_async<S: AsyncFileSource + ?Sized>(
+    source: &S,
+    (base, a, b): (&str, &str, &str),
+    paths: &[String],
+    policy: &Policy,
+) -> Result<Vec<Result<MergeOutcome>>> {
+    let policy = Arc::new(policy.clone());
+    let budget = Arc::new(Budget::new(policy.memory_limit()));
+    let mut tasks = Vec::with_capacity(paths.len());
+    for path in paths {
+        let versions = (source.read(base, path).await?, source.read(a, path).await?, source.read(b, path).await?);
+        let (policy, budget, path) = (Arc::clone(&policy), Arc::clone(&budget), path.clone());
+        tasks.push(MergeTask::spawn(move || merge_versions(path, versions, &policy, &budget)));
+    }
+    let mut outcomes = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        outcomes.push(task.await);
+    }
+    Ok(outcomes)
+}
+
+#[cfg(all(test, feature = "lang-typescript"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    struct Revisions(HashMap<(&'static str, &'static str), &'static str>);
+
+    impl AsyncFileSource for Revisions {
+        fn read<'a>(&'a self, rev: &'a str, path: &'a str) -> SourceFuture<'a, Option<String>> {
+            Box::pin(async move { Ok(self.0.get(&(rev, path)).map(|text| text.to_string())) })
+        }
+    }
+
+    fn merge_tree(files: &[((&'static str, &'static str), &'static str)], paths: &[&str]) -> Vec<MergeOutcome> {
+        let source = Revisions(files.iter().copied().collect());
+        let paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let outcomes = runtime.block_on(merge_tree_async(&source, ("base", "a", "b"), &paths, &Policy::default()));
+        outcomes.unwrap().into_iter().map(|o| o.unwrap()).collect()
+    }
+
+    #[test]
+    fn missing_sides_merge_as_deletions() {
+        let base = "export const x = 1;\n";
+        let outcomes = merge_tree(
+            &[
+                (("base", "kept.ts"), base),
+                (("a", "kept.ts"), base),
+                (("base", "changed.ts"), base),
+                (("a", "changed.ts"), "export const x = 2;\n"),
+                (("b", "added.ts"), base),
+            ],
+            &["kept.ts", "changed.ts", "added.ts"],
+        );
+        assert!(outcomes[0].deleted && outcomes[0].conflicts.is_empty());
+        assert!(!outcomes[1].deleted);
+        assert_eq!(outcomes[1].conflicts[0].kind, "delete-modify");
+        assert!(!outcomes[2].deleted && outcomes[2].conflicts.is_empty());
+        assert_eq!(outcomes[2].merged_code, base);
+    }
+
+    #[test]
+    fn changed_files_merge_on_the_blocking_pool() {
+        let outcomes = merge_tree(
+            &[
+                (("base", "x.ts"), "export const x = 1;\n"),
+                (("a", "x.ts"), "export const x = 1;\nexport const a = 1;\n"),
+                (("b", "x.ts"), "export const b = 1;\nexport const x = 1;\n"),
+            ],
+            &["x.ts"],
+        );
+        assert!(outcomes[0].conflicts.is_empty());
+        assert!(outcomes[0].merged_code.contains("const a") && outcomes[0].merged_code.contains("const b"));
+    }
+}
@@ -0,0 +1,326 @@
+//! testing.rs
+//! Golden-file merge fixtures.
+//!
+//! A fixture is a directory holding `base.ts`, `a.ts`, `b.ts` and
+//! `expected.ts` (or `.tsx` for all four), plus optionally
+//! `expected_conflicts.txt` with one conflict message per line. [`Golden`]
+//! finds every fixture under a root, merges it and diffs the result against
+//! the expectation:
+//!
+//! ```no_run
+//! let report = sink_core::testing::Golden::new("fixtures").run().unwrap();
+//! report.assert_passed();
+//! ```
+//!
+//! Trailing whitespace at the end of a file is ignored. With `bless`, failing
+//! fixtures get their expectations rewritten from the actual output instead.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::diff3::{Step, lcs_steps};
+use crate::{AstLanguage, Conflict, MergeOptions, MergeOutcome, three_way_merge, token_hash};
+
+/// One fixture directory.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    /// Path relative to the root, `/`-separated.
+    pub name: String,
+    pub dir: PathBuf,
+    /// `ts` or `tsx`.
+    pub ext: String,
+}
+
+impl Fixture {
+    pub fn lang(&self) -> AstLanguage {
+        if self.ext == "tsx" { AstLanguage::Tsx } else { AstLanguage::TypeScript }
+    }
+
+    fn file(&self, stem: &str) -> PathBuf {
+        self.dir.join(format!("{stem}.{}", self.ext))
+    }
+
+    fn read(&self, stem: &str) -> Result<String> {
+        let path = self.file(stem);
+        std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))
+    }
+
+    /// Merge `a` and `b` over `base`.
+    pub fn merge(&self, opts: &MergeOptions) -> Result<MergeOutcome> {
+        three_way_merge(&self.read("base")?, &self.read("a")?, &self.read("b")?, self.lang(), opts)
+    }
+}
+
+/// How one fixture went.
+#[derive(Debug, Clone)]
+pub struct FixtureResult {
+    pub name: String,
+    /// Line diff of expected vs. actual code and conflicts; empty when it passed.
+    pub diff: String,
+    /// The merge itself failed.
+    pub error: Option<String>,
+}
+
+impl FixtureResult {
+    pub fn passed(&self) -> bool {
+        self.diff.is_empty() && self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub results: Vec<FixtureResult>,
+}
+
+impl Report {
+    pub fn failures(&self) -> impl Iterator<Item = &FixtureResult> {
+        self.results.iter().filter(|r| !r.passed())
+    }
+
+    /// Panic with every failure's diff unless all fixtures passed.
+    pub fn assert_passed(&self) {
+        if self.failures().next().is_some() {
+            panic!("{self}");
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for r in self.failures() {
+            writeln!(f, "FAIL {}", r.name)?;
+            if let Some(e) = &r.error {
+                writeln!(f, "  error: {e}")?;
+            }
+            for line in r.diff.lines() {
+                writeln!(f, "  {line}")?;
+            }
+        }
+        let failed = self.failures().count();
+        write!(f, "{} fixtures, {} passed, {} failed", self.results.len(), self.results.len() - failed, failed)
+    }
+}
+
+/// Runs every fixture under a root directory.
+pub struct Golden {
+    root: PathBuf,
+    opts: MergeOptions,
+    bless: bool,
+}
+
+impl Golden {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Golden { root: root.into(), opts: MergeOptions::default(), bless: false }
+    }
+
+    /// Merge with `opts` (e.g. custom reconcilers) instead of the defaults.
+    pub fn with_options(mut self, opts: MergeOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Rewrite the expectations of failing fixtures from the actual output.
+    pub fn bless(mut self, bless: bool) -> Self {
+        self.bless = bless;
+        self
+    }
+
+    /// Fixture directories under the root, sorted by name.
+    pub fn discover(&self) -> Result<Vec<Fixture>> {
+        let mut out = Vec::new();
+        discover_in(&self.root, &self.root, &mut out)?;
+        if out.is_empty() {
+            bail!("no fixtures under {}", self.root.display());
+        }
+        out.sort_by(|x, y| x.name.cmp(&y.name));
+        Ok(out)
+    }
+
+    pub fn run(&self) -> Result<Report> {
+        let mut report = Report::default();
+        for fixture in self.discover()? {
+            report.results.push(self.run_one(&fixture)?);
+        }
+        Ok(report)
+    }
+
+    pub fn run_one(&self, fixture: &Fixture) -> Result<FixtureResult> {
+        let name = fixture.name.clone();
+        let outcome = match fixture.merge(&self.opts) {
+            Ok(outcome) => outcome,
+            Err(e) => return Ok(FixtureResult { name, diff: String::new(), error: Some(format!("{e:#}")) }),
+        };
+        let expected = fixture.read("expected")?;
+        let conflicts_path = fixture.dir.join("expected_conflicts.txt");
+        let expected_conflicts = std::fs::read_to_string(&conflicts_path).unwrap_or_default();
+        let actual_conflicts: String = outcome.conflicts.iter().map(|c| format!("{}\n", c.message)).collect();
+
+        let mut diff = line_diff(expected.trim_end(), outcome.merged_code.trim_end());
+        let conflicts_diff = line_diff(expected_conflicts.trim_end(), actual_conflicts.trim_end());
+        if !conflicts_diff.is_empty() {
+            diff.push_str("conflicts:\n");
+            diff.push_str(&conflicts_diff);
+        }
+        if self.bless && !diff.is_empty() {
+            std::fs::write(fixture.file("expected"), format!("{}\n", outcome.merged_code.trim_end()))?;
+            if actual_conflicts.is_empty() {
+                let _ = std::fs::remove_file(&conflicts_path);
+            } else {
+                std::fs::write(&conflicts_path, &actual_conflicts)?;
+            }
+            diff.clear();
+        }
+        Ok(FixtureResult { name, diff, error: None })
+    }
+}
+
+fn discover_in(root: &Path, dir: &Path, out: &mut Vec<Fixture>) -> Result<()> {
+    let ext = ["ts", "tsx"].into_iter().find(|ext| dir.join(format!("base.{ext}")).is_file());
+    if let Some(ext) = ext {
+        let rel = dir.strip_prefix(root).unwrap_or(dir);
+        let name = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        out.push(Fixture { name, dir: dir.to_path_buf(), ext: ext.to_string() });
+        return Ok(());
+    }
+    let entries = std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            discover_in(root, &path, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// `-`/`+` lines between `expected` and `actual` (longest common
+/// subsequence); empty when they are equal.
+pub fn line_diff(expected: &str, actual: &str) -> String {
+    if expected == actual {
+        return String::new();
+    }
+    let (x, y): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    let mut out = String::new();
+    let mut changed = false;
+    let (mut i, mut j) = (0, 0);
+    for step in lcs_steps(&x, &y, |p, q| p == q) {
+        match step {
+            Step::Keep => {
+                out.push_str(&format!("  {}\n", x[i]));
+                (i, j) = (i + 1, j + 1);
+            }
+            Step::Insert => {
+                out.push_str(&format!("+ {}\n", y[j]));
+                j += 1;
+                changed = true;
+            }
+            Step::Delete => {
+                out.push_str(&format!("- {}\n", x[i]));
+                i += 1;
+                changed = true;
+            }
+        }
+    }
+    if changed { out } else { "(line endings differ)\n".to_string() }
+}
+
+// ---------------------------------------------------------------------------
+// Invariants, for property tests and fuzz targets. Each returns an error
+// describing the violation (or the merge failure).
+
+/// Merging the same inputs twice gives byte-identical results.
+pub fn check_determinism(base: &str, a: &str, b: &str, lang: AstLanguage) -> Result<()> {
+    let opts = MergeOptions::default();
+    let first = three_way_merge(base, a, b, lang, &opts)?;
+    for _ in 0..4 {
+        let again = three_way_merge(base, a, b, lang, &opts)?;
+        let diff = line_diff(&first.merged_code, &again.merged_code);
+        if !diff.is_empty() {
+            bail!("merge is not deterministic:\n{diff}");
+        }
+        if again.conflicts != first.conflicts {
+            bail!("conflicts are not deterministic: {:?} vs {:?}", first.conflicts, again.conflicts);
+        }
+    }
+    Ok(())
+}
+
+/// merge(A, B) and merge(B, A) agree, up to which side conflict markers and
+/// messages call A, and formatting (equivalent inserts keep A's).
+pub fn check_symmetry(base: &str, a: &str, b: &str, lang: AstLanguage) -> Result<()> {
+    let opts = MergeOptions::default();
+    let ab = three_way_merge(base, a, b, lang, &opts)?;
+    let ba = three_way_merge(base, b, a, lang, &opts)?;
+    if ab.conflicts.is_empty() != ba.conflicts.is_empty() {
+        bail!("conflicts differ: {:?} vs {:?}", ab.conflicts, ba.conflicts);
+    }
+    if !ab.conflicts.is_empty() {
+        // both conflict: the units and kinds must match, the code may differ
+        let units = |c: &[Conflict]| {
+            let mut v: Vec<(String, Option<String>)> = c.iter().map(|c| (c.kind.clone(), c.unit.clone())).collect();
+            v.sort();
+            v
+        };
+        if units(&ab.conflicts) != units(&ba.conflicts) {
+            bail!("conflicts differ: {:?} vs {:?}", ab.conflicts, ba.conflicts);
+        }
+        return Ok(());
+    }
+    let diff = line_diff(&ab.merged_code, &ba.merged_code);
+    if !diff.is_empty() && token_hash(&ab.merged_code, lang) != token_hash(&ba.merged_code, lang) {
+        bail!("merge(A, B) differs from merge(B, A):\n{diff}");
+    }
+    Ok(())
+}
+
+/// A branch merged with an unchanged one comes out as-is:
+/// merge(base, base, X) == merge(base, X, base) == X.
+pub fn check_identity(base: &str, x: &str, lang: AstLanguage) -> Result<()> {
+    let opts = MergeOptions::default();
+    for (a, b) in [(base, x), (x, base)] {
+        let out = three_way_merge(base, a, b, lang, &opts)?;
+        if !out.conflicts.is_empty() {
+            bail!("unexpected conflicts: {:?}", out.conflicts);
+        }
+        let diff = line_diff(x, &out.merged_code);
+        if !diff.is_empty() {
+            bail!("merge with an unchanged side altered the other:\n{diff}");
+        }
+    }
+    Ok(())
+}
+
+/// A clean merge result M absorbs its inputs: merge(base, M, X) == M for X
+/// in {A, B}, up to formatting. (Merges with conflicts are skipped.)
+pub fn check_idempotence(base: &str, a: &str, b: &str, lang: AstLanguage) -> Result<()> {
+    remerge(base, a, b, lang, true)
+}
+
+/// Re-merging with the inputs in either order converges on the same file:
+/// merge(base, X, M) == M for X in {A, B}, up to formatting (equivalent
+/// inserts keep X's). (Merges with conflicts are skipped.)
+pub fn check_convergence(base: &str, a: &str, b: &str, lang: AstLanguage) -> Result<()> {
+    remerge(base, a, b, lang, false)
+}
+
+fn remerge(base: &str, a: &str, b: &str, lang: AstLanguage, result_first: bool) -> Result<()> {
+    let opts = MergeOptions::default();
+    let merged = three_way_merge(base, a, b, lang, &opts)?;
+    if !merged.conflicts.is_empty() {
+        return Ok(());
+    }
+    let m = merged.merged_code.as_str();
+    for (side, x) in [("A", a), ("B", b)] {
+        let (first, second) = if result_first { (m, x) } else { (x, m) };
+        let again = three_way_merge(base, first, second, lang, &opts)?;
+        if !again.conflicts.is_empty() {
+            bail!("re-merging the result with {side} conflicts: {:?}", again.conflicts);
+        }
+        let diff = line_diff(m, &again.merged_code);
+        if !diff.is_empty() && token_hash(m, lang) != token_hash(&again.merged_code, lang) {
+            bail!("re-merging the result with {side} changed it:\n{diff}");
+        }
+    }
+    Ok(())
+}
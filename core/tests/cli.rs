@@ -0,0 +1,186 @@
+//! The `sinkit` binary end to end: `merge --apply` and `commit-merge` on a
+//! git repo, and `merge-file` on loose files.
+#![cfg(feature = "lang-typescript")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn sinkit(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sinkit")).args(args).current_dir(dir).output().unwrap()
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let out = Command::new("git").args(args).current_dir(dir).output().unwrap();
+    assert!(out.status.success(), "git {args:?}: {}", String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout).unwrap()
+}
+
+/// Commit `files` (`None`: removing the file) on the current branch.
+fn commit(dir: &Path, files: &[(&str, Option<&str>)], message: &str) {
+    for (path, content) in files {
+        match content {
+            Some(content) => fs::write(dir.join(path), content).unwrap(),
+            None => fs::remove_file(dir.join(path)).unwrap(),
+        }
+    }
+    git(dir, &["add", "-A"]);
+    git(dir, &["commit", "-q", "-m", message]);
+}
+
+/// A repo with branches `a` and `b` off `main`, checked out at `a`.
+fn repo(base: &[(&str, Option<&str>)], a: &[(&str, Option<&str>)], b: &[(&str, Option<&str>)]) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path();
+    git(path, &["init", "-q", "-b", "main"]);
+    git(path, &["config", "user.name", "sinkit"]);
+    git(path, &["config", "user.email", "sinkit@example.com"]);
+    git(path, &["config", "commit.gpgsign", "false"]);
+    commit(path, base, "base");
+    git(path, &["checkout", "-q", "-b", "b"]);
+    commit(path, b, "b");
+    git(path, &["checkout", "-q", "-b", "a", "main"]);
+    commit(path, a, "a");
+    dir
+}
+
+/// `(path, status)` of every file in the run's manifest.
+fn manifest(dir: &Path) -> Vec<(String, String)> {
+    let text = fs::read_to_string(dir.join(".codesync/manifest.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let files = json["files"].as_array().unwrap();
+    files.iter().map(|f| (f["path"].as_str().unwrap().to_string(), f["status"].as_str().unwrap().to_string())).collect()
+}
+
+#[test]
+fn merge_file_follows_textual_rules() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, text: &str| fs::write(dir.path().join(name), text).unwrap();
+    write(".sinkit.toml", "[[rule]]\npath = \"**/*.generated.ts\"\nmerge = \"textual\"\n");
+    write("base.ts", "export const x = 1;\n");
+    write("a.ts", "export const x = 1;\nexport const a = 1;\n");
+    write("b.ts", "export const b = 1;\nexport const x = 1;\n");
+    let merge = |path: &str| sinkit(dir.path(), &["merge-file", "base.ts", "a.ts", "b.ts", "--path", path]);
+    let out = merge("src/api.ts");
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "export const b = 1;\nexport const x = 1;\nexport const a = 1;\n");
+    let out = merge("src/api.generated.ts");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("textual merge only"), "{}", String::from_utf8_lossy(&out.stderr));
+}
+
+#[test]
+fn merge_apply_takes_added_and_unmerged_paths() {
+    let dir = repo(
+        &[("keep.ts", Some("export const a = 1;\n")), ("README.md", Some("# app\n\nintro\n\nusage\n"))],
+        &[("keep.ts", Some("export const a = 2;\n")), ("a.md", Some("from a\n"))],
+        &[("README.md", Some("# app\n\nintro\n\nusage, longer\n")), ("new.ts", Some("export const n = 1;\n"))],
+    );
+    let out = sinkit(dir.path(), &["merge", ".", "a", "b", "--apply", "--porcelain"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "merged\tkeep.ts\t0\tast\nmerged\tREADME.md\t0\ttext\nmerged\tnew.ts\t0\ttext\n");
+    assert_eq!(fs::read_to_string(dir.path().join("README.md")).unwrap(), "# app\n\nintro\n\nusage, longer\n");
+    assert_eq!(fs::read_to_string(dir.path().join("new.ts")).unwrap(), "export const n = 1;\n");
+    assert_eq!(fs::read_to_string(dir.path().join("a.md")).unwrap(), "from a\n");
+
+    let dir = repo(&[("notes.txt", Some("x\n"))], &[("notes.txt", Some("a\n"))], &[("notes.txt", Some("b\n"))]);
+    let out = sinkit(dir.path(), &["merge", ".", "a", "b", "--apply", "--porcelain"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "conflict\tnotes.txt\t1\ttext\n");
+}
+
+#[test]
+fn merge_apply_writes_merges_and_deletions() {
+    let dir = repo(
+        &[("keep.ts", Some("export const a = 1;\n")), ("gone.ts", Some("export const x = 1;\n"))],
+        &[("keep.ts", Some("export const a = 1;\nexport const b = 2;\n"))],
+        &[("keep.ts", Some("export const c = 3;\nexport const a = 1;\n")), ("gone.ts", None)],
+    );
+    let out = sinkit(dir.path(), &["merge", ".", "a", "b", "--apply", "--porcelain"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "deleted\tgone.ts\t0\tast\nmerged\tkeep.ts\t0\tast\n");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("keep.ts")).unwrap(),
+        "export const c = 3;\nexport const a = 1;\nexport const b = 2;\n"
+    );
+    assert!(!dir.path().join("gone.ts").exists());
+    assert_eq!(manifest(dir.path()), [("gone.ts".into(), "deleted".into()), ("keep.ts".into(), "merged".into())]);
+}
+
+#[test]
+fn merge_exits_1_on_a_delete_modify_conflict() {
+    let dir = repo(
+        &[("fought.ts", Some("export const y = 1;\n"))],
+        &[("fought.ts", Some("export const y = 2;\n"))],
+        &[("fought.ts", None)],
+    );
+    let out = sinkit(dir.path(), &["merge", ".", "a", "b", "--apply", "--porcelain"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "conflict\tfought.ts\t1\ttext\n");
+    assert_eq!(manifest(dir.path()), [("fought.ts".into(), "conflict".into())]);
+}
+
+#[test]
+fn merge_file_writes_only_clean_merges() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, text: &str| fs::write(dir.path().join(name), text).unwrap();
+    write("base.env", "A=1\n");
+    write("a.env", "A=1\nB=2\n");
+    write("b.env", "C=3\nA=1\n");
+    let out = sinkit(dir.path(), &["merge-file", "base.env", "a.env", "b.env", "-o", "out.env", "--path", ".env"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(fs::read_to_string(dir.path().join("out.env")).unwrap(), "C=3\nA=1\nB=2\n");
+
+    write("a.env", "A=2\n");
+    write("b.env", "A=3\n");
+    let out = sinkit(dir.path(), &["merge-file", "base.env", "a.env", "b.env", "-o", "conflict.env", "--path", ".env"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Both branches changed key A differently"));
+    assert!(!dir.path().join("conflict.env").exists());
+}
+
+#[test]
+fn a_clean_commit_merge_commits_on_a_with_both_parents() {
+    let dir = repo(
+        &[
+            ("unit.ts", Some("export const a = 1;\n")),
+            ("lines.txt", Some("1\n2\n3\n")),
+            ("gone.ts", Some("export const x = 1;\n")),
+        ],
+        &[("unit.ts", Some("export const a = 1;\nexport const b = 2;\n")), ("lines.txt", Some("A\n2\n3\n"))],
+        &[("unit.ts", Some("export const c = 3;\nexport const a = 1;\n")), ("lines.txt", Some("1\n2\nB\n")), ("gone.ts", None)],
+    );
+    let path = dir.path();
+    let (a, b) = (git(path, &["rev-parse", "a"]), git(path, &["rev-parse", "b"]));
+    let out = sinkit(path, &["commit-merge", ".", "a", "b", "-m", "Merge b into a"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+    assert_eq!(git(path, &["rev-parse", "a^1"]), a);
+    assert_eq!(git(path, &["rev-parse", "a^2"]), b);
+    assert_eq!(git(path, &["log", "-1", "--format=%s", "a"]), "Merge b into a\n");
+    let merged = "export const c = 3;\nexport const a = 1;\nexport const b = 2;\n";
+    assert_eq!(git(path, &["show", "a:unit.ts"]), merged);
+    assert_eq!(git(path, &["show", "a:lines.txt"]), "A\n2\nB\n");
+    assert_eq!(git(path, &["ls-tree", "--name-only", "a"]), "lines.txt\nunit.ts\n");
+    // the checkout of a moved along; the scratch index is gone
+    assert_eq!(fs::read_to_string(path.join("unit.ts")).unwrap(), merged);
+    let entries = fs::read_dir(path.join(".git")).unwrap().flatten();
+    assert_eq!(entries.filter(|e| e.file_name().to_string_lossy().starts_with("sinkit-")).count(), 0);
+}
+
+#[test]
+fn commit_merge_conflicts_outside_a_checkout_of_a_make_no_commit() {
+    let dir = repo(
+        &[("unit.ts", Some("export const a = 1;\n")), ("lines.txt", Some("1\n2\n3\n"))],
+        &[("unit.ts", Some("export const a = 2;\n")), ("lines.txt", Some("A\n2\n3\n")), ("new.txt", Some("a\n"))],
+        &[("unit.ts", Some("export const a = 3;\n")), ("lines.txt", Some("1\n2\nB\n")), ("new.txt", Some("b\n"))],
+    );
+    let path = dir.path();
+    git(path, &["checkout", "-q", "main"]);
+    let a = git(path, &["rev-parse", "a"]);
+    let out = sinkit(path, &["commit-merge", ".", "a", "b"]);
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("no merge commit: conflicts remain in new.txt, unit.ts ("), "{stderr}");
+    assert_eq!(git(path, &["rev-parse", "a"]), a);
+}
@@ -0,0 +1,10 @@
+//! The golden merge fixtures under `fixtures/` (see `sink_core::testing`);
+//! `cargo run --example golden -- --bless` rewrites their expectations.
+#![cfg(feature = "lang-typescript")]
+
+use sink_core::testing::Golden;
+
+#[test]
+fn golden_fixtures() {
+    Golden::new("fixtures").run().unwrap().assert_passed();
+}
@@ -0,0 +1,30 @@
+//! The merge invariants (see `sink_core::testing`) on every golden fixture.
+#![cfg(feature = "lang-typescript")]
+
+use sink_core::testing::{
+    Golden, check_convergence, check_determinism, check_idempotence, check_identity, check_symmetry,
+};
+
+#[test]
+fn invariants_hold_on_fixtures() {
+    let mut failures = Vec::new();
+    for fixture in Golden::new("fixtures").discover().unwrap() {
+        let read = |stem: &str| std::fs::read_to_string(fixture.dir.join(format!("{stem}.{}", fixture.ext))).unwrap();
+        let (base, a, b) = (read("base"), read("a"), read("b"));
+        let lang = fixture.lang();
+        let checks = [
+            ("determinism", check_determinism(&base, &a, &b, lang)),
+            ("symmetry", check_symmetry(&base, &a, &b, lang)),
+            ("identity(A)", check_identity(&base, &a, lang)),
+            ("identity(B)", check_identity(&base, &b, lang)),
+            ("idempotence", check_idempotence(&base, &a, &b, lang)),
+            ("convergence", check_convergence(&base, &a, &b, lang)),
+        ];
+        for (name, result) in checks {
+            if let Err(e) = result {
+                failures.push(format!("{} {name}: {e:#}", fixture.name));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "invariant violations:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,51 @@
+//! The `AstFile` documents under `fixtures/schema/`, one per schema version
+//! (see `sink_core::AST_SCHEMA_VERSION`).
+
+use sink_core::{AST_SCHEMA_VERSION, AstFile, content_hash};
+
+#[test]
+fn every_schema_version_reads_back() {
+    let mut current = None;
+    for entry in std::fs::read_dir("fixtures/schema").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let file: AstFile = serde_json::from_str(&std::fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("{name}: {e}"));
+        for u in &file.units {
+            assert_eq!(u.content_hash, content_hash(&file.code[u.start_byte..u.end_byte]), "{name}: stale hash on {}", u.kind);
+        }
+        let written = serde_json::to_value(&file).unwrap();
+        assert_eq!(written["schema"], AST_SCHEMA_VERSION, "{name}");
+        let again: AstFile = serde_json::from_value(written.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&again).unwrap(), written, "{name}: changed on a second round trip");
+        if name == format!("v{AST_SCHEMA_VERSION}.json") {
+            current = Some(written);
+        }
+    }
+    let mut newer = current.unwrap_or_else(|| panic!("no v{AST_SCHEMA_VERSION}.json for the current schema"));
+    newer["schema"] = (AST_SCHEMA_VERSION + 1).into();
+    assert!(serde_json::from_value::<AstFile>(newer).is_err(), "schema {} was read", AST_SCHEMA_VERSION + 1);
+}
+
+#[test]
+fn ranges_outside_the_code_are_rejected() {
+    let path = format!("fixtures/schema/v{AST_SCHEMA_VERSION}.json");
+    let current: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let end = current["code"].as_str().unwrap().len() + 1;
+    let error = serde_json::json!({ "kind": "ERROR", "start_byte": 0, "end_byte": end, "line": 0, "column": 0 });
+    let mut corrupt = Vec::new();
+    for field in ["inner", "declarator", "statement"] {
+        let mut doc = current.clone();
+        doc["units"][0][field] = serde_json::json!([0, end]);
+        corrupt.push((field, doc));
+    }
+    let mut doc = current.clone();
+    doc["errors"] = serde_json::json!([error]);
+    corrupt.push(("errors", doc));
+    for (field, doc) in corrupt {
+        assert!(serde_json::from_value::<AstFile>(doc).is_err(), "{field} out of bounds was read");
+    }
+}